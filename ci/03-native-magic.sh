@@ -0,0 +1,5 @@
+#!/usr/bin/env bash
+
+# The native magic engine is opt-in, so the default build never tests it
+cargo test -p oe_file --features native-magic --lib || exit 1
+cargo test --features file/native-magic --test tests test_file::test_magic -- --test-threads=1 || exit 1
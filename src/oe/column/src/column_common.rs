@@ -6,9 +6,9 @@
 // that was distributed with this source code.
 
 use clap::{crate_version, Arg, ArgGroup, ArgMatches, Command};
+use comfy_table::{Attribute, Color};
 use libc::{setlocale, EXIT_FAILURE, LC_ALL};
-use serde::Serialize;
-use std::collections::HashMap;
+use std::env;
 use std::fs::File;
 use std::io::{self};
 use std::path::Path;
@@ -18,16 +18,178 @@ use uucore::{
 };
 
 use crate::lib_column::{
-    fillcols_main, fillrows_main, get_terminal_width, parse_columns, read_input, simple_main,
-    table_main, validate_args,
+    display_width, fillcols_main, fillrows_main, get_terminal_width, parse_columns, read_input,
+    simple_main, table_main, validate_args,
 };
 
-/// TableRow for json
-#[derive(Debug, Serialize)]
-pub struct TableRow {
-    #[serde(flatten)]
-    /// column
-    pub columns: HashMap<String, String>,
+/// JSON type of the values of a column
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum JsonType {
+    /// String, null when empty
+    String,
+    /// Number, null when empty
+    Number,
+    /// false when empty, "0", "N" or "n", true otherwise
+    Boolean,
+    /// Array of the lines of the cell
+    ArrayString,
+    /// Array of the numbers on the lines of the cell
+    ArrayNumber,
+}
+
+/// Colour or attribute of a column or of the header
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TextColor {
+    /// Foreground colour
+    Fg(Color),
+    /// Text attribute
+    Attr(Attribute),
+}
+
+/// Colour called `name`, with the names of util-linux terminal-colors.d(5)
+pub fn parse_color(name: &str) -> Option<TextColor> {
+    let color = match name {
+        "black" => TextColor::Fg(Color::Black),
+        "red" => TextColor::Fg(Color::DarkRed),
+        "green" => TextColor::Fg(Color::DarkGreen),
+        "brown" => TextColor::Fg(Color::DarkYellow),
+        "blue" => TextColor::Fg(Color::DarkBlue),
+        "magenta" => TextColor::Fg(Color::DarkMagenta),
+        "cyan" => TextColor::Fg(Color::DarkCyan),
+        "gray" | "lightgray" => TextColor::Fg(Color::Grey),
+        "darkgray" => TextColor::Fg(Color::DarkGrey),
+        "lightred" => TextColor::Fg(Color::Red),
+        "lightgreen" => TextColor::Fg(Color::Green),
+        "yellow" => TextColor::Fg(Color::Yellow),
+        "lightblue" => TextColor::Fg(Color::Blue),
+        "lightmagenta" => TextColor::Fg(Color::Magenta),
+        "lightcyan" => TextColor::Fg(Color::Cyan),
+        "white" => TextColor::Fg(Color::White),
+        "bold" => TextColor::Attr(Attribute::Bold),
+        "halfbright" => TextColor::Attr(Attribute::Dim),
+        "underline" => TextColor::Attr(Attribute::Underlined),
+        "blink" => TextColor::Attr(Attribute::SlowBlink),
+        "reverse" => TextColor::Attr(Attribute::Reverse),
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// Column of the table, defined with --table-column or by the
+/// --table-* shorthands
+#[derive(Debug, Clone)]
+pub struct ColumnDef {
+    /// Name in the header and in JSON
+    pub name: String,
+    /// Type of the values in JSON
+    pub json: JsonType,
+    /// Truncate the text when the table is too wide
+    pub trunc: bool,
+    /// Align the text to the right
+    pub right: bool,
+    /// Wrap the text on several lines when the table is too wide
+    pub wrap: bool,
+    /// Do not print the column
+    pub hidden: bool,
+    /// Ignore unusually long cells when computing the width
+    pub noextreme: bool,
+    /// Width hint
+    pub width: Option<usize>,
+    /// Use the width hint as the exact width
+    pub strict_width: bool,
+    /// Colour of the cells
+    pub color: Option<TextColor>,
+}
+
+impl ColumnDef {
+    /// Column with default attributes
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            json: JsonType::String,
+            trunc: false,
+            right: false,
+            wrap: false,
+            hidden: false,
+            noextreme: false,
+            width: None,
+            strict_width: false,
+            color: None,
+        }
+    }
+
+    /// Parse the comma separated attributes of --table-column, as in
+    /// `name=SIZE,json=number,right`
+    pub fn parse(spec: &str) -> UResult<Self> {
+        let mut def = Self::new(String::new());
+        for attr in spec.split(',') {
+            let (key, value) = match attr.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (attr, None),
+            };
+            match (key, value) {
+                ("name", Some(value)) => def.name = value.to_string(),
+                ("trunc", None) => def.trunc = true,
+                ("right", None) => def.right = true,
+                ("wrap", None) => def.wrap = true,
+                ("hide", None) => def.hidden = true,
+                ("noextreme", None) => def.noextreme = true,
+                ("strictwidth", None) => def.strict_width = true,
+                ("width", Some(value)) => match value.parse::<usize>() {
+                    Ok(width) if width > 0 => def.width = Some(width),
+                    _ => {
+                        return Err(USimpleError::new(
+                            EXIT_FAILURE,
+                            format!("invalid column width '{}'", value),
+                        ))
+                    }
+                },
+                ("color", Some(value)) => match parse_color(value) {
+                    Some(color) => def.color = Some(color),
+                    None => {
+                        return Err(USimpleError::new(
+                            EXIT_FAILURE,
+                            format!("unsupported color name '{}'", value),
+                        ))
+                    }
+                },
+                ("json", Some(value)) => {
+                    def.json = match value {
+                        "string" => JsonType::String,
+                        "number" => JsonType::Number,
+                        "boolean" => JsonType::Boolean,
+                        "array-string" => JsonType::ArrayString,
+                        "array-number" => JsonType::ArrayNumber,
+                        _ => {
+                            return Err(USimpleError::new(
+                                EXIT_FAILURE,
+                                format!("unsupported JSON type '{}'", value),
+                            ))
+                        }
+                    }
+                }
+                _ => {
+                    return Err(USimpleError::new(
+                        EXIT_FAILURE,
+                        format!("unsupported column attribute '{}'", attr),
+                    ))
+                }
+            }
+        }
+        if def.name.is_empty() {
+            return Err(USimpleError::new(
+                EXIT_FAILURE,
+                format!("column name missing in '{}'", spec),
+            ));
+        }
+        if def.strict_width && def.width.is_none() {
+            return Err(USimpleError::new(
+                EXIT_FAILURE,
+                format!("strictwidth needs a width in '{}'", spec),
+            ));
+        }
+        Ok(def)
+    }
 }
 
 /// ColumnMode
@@ -43,6 +205,17 @@ pub enum ColumnMode {
     Simple,
 }
 
+/// InputFormat
+#[derive(PartialEq, Clone, Copy)]
+pub enum InputFormat {
+    /// Lines split at the separator
+    Text,
+    /// RFC 4180 comma separated values
+    Csv,
+    /// Tab separated values, quoted as CSV
+    Tsv,
+}
+
 /// Config
 pub struct Config {
     ///
@@ -91,6 +264,17 @@ pub struct Config {
     pub table_noextreme: Option<Vec<usize>>,
     ///
     pub table_header_repeat: bool,
+    /// Format of the input records
+    pub input_format: InputFormat,
+    /// Colour the output
+    pub color: bool,
+    /// Colour of the header
+    pub table_header_color: Option<TextColor>,
+    /// Fill the whole output width
+    pub table_maxout: bool,
+    /// Columns defined with --table-column, then all the columns of the
+    /// table once the shorthands are applied
+    pub table_column_defs: Vec<ColumnDef>,
 }
 
 /// Command Options
@@ -103,6 +287,8 @@ pub mod options {
     pub static TABLE_ORDER: &str = "table-order";
     /// --table-columns <names>
     pub static TABLE_COLUMNS: &str = "table-columns";
+    /// --table-column <properties>
+    pub static TABLE_COLUMN: &str = "table-column";
     /// --table-columns-limit <num>
     pub static TABLE_COLUMNS_LIMIT: &str = "table-columns-limit";
     /// --table-noextreme <columns>
@@ -137,6 +323,14 @@ pub mod options {
     pub static SEPARATOR: &str = "separator";
     /// --fillrows
     pub static FILLROWS: &str = "fillrows";
+    /// --input-format <format>
+    pub static INPUT_FORMAT: &str = "input-format";
+    /// --color[=<when>]
+    pub static COLOR: &str = "color";
+    /// --table-header-color <color>
+    pub static TABLE_HEADER_COLOR: &str = "table-header-color";
+    /// --table-maxout
+    pub static TABLE_MAXOUT: &str = "table-maxout";
     /// input_files
     pub static INPUT_FILES: &str = "input_files";
 }
@@ -144,8 +338,19 @@ pub mod options {
 impl Config {
     /// Generate column general Config
     pub fn from(args_matches: &ArgMatches) -> UResult<Self> {
+        let input_format = match args_matches
+            .get_one::<String>(options::INPUT_FORMAT)
+            .map(|s| s.as_str())
+        {
+            Some("csv") => InputFormat::Csv,
+            Some("tsv") => InputFormat::Tsv,
+            _ => InputFormat::Text,
+        };
+
+        // Records are always split into columns
         let mode = if args_matches.contains_id(options::TABLE)
             || args_matches.contains_id(options::JSON)
+            || input_format != InputFormat::Text
         {
             ColumnMode::Table
         } else if args_matches.contains_id(options::FILLROWS) {
@@ -154,14 +359,30 @@ impl Config {
             ColumnMode::FillCols
         };
 
-        let table_columns = args_matches
-            .get_one::<String>(options::TABLE_COLUMNS)
-            .map(|value| {
-                value
-                    .split(',')
-                    .map(|s| s.to_string())
-                    .collect::<Vec<String>>()
-            });
+        let mut table_column_defs = Vec::new();
+        if let Some(specs) = args_matches.get_many::<String>(options::TABLE_COLUMN) {
+            for spec in specs {
+                table_column_defs.push(ColumnDef::parse(spec)?);
+            }
+        }
+
+        let table_columns = if table_column_defs.is_empty() {
+            args_matches
+                .get_one::<String>(options::TABLE_COLUMNS)
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(|s| s.to_string())
+                        .collect::<Vec<String>>()
+                })
+        } else {
+            Some(
+                table_column_defs
+                    .iter()
+                    .map(|def| def.name.clone())
+                    .collect(),
+            )
+        };
 
         let input_files = args_matches
             .get_many::<String>(options::INPUT_FILES)
@@ -277,6 +498,34 @@ impl Config {
 
         let table_header_repeat = args_matches.contains_id(options::TABLE_HEADER_REPEAT);
 
+        let color = match args_matches
+            .get_one::<String>(options::COLOR)
+            .map(|s| s.as_str())
+        {
+            Some("always") => true,
+            Some("never") => false,
+            _ => {
+                termion::is_tty(&io::stdout())
+                    && env::var_os("NO_COLOR").is_none()
+                    && env::var("TERM").map_or(true, |term| term != "dumb")
+            }
+        };
+
+        let table_header_color = match args_matches.get_one::<String>(options::TABLE_HEADER_COLOR) {
+            Some(name) => match parse_color(name) {
+                Some(color) => Some(color),
+                None => {
+                    return Err(USimpleError::new(
+                        EXIT_FAILURE,
+                        format!("unsupported color name '{}'", name),
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        let table_maxout = args_matches.contains_id(options::TABLE_MAXOUT);
+
         Ok(Self {
             mode,
             table_columns,
@@ -301,19 +550,17 @@ impl Config {
             table_wrap,
             table_noextreme,
             table_header_repeat,
+            input_format,
+            color,
+            table_header_color,
+            table_maxout,
+            table_column_defs,
         })
     }
 
     /// Width of a string
     pub fn width(&self, s: &str) -> usize {
-        s.chars().count()
-    }
-}
-
-impl TableRow {
-    /// Create a new TableRow.
-    pub fn new(columns: HashMap<String, String>) -> Self {
-        Self { columns }
+        display_width(s)
     }
 }
 
@@ -370,6 +617,17 @@ pub fn column_app<'a>(about: &'a str, usage: &'a str) -> Command<'a> {
                 .takes_value(true)
                 .display_order(40),
         )
+        .arg(
+            Arg::new(options::TABLE_COLUMN)
+                .short('C')
+                .long(options::TABLE_COLUMN)
+                .value_name("properties")
+                .help("define a column, as in name=SIZE,right,width=8")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .conflicts_with(options::TABLE_COLUMNS)
+                .display_order(45),
+        )
         .arg(
             Arg::with_name(options::TABLE_COLUMNS_LIMIT)
                 .short('l')
@@ -403,6 +661,14 @@ pub fn column_app<'a>(about: &'a str, usage: &'a str) -> Command<'a> {
                 .help("repeat header for each page")
                 .display_order(80),
         )
+        .arg(
+            Arg::new(options::TABLE_MAXOUT)
+                .short('m')
+                .long(options::TABLE_MAXOUT)
+                .help("fill all available space")
+                .takes_value(false)
+                .display_order(85),
+        )
         .arg(
             Arg::with_name(options::TABLE_HIDE)
                 .short('H')
@@ -522,6 +788,35 @@ pub fn column_app<'a>(about: &'a str, usage: &'a str) -> Command<'a> {
                 .takes_value(false)
                 .display_order(210),
         )
+        .arg(
+            Arg::new(options::INPUT_FORMAT)
+                .long(options::INPUT_FORMAT)
+                .value_name("format")
+                .help("parse input as text, csv or tsv records (csv and tsv imply --table)")
+                .takes_value(true)
+                .possible_values(["text", "csv", "tsv"])
+                .display_order(220),
+        )
+        .arg(
+            Arg::new(options::COLOR)
+                .long(options::COLOR)
+                .value_name("when")
+                .help("colorize the output (auto, always or never)")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .default_missing_value("auto")
+                .possible_values(["auto", "always", "never"])
+                .display_order(230),
+        )
+        .arg(
+            Arg::new(options::TABLE_HEADER_COLOR)
+                .long(options::TABLE_HEADER_COLOR)
+                .value_name("color")
+                .help("color of the table header")
+                .takes_value(true)
+                .display_order(240),
+        )
         .arg(
             Arg::new(options::INPUT_FILES)
                 .help("Specifies the input file")
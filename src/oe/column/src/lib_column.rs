@@ -5,16 +5,18 @@
 // For the full copyright and license information, please view the LICENSE file
 // that was distributed with this source code.
 
-use crate::column_common::{ColumnMode, Config, TableRow};
-use comfy_table::{CellAlignment, ColumnConstraint, ContentArrangement, Table};
+use crate::column_common::{ColumnDef, ColumnMode, Config, InputFormat, JsonType, TextColor};
+use comfy_table::{Cell, CellAlignment, ColumnConstraint, ContentArrangement, Row, Table, Width};
 use libc::{nl_langinfo, CODESET, EXIT_FAILURE};
-use serde_json::json;
 use std::{
     collections::HashMap,
     ffi::CStr,
     io::{self, stdout, BufRead, BufReader, Read, Write},
+    mem,
 };
 use termion::terminal_size;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 use uucore::error::{UResult, USimpleError};
 
 /// TABCHAR_CELLS
@@ -24,6 +26,9 @@ const SEPARATOR_INDEX: usize = 8;
 
 /// Read input function
 pub fn read_input<R: Read>(reader: R, config: &mut Config) -> io::Result<()> {
+    if config.input_format != InputFormat::Text {
+        return read_records(reader, config);
+    }
     let buf_reader = BufReader::new(reader);
     let mut separator = " ".to_owned();
     if let Some(ref sep) = config.input_separator {
@@ -31,16 +36,7 @@ pub fn read_input<R: Read>(reader: R, config: &mut Config) -> io::Result<()> {
     }
 
     for byte_line in buf_reader.split(b'\n') {
-        let byte_line = byte_line?;
-        let mut line = String::new();
-
-        for &byte in &byte_line {
-            if byte.is_ascii_graphic() || byte.is_ascii_whitespace() {
-                line.push(byte as char);
-            } else {
-                line.push_str(&format!("\\x{:02x}", byte));
-            }
-        }
+        let line = decode_input(&byte_line?);
 
         let split_line: Vec<String>;
         match config.mode {
@@ -61,9 +57,7 @@ pub fn read_input<R: Read>(reader: R, config: &mut Config) -> io::Result<()> {
         }
 
         for cell in split_line.iter() {
-            if cell.len() > config.maxlength {
-                config.maxlength = cell.len();
-            }
+            config.maxlength = config.maxlength.max(display_width(cell));
         }
 
         // keep empty lines
@@ -75,6 +69,225 @@ pub fn read_input<R: Read>(reader: R, config: &mut Config) -> io::Result<()> {
     Ok(())
 }
 
+/// Read CSV or TSV records
+fn read_records<R: Read>(mut reader: R, config: &mut Config) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let delimiter = match (&config.input_separator, config.input_format) {
+        (Some(separator), _) => separator.chars().next().unwrap_or(','),
+        (None, InputFormat::Tsv) => '\t',
+        (None, _) => ',',
+    };
+    let records = parse_records(&decode_input(&bytes), delimiter)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    for record in records {
+        // keep empty lines
+        if !config.keep_empty_lines && record.is_empty() {
+            continue;
+        }
+        for cell in record.iter() {
+            config.maxlength = config.maxlength.max(display_width(cell));
+        }
+        config.ents.push(record);
+    }
+    Ok(())
+}
+
+/// Split RFC 4180 text into records. Quoted fields may hold the delimiter,
+/// line breaks and doubled quotes; an empty line is an empty record.
+pub fn parse_records(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    // Inside quotes, and whether the current field started with one
+    let mut quoted = false;
+    let mut was_quoted = false;
+    let mut line = 1;
+    let mut quote_line = 0;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() && !was_quoted => {
+                quoted = true;
+                was_quoted = true;
+                quote_line = line;
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                line += 1;
+                if !field.is_empty() || was_quoted || !record.is_empty() {
+                    record.push(mem::take(&mut field));
+                }
+                records.push(mem::take(&mut record));
+                was_quoted = false;
+            }
+            c if c == delimiter => {
+                record.push(mem::take(&mut field));
+                was_quoted = false;
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("line {}: unterminated quoted field", quote_line));
+    }
+    if !field.is_empty() || was_quoted || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Decode input as UTF-8. Invalid bytes and control characters are kept
+/// as `\xNN`, so that no input is lost.
+fn decode_input(mut bytes: &[u8]) -> String {
+    let mut line = String::with_capacity(bytes.len());
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                push_printable(&mut line, valid);
+                return line;
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                push_printable(&mut line, std::str::from_utf8(valid).unwrap_or_default());
+                let invalid = e.error_len().unwrap_or(rest.len());
+                for byte in &rest[..invalid] {
+                    line.push_str(&format!("\\x{:02x}", byte));
+                }
+                bytes = &rest[invalid..];
+            }
+        }
+    }
+}
+
+fn push_printable(line: &mut String, s: &str) {
+    for c in s.chars() {
+        if c.is_control() && !c.is_ascii_whitespace() {
+            for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                line.push_str(&format!("\\x{:02x}", byte));
+            }
+        } else {
+            line.push(c);
+        }
+    }
+}
+
+/// Width of `s` in terminal cells
+pub fn display_width(s: &str) -> usize {
+    UnicodeWidthStr::width(s)
+}
+
+/// Longest prefix of `s` made of whole graphemes and at most `width` cells
+/// wide
+pub fn truncate_width(s: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (pos, grapheme) in s.grapheme_indices(true) {
+        used += display_width(grapheme);
+        if used > width {
+            return &s[..pos];
+        }
+    }
+    s
+}
+
+/// Split `s` in lines of at most `width` cells, between graphemes. A
+/// grapheme wider than `width` gets a line of its own.
+pub fn wrap_width(s: &str, width: usize) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut used = 0;
+    for (pos, grapheme) in s.grapheme_indices(true) {
+        let grapheme_width = display_width(grapheme);
+        if used + grapheme_width > width && pos > start {
+            lines.push(&s[start..pos]);
+            start = pos;
+            used = 0;
+        }
+        used += grapheme_width;
+    }
+    if start < s.len() || lines.is_empty() {
+        lines.push(&s[start..]);
+    }
+    lines
+}
+
+/// Set `config.table_column_defs` to one definition per column of the
+/// table: the ones of --table-column, or the --table-columns names, with
+/// the --table-* shorthands applied on top
+pub fn resolve_column_defs(config: &mut Config) {
+    let mut defs = if config.table_column_defs.is_empty() {
+        config
+            .table_columns
+            .iter()
+            .flatten()
+            .map(|name| ColumnDef::new(name.clone()))
+            .collect()
+    } else {
+        config.table_column_defs.clone()
+    };
+    let count = config.ents.iter().map(Vec::len).max().unwrap_or(0);
+    if defs.len() < count {
+        defs.resize_with(count, || ColumnDef::new(String::new()));
+    }
+
+    apply_shorthand(&mut defs, &config.table_hide, |def| def.hidden = true);
+    apply_shorthand(&mut defs, &config.table_right, |def| def.right = true);
+    apply_shorthand(&mut defs, &config.table_truncate, |def| def.trunc = true);
+    apply_shorthand(&mut defs, &config.table_wrap, |def| def.wrap = true);
+    apply_shorthand(&mut defs, &config.table_noextreme, |def| {
+        def.noextreme = true
+    });
+    config.table_column_defs = defs;
+}
+
+/// Set an attribute of the columns listed by a --table-* option
+fn apply_shorthand(defs: &mut [ColumnDef], indices: &Option<Vec<usize>>, set: fn(&mut ColumnDef)) {
+    for &index in indices.iter().flatten() {
+        if let Some(def) = defs.get_mut(index) {
+            set(def);
+        }
+    }
+}
+
+/// Cut the cells of the strictwidth columns to their width, on several
+/// lines in wrap columns
+fn apply_strict_widths(config: &mut Config) {
+    for (index, def) in config.table_column_defs.iter().enumerate() {
+        let width = match def.width {
+            Some(width) if def.strict_width => width,
+            _ => continue,
+        };
+        for row in &mut config.ents {
+            if let Some(content) = row.get_mut(index) {
+                *content = if def.wrap {
+                    wrap_width(content, width).join("\n")
+                } else {
+                    truncate_width(content, width).to_string()
+                };
+            }
+        }
+    }
+}
+
 /// Table main function
 pub fn table_main(config: &mut Config) -> UResult<()> {
     // table columns limit
@@ -97,6 +310,16 @@ pub fn table_main(config: &mut Config) -> UResult<()> {
         }
     }
 
+    resolve_column_defs(config);
+
+    // JSON keeps the data as it is and nests the tree
+    if config.json {
+        if config.table_order.is_some() {
+            reorder_columns(config)?;
+        }
+        return print_json(config);
+    }
+
     // Tree main
     if config.tree.is_some() {
         tree_main(config)?;
@@ -107,28 +330,26 @@ pub fn table_main(config: &mut Config) -> UResult<()> {
         reorder_columns(config)?;
     }
 
+    // Columns of a strict width
+    apply_strict_widths(config);
+
     // Table truncate the col
-    if !config.table_truncate.is_none() {
+    if config.table_column_defs.iter().any(|def| def.trunc) {
         truncate_columns(config)?;
     }
 
     // Table wrap the col
-    if !config.table_wrap.is_none() {
+    if config.table_column_defs.iter().any(|def| def.wrap) {
         wrap_columns(config)?;
     }
 
-    // JSON
-    if config.json {
-        print_json(config);
-        return Ok(());
-    }
-
     // Create the table
     let mut table = Table::new();
 
     // Assign value to table
     for cells in config.ents.iter() {
-        table.add_row(cells);
+        let colors = config.table_column_defs.iter().map(|def| def.color);
+        table.add_row(colored_row(config, cells, colors));
     }
 
     // Set outer border
@@ -142,12 +363,20 @@ pub fn table_main(config: &mut Config) -> UResult<()> {
     table
         .set_content_arrangement(ContentArrangement::Dynamic)
         .load_preset(preset.as_str());
+    if config.table_maxout {
+        table
+            .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+            .set_width(config.termwidth.unwrap_or(80) as u16);
+    }
+    if config.color {
+        // Padding and alignment are computed on the text without colours
+        table.enforce_styling().style_text_only();
+    }
 
     // Table col right
-    if let Some(right_indices) = &config.table_right {
-        // println!("right_indices = {:?}", right_indices);
-        for (column_index, column) in table.column_iter_mut().enumerate() {
-            if right_indices.contains(&column_index) {
+    for (column_index, column) in table.column_iter_mut().enumerate() {
+        if let Some(def) = config.table_column_defs.get(column_index) {
+            if def.right {
                 column.set_cell_alignment(CellAlignment::Right);
             }
         }
@@ -170,25 +399,35 @@ pub fn table_main(config: &mut Config) -> UResult<()> {
     // Set the table header
     if let Some(table_columns) = &config.table_columns {
         if !config.table_noheadings {
-            table.set_header(table_columns.clone());
+            let colors = table_columns.iter().map(|_| config.table_header_color);
+            table.set_header(colored_row(config, table_columns, colors));
         }
     }
 
-    // table_hide
-    if let Some(hide_indices) = &config.table_hide {
-        for &index in hide_indices {
-            table
-                .column_mut(index)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden);
+    // Column widths, and table_hide
+    for (column_index, column) in table.column_iter_mut().enumerate() {
+        let def = match config.table_column_defs.get(column_index) {
+            Some(def) => def,
+            None => continue,
+        };
+        // Fixed widths include the padding
+        let padding = column.padding_width() as usize;
+        if def.hidden {
+            column.set_constraint(ColumnConstraint::Hidden);
+        } else if let Some(width) = def.width {
+            let width = Width::Fixed((width + padding) as u16);
+            column.set_constraint(if def.strict_width {
+                ColumnConstraint::Absolute(width)
+            } else {
+                ColumnConstraint::LowerBoundary(width)
+            });
         }
     }
 
-    if table.lines().count() == 1 {
-        for row in table.lines() {
-            println!("{}", row.replace(" ", ""));
-        }
-    } else if config.table_wrap.is_some() {
+    if config.table_maxout {
+        // Every line fills the width
+        println!("{}", table);
+    } else if config.ents.iter().flatten().any(|cell| cell.contains('\n')) {
         println!("{}", table.trim_fmt());
     } else {
         println!("{}", trim_fmt_(config, table));
@@ -197,6 +436,26 @@ pub fn table_main(config: &mut Config) -> UResult<()> {
     Ok(())
 }
 
+/// Cells of a table row, coloured when colours are on. Empty cells stay
+/// plain, so that only spaces pad the columns.
+fn colored_row<I>(config: &Config, cells: &[String], colors: I) -> Row
+where
+    I: Iterator<Item = Option<TextColor>>,
+{
+    let mut colors = colors.fuse();
+    let cells = cells.iter().map(|content| {
+        let cell = Cell::new(content);
+        match colors.next().flatten() {
+            Some(color) if config.color && !content.is_empty() => match color {
+                TextColor::Fg(color) => cell.fg(color),
+                TextColor::Attr(attribute) => cell.add_attribute(attribute),
+            },
+            _ => cell,
+        }
+    });
+    Row::from(cells.collect::<Vec<_>>())
+}
+
 /// Fillcols main function
 pub fn fillcols_main(config: &mut Config) {
     let termwidth = config.termwidth.unwrap();
@@ -294,13 +553,8 @@ pub fn fillrows_main(config: &mut Config) {
 pub fn simple_main(config: &Config) {
     for row in &config.ents {
         for (i, cell) in row.iter().enumerate() {
-            for byte in cell.bytes() {
-                if byte.is_ascii_graphic() || byte.is_ascii_whitespace() {
-                    print!("{}", byte as char);
-                } else {
-                    print!("\\x{:02x}", byte);
-                }
-            }
+            // Already escaped by read_input
+            print!("{}", cell);
             if i < row.len() - 1 {
                 print!(" ");
             }
@@ -309,17 +563,8 @@ pub fn simple_main(config: &Config) {
     }
 }
 
-/// Get last order index
-pub fn get_last_order_index(table_columns: Vec<String>, table_order: Vec<String>) -> usize {
-    let index = table_columns
-        .iter()
-        .position(|res| res == &table_order[table_order.len() - 1])
-        .unwrap();
-    index
-}
-
 /// Trim fmt
-pub fn trim_fmt_(config: &mut Config, mut table: Table) -> String {
+pub fn trim_fmt_(config: &mut Config, table: Table) -> String {
     let mut column_count = 0;
     for col in table.column_iter() {
         if !col.is_hidden() {
@@ -327,32 +572,12 @@ pub fn trim_fmt_(config: &mut Config, mut table: Table) -> String {
         }
     }
 
-    let mut last_column_index: usize = 0;
-    let mut table_columns: Vec<String> = Vec::new();
-    let mut table_order: Vec<String> = Vec::new();
-    let mut table_hide: Vec<usize> = Vec::new();
-
-    if let Some(result) = &config.table_columns {
-        table_columns = result.to_owned();
-    }
-    if let Some(result) = &config.table_order {
-        table_order = result.to_owned();
-    }
-    if let Some(result) = &config.table_hide {
-        table_hide = result.to_owned();
-    }
-
-    if config.table_columns.is_some() && config.table_order.is_some() {
-        last_column_index = get_last_order_index(table_columns, table_order);
-    } else if config.table_columns.is_some() && config.table_hide.is_some() {
-        for (index, _column) in table_columns.iter().enumerate() {
-            if !table_hide.contains(&index) {
-                last_column_index = index;
-            }
-        }
-    } else {
-        last_column_index = table.column_count() - 1;
-    }
+    // The last visible column, whose padding is trimmed
+    let last_column_index = config
+        .table_column_defs
+        .iter()
+        .rposition(|def| !def.hidden)
+        .unwrap_or(0);
 
     let mut last_column_vec: Vec<String> = Vec::new();
 
@@ -387,8 +612,9 @@ pub fn trim_fmt_(config: &mut Config, mut table: Table) -> String {
             continue;
         } else {
             let row_str = &table_format_vec[i];
-            if let Some(pos) = row_str.rfind(last_col_str) {
-                start_index = pos;
+            if let Some(pos) = rfind_visible(row_str, last_col_str) {
+                // Blank rows hold no escape sequences, only the visible width
+                start_index = display_width(&strip_escapes(&row_str[..pos]));
                 break;
             }
         }
@@ -406,8 +632,14 @@ pub fn trim_fmt_(config: &mut Config, mut table: Table) -> String {
                 table_format_vec[i].truncate(start_index);
             }
         } else if !last_column_vec[i].is_empty() {
-            if let Some(pos) = table_format_vec[i].rfind(&last_column_vec[i]) {
-                table_format_vec[i].truncate(pos + last_column_vec[i].len());
+            if let Some(pos) = rfind_visible(&table_format_vec[i], &last_column_vec[i]) {
+                // Keep the escape sequences that end the colour of the cell
+                let end = pos + last_column_vec[i].len();
+                let end = escape_ranges(&table_format_vec[i]).into_iter().fold(
+                    end,
+                    |end, (start, stop)| if start == end { stop } else { end },
+                );
+                table_format_vec[i].truncate(end);
             }
         }
     }
@@ -415,6 +647,52 @@ pub fn trim_fmt_(config: &mut Config, mut table: Table) -> String {
     table_format_vec.join("\n")
 }
 
+/// Byte ranges of the ANSI escape sequences in `line`
+fn escape_ranges(line: &str) -> Vec<(usize, usize)> {
+    let bytes = line.as_bytes();
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == 0x1b && bytes.get(i + 1) == Some(&b'[') {
+            let start = i;
+            i += 2;
+            while i < bytes.len() && !(0x40..=0x7e).contains(&bytes[i]) {
+                i += 1;
+            }
+            i = (i + 1).min(bytes.len());
+            ranges.push((start, i));
+        } else {
+            i += 1;
+        }
+    }
+    ranges
+}
+
+/// `line` without its ANSI escape sequences
+fn strip_escapes(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut last = 0;
+    for (start, stop) in escape_ranges(line) {
+        text.push_str(&line[last..start]);
+        last = stop;
+    }
+    text.push_str(&line[last..]);
+    text
+}
+
+/// Last position of `needle` in `line` that is not part of an escape sequence
+fn rfind_visible(line: &str, needle: &str) -> Option<usize> {
+    let ranges = escape_ranges(line);
+    line.rmatch_indices(needle)
+        .map(|(pos, _)| pos)
+        .find(|&pos| {
+            let end = pos + needle.len();
+            !ranges
+                .iter()
+                .any(|&(start, stop)| pos < stop && end > start)
+        })
+}
+
 /// Reorder columns
 pub fn reorder_columns(config: &mut Config) -> UResult<()> {
     let table_columns = if let Some(table_columns) = &config.table_columns {
@@ -477,118 +755,110 @@ pub fn reorder_columns(config: &mut Config) -> UResult<()> {
             .collect(),
     );
 
+    let defs = &config.table_column_defs;
+    if defs.len() >= table_columns.len() {
+        config.table_column_defs = final_order_indices
+            .iter()
+            .map(|&i| defs[i].clone())
+            .chain(defs[table_columns.len()..].iter().cloned())
+            .collect();
+    }
+
     Ok(())
 }
 
-/// Calculate column lengths
-pub fn calculate_column_lengths(config: &Config) -> (Vec<usize>, Vec<usize>, usize, Vec<usize>) {
-    let num_columns = config.ents[0].len();
-
-    let table_hide_index = if let Some(table_hide) = &config.table_hide {
-        table_hide.clone()
-    } else {
-        Vec::new()
-    };
-
-    let mut col_cells_and_titles_max_lengths: Vec<usize> = vec![0; num_columns];
-    let mut title_columns_lengths: Vec<usize> = vec![0; num_columns];
-
-    for row in &config.ents {
-        for (i, item) in row.iter().enumerate() {
-            if !table_hide_index.contains(&i) {
-                col_cells_and_titles_max_lengths[i] =
-                    col_cells_and_titles_max_lengths[i].max(item.len());
-            }
+/// Calculate column lengths: the widest cell or header, 0 for hidden
+/// columns, at least the width hint, and without the unusually long cells
+/// of noextreme columns
+pub fn calculate_column_lengths(config: &Config) -> Vec<usize> {
+    let header = config.table_columns.is_some() && !config.table_noheadings;
+    let mut lengths = Vec::with_capacity(config.table_column_defs.len());
+    for (index, def) in config.table_column_defs.iter().enumerate() {
+        if def.hidden {
+            lengths.push(0);
+            continue;
         }
-    }
-
-    if let Some(table_columns) = &config.table_columns {
-        for (i, column) in table_columns.iter().enumerate() {
-            if !table_hide_index.contains(&i) {
-                title_columns_lengths[i] = column.len();
-                col_cells_and_titles_max_lengths[i] =
-                    col_cells_and_titles_max_lengths[i].max(column.len());
-            }
+        if def.strict_width {
+            lengths.push(def.width.unwrap_or(0));
+            continue;
+        }
+        let widths: Vec<usize> = config
+            .ents
+            .iter()
+            .filter_map(|row| row.get(index))
+            .map(|cell| display_width(cell))
+            .collect();
+        let mut length = widths.iter().copied().max().unwrap_or(0);
+        if def.noextreme && !widths.is_empty() {
+            let average = widths.iter().sum::<usize>() / widths.len();
+            length = widths
+                .iter()
+                .copied()
+                .filter(|&width| width <= average * 2)
+                .max()
+                .unwrap_or(average);
         }
+        if header {
+            length = length.max(display_width(&def.name));
+        }
+        lengths.push(length.max(def.width.unwrap_or(0)));
     }
+    lengths
+}
 
-    for &index in &table_hide_index {
-        col_cells_and_titles_max_lengths[index] = 0;
-        title_columns_lengths[index] = 0;
+/// Share the width the other columns leave between the columns selected
+/// by `fit`. When none is left, they get the width of their header.
+fn fit_lengths(config: &Config, fit: fn(&ColumnDef) -> bool) -> Vec<(usize, usize)> {
+    let defs = &config.table_column_defs;
+    let lengths = calculate_column_lengths(config);
+    let targets: Vec<usize> = (0..defs.len())
+        .filter(|&index| fit(&defs[index]) && !defs[index].hidden && !defs[index].strict_width)
+        .collect();
+    if targets.is_empty() {
+        return Vec::new();
     }
 
-    let termin_width = config.termwidth.unwrap();
-
-    (
-        col_cells_and_titles_max_lengths,
-        title_columns_lengths,
-        termin_width,
-        table_hide_index,
-    )
+    let visible = defs.iter().filter(|def| !def.hidden).count();
+    let separator = config.output_separator.as_deref().map_or(2, display_width);
+    let used = (0..defs.len())
+        .filter(|index| !targets.contains(index))
+        .map(|index| lengths[index])
+        .sum::<usize>()
+        + separator * visible.saturating_sub(1);
+    let termin_width = config.termwidth.unwrap_or(80);
+
+    if used >= termin_width {
+        return targets
+            .iter()
+            .map(|&index| (index, display_width(&defs[index].name).max(1)))
+            .collect();
+    }
+    let left = termin_width - used;
+    targets
+        .iter()
+        .enumerate()
+        .map(|(n, &index)| {
+            let extra = usize::from(n < left % targets.len());
+            (index, left / targets.len() + extra)
+        })
+        .collect()
 }
 
 /// Truncate columns
 pub fn truncate_columns(config: &mut Config) -> UResult<()> {
-    // Implementation for truncating columns
     if config.ents.is_empty() {
         return Err(USimpleError::new(
             EXIT_FAILURE,
             "Error: Empty table. Cannot truncate columns.",
         ));
     }
-
-    let num_columns = config.ents[0].len();
-
-    let (col_cells_and_titles_max_lengths, title_columns_lengths, termin_width, table_hide_index) =
-        calculate_column_lengths(config);
-
-    let mut temp_lengths: usize = 0;
-    let mut table_truncate_index: Vec<usize> = Vec::new();
-
-    if let Some(table_truncate) = &config.table_truncate {
-        table_truncate_index.extend(table_truncate);
-        table_truncate_index.sort();
-
-        for (index, &len) in col_cells_and_titles_max_lengths.iter().enumerate() {
-            if !table_truncate_index.contains(&index) && !table_hide_index.contains(&index) {
-                temp_lengths += len;
-            }
-        }
-
-        temp_lengths += 2 * (num_columns - 1 - table_hide_index.len());
-    }
-
-    if temp_lengths >= termin_width {
-        for index in table_truncate_index {
-            if index < num_columns {
-                for row in &mut config.ents {
-                    if let Some(content) = row.get_mut(index) {
-                        *content = content.chars().take(title_columns_lengths[index]).collect();
-                    }
-                }
-            }
-        }
-    } else {
-        let truncate_length_sum = termin_width - temp_lengths;
-        let average_length = truncate_length_sum / table_truncate_index.len();
-        let remainder = truncate_length_sum % table_truncate_index.len();
-
-        let mut truncate_lengths = vec![average_length; table_truncate_index.len()];
-        for i in 0..remainder {
-            truncate_lengths[i] += 1;
-        }
-
-        for (i, &index) in table_truncate_index.iter().enumerate() {
-            if index < num_columns {
-                for row in &mut config.ents {
-                    if let Some(content) = row.get_mut(index) {
-                        *content = content.chars().take(truncate_lengths[i]).collect();
-                    }
-                }
+    for (index, length) in fit_lengths(config, |def| def.trunc) {
+        for row in &mut config.ents {
+            if let Some(content) = row.get_mut(index) {
+                *content = truncate_width(content, length).to_string();
             }
         }
     }
-
     Ok(())
 }
 
@@ -600,97 +870,187 @@ pub fn wrap_columns(config: &mut Config) -> UResult<()> {
             "Error: Empty table. Cannot insert newlines in columns.",
         ));
     }
-
-    let num_columns = config.ents[0].len();
-
-    let (col_cells_and_titles_max_lengths, title_columns_lengths, termin_width, table_hide_index) =
-        calculate_column_lengths(config);
-
-    let mut temp_lengths: usize = 0;
-    let mut table_wrap_index: Vec<usize> = Vec::new();
-
-    if let Some(table_wrap) = &config.table_wrap {
-        table_wrap_index.extend(table_wrap);
-        table_wrap_index.sort();
-
-        for (index, &len) in col_cells_and_titles_max_lengths.iter().enumerate() {
-            if !table_wrap_index.contains(&index) && !table_hide_index.contains(&index) {
-                temp_lengths += len;
+    for (index, length) in fit_lengths(config, |def| def.wrap) {
+        for row in &mut config.ents {
+            if let Some(content) = row.get_mut(index) {
+                *content = wrap_width(content, length).join("\n");
             }
         }
-
-        temp_lengths += 2 * (num_columns - 1 - table_hide_index.len());
     }
+    Ok(())
+}
 
-    if temp_lengths >= termin_width {
-        for index in table_wrap_index {
-            if index < num_columns {
-                for row in &mut config.ents {
-                    if let Some(content) = row.get_mut(index) {
-                        let wrapped_content: String = content
-                            .chars()
-                            .collect::<Vec<_>>()
-                            .chunks(title_columns_lengths[index])
-                            .map(|chunk| chunk.iter().collect::<String>())
-                            .collect::<Vec<_>>()
-                            .join("\n");
-                        *content = wrapped_content;
-                    }
-                }
+/// Print the table as JSON, in the layout of libsmartcols: members in
+/// column order, typed values, and the children of tree rows nested.
+pub fn print_json(config: &Config) -> UResult<()> {
+    let names = config.table_columns.clone().unwrap_or_default();
+    for (line, row) in config.ents.iter().enumerate() {
+        if row.len() > names.len() {
+            return Err(USimpleError::new(
+                EXIT_FAILURE,
+                format!(
+                    "line {}: {} cells, but {} column names",
+                    line + 1,
+                    row.len(),
+                    names.len()
+                ),
+            ));
+        }
+    }
+    let table_name = config.table_name.as_deref().unwrap_or("table");
+
+    // Rows whose parent is not a row of the table are roots
+    let count = config.ents.len();
+    let mut parents = vec![None; count];
+    if let (Some(_), Some(tree_id), Some(tree_parent)) =
+        (&config.tree, &config.tree_id, &config.tree_parent)
+    {
+        let id = parse_segment(tree_id, &config.table_columns)?;
+        let parent = parse_segment(tree_parent, &config.table_columns)?;
+        let mut ids = HashMap::new();
+        for (row, cells) in config.ents.iter().enumerate() {
+            if let Some(value) = cells.get(id) {
+                ids.entry(value.as_str()).or_insert(row);
             }
         }
-    } else {
-        let wrap_length_sum = termin_width - temp_lengths;
-        let average_length = wrap_length_sum / table_wrap_index.len();
-        let remainder = wrap_length_sum % table_wrap_index.len();
-
-        let mut wrap_lengths = vec![average_length; table_wrap_index.len()];
-        for i in 0..remainder {
-            wrap_lengths[i] += 1;
-        }
-
-        for (i, &index) in table_wrap_index.iter().enumerate() {
-            if index < num_columns {
-                for row in &mut config.ents {
-                    if let Some(content) = row.get_mut(index) {
-                        let wrapped_content: String = content
-                            .chars()
-                            .collect::<Vec<_>>()
-                            .chunks(wrap_lengths[i])
-                            .map(|chunk| chunk.iter().collect::<String>())
-                            .collect::<Vec<_>>()
-                            .join("\n");
-                        *content = wrapped_content;
+        for (row, cells) in config.ents.iter().enumerate() {
+            parents[row] = cells
+                .get(parent)
+                .and_then(|value| ids.get(value.as_str()).copied())
+                .filter(|&parent_row| parent_row != row);
+        }
+        // A row in a cycle of parents becomes a root, so that none is lost
+        for row in 0..count {
+            let mut up = parents[row];
+            for _ in 0..count {
+                match up {
+                    Some(up_row) if up_row == row => {
+                        parents[row] = None;
+                        break;
                     }
+                    Some(up_row) => up = parents[up_row],
+                    None => break,
                 }
             }
         }
     }
+    let mut roots = Vec::new();
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (row, parent) in parents.iter().enumerate() {
+        match parent {
+            Some(parent) => children.entry(*parent).or_default().push(row),
+            None => roots.push(row),
+        }
+    }
 
+    let mut out = format!("{{\n   {}: [\n", json_string(table_name));
+    write_json_rows(&mut out, config, &names, &roots, &children, 2);
+    out.push_str("   ]\n}");
+    println!("{}", out);
     Ok(())
 }
 
-/// Print json
-pub fn print_json(config: &mut Config) {
-    let table_name = if let Some(table_name) = &config.table_name {
-        table_name.clone()
-    } else {
-        "table".to_string()
-    };
+/// Write `rows` as the objects of a JSON array at indentation `level`
+fn write_json_rows(
+    out: &mut String,
+    config: &Config,
+    names: &[String],
+    rows: &[usize],
+    children: &HashMap<usize, Vec<usize>>,
+    level: usize,
+) {
+    let indent = "   ".repeat(level);
+    for (n, &row) in rows.iter().enumerate() {
+        out.push_str(&indent);
+        out.push_str(if n == 0 { "{" } else { "},{" });
+        out.push('\n');
+
+        let mut members = Vec::new();
+        for (index, name) in names.iter().enumerate() {
+            if matches!(config.table_column_defs.get(index), Some(def) if def.hidden) {
+                continue;
+            }
+            let value = config.ents[row].get(index).map_or("", |s| s.as_str());
+            let value = json_value(value, json_type(config, name), level + 1);
+            members.push(format!(
+                "{}   {}: {}",
+                indent,
+                json_string(&name.to_lowercase()),
+                value
+            ));
+        }
+        if let Some(row_children) = children.get(&row) {
+            let mut nested = format!("{}   \"children\": [\n", indent);
+            write_json_rows(
+                &mut nested,
+                config,
+                names,
+                row_children,
+                children,
+                level + 2,
+            );
+            nested.push_str(&format!("{}   ]", indent));
+            members.push(nested);
+        }
+        out.push_str(&members.join(",\n"));
+        out.push('\n');
+    }
+    if !rows.is_empty() {
+        out.push_str(&indent);
+        out.push_str("}\n");
+    }
+}
 
-    let mut json_output = Vec::new();
-    for row in &config.ents {
-        let mut columns = HashMap::new();
-        if let Some(table_columns) = &config.table_columns {
-            for (i, column) in table_columns.iter().enumerate() {
-                columns.insert(column.clone(), row[i].clone());
+/// JSON type of the column called `name`
+fn json_type(config: &Config, name: &str) -> JsonType {
+    config
+        .table_column_defs
+        .iter()
+        .find(|def| def.name == name)
+        .map_or(JsonType::String, |def| def.json)
+}
+
+/// `value` as JSON of type `json_type`. Numbers that do not parse are
+/// kept as strings rather than making the output invalid.
+fn json_value(value: &str, json_type: JsonType, level: usize) -> String {
+    let number = |value: &str| match value.parse::<serde_json::Number>() {
+        Ok(number) => number.to_string(),
+        Err(_) => json_string(value),
+    };
+    match json_type {
+        JsonType::String if value.is_empty() => "null".to_string(),
+        JsonType::String => json_string(value),
+        JsonType::Number if value.is_empty() => "null".to_string(),
+        JsonType::Number => number(value),
+        JsonType::Boolean => {
+            let is_false = matches!(value.chars().next(), None | Some('0' | 'N' | 'n'));
+            (!is_false).to_string()
+        }
+        JsonType::ArrayString | JsonType::ArrayNumber => {
+            let indent = "   ".repeat(level);
+            let items: Vec<String> = value
+                .lines()
+                .map(|item| {
+                    let item = if json_type == JsonType::ArrayNumber {
+                        number(item)
+                    } else {
+                        json_string(item)
+                    };
+                    format!("{}   {}", indent, item)
+                })
+                .collect();
+            if items.is_empty() {
+                format!("[\n{}]", indent)
+            } else {
+                format!("[\n{}\n{}]", items.join(",\n"), indent)
             }
         }
-        json_output.push(TableRow { columns });
     }
-    let json_obj = json!({ table_name: json_output });
-    let json_output = serde_json::to_string_pretty(&json_obj).unwrap();
-    println!("{}", json_output);
+}
+
+/// `s` as a JSON string
+fn json_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_default()
 }
 
 /// Print node tree
@@ -872,6 +1232,9 @@ pub fn validate_args(config: &mut Config) -> UResult<()> {
             || config.table_truncate.is_some()
             || config.table_right.is_some()
             || config.table_name.is_some()
+            || !config.table_column_defs.is_empty()
+            || config.table_header_color.is_some()
+            || config.table_maxout
         {
             return Err(USimpleError::new(
                 EXIT_FAILURE,
@@ -880,7 +1243,18 @@ pub fn validate_args(config: &mut Config) -> UResult<()> {
         }
     }
 
-    if config.table_name.is_none() && config.json {
+    if config.input_format != InputFormat::Text {
+        if let Some(separator) = &config.input_separator {
+            if separator.chars().count() != 1 || separator == "\"" {
+                return Err(USimpleError::new(
+                    EXIT_FAILURE,
+                    "Error: the separator of --input-format csv and tsv must be one character other than '\"'",
+                ));
+            }
+        }
+    }
+
+    if config.table_columns.is_none() && config.json {
         return Err(USimpleError::new(
             EXIT_FAILURE,
             "Error: option --table-columns required for --json",
@@ -9,10 +9,17 @@ use clap::Command;
 use uucore::{error::UResult, help_section, help_usage};
 
 mod file_common;
+mod file_decompress;
+pub mod file_detect;
 pub mod file_magic;
+#[cfg(feature = "native-magic")]
+mod file_native;
+pub mod file_sandbox;
 mod file_unsafe;
 mod file_utils;
 
+pub use file_detect::{Detector, FileType};
+
 const ABOUT: &str = help_section!("about", "file.md");
 const USAGE: &str = help_usage!("file.md");
 
@@ -5,8 +5,12 @@
 // For the full copyright and license information, please view the LICENSE file
 // that was distributed with this source code.
 
-use std::fs::File;
-use std::io::{stdout, BufRead, BufReader, Read, Write};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{stdin, stdout, BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
 
 use uucore::error::{UResult, USimpleError, UUsageError};
 use uucore::msg_log::{err, errx, warn, warnx};
@@ -14,12 +18,18 @@ use uucore::{format_usage, util_name};
 
 use clap::{crate_version, Arg, Command};
 
+use crate::file_detect::classify;
+use crate::file_magic::*;
+#[cfg(feature = "native-magic")]
+use crate::file_native::Magic;
+use crate::file_sandbox::enable_sandbox;
 use crate::file_utils::*;
-use crate::{file_magic::*, oe_app};
+use crate::oe_app;
 
 /// file cmd parse error code.
 pub static FILE_CMD_PARSE_ERROR: i32 = 1;
 
+#[derive(Clone)]
 struct Param {
     name: String,
     value: Option<usize>,
@@ -37,7 +47,7 @@ impl Param {
 }
 
 /// Config
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Config {
     ///
     pub files: Vec<String>,
@@ -95,12 +105,15 @@ pub struct Config {
     ///
     pub special_files: bool,
     ///
-    #[cfg(feature = "sandbox")]
     pub no_sandbox: bool,
     ///
     pub compile: bool,
     ///
     pub debug: bool,
+    ///
+    pub json: bool,
+    ///
+    pub jobs: usize,
 }
 
 pub mod options {
@@ -162,6 +175,10 @@ pub mod options {
     pub static COMPILE: &str = "compile";
     ///
     pub static DEBUG: &str = "debug";
+    ///
+    pub static JSON: &str = "json";
+    ///
+    pub static JOBS: &str = "jobs";
 }
 
 impl Config {
@@ -181,6 +198,20 @@ impl Config {
             }
         }
 
+        let jobs = match options.get_one::<String>(options::JOBS) {
+            Some(jobs) => match jobs.parse::<usize>() {
+                Ok(0) => thread::available_parallelism().map_or(1, |n| n.get()),
+                Ok(jobs) => jobs,
+                Err(_) => {
+                    return Err(UUsageError::new(
+                        FILE_CMD_PARSE_ERROR,
+                        format!("invalid number of jobs: '{}'", jobs),
+                    ))
+                }
+            },
+            None => 1,
+        };
+
         Ok(Self {
             files: options
                 .get_many::<String>("files")
@@ -232,10 +263,11 @@ impl Config {
                 .collect::<Vec<_>>(),
             raw: options.contains_id(options::RAW),
             special_files: options.contains_id(options::SPECIAL_FILES),
-            #[cfg(feature = "sandbox")]
             no_sandbox: options.contains_id(options::NO_SANDBOX),
             compile: options.contains_id(options::COMPILE),
             debug: options.contains_id(options::DEBUG),
+            json: options.contains_id(options::JSON),
+            jobs,
         })
     }
 }
@@ -438,6 +470,19 @@ pub fn base_app<'a>(about: &'a str, usage: &'a str) -> Command<'a> {
                 .long(options::DEBUG)
                 .help("print debugging messages"),
         )
+        .arg(
+            Arg::new(options::JSON)
+                .long(options::JSON)
+                .help("output one JSON object per file with its name, description, MIME type and encoding"),
+        )
+        .arg(
+            Arg::new(options::JOBS)
+                .short('j')
+                .long(options::JOBS)
+                .takes_value(true)
+                .value_name("N")
+                .help("classify the files with N threads, 0 for one per CPU; the output keeps the order of the files"),
+        )
         .arg(
             Arg::new("files")
                 .takes_value(true)
@@ -452,7 +497,7 @@ pub fn handle_input(config: &Config) -> UResult<()> {
 
     let mut flags = MAGIC_NONE;
     let mut action = MAGIC_NONE;
-    let mut magic: magic_t = std::ptr::null_mut();
+    let mut magic: Option<Magic> = None;
     let mut didsomefiles = false;
     let mut params = vec![
         Param::new("bytes", MAGIC_PARAM_BYTES_MAX),
@@ -573,19 +618,8 @@ pub fn handle_input(config: &Config) -> UResult<()> {
         }
     }
 
-    if !config.files_from.is_empty() {
-        if magic.is_null() {
-            magic = load(flags, config);
-            if magic.is_null() {
-                return Err(USimpleError::new(
-                    FILE_CMD_PARSE_ERROR,
-                    "Cannot load magic file",
-                ));
-            }
-        }
-        apply_param(magic, &params);
-        unwrap(magic, &config.files_from, config)?;
-        didsomefiles = true;
+    if !config.no_sandbox {
+        flags |= MAGIC_NO_COMPRESS_FORK;
     }
 
     'param: for expression in &config.parameter {
@@ -606,66 +640,69 @@ pub fn handle_input(config: &Config) -> UResult<()> {
         );
     }
 
-    #[cfg(feature = "sandbox")]
-    if !config.no_sandbox {
-        if !enable_sandbox_full() {
-            err(FILE_CMD_PARSE_ERROR, "SECCOMP initialisation failed");
-        }
-        flags |= MAGIC_NO_COMPRESS_FORK;
-    }
-
     match action {
         FILE_CHECK | FILE_COMPILE | FILE_LIST => {
-            magic = magic_open(flags | MAGIC_CHECK);
-            if magic.is_null() {
-                warn("Can't create magic");
-                return Err(USimpleError::new(
-                    FILE_CMD_PARSE_ERROR,
-                    "Can't create magic",
-                ));
-            }
+            let mut cookie = match Magic::open(flags | MAGIC_CHECK) {
+                Some(cookie) => cookie,
+                None => {
+                    warn("Can't create magic");
+                    return Err(USimpleError::new(
+                        FILE_CMD_PARSE_ERROR,
+                        "Can't create magic",
+                    ));
+                }
+            };
 
             if match action {
-                FILE_CHECK => magic_check(magic, config.magic_file.as_deref()),
-                FILE_COMPILE => magic_compile(magic, config.magic_file.as_deref()),
-                FILE_LIST => magic_list(magic, config.magic_file.as_deref()),
+                FILE_CHECK => cookie.check(config.magic_file.as_deref()),
+                FILE_COMPILE => cookie.compile(config.magic_file.as_deref()),
+                FILE_LIST => cookie.list(config.magic_file.as_deref()),
                 _ => panic!("Invalid action"),
             } == -1
             {
-                warnx(&magic_error(magic).unwrap_or_default());
+                warnx(&cookie.error().unwrap_or_default());
                 if !config.no_buffer {
                     stdout().flush()?;
                 }
-                if !magic.is_null() {
-                    magic_close(magic);
-                }
                 return Err(USimpleError::new(FILE_CMD_PARSE_ERROR, "action failed"));
             }
+            magic = Some(cookie);
             didsomefiles = true;
         }
         _ => {
-            if magic.is_null() {
+            if magic.is_none() {
                 magic = load(flags, config);
-                if magic.is_null() {
+            }
+            match magic.as_mut() {
+                Some(cookie) => apply_param(cookie, &params),
+                None => {
                     return Err(USimpleError::new(
                         FILE_CMD_PARSE_ERROR,
                         "Cannot load magic file",
-                    ));
+                    ))
                 }
             }
-            apply_param(magic, &params);
         }
     }
 
+    // The magic database is loaded: from here on, only untrusted input is read
+    if !config.no_sandbox && enable_sandbox().is_err() {
+        err(FILE_CMD_PARSE_ERROR, "SECCOMP initialisation failed");
+    }
+
+    if !config.files_from.is_empty() {
+        if let Some(cookie) = magic.as_mut() {
+            unwrap(cookie, &config.files_from, config, flags, &params)?;
+        }
+        didsomefiles = true;
+    }
+
     if config.files.is_empty() && !didsomefiles {
         println!("{}", oe_app().render_usage());
 
         if !config.no_buffer {
             stdout().flush()?;
         }
-        if !magic.is_null() {
-            magic_close(magic);
-        }
 
         return Ok(());
     }
@@ -675,50 +712,53 @@ pub fn handle_input(config: &Config) -> UResult<()> {
         width = width.max(file_mbswidth(f, config.raw));
     }
 
-    for f in &config.files {
-        process(magic, f, config, width);
+    if let Some(cookie) = magic.as_mut() {
+        process_many(cookie, &config.files, config, width, flags, &params)?;
     }
 
     if !config.no_buffer {
         stdout().flush()?;
     }
-    if !magic.is_null() {
-        magic_close(magic);
-    }
 
     Ok(())
 }
 
-fn load(flags: i32, config: &Config) -> magic_t {
-    let magic = magic_open(flags);
-
-    if magic.is_null() {
-        warn("Can't create magic");
-        return std::ptr::null_mut();
-    }
-    if magic_load(magic, config.magic_file.as_deref()) == -1 {
-        warn(&magic_error(magic).unwrap_or_default());
-        magic_close(magic);
-        return std::ptr::null_mut();
+fn load(flags: i32, config: &Config) -> Option<Magic> {
+    let mut magic = match Magic::open(flags) {
+        Some(magic) => magic,
+        None => {
+            warn("Can't create magic");
+            return None;
+        }
+    };
+    if magic.load(config.magic_file.as_deref()) == -1 {
+        warn(&magic.error().unwrap_or_default());
+        return None;
     }
-    if let Some(e) = magic_error(magic) {
+    if let Some(e) = magic.error() {
         warn(&e);
     }
 
-    magic
+    Some(magic)
 }
 
-fn apply_param(magic: magic_t, params: &Vec<Param>) {
+fn apply_param(magic: &mut Magic, params: &Vec<Param>) {
     for p in params {
         if let Some(value) = p.value {
-            if magic_setparam(magic, p.tag, value) == -1 {
+            if magic.setparam(p.tag, value) == -1 {
                 err(FILE_CMD_PARSE_ERROR, &format!("Can't set {}", p.name));
             }
         }
     }
 }
 
-fn unwrap(magic: magic_t, namefile: &str, config: &Config) -> UResult<()> {
+fn unwrap(
+    magic: &mut Magic,
+    namefile: &str,
+    config: &Config,
+    flags: i32,
+    params: &[Param],
+) -> UResult<()> {
     let mut reader = BufReader::new(if namefile == "-" {
         Box::new(std::io::stdin()) as Box<dyn Read>
     } else {
@@ -735,7 +775,7 @@ fn unwrap(magic: magic_t, namefile: &str, config: &Config) -> UResult<()> {
         line = line.trim().to_string();
         let width = file_mbswidth(&line, config.raw);
         if config.no_buffer {
-            if process(magic, &line, config, width) {
+            if process(magic, &line, config, width, flags) {
                 return Err(USimpleError::new(FILE_CMD_PARSE_ERROR, "process failed"));
             }
             continue;
@@ -744,21 +784,143 @@ fn unwrap(magic: magic_t, namefile: &str, config: &Config) -> UResult<()> {
         files.push(line);
     }
 
-    if !config.no_buffer {
-        for f in files {
-            if process(magic, &f, config, max_width) {
-                return Err(USimpleError::new(FILE_CMD_PARSE_ERROR, "process failed"));
+    if !config.no_buffer && process_many(magic, &files, config, max_width, flags, params)? {
+        return Err(USimpleError::new(FILE_CMD_PARSE_ERROR, "process failed"));
+    }
+
+    Ok(())
+}
+
+/// Describe all the files in order, with a pool of threads for `--jobs`;
+/// `true` when any of them failed
+fn process_many(
+    magic: &mut Magic,
+    names: &[String],
+    config: &Config,
+    width: usize,
+    flags: i32,
+    params: &[Param],
+) -> UResult<bool> {
+    if config.jobs > 1 && names.len() > 1 && reloadable(config) {
+        return process_parallel(names, config, width, flags, params);
+    }
+    let mut haderror = false;
+    for name in names {
+        haderror |= process(magic, name, config, width, flags);
+    }
+    Ok(haderror)
+}
+
+/// Whether every thread can load the magic database again: a pipe such as
+/// `-m /dev/stdin` can only be read once
+fn reloadable(config: &Config) -> bool {
+    match config.magic_file.as_deref() {
+        Some(paths) => paths.split(':').all(|path| {
+            fs::metadata(path)
+                .map(|meta| meta.is_file() || meta.is_dir())
+                .unwrap_or(true)
+        }),
+        None => true,
+    }
+}
+
+/// Describe the files with `config.jobs` threads, each with its own magic
+/// cookie, and print the lines in the order of `names`
+fn process_parallel(
+    names: &[String],
+    config: &Config,
+    width: usize,
+    flags: i32,
+    params: &[Param],
+) -> UResult<bool> {
+    let names = Arc::new(names.to_vec());
+    let config = Arc::new(config.clone());
+    let params = Arc::new(params.to_vec());
+    let next = Arc::new(AtomicUsize::new(0));
+    let load_failed = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+
+    let workers = (0..config.jobs.min(names.len()))
+        .map(|_| {
+            let names = Arc::clone(&names);
+            let config = Arc::clone(&config);
+            let params = Arc::clone(&params);
+            let next = Arc::clone(&next);
+            let load_failed = Arc::clone(&load_failed);
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let mut magic = match load(flags, &config) {
+                    Some(magic) => magic,
+                    None => {
+                        load_failed.store(true, Ordering::SeqCst);
+                        return;
+                    }
+                };
+                apply_param(&mut magic, &params);
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    if index >= names.len() {
+                        break;
+                    }
+                    let result = describe(&mut magic, &names[index], &config, width, flags);
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(sender);
+
+    // Lines finished early wait for the ones before them
+    let mut pending = BTreeMap::new();
+    let mut printed = 0;
+    let mut haderror = false;
+    for (index, result) in receiver {
+        pending.insert(index, result);
+        while let Some((line, failed)) = pending.remove(&printed) {
+            haderror |= failed || write!(stdout(), "{}", line).is_err();
+            if config.no_buffer {
+                haderror |= stdout().flush().is_err();
             }
+            printed += 1;
         }
     }
+    for worker in workers {
+        let _ = worker.join();
+    }
+    if load_failed.load(Ordering::SeqCst) {
+        return Err(USimpleError::new(
+            FILE_CMD_PARSE_ERROR,
+            "Cannot load magic file",
+        ));
+    }
+    Ok(haderror || printed < names.len())
+}
 
-    Ok(())
+fn process(magic: &mut Magic, name: &str, config: &Config, width: usize, flags: i32) -> bool {
+    let (line, mut haderror) = describe(magic, name, config, width, flags);
+    haderror |= write!(stdout(), "{}", line).is_err();
+    if config.no_buffer {
+        haderror |= stdout().flush().is_err();
+    }
+    haderror
 }
 
-fn process(magic: magic_t, name: &str, config: &Config, width: usize) -> bool {
+/// Line printed for a file, and whether libmagic failed
+fn describe(
+    magic: &mut Magic,
+    name: &str,
+    config: &Config,
+    width: usize,
+    flags: i32,
+) -> (String, bool) {
+    if config.json {
+        return describe_json(magic, name, flags);
+    }
     let c = if config.print0 > 1 { '\0' } else { '\n' };
     let std_in = name == "-";
-    let mut haderror = false;
+    let mut line = String::new();
     let bflag = if config.brief == 2 {
         !config.files.is_empty()
     } else {
@@ -768,15 +930,15 @@ fn process(magic: magic_t, name: &str, config: &Config, width: usize) -> bool {
     if width > 0 && !bflag {
         let pname = if std_in { "/dev/stdin" } else { name };
         if !config.raw {
-            fname_print(pname);
+            line.push_str(&fname_printable(pname));
         } else {
-            print!("{}", pname);
+            line.push_str(pname);
         }
         if config.print0 != 0 {
-            print!("\0");
+            line.push('\0');
         }
         if config.print0 < 2 {
-            print!(
+            line.push_str(&format!(
                 "{}{:width$} ",
                 config.separator,
                 "",
@@ -785,20 +947,57 @@ fn process(magic: magic_t, name: &str, config: &Config, width: usize) -> bool {
                 } else {
                     width - file_mbswidth(name, config.raw)
                 }
-            );
+            ));
         }
     }
 
-    let t = magic_file(magic, if std_in { None } else { Some(name) });
+    match magic.file(if std_in { None } else { Some(name) }) {
+        Some(t) => {
+            line.push_str(&format!("{}{}", t, c));
+            (line, false)
+        }
+        None => {
+            line.push_str(&format!(
+                "ERROR: {}{}",
+                magic.error().unwrap_or_default(),
+                c
+            ));
+            (line, true)
+        }
+    }
+}
 
-    if t.is_none() {
-        print!("ERROR: {}{}", magic_error(magic).unwrap_or_default(), c);
-        haderror = true;
+/// JSON object of `--json` for a file. The output flags like `-i` do not
+/// apply, the object has all the fields.
+fn describe_json(magic: &mut Magic, name: &str, flags: i32) -> (String, bool) {
+    // Files that cannot be opened are errors rather than descriptions
+    let json_flags = flags & !MAGIC_NODESC | MAGIC_ERROR;
+    let result = if name == "-" {
+        // stdin can only be read once
+        let mut buffer = Vec::new();
+        match stdin().read_to_end(&mut buffer) {
+            Ok(_) => classify(magic, json_flags, |magic| magic.buffer(&buffer)),
+            Err(e) => Err(format!("cannot read `(stdin)' ({})", e)),
+        }
     } else {
-        haderror |= write!(stdout(), "{}{}", t.unwrap(), c).is_err();
-    }
-    if config.no_buffer {
-        haderror |= std::io::stdout().flush().is_err();
+        classify(magic, json_flags, |magic| magic.file(Some(name)))
+    };
+    magic.setflags(flags);
+    let name = json_string(if name == "-" { "/dev/stdin" } else { name });
+    match result {
+        Ok(file_type) => (
+            format!(
+                "{{\"name\":{},\"description\":{},\"mime_type\":{},\"encoding\":{}}}\n",
+                name,
+                json_string(&file_type.description),
+                json_string(&file_type.mime_type),
+                json_string(&file_type.encoding)
+            ),
+            false,
+        ),
+        Err(e) => (
+            format!("{{\"name\":{},\"error\":{}}}\n", name, json_string(&e)),
+            true,
+        ),
     }
-    haderror
 }
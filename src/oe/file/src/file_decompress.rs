@@ -0,0 +1,242 @@
+//! This file is part of the easybox package.
+//
+// (c) Zhihua Zhao <YuukaC@outlook.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+//! In-process decompression for `MAGIC_COMPRESS`, so that `-z` needs no
+//! external decompressor. The output follows libmagic: the type of the
+//! decompressed data, then the type of the file in parentheses.
+
+use flate2::read::MultiGzDecoder;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use crate::file_magic::*;
+#[cfg(feature = "native-magic")]
+use crate::file_native::Magic;
+
+/// Flags handled here rather than by the engine
+pub const DECOMPRESS_FLAGS: i32 = MAGIC_COMPRESS | MAGIC_COMPRESS_TRANSP;
+
+/// Compression formats looked into
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Method {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+    Lzma,
+}
+
+impl Method {
+    /// Format of the data starting with `buf`
+    fn detect(buf: &[u8]) -> Option<Self> {
+        if buf.starts_with(b"\x1f\x8b") {
+            Some(Method::Gzip)
+        } else if buf.starts_with(b"BZh") {
+            Some(Method::Bzip2)
+        } else if buf.starts_with(b"\xfd7zXZ\0") {
+            Some(Method::Xz)
+        } else if buf.starts_with(b"\x28\xb5\x2f\xfd") {
+            Some(Method::Zstd)
+        } else if buf.starts_with(b"\x5d\0\0") {
+            Some(Method::Lzma)
+        } else {
+            None
+        }
+    }
+
+    /// Name of the decompressor in error messages
+    fn name(self) -> &'static str {
+        match self {
+            Method::Gzip => "gzip",
+            Method::Bzip2 => "bzip2",
+            Method::Xz => "xz",
+            Method::Zstd => "zstd",
+            Method::Lzma => "lzma",
+        }
+    }
+
+    /// Decompress at most `limit` bytes of `input`. Data decoded before an
+    /// error is kept, as a truncated file is still worth classifying.
+    fn decompress<R: BufRead>(self, mut input: R, limit: usize) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        let result = match self {
+            Method::Gzip => MultiGzDecoder::new(input)
+                .take(limit as u64)
+                .read_to_end(&mut out)
+                .map(drop)
+                .map_err(|e| e.to_string()),
+            // Only the first of concatenated streams is read, which is
+            // enough to classify the data
+            Method::Bzip2 => bzip2_rs::DecoderReader::new(input)
+                .take(limit as u64)
+                .read_to_end(&mut out)
+                .map(drop)
+                .map_err(|e| e.to_string()),
+            Method::Xz => {
+                let mut output = Limited { out, limit };
+                let result = lzma_rs::xz_decompress(&mut input, &mut output);
+                out = output.out;
+                result.map_err(|e| e.to_string())
+            }
+            Method::Lzma => {
+                let mut output = Limited { out, limit };
+                let result = lzma_rs::lzma_decompress(&mut input, &mut output);
+                out = output.out;
+                result.map_err(|e| e.to_string())
+            }
+            Method::Zstd => match ruzstd::StreamingDecoder::new(input) {
+                Ok(decoder) => decoder
+                    .take(limit as u64)
+                    .read_to_end(&mut out)
+                    .map(drop)
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            },
+        };
+        match result {
+            Err(e) if out.is_empty() => Err(e),
+            _ => Ok(out),
+        }
+    }
+}
+
+/// Output that stops the decoder once `limit` bytes are written
+struct Limited {
+    out: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for Limited {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let room = self.limit - self.out.len();
+        if room == 0 {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "enough data"));
+        }
+        let n = buf.len().min(room);
+        self.out.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Whether `flags` ask to look into compressed data
+pub fn wanted(flags: i32) -> bool {
+    flags & MAGIC_COMPRESS != 0 && flags & MAGIC_NO_CHECK_COMPRESS == 0
+}
+
+/// Describe a file, or stdin when `name` is `None`, looking into it when
+/// it is compressed
+pub fn file(magic: &mut Magic, name: Option<&str>) -> Option<String> {
+    let flags = magic.flags();
+    magic.setflags(flags & !DECOMPRESS_FLAGS);
+    let limit = bytes_max(magic);
+    let result = match name {
+        Some(name) => describe_file(magic, name, flags, limit),
+        None => {
+            let mut buf = Vec::new();
+            match io::stdin().take(limit as u64).read_to_end(&mut buf) {
+                Ok(_) => describe_buffer(magic, &buf, flags, limit),
+                Err(e) => Some(format!("cannot read `(stdin)' ({})", e)),
+            }
+        }
+    };
+    magic.setflags(flags);
+    result
+}
+
+/// Describe a buffer, looking into it when it is compressed
+pub fn buffer(magic: &mut Magic, buf: &[u8]) -> Option<String> {
+    let flags = magic.flags();
+    magic.setflags(flags & !DECOMPRESS_FLAGS);
+    let limit = bytes_max(magic);
+    let result = describe_buffer(magic, buf, flags, limit);
+    magic.setflags(flags);
+    result
+}
+
+/// Bytes looked at, set with `-P bytes=N` as libmagic does
+fn bytes_max(magic: &Magic) -> usize {
+    magic
+        .getparam(MAGIC_PARAM_BYTES_MAX)
+        .unwrap_or(DEFAULT_BYTES_LIMIT)
+}
+
+fn describe_file(magic: &mut Magic, name: &str, flags: i32, limit: usize) -> Option<String> {
+    let metadata = if flags & MAGIC_SYMLINK != 0 {
+        fs::metadata(name)
+    } else {
+        fs::symlink_metadata(name)
+    };
+    if !matches!(metadata, Ok(ref m) if m.is_file()) {
+        return magic.file(Some(name));
+    }
+    // The compressed data is described from its start, as libmagic does,
+    // but the whole file is decompressed until the limit
+    let mut head = Vec::new();
+    let read = File::open(name).and_then(|file| file.take(limit as u64).read_to_end(&mut head));
+    match read.map(|_| Method::detect(&head)) {
+        Ok(Some(method)) => {
+            let inner = File::open(name)
+                .map_err(|e| e.to_string())
+                .and_then(|file| method.decompress(BufReader::new(file), limit));
+            combine(magic, method, inner, flags, |magic| magic.buffer(&head))
+        }
+        _ => magic.file(Some(name)),
+    }
+}
+
+fn describe_buffer(magic: &mut Magic, buf: &[u8], flags: i32, limit: usize) -> Option<String> {
+    match Method::detect(buf) {
+        Some(method) => {
+            let inner = method.decompress(buf, limit);
+            combine(magic, method, inner, flags, |magic| magic.buffer(buf))
+        }
+        None => magic.buffer(buf),
+    }
+}
+
+/// Type of the decompressed data, then the type of the compressed data as
+/// `outer` finds it, unless `-Z` or a single MIME field is asked for
+fn combine<F>(
+    magic: &mut Magic,
+    method: Method,
+    inner: Result<Vec<u8>, String>,
+    flags: i32,
+    outer: F,
+) -> Option<String>
+where
+    F: FnOnce(&mut Magic) -> Option<String>,
+{
+    let mime = flags & MAGIC_MIME;
+    let mut out = match inner {
+        Ok(data) => magic.buffer(&data)?,
+        Err(e) if mime != 0 => {
+            let e = e
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .collect::<String>();
+            format!("application/x-decompression-error-{}-{}", method.name(), e)
+        }
+        Err(e) => format!("ERROR:[{}: {}]", method.name(), e),
+    };
+    if flags & MAGIC_COMPRESS_TRANSP != 0 || (mime != 0 && mime != MAGIC_MIME) {
+        return Some(out);
+    }
+    out.push_str(if mime != 0 {
+        " compressed-encoding="
+    } else {
+        " ("
+    });
+    out.push_str(&outer(magic)?);
+    if mime == 0 {
+        out.push(')');
+    }
+    Some(out)
+}
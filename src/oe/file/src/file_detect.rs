@@ -0,0 +1,129 @@
+//! This file is part of the easybox package.
+//
+// (c) Zhihua Zhao <YuukaC@outlook.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+//! Detection of file types for other programs, without running `file`.
+
+use uucore::error::{UResult, USimpleError};
+
+use crate::file_magic::*;
+#[cfg(feature = "native-magic")]
+use crate::file_native::Magic;
+
+/// MIME types of the compressed formats
+const COMPRESSED_TYPES: [&str; 11] = [
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/zstd",
+    "application/x-lzma",
+    "application/x-lzip",
+    "application/x-lz4",
+    "application/x-compress",
+    "application/x-lrzip",
+    "application/x-brotli",
+];
+
+/// Type of a file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileType {
+    /// description printed by `file`, like `ASCII text`
+    pub description: String,
+    /// MIME type, like `text/plain`
+    pub mime_type: String,
+    /// MIME encoding, like `us-ascii` or `binary`
+    pub encoding: String,
+    /// usual extensions of the type, empty when unknown or not asked for
+    pub extensions: Vec<String>,
+    /// whether the file is compressed data
+    pub compressed: bool,
+}
+
+/// Loaded magic database, reused for any number of files
+pub struct Detector {
+    magic: Magic,
+    flags: i32,
+}
+
+impl Detector {
+    /// Load the colon separated list of magic files, or the default database
+    /// when `magic_file` is `None`. `flags` are `MAGIC_*` flags like
+    /// `MAGIC_SYMLINK` or `MAGIC_COMPRESS`; the extensions of the types are
+    /// only looked up with `MAGIC_EXTENSION`.
+    pub fn new(magic_file: Option<&str>, flags: i32) -> UResult<Self> {
+        let mut magic =
+            Magic::open(flags).ok_or_else(|| USimpleError::new(1, "Can't create magic"))?;
+        if magic.load(magic_file) == -1 {
+            return Err(USimpleError::new(1, magic.error().unwrap_or_default()));
+        }
+        Ok(Self { magic, flags })
+    }
+
+    /// Type of the file at `path`
+    pub fn detect_path(&mut self, path: &str) -> UResult<FileType> {
+        classify(&mut self.magic, self.flags, |magic| magic.file(Some(path)))
+            .map_err(|e| USimpleError::new(1, e))
+    }
+
+    /// Type of data in memory
+    pub fn detect_buffer(&mut self, buffer: &[u8]) -> UResult<FileType> {
+        classify(&mut self.magic, self.flags, |magic| magic.buffer(buffer))
+            .map_err(|e| USimpleError::new(1, e))
+    }
+}
+
+/// Run `describe` for the description, then for the MIME type and
+/// encoding, and for the extensions only when `flags` has
+/// `MAGIC_EXTENSION`; `flags` are restored afterwards. Fails with the
+/// libmagic error.
+pub(crate) fn classify<F>(
+    magic: &mut Magic,
+    flags: i32,
+    mut describe: F,
+) -> Result<FileType, String>
+where
+    F: FnMut(&mut Magic) -> Option<String>,
+{
+    let base = flags & !MAGIC_NODESC;
+    let mut passes = vec![base, base | MAGIC_MIME];
+    if flags & MAGIC_EXTENSION != 0 {
+        passes.push(base | MAGIC_EXTENSION);
+    }
+    let mut outputs = Vec::with_capacity(passes.len());
+    for pass in passes {
+        magic.setflags(pass);
+        match describe(magic) {
+            Some(output) => outputs.push(output),
+            None => {
+                magic.setflags(flags);
+                return Err(magic.error().unwrap_or_default());
+            }
+        }
+    }
+    magic.setflags(flags);
+
+    // `type; charset=encoding`, possibly followed by the type of the
+    // compressed data
+    let (mime_type, encoding) = match outputs[1].split_once("; charset=") {
+        Some((mime_type, rest)) => (
+            mime_type.to_string(),
+            rest.split_whitespace().next().unwrap_or_default().to_string(),
+        ),
+        None => (outputs[1].clone(), String::new()),
+    };
+    let extensions = match outputs.get(2).map(String::as_str) {
+        None | Some("???") => Vec::new(),
+        Some(list) => list.split('/').map(str::to_string).collect(),
+    };
+    Ok(FileType {
+        description: outputs[0].clone(),
+        compressed: COMPRESSED_TYPES.contains(&mime_type.as_str()),
+        mime_type,
+        encoding,
+        extensions,
+    })
+}
@@ -9,9 +9,15 @@
 //!
 //! https://man7.org/linux/man-pages/man3/libmagic.3.html
 
-use libc::{c_char, c_int, c_void, size_t};
+#[cfg(not(feature = "native-magic"))]
+use libc::{c_char, c_void};
+use libc::{c_int, size_t};
+#[cfg(not(feature = "native-magic"))]
 use std::{ffi::CStr, ptr::null};
 
+#[cfg(not(feature = "native-magic"))]
+use crate::file_decompress::{self, DECOMPRESS_FLAGS};
+
 /// No special handling.
 pub const MAGIC_NONE: c_int = 0x0000000;
 /// Print debugging messages to stderr.
@@ -166,6 +172,7 @@ pub struct magic_set {
 #[allow(non_camel_case_types)]
 pub type magic_t = *mut magic_set;
 
+#[cfg(not(feature = "native-magic"))]
 mod libmagic {
     use super::*;
 
@@ -179,6 +186,16 @@ mod libmagic {
         /// Return a textual description of the contents of the filename argument, or NULL if an error occurred. If the filename is NULL, then stdin is used.
         pub fn magic_file(cookie: magic_t, filename: *const c_char) -> *const c_char;
 
+        /// Return a textual description of the contents of the buffer argument with length bytes size.
+        pub fn magic_buffer(
+            cookie: magic_t,
+            buffer: *const c_void,
+            length: size_t,
+        ) -> *const c_char;
+
+        /// Set the flags of the cookie. Returns -1 when MAGIC_PRESERVE_ATIME is set on systems that don't support utime(3).
+        pub fn magic_setflags(cookie: magic_t, flags: c_int) -> c_int;
+
         /// Return a textual explanation of the last error, or NULL if there was no error.
         pub fn magic_error(cookie: magic_t) -> *const c_char;
 
@@ -195,19 +212,25 @@ mod libmagic {
 
         ///
         pub fn magic_setparam(cookie: magic_t, param: c_int, value: *const c_void) -> c_int;
+
+        ///
+        pub fn magic_getparam(cookie: magic_t, param: c_int, value: *mut c_void) -> c_int;
     }
 }
 
+#[cfg(not(feature = "native-magic"))]
 ///
 pub fn magic_open(flags: i32) -> magic_t {
     unsafe { libmagic::magic_open(flags) }
 }
 
+#[cfg(not(feature = "native-magic"))]
 ///
 pub fn magic_close(cookie: magic_t) {
     unsafe { libmagic::magic_close(cookie) }
 }
 
+#[cfg(not(feature = "native-magic"))]
 ///
 pub fn magic_file(cookie: magic_t, filename: Option<&str>) -> Option<String> {
     unsafe {
@@ -220,11 +243,29 @@ pub fn magic_file(cookie: magic_t, filename: Option<&str>) -> Option<String> {
     }
 }
 
+#[cfg(not(feature = "native-magic"))]
+fn magic_buffer(cookie: magic_t, buffer: &[u8]) -> Option<String> {
+    unsafe {
+        pointer_to_string(libmagic::magic_buffer(
+            cookie,
+            buffer.as_ptr() as *const c_void,
+            buffer.len(),
+        ))
+    }
+}
+
+#[cfg(not(feature = "native-magic"))]
+fn magic_setflags(cookie: magic_t, flags: i32) -> i32 {
+    unsafe { libmagic::magic_setflags(cookie, flags) }
+}
+
+#[cfg(not(feature = "native-magic"))]
 ///
 pub fn magic_error(cookie: magic_t) -> Option<String> {
     unsafe { pointer_to_string(libmagic::magic_error(cookie)) }
 }
 
+#[cfg(not(feature = "native-magic"))]
 ///
 pub fn magic_load(cookie: magic_t, filename: Option<&str>) -> i32 {
     unsafe {
@@ -237,6 +278,7 @@ pub fn magic_load(cookie: magic_t, filename: Option<&str>) -> i32 {
     }
 }
 
+#[cfg(not(feature = "native-magic"))]
 ///
 pub fn magic_compile(cookie: magic_t, filename: Option<&str>) -> i32 {
     unsafe {
@@ -249,6 +291,7 @@ pub fn magic_compile(cookie: magic_t, filename: Option<&str>) -> i32 {
     }
 }
 
+#[cfg(not(feature = "native-magic"))]
 ///
 pub fn magic_check(cookie: magic_t, filename: Option<&str>) -> i32 {
     unsafe {
@@ -261,6 +304,7 @@ pub fn magic_check(cookie: magic_t, filename: Option<&str>) -> i32 {
     }
 }
 
+#[cfg(not(feature = "native-magic"))]
 ///
 pub fn magic_list(cookie: magic_t, filename: Option<&str>) -> i32 {
     unsafe {
@@ -273,11 +317,25 @@ pub fn magic_list(cookie: magic_t, filename: Option<&str>) -> i32 {
     }
 }
 
+#[cfg(not(feature = "native-magic"))]
 ///
 pub fn magic_setparam(cookie: magic_t, param: i32, value: usize) -> i32 {
     unsafe { libmagic::magic_setparam(cookie, param, &value as *const usize as *const c_void) }
 }
 
+#[cfg(not(feature = "native-magic"))]
+fn magic_getparam(cookie: magic_t, param: i32) -> Option<usize> {
+    let mut value: usize = 0;
+    let ret =
+        unsafe { libmagic::magic_getparam(cookie, param, &mut value as *mut usize as *mut c_void) };
+    if ret == -1 {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+#[cfg(not(feature = "native-magic"))]
 unsafe fn pointer_to_string(p: *const c_char) -> Option<String> {
     if p.is_null() {
         None
@@ -286,3 +344,93 @@ unsafe fn pointer_to_string(p: *const c_char) -> Option<String> {
         Some(c_str.to_str().unwrap().to_string())
     }
 }
+
+/// Cookie of the system libmagic, closed when dropped
+#[cfg(not(feature = "native-magic"))]
+pub struct Magic {
+    cookie: magic_t,
+    flags: i32,
+}
+
+#[cfg(not(feature = "native-magic"))]
+impl Magic {
+    /// Create a cookie, `None` when libmagic cannot allocate it
+    pub fn open(flags: i32) -> Option<Self> {
+        let cookie = magic_open(flags & !DECOMPRESS_FLAGS);
+        if cookie.is_null() {
+            None
+        } else {
+            Some(Self { cookie, flags })
+        }
+    }
+
+    /// Load the colon separated list of magic files, or the default database
+    pub fn load(&mut self, filename: Option<&str>) -> i32 {
+        magic_load(self.cookie, filename)
+    }
+
+    /// Describe a file, or stdin when `filename` is `None`
+    pub fn file(&mut self, filename: Option<&str>) -> Option<String> {
+        if file_decompress::wanted(self.flags) {
+            return file_decompress::file(self, filename);
+        }
+        magic_file(self.cookie, filename)
+    }
+
+    /// Describe the contents of a buffer
+    pub fn buffer(&mut self, buffer: &[u8]) -> Option<String> {
+        if file_decompress::wanted(self.flags) {
+            return file_decompress::buffer(self, buffer);
+        }
+        magic_buffer(self.cookie, buffer)
+    }
+
+    /// Replace the `MAGIC_*` flags given to `open`. Decompression is done
+    /// in-process, libmagic never sees `MAGIC_COMPRESS`.
+    pub fn setflags(&mut self, flags: i32) -> i32 {
+        self.flags = flags;
+        magic_setflags(self.cookie, flags & !DECOMPRESS_FLAGS)
+    }
+
+    /// Current `MAGIC_*` flags
+    pub fn flags(&self) -> i32 {
+        self.flags
+    }
+
+    /// Last error
+    pub fn error(&self) -> Option<String> {
+        magic_error(self.cookie)
+    }
+
+    /// Compile the magic files to `.mgc`
+    pub fn compile(&mut self, filename: Option<&str>) -> i32 {
+        magic_compile(self.cookie, filename)
+    }
+
+    /// Check the magic files
+    pub fn check(&mut self, filename: Option<&str>) -> i32 {
+        magic_check(self.cookie, filename)
+    }
+
+    /// Print the magic entries with their strength
+    pub fn list(&mut self, filename: Option<&str>) -> i32 {
+        magic_list(self.cookie, filename)
+    }
+
+    /// Set one of the `MAGIC_PARAM_*` limits
+    pub fn setparam(&mut self, param: i32, value: usize) -> i32 {
+        magic_setparam(self.cookie, param, value)
+    }
+
+    /// Value of one of the `MAGIC_PARAM_*` limits, `None` when it is unknown
+    pub fn getparam(&self, param: i32) -> Option<usize> {
+        magic_getparam(self.cookie, param)
+    }
+}
+
+#[cfg(not(feature = "native-magic"))]
+impl Drop for Magic {
+    fn drop(&mut self) {
+        magic_close(self.cookie);
+    }
+}
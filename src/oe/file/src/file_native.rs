@@ -0,0 +1,550 @@
+//! This file is part of the easybox package.
+//
+// (c) Zhihua Zhao <YuukaC@outlook.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+//! Magic engine written in Rust, used in place of libmagic with the
+//! `native-magic` feature. It reads the magic(5) source format.
+
+mod apprentice;
+mod encoding;
+mod json;
+mod softmagic;
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use crate::file_decompress;
+use crate::file_magic::*;
+use apprentice::Database;
+use softmagic::{Found, Limits, Matcher};
+
+/// Magic files read when neither `-m` nor `MAGIC` is given
+const DEFAULT_MAGIC: &str = "/etc/magic:/usr/share/misc/magic";
+
+/// Database used when no magic file could be read from the default path
+const BUILTIN_MAGIC: &str = include_str!("file_native/builtin.magic");
+
+/// Header of the compiled `.mgc` files, which cannot be read
+const COMPILED_MAGIC: [u8; 4] = [0x1c, 0x04, 0x1e, 0xf1];
+
+/// Description of a file, before it is printed as the flags ask
+#[derive(Default)]
+struct Description {
+    desc: String,
+    mime: Option<String>,
+    encoding: Option<&'static str>,
+    ext: Option<String>,
+    apple: Option<String>,
+}
+
+impl Description {
+    fn new(desc: &str, mime: &str, encoding: Option<&'static str>) -> Self {
+        Self {
+            desc: desc.to_string(),
+            mime: Some(mime.to_string()),
+            encoding,
+            ..Default::default()
+        }
+    }
+}
+
+/// Loaded database and settings, the counterpart of a libmagic cookie
+pub struct Magic {
+    flags: i32,
+    db: Database,
+    limits: Limits,
+    bytes_max: usize,
+    encoding_max: usize,
+    error: Option<String>,
+}
+
+impl Magic {
+    /// Create an empty database
+    pub fn open(flags: i32) -> Option<Self> {
+        Some(Self {
+            flags,
+            db: Database::default(),
+            limits: Limits {
+                indir: DEFAULT_INDIR_LIMIT,
+                name: DEFAULT_NAME_LIMIT,
+                regex: DEFAULT_REGEX_LIMIT,
+            },
+            bytes_max: DEFAULT_BYTES_LIMIT,
+            encoding_max: DEFAULT_ENCODING_LIMIT,
+            error: None,
+        })
+    }
+
+    /// Load the colon separated list of magic files, or the default database
+    pub fn load(&mut self, filename: Option<&str>) -> i32 {
+        let mut warnings = Vec::new();
+        let db = read_database(filename, self.has(MAGIC_CHECK), &mut warnings);
+        for warning in &warnings {
+            eprintln!("{}", warning);
+        }
+        match db {
+            Some(db) => {
+                self.db = db;
+                0
+            }
+            None => {
+                self.error = Some("could not find any valid magic files!".to_string());
+                -1
+            }
+        }
+    }
+
+    /// Describe a file, or stdin when `filename` is `None`
+    pub fn file(&mut self, filename: Option<&str>) -> Option<String> {
+        if file_decompress::wanted(self.flags) {
+            return file_decompress::file(self, filename);
+        }
+        self.error = None;
+        let description = match filename {
+            None => {
+                let mut buf = Vec::new();
+                match io::stdin()
+                    .take(self.bytes_max as u64)
+                    .read_to_end(&mut buf)
+                {
+                    Ok(_) => self.describe_buffer(&buf),
+                    Err(e) => {
+                        self.error = Some(format!("cannot read `(stdin)' ({})", strerror(&e)));
+                        return None;
+                    }
+                }
+            }
+            Some(name) => self.describe_path(name)?,
+        };
+        Some(self.render(description))
+    }
+
+    /// Describe the contents of a buffer
+    pub fn buffer(&mut self, buffer: &[u8]) -> Option<String> {
+        if file_decompress::wanted(self.flags) {
+            return file_decompress::buffer(self, buffer);
+        }
+        self.error = None;
+        Some(self.render(self.describe_buffer(buffer)))
+    }
+
+    /// Replace the `MAGIC_*` flags given to `open`
+    pub fn setflags(&mut self, flags: i32) -> i32 {
+        self.flags = flags;
+        0
+    }
+
+    /// Current `MAGIC_*` flags
+    pub fn flags(&self) -> i32 {
+        self.flags
+    }
+
+    /// Last error
+    pub fn error(&self) -> Option<String> {
+        self.error.clone()
+    }
+
+    /// Compiled magic files are a libmagic format, which this engine does
+    /// not write
+    pub fn compile(&mut self, _filename: Option<&str>) -> i32 {
+        self.error = Some("compiling magic files is not supported by the native engine".into());
+        -1
+    }
+
+    /// Check the magic files and print their parsed form
+    pub fn check(&mut self, filename: Option<&str>) -> i32 {
+        let mut warnings = Vec::new();
+        let db = read_database(filename, self.has(MAGIC_CHECK), &mut warnings);
+        for warning in &warnings {
+            eprintln!("{}", warning);
+        }
+        match db {
+            Some(db) => {
+                eprintln!("cont\toffset\ttype\topcode\tmask\tvalue\tdesc");
+                eprint!("{}", db.dump());
+                self.db = db;
+                0
+            }
+            None => {
+                self.error = Some("could not find any valid magic files!".to_string());
+                -1
+            }
+        }
+    }
+
+    /// Print the magic entries with their strength
+    pub fn list(&mut self, filename: Option<&str>) -> i32 {
+        if self.load(filename) == -1 {
+            return -1;
+        }
+        print!("{}", self.db.list());
+        0
+    }
+
+    /// Set one of the `MAGIC_PARAM_*` limits
+    pub fn setparam(&mut self, param: i32, value: usize) -> i32 {
+        match param {
+            MAGIC_PARAM_INDIR_MAX => self.limits.indir = value,
+            MAGIC_PARAM_NAME_MAX => self.limits.name = value,
+            MAGIC_PARAM_REGEX_MAX => self.limits.regex = value,
+            MAGIC_PARAM_BYTES_MAX => self.bytes_max = value,
+            MAGIC_PARAM_ENCODING_MAX => self.encoding_max = value,
+            // The ELF limits and the warning count have no use here
+            MAGIC_PARAM_ELF_PHNUM_MAX
+            | MAGIC_PARAM_ELF_SHNUM_MAX
+            | MAGIC_PARAM_ELF_NOTES_MAX
+            | MAGIC_PARAM_ELF_SHSIZE_MAX
+            | MAGIC_PARAM_MAGWARN_MAX => {}
+            _ => return -1,
+        }
+        0
+    }
+
+    /// Value of one of the `MAGIC_PARAM_*` limits, `None` when it is unknown
+    /// or not kept
+    pub fn getparam(&self, param: i32) -> Option<usize> {
+        match param {
+            MAGIC_PARAM_INDIR_MAX => Some(self.limits.indir),
+            MAGIC_PARAM_NAME_MAX => Some(self.limits.name),
+            MAGIC_PARAM_REGEX_MAX => Some(self.limits.regex),
+            MAGIC_PARAM_BYTES_MAX => Some(self.bytes_max),
+            MAGIC_PARAM_ENCODING_MAX => Some(self.encoding_max),
+            _ => None,
+        }
+    }
+
+    fn has(&self, flag: i32) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Type of the file from its metadata, then from its contents
+    fn describe_path(&mut self, name: &str) -> Option<Description> {
+        let metadata = if self.has(MAGIC_SYMLINK) {
+            fs::metadata(name)
+        } else {
+            fs::symlink_metadata(name)
+        };
+        let metadata = match metadata {
+            Ok(metadata) => metadata,
+            Err(e) if self.has(MAGIC_ERROR) => {
+                self.error = Some(format!("cannot stat `{}' ({})", name, strerror(&e)));
+                return None;
+            }
+            Err(e) => {
+                let desc = format!("cannot open `{}' ({})", name, strerror(&e));
+                return Some(Description {
+                    desc,
+                    ..Default::default()
+                });
+            }
+        };
+
+        let file_type = metadata.file_type();
+        let devices = self.has(MAGIC_DEVICES);
+        if file_type.is_dir() {
+            return Some(Description::new(
+                "directory",
+                "inode/directory",
+                Some("binary"),
+            ));
+        }
+        if file_type.is_symlink() {
+            let target = fs::read_link(name)
+                .map(|t| t.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            return Some(if fs::metadata(name).is_err() {
+                Description::new(
+                    &format!("broken symbolic link to {}", target),
+                    "inode/symlink",
+                    None,
+                )
+            } else {
+                Description::new(
+                    &format!("symbolic link to {}", target),
+                    "inode/symlink",
+                    Some("binary"),
+                )
+            });
+        }
+        if file_type.is_fifo() {
+            return Some(Description::new(
+                "fifo (named pipe)",
+                "inode/fifo",
+                Some("binary"),
+            ));
+        }
+        if file_type.is_socket() {
+            return Some(Description::new("socket", "inode/socket", Some("binary")));
+        }
+        if !devices && (file_type.is_char_device() || file_type.is_block_device()) {
+            let (kind, mime) = if file_type.is_char_device() {
+                ("character special", "inode/chardevice")
+            } else {
+                ("block special", "inode/blockdevice")
+            };
+            let rdev = metadata.rdev();
+            let major = ((rdev >> 8) & 0xfff) | ((rdev >> 32) & !0xfff);
+            let minor = (rdev & 0xff) | ((rdev >> 12) & !0xff);
+            return Some(Description::new(
+                &format!("{} ({}/{})", kind, major, minor),
+                mime,
+                Some("binary"),
+            ));
+        }
+        if file_type.is_file() && metadata.len() == 0 {
+            return Some(Description::new("empty", "inode/x-empty", Some("binary")));
+        }
+
+        let mut buf = Vec::new();
+        let read =
+            File::open(name).and_then(|f| f.take(self.bytes_max as u64).read_to_end(&mut buf));
+        match read {
+            Ok(_) if buf.is_empty() => {
+                Some(Description::new("empty", "inode/x-empty", Some("binary")))
+            }
+            Ok(_) => Some(self.describe_buffer(&buf)),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                // The file can be seen but not read
+                let mode = metadata.permissions().mode();
+                let mut desc = String::new();
+                if mode & 0o222 != 0 {
+                    desc.push_str("writable, ");
+                }
+                if mode & 0o111 != 0 {
+                    desc.push_str("executable, ");
+                }
+                if file_type.is_file() {
+                    desc.push_str("regular file, ");
+                }
+                desc.push_str("no read permission");
+                Some(Description {
+                    desc,
+                    ..Default::default()
+                })
+            }
+            Err(e) => {
+                self.error = Some(format!("cannot read `{}' ({})", name, strerror(&e)));
+                None
+            }
+        }
+    }
+
+    /// Type of the contents of a file: JSON, then the binary tests, then
+    /// the encoding of text with the text tests
+    fn describe_buffer(&self, buf: &[u8]) -> Description {
+        if buf.is_empty() {
+            return Description::new("empty", "inode/x-empty", Some("binary"));
+        }
+        let text = encoding::detect(&buf[..buf.len().min(self.encoding_max)]);
+        let charset = text.as_ref().map_or("binary", |e| e.mime);
+        if buf.len() == 1 {
+            return Description::new(
+                "very short file (no magic)",
+                "application/octet-stream",
+                Some("binary"),
+            );
+        }
+
+        let keep_going = self.has(MAGIC_CONTINUE);
+        let matcher = Matcher {
+            db: &self.db,
+            keep_going,
+            limits: self.limits,
+        };
+        let mut out = Description {
+            encoding: Some(charset),
+            ..Default::default()
+        };
+        let add = |out: &mut Description, found: Found| {
+            if !out.desc.is_empty() {
+                out.desc.push_str("\n- ");
+            }
+            out.desc.push_str(&found.desc);
+            out.mime = out.mime.take().or(found.mime);
+            out.ext = out.ext.take().or(found.ext);
+            out.apple = out.apple.take().or(found.apple);
+        };
+
+        if !self.has(MAGIC_NO_CHECK_JSON) && text.is_some() {
+            if let Some(kind) = json::check(buf) {
+                add(
+                    &mut out,
+                    Found {
+                        desc: kind.description().to_string(),
+                        mime: Some(kind.mime().to_string()),
+                        ..Default::default()
+                    },
+                );
+                if !keep_going {
+                    return out;
+                }
+            }
+        }
+
+        if !self.has(MAGIC_NO_CHECK_SOFT) {
+            if let Some(found) = matcher.run(buf, false, text.is_some()) {
+                add(&mut out, found);
+                if !keep_going {
+                    return out;
+                }
+            }
+        }
+
+        match text {
+            Some(text) if !self.has(MAGIC_NO_CHECK_TEXT) => {
+                if !out.desc.is_empty() {
+                    out.desc.push_str("\n- ");
+                }
+                if !self.has(MAGIC_NO_CHECK_SOFT) {
+                    if let Some(found) = matcher.run(&text.utf8(), true, true) {
+                        out.desc.push_str(&found.desc);
+                        out.mime = out.mime.take().or(found.mime);
+                        out.ext = out.ext.take().or(found.ext);
+                        out.apple = out.apple.take().or(found.apple);
+                    }
+                }
+                // Like ascmagic, the encoding ends what the text tests printed
+                let mut executable = false;
+                if !out.desc.is_empty() {
+                    if let Some(desc) = out.desc.strip_suffix(" text") {
+                        out.desc = format!("{}, ", desc);
+                    } else if let Some(desc) = out.desc.strip_suffix(" text executable") {
+                        out.desc = format!("{}, ", desc);
+                        executable = true;
+                    } else {
+                        out.desc.push_str(", ");
+                    }
+                }
+                out.desc.push_str(text.code);
+                out.desc.push_str(" text");
+                if executable {
+                    out.desc.push_str(" executable");
+                }
+                out.desc.push_str(&text.details());
+                if out.mime.is_none() {
+                    out.mime = Some("text/plain".to_string());
+                }
+            }
+            _ => {
+                if out.desc.is_empty() || keep_going {
+                    if !out.desc.is_empty() {
+                        out.desc.push_str("\n- ");
+                    }
+                    out.desc.push_str("data");
+                }
+                if out.mime.is_none() {
+                    out.mime = Some("application/octet-stream".to_string());
+                }
+            }
+        }
+        out
+    }
+
+    /// Text printed for a description, as the flags ask
+    fn render(&self, description: Description) -> String {
+        let text = if self.has(MAGIC_EXTENSION) {
+            description.ext.unwrap_or_else(|| "???".to_string())
+        } else if self.has(MAGIC_APPLE) {
+            description.apple.unwrap_or_else(|| "UNKNUNKN".to_string())
+        } else if self.has(MAGIC_MIME) && description.mime.is_some() {
+            let mime = description.mime.unwrap_or_default();
+            match (
+                self.has(MAGIC_MIME_TYPE),
+                self.has(MAGIC_MIME_ENCODING),
+                description.encoding,
+            ) {
+                (true, true, Some(encoding)) => format!("{}; charset={}", mime, encoding),
+                (false, true, Some(encoding)) => encoding.to_string(),
+                (false, true, None) => String::new(),
+                _ => mime,
+            }
+        } else {
+            description.desc
+        };
+        if self.has(MAGIC_RAW) {
+            text
+        } else {
+            printable(&text)
+        }
+    }
+}
+
+/// Read the magic files of `filename`, of `MAGIC` or of the default path.
+/// The default path falls back to the built-in database. `verbose` reports
+/// the files read, as `file -c` does.
+fn read_database(
+    filename: Option<&str>,
+    verbose: bool,
+    warnings: &mut Vec<String>,
+) -> Option<Database> {
+    let explicit = filename.is_some() || std::env::var_os("MAGIC").is_some();
+    let path = match filename {
+        Some(path) => path.to_string(),
+        None => std::env::var("MAGIC").unwrap_or_else(|_| DEFAULT_MAGIC.to_string()),
+    };
+    let mut db = Database::default();
+    for part in path.split(':').filter(|p| !p.is_empty()) {
+        let mut files = Vec::new();
+        match fs::read_dir(part) {
+            Ok(entries) => {
+                let mut entries = entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.is_file())
+                    .collect::<Vec<_>>();
+                entries.sort();
+                files.extend(entries);
+            }
+            Err(_) => files.push(Path::new(part).to_path_buf()),
+        }
+        for file in files {
+            let source = match fs::read(&file) {
+                Ok(source) if !source.starts_with(&COMPILED_MAGIC) => source,
+                _ => continue,
+            };
+            let name = file.to_string_lossy();
+            if verbose {
+                warnings.push(format!("Warning: using regular magic file `{}'", name));
+            }
+            db.parse(&String::from_utf8_lossy(&source), &name, warnings);
+        }
+    }
+    if db.is_empty() && !explicit {
+        db.parse(BUILTIN_MAGIC, "builtin", warnings);
+    }
+    if db.is_empty() {
+        return None;
+    }
+    db.finish();
+    Some(db)
+}
+
+/// Message of an I/O error, without the error number Rust adds
+fn strerror(e: &io::Error) -> String {
+    let message = e.to_string();
+    match message.find(" (os error") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    }
+}
+
+/// Unprintable characters in octal, as libmagic prints them
+fn printable(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_control() {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                out.push_str(&format!("\\{:03o}", b));
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
@@ -0,0 +1,1288 @@
+//! This file is part of the easybox package.
+//
+// (c) Zhihua Zhao <YuukaC@outlook.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+//! Parser of the magic(5) source format.
+
+use regex::bytes::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Weight of one byte of test, as in libmagic
+const MULT: i64 = 10;
+
+/// Byte order of a value read from the file
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Endian {
+    ///
+    Little,
+    ///
+    Big,
+    /// PDP-11 order of 32 bit values (`melong`)
+    Middle,
+}
+
+impl Endian {
+    fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            Endian::Big
+        } else {
+            Endian::Little
+        }
+    }
+
+    /// Big and little endian exchanged, for `use ^name`
+    pub fn flip(self) -> Self {
+        match self {
+            Endian::Little => Endian::Big,
+            Endian::Big => Endian::Little,
+            Endian::Middle => Endian::Middle,
+        }
+    }
+}
+
+/// Arithmetic applied to a value or to an indirect offset
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Op {
+    ///
+    And,
+    ///
+    Or,
+    ///
+    Xor,
+    ///
+    Add,
+    ///
+    Sub,
+    ///
+    Mul,
+    ///
+    Div,
+    ///
+    Mod,
+}
+
+impl Op {
+    fn from_char(c: char) -> Option<Self> {
+        Some(match c {
+            '&' => Op::And,
+            '|' => Op::Or,
+            '^' => Op::Xor,
+            '+' => Op::Add,
+            '-' => Op::Sub,
+            '*' => Op::Mul,
+            '/' => Op::Div,
+            '%' => Op::Mod,
+            _ => return None,
+        })
+    }
+
+    fn as_char(self) -> char {
+        match self {
+            Op::And => '&',
+            Op::Or => '|',
+            Op::Xor => '^',
+            Op::Add => '+',
+            Op::Sub => '-',
+            Op::Mul => '*',
+            Op::Div => '/',
+            Op::Mod => '%',
+        }
+    }
+
+    /// Apply the operation, a division by zero leaves the value unchanged
+    pub fn apply(self, value: u64, arg: u64) -> u64 {
+        match self {
+            Op::And => value & arg,
+            Op::Or => value | arg,
+            Op::Xor => value ^ arg,
+            Op::Add => value.wrapping_add(arg),
+            Op::Sub => value.wrapping_sub(arg),
+            Op::Mul => value.wrapping_mul(arg),
+            Op::Div => value.checked_div(arg).unwrap_or(value),
+            Op::Mod => value.checked_rem(arg).unwrap_or(value),
+        }
+    }
+}
+
+/// Type of the value a line tests
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    /// `byte`, `short`, `long` and `quad`
+    Int { size: usize, endian: Endian },
+    /// seconds since the epoch, shown in UTC or in local time
+    Date {
+        size: usize,
+        endian: Endian,
+        local: bool,
+    },
+    /// `float` and `double`
+    Float { size: usize, endian: Endian },
+    ///
+    String,
+    /// string preceded by its length
+    PString {
+        size: usize,
+        endian: Endian,
+        includes_length: bool,
+    },
+    /// UCS-2 string
+    String16 { endian: Endian },
+    /// string searched within a range
+    Search,
+    /// regular expression matched within a range
+    Regex,
+    /// matches when no other test of the same level did
+    Default,
+    /// forget the matches of the same level, for a later `default`
+    Clear,
+    /// start of a named test run by `use`
+    Name,
+    /// run a named test, with the byte order swapped for `^name`
+    Use { flip: bool },
+    /// run the whole database again at the offset
+    Indirect { relative: bool },
+    /// the offset itself, compared as a number
+    Offset,
+}
+
+impl Kind {
+    /// Whether the value is one of the string types
+    pub fn is_string(self) -> bool {
+        matches!(
+            self,
+            Kind::String
+                | Kind::PString { .. }
+                | Kind::String16 { .. }
+                | Kind::Search
+                | Kind::Regex
+        )
+    }
+
+    /// Same type read in the other byte order
+    pub fn flip(self) -> Self {
+        match self {
+            Kind::Int { size, endian } => Kind::Int {
+                size,
+                endian: endian.flip(),
+            },
+            Kind::Date {
+                size,
+                endian,
+                local,
+            } => Kind::Date {
+                size,
+                endian: endian.flip(),
+                local,
+            },
+            Kind::Float { size, endian } => Kind::Float {
+                size,
+                endian: endian.flip(),
+            },
+            kind => kind,
+        }
+    }
+}
+
+/// Modifiers of the string types
+#[derive(Clone, Copy, Default, Debug)]
+pub struct StrFlags {
+    /// `W`: a blank matches one or more blanks
+    pub compact_blank: bool,
+    /// `w`: a blank matches zero or more blanks
+    pub optional_blank: bool,
+    /// `c`: lower case letters match either case
+    pub lower: bool,
+    /// `C`: upper case letters match either case
+    pub upper: bool,
+    /// `t`: test only text files
+    pub text: bool,
+    /// `b`: test only binary files
+    pub binary: bool,
+    /// `T`: trim the blanks around the matched string
+    pub trim: bool,
+    /// `f`: the match must be a full word
+    pub full_word: bool,
+    /// `s`: the next offset is the start of the match
+    pub start: bool,
+    /// `l`: the range of a regex counts lines
+    pub lines: bool,
+}
+
+impl StrFlags {
+    fn as_string(&self) -> String {
+        [
+            (self.compact_blank, 'W'),
+            (self.optional_blank, 'w'),
+            (self.lower, 'c'),
+            (self.upper, 'C'),
+            (self.text, 't'),
+            (self.binary, 'b'),
+            (self.trim, 'T'),
+            (self.full_word, 'f'),
+            (self.start, 's'),
+            (self.lines, 'l'),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, c)| *c)
+        .collect()
+    }
+}
+
+/// Comparison of the value read with the value of the line
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Relation {
+    /// `x`
+    Any,
+    ///
+    Eq,
+    ///
+    Ne,
+    ///
+    Lt,
+    ///
+    Gt,
+    /// all the bits of the value are set
+    And,
+    /// some bits of the value are clear
+    Xor,
+}
+
+impl Relation {
+    fn as_char(self) -> char {
+        match self {
+            Relation::Any => 'x',
+            Relation::Eq => '=',
+            Relation::Ne => '!',
+            Relation::Lt => '<',
+            Relation::Gt => '>',
+            Relation::And => '&',
+            Relation::Xor => '^',
+        }
+    }
+}
+
+/// Value of the line the file is compared with
+#[derive(Clone, Debug)]
+pub enum Value {
+    ///
+    None,
+    ///
+    Int(u64),
+    ///
+    Float(f64),
+    ///
+    Bytes(Vec<u8>),
+    /// compiled expression and its source
+    Regex(Regex, String),
+    /// name of a `name` or `use` line
+    Name(String),
+}
+
+/// Indirect offset: the offset is read from the file
+#[derive(Clone, Copy, Debug)]
+pub struct Indirect {
+    /// the address of the value is relative to the previous match
+    pub relative: bool,
+    /// size of the value read
+    pub size: usize,
+    ///
+    pub endian: Endian,
+    /// the value is sign extended
+    pub signed: bool,
+    /// the value is an ID3 syncsafe integer
+    pub id3: bool,
+    /// arithmetic applied to the value read
+    pub op: Option<(Op, i64)>,
+    /// the argument of `op` is itself read at that offset
+    pub op_indirect: bool,
+}
+
+/// Offset of the value tested
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Offset {
+    /// relative to the end of the previous match (`&`)
+    pub relative: bool,
+    /// the offset, or the address of the offset when indirect
+    pub value: i64,
+    ///
+    pub indirect: Option<Indirect>,
+}
+
+/// One line of a magic file
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// number of `>`
+    pub level: usize,
+    ///
+    pub line: usize,
+    ///
+    pub offset: Offset,
+    ///
+    pub kind: Kind,
+    /// compare numbers as unsigned values
+    pub unsigned: bool,
+    /// operation applied to the value read
+    pub mask: Option<(Op, u64)>,
+    /// the value read is inverted (`~`)
+    pub inverse: bool,
+    ///
+    pub flags: StrFlags,
+    /// bytes or lines searched by `search` and `regex`
+    pub range: usize,
+    ///
+    pub relation: Relation,
+    ///
+    pub value: Value,
+    /// the message starts with `\b`, no blank is printed before it
+    pub nospace: bool,
+    ///
+    pub message: String,
+    /// `!:mime`
+    pub mime: Option<String>,
+    /// `!:ext`
+    pub ext: Option<String>,
+    /// `!:apple`
+    pub apple: Option<String>,
+    /// `!:strength`
+    pub factor: Option<(Op, i64)>,
+}
+
+/// A test: the line of level 0 and its continuations
+#[derive(Clone, Debug)]
+pub struct Group {
+    ///
+    pub entries: Vec<Entry>,
+    /// file the test was read from
+    pub file: String,
+    /// strength, the strongest tests are tried first
+    pub strength: i64,
+    /// the test is run on binary data
+    pub binary: bool,
+    /// the test is run on text
+    pub text: bool,
+}
+
+/// Magic database loaded from source files
+#[derive(Default)]
+pub struct Database {
+    /// tests, strongest first once `finish` was called
+    pub groups: Vec<Group>,
+    /// tests started by `name`, run through `use`
+    pub names: HashMap<String, Group>,
+}
+
+impl Database {
+    /// Add the tests of one magic file. An invalid line rejects the whole
+    /// file, as libmagic does; the messages are added to `warnings`.
+    pub fn parse(&mut self, source: &str, file: &str, warnings: &mut Vec<String>) -> bool {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut valid = true;
+        for (index, line) in source.lines().enumerate() {
+            let lineno = index + 1;
+            let line = line.trim_start_matches([' ', '\t']);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(annotation) = line.strip_prefix("!:") {
+                let entry = groups.last_mut().and_then(|g| g.entries.last_mut());
+                match (entry, parse_annotation(annotation)) {
+                    (Some(entry), Ok(annotation)) => annotation.apply(entry),
+                    (None, _) => {
+                        warnings.push(format!(
+                            "{}, {}: Warning: No current entry for :!{} type",
+                            file, lineno, annotation
+                        ));
+                        valid = false;
+                    }
+                    (_, Err(e)) => {
+                        warnings.push(format!("{}, {}: Warning: {}", file, lineno, e));
+                        valid = false;
+                    }
+                }
+                continue;
+            }
+            match parse_line(line, lineno) {
+                Ok(entry) if entry.level == 0 => groups.push(Group {
+                    entries: vec![entry],
+                    file: file.to_string(),
+                    strength: 0,
+                    binary: false,
+                    text: false,
+                }),
+                Ok(entry) => match groups.last_mut() {
+                    Some(group) => group.entries.push(entry),
+                    None => {
+                        warnings.push(format!(
+                            "{}, {}: Warning: No current entry for continuation",
+                            file, lineno
+                        ));
+                        valid = false;
+                    }
+                },
+                Err(e) => {
+                    warnings.push(format!("{}, {}: Warning: {}", file, lineno, e));
+                    valid = false;
+                }
+            }
+        }
+        if !valid {
+            return false;
+        }
+        for group in groups {
+            match (&group.entries[0].kind, &group.entries[0].value) {
+                (Kind::Name, Value::Name(name)) => {
+                    self.names.insert(name.clone(), group);
+                }
+                _ => self.groups.push(group),
+            }
+        }
+        true
+    }
+
+    /// Compute the strength and the kind of data of each test, and sort them
+    pub fn finish(&mut self) {
+        for group in &mut self.groups {
+            group.strength = strength(&group.entries[0]);
+            set_test_type(group);
+        }
+        // A stable sort keeps the order of the files for equal strengths
+        self.groups.sort_by_key(|g| std::cmp::Reverse(g.strength));
+    }
+
+    /// Whether no test was loaded
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty() && self.names.is_empty()
+    }
+
+    /// Tests with their strength, as printed by `file -l`
+    pub fn list(&self) -> String {
+        let mut out = String::new();
+        for set in 0..2 {
+            let _ = writeln!(out, "Set {}:", set);
+            for (title, text) in [("Binary patterns:", false), ("Text patterns:", true)] {
+                let _ = writeln!(out, "{}", title);
+                if set > 0 {
+                    continue;
+                }
+                for group in &self.groups {
+                    if (text && !group.text) || (!text && !group.binary) {
+                        continue;
+                    }
+                    let message = group
+                        .entries
+                        .iter()
+                        .map(|e| e.message.as_str())
+                        .find(|m| !m.is_empty())
+                        .unwrap_or_default();
+                    let mime = group
+                        .entries
+                        .iter()
+                        .find_map(|e| e.mime.as_deref())
+                        .unwrap_or_default();
+                    let _ = writeln!(
+                        out,
+                        "Strength = {:3}@{}: {} [{}]",
+                        group.strength, group.entries[0].line, message, mime
+                    );
+                }
+            }
+        }
+        out
+    }
+
+    /// Parsed form of each line, as printed by `file -c`
+    pub fn dump(&self) -> String {
+        let mut groups = self
+            .groups
+            .iter()
+            .chain(self.names.values())
+            .collect::<Vec<_>>();
+        groups.sort_by(|a, b| (&a.file, a.entries[0].line).cmp(&(&b.file, b.entries[0].line)));
+        let mut out = String::new();
+        for entry in groups.iter().flat_map(|g| g.entries.iter()) {
+            let _ = writeln!(out, "{}", dump_entry(entry));
+        }
+        out
+    }
+}
+
+/// `!:` line, applied to the entry before it
+enum Annotation {
+    Mime(String),
+    Ext(String),
+    Apple(String),
+    Strength(Op, i64),
+}
+
+impl Annotation {
+    fn apply(self, entry: &mut Entry) {
+        match self {
+            Annotation::Mime(mime) => entry.mime = Some(mime),
+            Annotation::Ext(ext) => entry.ext = Some(ext),
+            Annotation::Apple(apple) => entry.apple = Some(apple),
+            Annotation::Strength(op, factor) => entry.factor = Some((op, factor)),
+        }
+    }
+}
+
+fn parse_annotation(text: &str) -> Result<Annotation, String> {
+    let (name, value) = text
+        .split_once(|c: char| c.is_ascii_whitespace())
+        .unwrap_or((text, ""));
+    // A comment may follow the value
+    let value = value.split('#').next().unwrap_or_default().trim();
+    match name {
+        "mime" => Ok(Annotation::Mime(value.to_string())),
+        "ext" => Ok(Annotation::Ext(value.to_string())),
+        "apple" => Ok(Annotation::Apple(value.to_string())),
+        "strength" => {
+            let mut chars = value.chars();
+            let op = chars
+                .next()
+                .and_then(Op::from_char)
+                .filter(|op| matches!(op, Op::Add | Op::Sub | Op::Mul | Op::Div))
+                .ok_or_else(|| format!("Unknown factor op `{}'", value))?;
+            let (factor, _) = parse_number(chars.as_str().trim_start())
+                .ok_or_else(|| format!("Bad factor `{}'", value))?;
+            if op == Op::Div && factor == 0 {
+                return Err("Cannot have factor op `/' and factor 0".to_string());
+            }
+            Ok(Annotation::Strength(op, factor))
+        }
+        _ => Err(format!("Unknown !: entry `{}'", text)),
+    }
+}
+
+/// Parse a line of the form `[>...]offset type test message`
+fn parse_line(line: &str, lineno: usize) -> Result<Entry, String> {
+    let level = line.bytes().take_while(|&b| b == b'>').count();
+    let (offset, rest) = split_field(&line[level..]);
+    let offset =
+        parse_offset(offset).ok_or_else(|| format!("offset `{}' invalid", line[level..].trim()))?;
+    let (type_name, rest) = split_field(rest);
+    if type_name.is_empty() {
+        return Err(format!("type `{}' invalid", rest));
+    }
+    let mut entry = parse_type(type_name, lineno, level, offset)
+        .map_err(|e| e.unwrap_or_else(|| format!("type `{}' invalid", line[level..].trim())))?;
+    let (test, rest) = split_field(rest);
+    parse_test(&mut entry, test)?;
+
+    let mut message = rest.trim_start_matches([' ', '\t']).trim_end();
+    if let Some(stripped) = message.strip_prefix("\\b") {
+        entry.nospace = true;
+        message = stripped;
+    }
+    entry.message = message.to_string();
+    Ok(entry)
+}
+
+/// Split the first field of a line, a backslash escapes a blank
+fn split_field(text: &str) -> (&str, &str) {
+    let text = text.trim_start_matches([' ', '\t']);
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() && !bytes[i].is_ascii_whitespace() {
+        i += if bytes[i] == b'\\' { 2 } else { 1 };
+    }
+    let i = i.min(bytes.len());
+    (&text[..i], &text[i..])
+}
+
+/// Parse a number the way `strtoll(s, &end, 0)` does, returning the rest
+pub fn parse_number(text: &str) -> Option<(i64, &str)> {
+    let (negative, digits) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let (radix, digits) = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        (16, hex)
+    } else if digits.starts_with('0') && digits.len() > 1 {
+        (8, &digits[1..])
+    } else {
+        (10, digits)
+    };
+    let end = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    let number = if end == 0 {
+        // "0" alone, or "0" followed by something else than an octal digit
+        if radix == 8 {
+            0
+        } else {
+            return None;
+        }
+    } else {
+        u64::from_str_radix(&digits[..end], radix).ok()? as i64
+    };
+    let rest = &digits[end..];
+    Some((
+        if negative {
+            number.wrapping_neg()
+        } else {
+            number
+        },
+        rest,
+    ))
+}
+
+fn parse_offset(text: &str) -> Option<Offset> {
+    let (relative, text) = match text.strip_prefix('&') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let inner = match text.strip_prefix('(') {
+        Some(inner) => inner.strip_suffix(')')?,
+        None => {
+            let (value, rest) = parse_number(text)?;
+            if !rest.is_empty() {
+                return None;
+            }
+            return Some(Offset {
+                relative,
+                value,
+                indirect: None,
+            });
+        }
+    };
+
+    let (inner_relative, inner) = match inner.strip_prefix('&') {
+        Some(inner) => (true, inner),
+        None => (false, inner),
+    };
+    let (value, mut rest) = parse_number(inner)?;
+    let mut indirect = Indirect {
+        relative: inner_relative,
+        size: 4,
+        endian: Endian::native(),
+        signed: false,
+        id3: false,
+        op: None,
+        op_indirect: false,
+    };
+    if let Some(c) = rest.chars().next().filter(|&c| c == '.' || c == ',') {
+        indirect.signed = c == ',';
+        let t = rest[1..].chars().next()?;
+        let (size, endian) = match t {
+            'b' | 'c' | 'B' | 'C' => (1, Endian::native()),
+            'h' | 's' => (2, Endian::Little),
+            'H' | 'S' => (2, Endian::Big),
+            'l' => (4, Endian::Little),
+            'L' => (4, Endian::Big),
+            'm' => (4, Endian::Middle),
+            'i' => {
+                indirect.id3 = true;
+                (4, Endian::Little)
+            }
+            'I' => {
+                indirect.id3 = true;
+                (4, Endian::Big)
+            }
+            'q' => (8, Endian::Little),
+            'Q' => (8, Endian::Big),
+            _ => return None,
+        };
+        indirect.size = size;
+        indirect.endian = endian;
+        rest = &rest[2..];
+    }
+    if let Some(op) = rest.chars().next().and_then(Op::from_char) {
+        let arg = &rest[1..];
+        let (arg, op_indirect) = match arg.strip_prefix('(') {
+            Some(arg) => (arg.strip_suffix(')')?, true),
+            None => (arg, false),
+        };
+        let (arg, arg_rest) = parse_number(arg)?;
+        if !arg_rest.is_empty() {
+            return None;
+        }
+        indirect.op = Some((op, arg));
+        indirect.op_indirect = op_indirect;
+    } else if !rest.is_empty() {
+        return None;
+    }
+    Some(Offset {
+        relative,
+        value,
+        indirect: Some(indirect),
+    })
+}
+
+/// Parse the type field with its mask or modifiers. `Err(None)` is an
+/// unknown type.
+fn parse_type(
+    text: &str,
+    line: usize,
+    level: usize,
+    offset: Offset,
+) -> Result<Entry, Option<String>> {
+    let end = text
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(text.len());
+    let (name, modifiers) = text.split_at(end);
+    let (unsigned, kind) = match type_from_name(name) {
+        Some(kind) => (false, kind),
+        None => match name.strip_prefix('u').and_then(type_from_name) {
+            Some(kind @ (Kind::Int { .. } | Kind::Date { .. })) => (true, kind),
+            _ => return Err(None),
+        },
+    };
+    let mut entry = Entry {
+        level,
+        line,
+        offset,
+        kind,
+        unsigned,
+        mask: None,
+        inverse: false,
+        flags: StrFlags::default(),
+        range: 0,
+        relation: Relation::Eq,
+        value: Value::None,
+        nospace: false,
+        message: String::new(),
+        mime: None,
+        ext: None,
+        apple: None,
+        factor: None,
+    };
+    if modifiers.is_empty() {
+        return Ok(entry);
+    }
+
+    match kind {
+        Kind::Int { .. } | Kind::Date { .. } | Kind::Float { .. } | Kind::Offset => {
+            let mut modifiers = modifiers;
+            if let Some(rest) = modifiers.strip_prefix('~') {
+                entry.inverse = true;
+                modifiers = rest;
+            }
+            if modifiers.is_empty() {
+                return Ok(entry);
+            }
+            let op = modifiers
+                .chars()
+                .next()
+                .and_then(Op::from_char)
+                .ok_or(None)?;
+            let (mask, rest) = parse_number(&modifiers[1..])
+                .ok_or_else(|| Some(format!("bad mask `{}'", modifiers)))?;
+            if !rest.is_empty() {
+                return Err(Some(format!("bad mask `{}'", modifiers)));
+            }
+            entry.mask = Some((op, mask as u64));
+        }
+        Kind::String
+        | Kind::PString { .. }
+        | Kind::String16 { .. }
+        | Kind::Search
+        | Kind::Regex
+        | Kind::Indirect { .. } => {
+            for part in modifiers.split('/').skip(1) {
+                let flags = if part.starts_with(|c: char| c.is_ascii_digit()) {
+                    let (range, rest) =
+                        parse_number(part).ok_or_else(|| Some(format!("bad range `{}'", part)))?;
+                    entry.range = range.max(0) as usize;
+                    rest
+                } else {
+                    part
+                };
+                for c in flags.chars() {
+                    set_modifier(&mut entry, c)?;
+                }
+            }
+        }
+        _ => return Err(None),
+    }
+    Ok(entry)
+}
+
+fn set_modifier(entry: &mut Entry, c: char) -> Result<(), Option<String>> {
+    let flags = &mut entry.flags;
+    match (&mut entry.kind, c) {
+        (
+            Kind::PString {
+                size,
+                endian,
+                includes_length,
+            },
+            _,
+        ) => match c {
+            'B' => *size = 1,
+            'H' => {
+                *size = 2;
+                *endian = Endian::Big
+            }
+            'h' => {
+                *size = 2;
+                *endian = Endian::Little
+            }
+            'L' => {
+                *size = 4;
+                *endian = Endian::Big
+            }
+            'l' => {
+                *size = 4;
+                *endian = Endian::Little
+            }
+            'J' => *includes_length = true,
+            _ => return Err(Some(format!("Bad modifier `{}' for pstring", c))),
+        },
+        (Kind::Indirect { relative }, 'r') => *relative = true,
+        (Kind::Regex, 'c') => flags.lower = true,
+        (Kind::Regex, 's') => flags.start = true,
+        (Kind::Regex, 'l') => flags.lines = true,
+        (Kind::Regex | Kind::Indirect { .. }, _) => {
+            return Err(Some(format!("Bad modifier `{}'", c)))
+        }
+        (_, 'W') => flags.compact_blank = true,
+        (_, 'w') => flags.optional_blank = true,
+        (_, 'c') => flags.lower = true,
+        (_, 'C') => flags.upper = true,
+        (_, 't') => flags.text = true,
+        (_, 'b') => flags.binary = true,
+        (_, 'T') => flags.trim = true,
+        (_, 'f') => flags.full_word = true,
+        (_, 's') => flags.start = true,
+        _ => return Err(Some(format!("string modifier `{}' invalid", c))),
+    }
+    Ok(())
+}
+
+fn type_from_name(name: &str) -> Option<Kind> {
+    let (endian, base) = if let Some(base) = name.strip_prefix("be") {
+        (Endian::Big, base)
+    } else if let Some(base) = name.strip_prefix("le") {
+        (Endian::Little, base)
+    } else if let Some(base) = name.strip_prefix("me") {
+        (Endian::Middle, base)
+    } else {
+        (Endian::native(), name)
+    };
+    let explicit = base.len() != name.len();
+    let kind = match base {
+        "byte" if !explicit => Kind::Int { size: 1, endian },
+        "short" => Kind::Int { size: 2, endian },
+        "long" => Kind::Int { size: 4, endian },
+        "quad" => Kind::Int { size: 8, endian },
+        "date" | "ldate" | "qdate" | "qldate" => Kind::Date {
+            size: if base.starts_with('q') { 8 } else { 4 },
+            endian,
+            local: base.ends_with("ldate"),
+        },
+        "float" => Kind::Float { size: 4, endian },
+        "double" => Kind::Float { size: 8, endian },
+        "string16" if explicit && endian != Endian::Middle => Kind::String16 { endian },
+        _ if explicit => return None,
+        "string" => Kind::String,
+        "pstring" => Kind::PString {
+            size: 1,
+            endian: Endian::Big,
+            includes_length: false,
+        },
+        "search" => Kind::Search,
+        "regex" => Kind::Regex,
+        "default" => Kind::Default,
+        "clear" => Kind::Clear,
+        "name" => Kind::Name,
+        "use" => Kind::Use { flip: false },
+        "indirect" => Kind::Indirect { relative: false },
+        "offset" => Kind::Offset,
+        _ => return None,
+    };
+    if endian == Endian::Middle
+        && !matches!(kind, Kind::Int { size: 4, .. } | Kind::Date { size: 4, .. })
+    {
+        return None;
+    }
+    Some(kind)
+}
+
+/// Parse the test field: relation and value
+fn parse_test(entry: &mut Entry, test: &str) -> Result<(), String> {
+    match entry.kind {
+        Kind::Name | Kind::Use { .. } => {
+            // A bare `^` is read as a relation, as libmagic does, only
+            // `\^name` swaps the byte order
+            let (flip, name) = match test.strip_prefix("\\^") {
+                Some(name) => (true, name),
+                None => (false, test.trim_start_matches(['=', '^'])),
+            };
+            if name.is_empty() {
+                return Err("missing name".to_string());
+            }
+            if let Kind::Use { .. } = entry.kind {
+                entry.kind = Kind::Use { flip };
+            }
+            entry.relation = if test.starts_with('^') {
+                Relation::Xor
+            } else {
+                Relation::Eq
+            };
+            entry.value = Value::Name(name.to_string());
+            return Ok(());
+        }
+        Kind::Default | Kind::Clear | Kind::Indirect { .. } => {
+            entry.relation = Relation::Any;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    if test == "x" || test.is_empty() {
+        entry.relation = Relation::Any;
+        return Ok(());
+    }
+    let numeric = !entry.kind.is_string();
+    let mut chars = test.chars();
+    let relation = match chars.next() {
+        Some('=') => Some(Relation::Eq),
+        Some('!') => Some(Relation::Ne),
+        Some('<') => Some(Relation::Lt),
+        Some('>') => Some(Relation::Gt),
+        Some('&') => Some(Relation::And),
+        Some('^') => Some(Relation::Xor),
+        _ => None,
+    };
+    let value = match relation {
+        Some(relation) => {
+            entry.relation = relation;
+            chars.as_str()
+        }
+        None => test,
+    };
+
+    entry.value = match entry.kind {
+        Kind::Float { .. } => Value::Float(
+            value
+                .parse::<f64>()
+                .map_err(|_| format!("Unparsable float `{}'", value))?,
+        ),
+        Kind::Regex => {
+            let source = unescape_regex(value);
+            let regex = RegexBuilder::new(&format!("(?-u){}", source))
+                .case_insensitive(entry.flags.lower)
+                .multi_line(true)
+                .build()
+                .map_err(|e| format!("regex error `{}': {}", source, e))?;
+            Value::Regex(regex, source)
+        }
+        _ if numeric => {
+            let (value, inverted) = match value.strip_prefix('~') {
+                Some(value) => (value, true),
+                None => (value, false),
+            };
+            let (number, rest) =
+                parse_number(value).ok_or_else(|| format!("Bad value `{}'", value))?;
+            // C suffixes of the value
+            if !rest.trim_start_matches(['l', 'L', 'u', 'U']).is_empty() {
+                return Err(format!("Bad value `{}'", value));
+            }
+            let number = number as u64;
+            Value::Int(if inverted { !number } else { number })
+        }
+        _ => Value::Bytes(unescape(value)),
+    };
+    Ok(())
+}
+
+/// Decode the C escapes of a string value
+pub fn unescape(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        i += 1;
+        if b != b'\\' || i == bytes.len() {
+            out.push(b);
+            continue;
+        }
+        let c = bytes[i];
+        i += 1;
+        match c {
+            b'n' => out.push(b'\n'),
+            b't' => out.push(b'\t'),
+            b'r' => out.push(b'\r'),
+            b'b' => out.push(8),
+            b'f' => out.push(12),
+            b'v' => out.push(11),
+            b'a' => out.push(7),
+            b'e' => out.push(27),
+            b'0'..=b'7' => {
+                let mut value = u32::from(c - b'0');
+                let mut digits = 1;
+                while digits < 3 && i < bytes.len() && (b'0'..=b'7').contains(&bytes[i]) {
+                    value = value * 8 + u32::from(bytes[i] - b'0');
+                    i += 1;
+                    digits += 1;
+                }
+                out.push(value as u8);
+            }
+            b'x' => {
+                let mut value = 0u32;
+                let mut digits = 0;
+                while digits < 2 && i < bytes.len() && bytes[i].is_ascii_hexdigit() {
+                    value = value * 16 + (bytes[i] as char).to_digit(16).unwrap_or(0);
+                    i += 1;
+                    digits += 1;
+                }
+                if digits == 0 {
+                    out.push(b'x');
+                } else {
+                    out.push(value as u8);
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// A regex keeps its escapes, except for the escaped blanks of the field
+fn unescape_regex(text: &str) -> String {
+    text.replace("\\ ", " ").replace("\\\t", "\t")
+}
+
+/// Strength of a line, as computed by libmagic
+fn strength_of(entry: &Entry) -> i64 {
+    let mut value = 2 * MULT;
+    let length = match &entry.value {
+        Value::Bytes(bytes) => bytes.len() as i64,
+        _ => 0,
+    };
+    match entry.kind {
+        Kind::Default => return 0,
+        Kind::Int { size, .. } | Kind::Date { size, .. } | Kind::Float { size, .. } => {
+            value += size as i64 * MULT
+        }
+        Kind::String | Kind::PString { .. } => value += length * MULT,
+        Kind::String16 { .. } => value += length * MULT / 2,
+        Kind::Search => {
+            if length > 0 {
+                value += length * (MULT / length).max(1)
+            }
+        }
+        Kind::Regex => {
+            if let Value::Regex(_, source) = &entry.value {
+                let length = nonmagic(source);
+                value += length * (MULT / length).max(1);
+            }
+        }
+        Kind::Offset => value += 4 * MULT,
+        Kind::Clear | Kind::Name | Kind::Use { .. } | Kind::Indirect { .. } => {}
+    }
+    match entry.relation {
+        Relation::Any | Relation::Ne => value = 0,
+        Relation::Eq => value += MULT,
+        Relation::Lt | Relation::Gt => value -= 2 * MULT,
+        Relation::And | Relation::Xor => value -= MULT,
+    }
+    value
+}
+
+/// Strength of a test, with its `!:strength` factor
+fn strength(entry: &Entry) -> i64 {
+    if entry.kind == Kind::Default {
+        return 0;
+    }
+    let mut value = strength_of(entry);
+    if let Some((op, factor)) = entry.factor {
+        value = match op {
+            Op::Add => value + factor,
+            Op::Sub => value - factor,
+            Op::Mul => value * factor,
+            Op::Div => value / factor,
+            _ => value,
+        };
+    }
+    value.max(1)
+}
+
+/// Number of characters of a regex that are not operators
+fn nonmagic(source: &str) -> i64 {
+    let bytes = source.as_bytes();
+    let mut count = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                i += 1;
+                count += 1;
+            }
+            b'?' | b'*' | b'.' | b'+' | b'^' | b'$' => {}
+            b'[' => {
+                while i + 1 < bytes.len() && bytes[i + 1] != b']' {
+                    i += 1;
+                }
+            }
+            b'{' => {
+                while i < bytes.len() && bytes[i] != b'}' {
+                    i += 1;
+                }
+            }
+            _ => count += 1,
+        }
+        i += 1;
+    }
+    count.max(1)
+}
+
+/// Run a test on binary data, on text, or both, as libmagic decides it
+fn set_test_type(group: &mut Group) {
+    // Only the first line counts, its continuations run with it
+    let entry = &group.entries[0];
+    let flags = entry.flags;
+    match entry.kind {
+        Kind::String | Kind::PString { .. } | Kind::String16 { .. } => {
+            if flags.text {
+                group.text = true;
+            } else {
+                group.binary = true;
+            }
+        }
+        Kind::Search | Kind::Regex => {
+            group.binary = flags.binary;
+            group.text = flags.text;
+            if group.binary || group.text {
+                return;
+            }
+            let pattern = match &entry.value {
+                Value::Bytes(bytes) => bytes.as_slice(),
+                Value::Regex(_, source) => source.as_bytes(),
+                _ => &[],
+            };
+            if super::encoding::looks_utf8(pattern) {
+                group.text = true;
+            } else {
+                group.binary = true;
+            }
+        }
+        Kind::Default | Kind::Clear | Kind::Name | Kind::Use { .. } | Kind::Indirect { .. } => {}
+        _ => group.binary = true,
+    }
+}
+
+fn dump_entry(entry: &Entry) -> String {
+    let mut out = format!(
+        "{}: {} {}",
+        entry.line,
+        ">".repeat(entry.level + 1),
+        entry.offset.value
+    );
+    if let Some(indirect) = entry.offset.indirect {
+        let name = if indirect.id3 {
+            "id3"
+        } else {
+            ["", "byte", "short", "", "long", "", "", "", "quad"][indirect.size]
+        };
+        let (op, arg) = indirect.op.unwrap_or((Op::Add, 0));
+        let _ = write!(
+            out,
+            "({}{},{}{}),",
+            endian_prefix(indirect.size.max(2), indirect.endian),
+            name,
+            op.as_char(),
+            arg as i32
+        );
+    }
+    out.push(' ');
+    if entry.unsigned {
+        out.push('u');
+    }
+    let name = match entry.kind {
+        Kind::Int { size, endian } => format!(
+            "{}{}",
+            endian_prefix(size, endian),
+            ["", "byte", "short", "", "long", "", "", "", "quad"][size]
+        ),
+        Kind::Date {
+            size,
+            endian,
+            local,
+        } => format!(
+            "{}{}{}date",
+            endian_prefix(size, endian),
+            if size == 8 { "q" } else { "" },
+            if local { "l" } else { "" }
+        ),
+        Kind::Float { size, endian } => format!(
+            "{}{}",
+            endian_prefix(size, endian),
+            if size == 8 { "double" } else { "float" }
+        ),
+        Kind::String => "string".to_string(),
+        Kind::PString { .. } => "pstring".to_string(),
+        Kind::String16 { endian } => format!("{}string16", endian_prefix(2, endian)),
+        Kind::Search => "search".to_string(),
+        Kind::Regex => "regex".to_string(),
+        Kind::Default => "default".to_string(),
+        Kind::Clear => "clear".to_string(),
+        Kind::Name => "name".to_string(),
+        Kind::Use { .. } => "use".to_string(),
+        Kind::Indirect { .. } => "indirect".to_string(),
+        Kind::Offset => "offset".to_string(),
+    };
+    out.push_str(&name);
+    if entry.inverse {
+        out.push('~');
+    }
+    if entry.kind.is_string() || matches!(entry.kind, Kind::Name | Kind::Use { .. }) {
+        let flags = entry.flags.as_string();
+        if !flags.is_empty() {
+            let _ = write!(out, "/{}", flags);
+        }
+        if entry.range > 0 {
+            let _ = write!(out, "/{}", entry.range);
+        }
+    } else {
+        match entry.mask {
+            Some((op, mask)) => {
+                let _ = write!(out, "{}{:08x}", op.as_char(), mask);
+            }
+            None => out.push('&'),
+        }
+    }
+    let _ = write!(out, ",{}", entry.relation.as_char());
+    if entry.relation != Relation::Any {
+        match &entry.value {
+            Value::Int(value) => {
+                let _ = match entry.kind {
+                    Kind::Int { size: 8, .. } | Kind::Date { size: 8, .. } => {
+                        write!(out, "{}", *value as i64)
+                    }
+                    _ => write!(out, "{}", *value as i32),
+                };
+            }
+            Value::Float(value) => {
+                let _ = write!(out, "{}", value);
+            }
+            Value::Bytes(bytes) => out.push_str(&super::softmagic::show_bytes(bytes)),
+            Value::Regex(_, source) => out.push_str(source),
+            Value::Name(name) => {
+                let _ = write!(out, "'{}'", name);
+            }
+            Value::None => {}
+        }
+    }
+    let _ = write!(out, ",\"{}\"]", entry.message);
+    out
+}
+
+fn endian_prefix(size: usize, endian: Endian) -> &'static str {
+    match (size, endian) {
+        (1, _) => "",
+        (_, Endian::Big) => "be",
+        (_, Endian::Middle) => "me",
+        (_, Endian::Little) => "le",
+    }
+}
@@ -0,0 +1,248 @@
+//! This file is part of the easybox package.
+//
+// (c) Zhihua Zhao <YuukaC@outlook.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+//! Character encoding of text files, and the details printed after it.
+
+/// Never in text
+const F: u8 = 0;
+/// Plain ASCII text
+const T: u8 = 1;
+/// ISO-8859 text
+const I: u8 = 2;
+/// Non-ISO extended ASCII (Mac, IBM PC)
+const X: u8 = 3;
+
+/// Class of each byte, as libmagic sees it
+#[rustfmt::skip]
+const TEXT_CHARS: [u8; 256] = [
+    // BEL BS HT LF VT FF CR
+    F, F, F, F, F, F, F, T, T, T, T, T, T, T, F, F,
+    // ESC
+    F, F, F, F, F, F, F, F, F, F, F, T, F, F, F, F,
+    T, T, T, T, T, T, T, T, T, T, T, T, T, T, T, T,
+    T, T, T, T, T, T, T, T, T, T, T, T, T, T, T, T,
+    T, T, T, T, T, T, T, T, T, T, T, T, T, T, T, T,
+    T, T, T, T, T, T, T, T, T, T, T, T, T, T, T, T,
+    T, T, T, T, T, T, T, T, T, T, T, T, T, T, T, T,
+    T, T, T, T, T, T, T, T, T, T, T, T, T, T, T, F,
+    // NEL
+    X, X, X, X, X, T, X, X, X, X, X, X, X, X, X, X,
+    X, X, X, X, X, X, X, X, X, X, X, X, X, X, X, X,
+    I, I, I, I, I, I, I, I, I, I, I, I, I, I, I, I,
+    I, I, I, I, I, I, I, I, I, I, I, I, I, I, I, I,
+    I, I, I, I, I, I, I, I, I, I, I, I, I, I, I, I,
+    I, I, I, I, I, I, I, I, I, I, I, I, I, I, I, I,
+    I, I, I, I, I, I, I, I, I, I, I, I, I, I, I, I,
+    I, I, I, I, I, I, I, I, I, I, I, I, I, I, I, I,
+];
+
+/// Lines longer than this are reported
+const MAXLINELEN: usize = 300;
+
+/// Encoding of a text file
+pub struct Encoding {
+    /// name printed in the description, like `ASCII`
+    pub code: &'static str,
+    /// charset of the MIME type
+    pub mime: &'static str,
+    /// characters of the text
+    pub chars: Vec<u32>,
+}
+
+impl Encoding {
+    /// The text converted to UTF-8, where the text tests are run
+    pub fn utf8(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.chars.len());
+        let mut buf = [0; 4];
+        for &c in &self.chars {
+            let c = char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER);
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+        out
+    }
+
+    /// Line terminators, long lines, escapes and overstriking, as in
+    /// `, with CRLF line terminators`
+    pub fn details(&self) -> String {
+        let (mut n_crlf, mut n_cr, mut n_lf, mut n_nel) = (0, 0, 0, 0);
+        let mut seen_cr = false;
+        let mut has_escapes = false;
+        let mut has_backspace = false;
+        // Index of the last line end, plus one
+        let mut line_start = 0;
+        let mut longest = 0;
+        for (i, &c) in self.chars.iter().enumerate() {
+            if c == u32::from(b'\n') {
+                if seen_cr {
+                    n_crlf += 1;
+                } else {
+                    n_lf += 1;
+                }
+                line_start = i + 1;
+            } else if seen_cr {
+                n_cr += 1;
+            }
+            seen_cr = c == u32::from(b'\r');
+            if seen_cr {
+                line_start = i + 1;
+            }
+            if c == 0x85 {
+                n_nel += 1;
+                line_start = i + 1;
+            }
+            if i + 1 > line_start + MAXLINELEN {
+                longest = longest.max(i + 1 - line_start);
+            }
+            has_escapes |= c == 0x1b;
+            has_backspace |= c == 0x08;
+        }
+
+        let mut out = String::new();
+        if longest > 0 {
+            out.push_str(&format!(", with very long lines ({})", longest));
+        }
+        let none = n_crlf == 0 && n_cr == 0 && n_nel == 0 && n_lf == 0;
+        if none || n_crlf != 0 || n_cr != 0 || n_nel != 0 {
+            out.push_str(", with");
+            if none {
+                out.push_str(" no");
+            } else {
+                let terminators = [(n_crlf, "CRLF"), (n_cr, "CR"), (n_lf, "LF"), (n_nel, "NEL")];
+                let names = terminators
+                    .iter()
+                    .filter(|(count, _)| *count > 0)
+                    .map(|(_, name)| *name)
+                    .collect::<Vec<_>>();
+                out.push(' ');
+                out.push_str(&names.join(", "));
+            }
+            out.push_str(" line terminators");
+        }
+        if has_escapes {
+            out.push_str(", with escape sequences");
+        }
+        if has_backspace {
+            out.push_str(", with overstriking");
+        }
+        out
+    }
+}
+
+/// Encoding of `buf`, `None` for binary data
+pub fn detect(buf: &[u8]) -> Option<Encoding> {
+    let latin = |buf: &[u8]| buf.iter().map(|&b| u32::from(b)).collect::<Vec<_>>();
+    let (code, mime, chars) = if buf.iter().all(|&b| TEXT_CHARS[b as usize] == T) {
+        ("ASCII", "us-ascii", latin(buf))
+    } else if buf.starts_with(&[0xef, 0xbb, 0xbf]) && utf8_class(&buf[3..]) > 0 {
+        (
+            "Unicode text, UTF-8 (with BOM)",
+            "utf-8",
+            decode_utf8(&buf[3..]),
+        )
+    } else if utf8_class(buf) > 1 {
+        ("Unicode text, UTF-8", "utf-8", decode_utf8(buf))
+    } else if let Some((big, chars)) = ucs(buf, 4) {
+        if big {
+            ("Unicode text, UTF-32, big-endian", "utf-32be", chars)
+        } else {
+            ("Unicode text, UTF-32, little-endian", "utf-32le", chars)
+        }
+    } else if let Some((big, chars)) = ucs(buf, 2) {
+        if big {
+            ("Unicode text, UTF-16, big-endian", "utf-16be", chars)
+        } else {
+            ("Unicode text, UTF-16, little-endian", "utf-16le", chars)
+        }
+    } else if buf.iter().all(|&b| matches!(TEXT_CHARS[b as usize], T | I)) {
+        ("ISO-8859", "iso-8859-1", latin(buf))
+    } else if buf.iter().all(|&b| TEXT_CHARS[b as usize] != F) {
+        ("Non-ISO extended-ASCII", "unknown-8bit", latin(buf))
+    } else {
+        return None;
+    };
+    Some(Encoding { code, mime, chars })
+}
+
+/// Whether a pattern of a magic file is text
+pub fn looks_utf8(buf: &[u8]) -> bool {
+    utf8_class(buf) > 0
+}
+
+/// -1 for invalid UTF-8 or control characters, 1 for plain ASCII, 2 for
+/// UTF-8 with multibyte characters; a sequence cut by the end is accepted
+fn utf8_class(buf: &[u8]) -> i32 {
+    let mut ctrl = false;
+    let mut multibyte = false;
+    let mut i = 0;
+    while i < buf.len() {
+        let c = buf[i];
+        if c & 0x80 == 0 {
+            ctrl |= TEXT_CHARS[c as usize] != T;
+            i += 1;
+            continue;
+        }
+        let following = match c {
+            0xc0..=0xdf => 1,
+            0xe0..=0xef => 2,
+            0xf0..=0xf7 => 3,
+            0xf8..=0xfb => 4,
+            0xfc..=0xfd => 5,
+            _ => return -1,
+        };
+        for n in 1..=following {
+            match buf.get(i + n) {
+                None => return if ctrl { 0 } else { 2 },
+                Some(b) if b & 0xc0 != 0x80 => return -1,
+                _ => {}
+            }
+        }
+        multibyte = true;
+        i += following + 1;
+    }
+    if ctrl {
+        0
+    } else if multibyte {
+        2
+    } else {
+        1
+    }
+}
+
+fn decode_utf8(buf: &[u8]) -> Vec<u32> {
+    String::from_utf8_lossy(buf)
+        .chars()
+        .map(|c| c as u32)
+        .collect()
+}
+
+/// UCS-2 or UCS-4 text with a byte order mark, `true` for big endian
+fn ucs(buf: &[u8], width: usize) -> Option<(bool, Vec<u32>)> {
+    if buf.len() < width {
+        return None;
+    }
+    let big = match (width, &buf[..width]) {
+        (2, [0xff, 0xfe]) | (4, [0xff, 0xfe, 0, 0]) => false,
+        (2, [0xfe, 0xff]) | (4, [0, 0, 0xfe, 0xff]) => true,
+        _ => return None,
+    };
+    let mut chars = Vec::with_capacity(buf.len() / width);
+    for unit in buf[width..].chunks_exact(width) {
+        let c = unit.iter().fold(0u32, |acc, &b| (acc << 8) | u32::from(b));
+        let c = if big {
+            c
+        } else if width == 2 {
+            u32::from((c as u16).swap_bytes())
+        } else {
+            c.swap_bytes()
+        };
+        if c == 0xfffe || (c < 128 && TEXT_CHARS[c as usize] != T) {
+            return None;
+        }
+        chars.push(c);
+    }
+    Some((big, chars))
+}
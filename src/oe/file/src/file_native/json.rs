@@ -0,0 +1,231 @@
+//! This file is part of the easybox package.
+//
+// (c) Zhihua Zhao <YuukaC@outlook.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+//! Detection of JSON text, built in as libmagic's `is_json.c`.
+
+/// Kind of JSON text
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Json {
+    /// one value
+    Single,
+    /// one value per line
+    NewlineDelimited,
+}
+
+impl Json {
+    /// Description of the text
+    pub fn description(self) -> &'static str {
+        match self {
+            Json::Single => "JSON text data",
+            Json::NewlineDelimited => "New Line Delimited JSON text data",
+        }
+    }
+
+    /// MIME type of the text
+    pub fn mime(self) -> &'static str {
+        match self {
+            Json::Single => "application/json",
+            Json::NewlineDelimited => "application/x-ndjson",
+        }
+    }
+}
+
+/// Parser state: position and whether an object or a non empty array was
+/// seen, plain values alone are not reported as JSON
+struct Parser<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    structured: bool,
+}
+
+/// Whether `buf` is JSON text
+pub fn check(buf: &[u8]) -> Option<Json> {
+    let mut parser = Parser {
+        buf,
+        pos: 0,
+        structured: false,
+    };
+    let mut values = 0;
+    loop {
+        let newline = parser.skip_blanks();
+        if parser.pos == buf.len() {
+            break;
+        }
+        if values > 0 && !newline {
+            return None;
+        }
+        parser.value(0)?;
+        values += 1;
+    }
+    match (values, parser.structured) {
+        (0, _) | (_, false) => None,
+        (1, _) => Some(Json::Single),
+        _ => Some(Json::NewlineDelimited),
+    }
+}
+
+impl<'a> Parser<'a> {
+    /// Skip the white space, `true` when it contains a new line
+    fn skip_blanks(&mut self) -> bool {
+        let mut newline = false;
+        while let Some(&c) = self.buf.get(self.pos) {
+            match c {
+                b'\n' => newline = true,
+                b' ' | b'\t' | b'\r' => {}
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        newline
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.buf.get(self.pos).copied()
+    }
+
+    fn value(&mut self, depth: usize) -> Option<()> {
+        // Deeply nested data is not worth the stack
+        if depth > 64 {
+            return None;
+        }
+        self.skip_blanks();
+        match self.peek()? {
+            b'{' => self.object(depth),
+            b'[' => self.array(depth),
+            b'"' => self.string(),
+            b't' => self.literal(b"true"),
+            b'f' => self.literal(b"false"),
+            b'n' => self.literal(b"null"),
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => None,
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Option<()> {
+        self.pos += 1;
+        self.structured = true;
+        self.skip_blanks();
+        if self.peek()? == b'}' {
+            self.pos += 1;
+            return Some(());
+        }
+        loop {
+            self.skip_blanks();
+            if self.peek()? != b'"' {
+                return None;
+            }
+            self.string()?;
+            self.skip_blanks();
+            if self.peek()? != b':' {
+                return None;
+            }
+            self.pos += 1;
+            self.value(depth + 1)?;
+            self.skip_blanks();
+            match self.peek()? {
+                b',' => self.pos += 1,
+                b'}' => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Option<()> {
+        self.pos += 1;
+        self.skip_blanks();
+        if self.peek()? == b']' {
+            self.pos += 1;
+            return Some(());
+        }
+        self.structured = true;
+        loop {
+            self.value(depth + 1)?;
+            self.skip_blanks();
+            match self.peek()? {
+                b',' => self.pos += 1,
+                b']' => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<()> {
+        self.pos += 1;
+        loop {
+            let c = self.peek()?;
+            self.pos += 1;
+            match c {
+                b'"' => return Some(()),
+                b'\\' => {
+                    match self.peek()? {
+                        b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => {}
+                        b'u' => {
+                            let digits = self.buf.get(self.pos + 1..self.pos + 5)?;
+                            if !digits.iter().all(u8::is_ascii_hexdigit) {
+                                return None;
+                            }
+                            self.pos += 4;
+                        }
+                        _ => return None,
+                    }
+                    self.pos += 1;
+                }
+                0..=0x1f => return None,
+                _ => {}
+            }
+        }
+    }
+
+    fn literal(&mut self, word: &[u8]) -> Option<()> {
+        if self.buf[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn number(&mut self) -> Option<()> {
+        let digits = |parser: &mut Self| {
+            let start = parser.pos;
+            while matches!(parser.peek(), Some(b'0'..=b'9')) {
+                parser.pos += 1;
+            }
+            parser.pos > start
+        };
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'0') {
+            self.pos += 1;
+        } else if !digits(self) {
+            return None;
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if !digits(self) {
+                return None;
+            }
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return None;
+            }
+        }
+        Some(())
+    }
+}
@@ -0,0 +1,1131 @@
+//! This file is part of the easybox package.
+//
+// (c) Zhihua Zhao <YuukaC@outlook.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+//! Matching of the magic tests against the contents of a file.
+
+use super::apprentice::{Database, Endian, Entry, Kind, Relation, Value};
+use crate::file_unsafe::broken_down_time;
+
+/// Longest string read for `%s` when the test does not give its length
+const MAXSTRING: usize = 127;
+
+/// Limits of the matching, set with `-P`
+#[derive(Clone, Copy)]
+pub struct Limits {
+    /// nested `indirect` tests
+    pub indir: usize,
+    /// nested `use` tests
+    pub name: usize,
+    /// bytes searched by `regex`
+    pub regex: usize,
+}
+
+/// Result of the magic tests
+#[derive(Default, Debug)]
+pub struct Found {
+    /// description, like `RPM v3.0 bin`
+    pub desc: String,
+    /// first `!:mime` of the matched lines
+    pub mime: Option<String>,
+    /// first `!:ext` of the matched lines
+    pub ext: Option<String>,
+    /// first `!:apple` of the matched lines
+    pub apple: Option<String>,
+}
+
+/// Value read from the file, printed in the message
+enum Arg {
+    None,
+    Int { value: u64, size: usize },
+    Float(f64),
+    Str(Vec<u8>),
+}
+
+/// A line that matched: its value and where the next relative offset starts
+struct Matched {
+    end: usize,
+    arg: Arg,
+}
+
+/// `got_match` and `off` of libmagic, for one level of continuation
+#[derive(Clone, Copy, Default)]
+struct Level {
+    end: usize,
+    got_match: bool,
+}
+
+/// Output shared by a test and the named tests it uses
+struct Output<'f> {
+    found: &'f mut Found,
+    need_separator: bool,
+    printed_something: bool,
+}
+
+/// Run the tests of a database on a buffer
+pub struct Matcher<'a> {
+    ///
+    pub db: &'a Database,
+    /// report all the matches, not only the first (`-k`)
+    pub keep_going: bool,
+    ///
+    pub limits: Limits,
+}
+
+impl<'a> Matcher<'a> {
+    /// Run the binary tests, or the text tests when `text_pass`, on `buf`.
+    /// `looks_text` skips the string tests restricted to the other kind.
+    pub fn run(&self, buf: &[u8], text_pass: bool, looks_text: bool) -> Option<Found> {
+        self.run_nested(buf, text_pass, looks_text, 0)
+    }
+
+    fn run_nested(
+        &self,
+        buf: &[u8],
+        text_pass: bool,
+        looks_text: bool,
+        indir: usize,
+    ) -> Option<Found> {
+        let mut found = Found::default();
+        let mut any = false;
+        let mut firstline = true;
+        for group in &self.db.groups {
+            if (text_pass && !group.text) || (!text_pass && !group.binary) {
+                continue;
+            }
+            let flags = group.entries[0].flags;
+            if group.entries[0].kind.is_string()
+                && ((looks_text && flags.binary && !flags.text)
+                    || (!looks_text && flags.text && !flags.binary))
+            {
+                continue;
+            }
+            let mut out = Output {
+                found: &mut found,
+                need_separator: false,
+                printed_something: false,
+            };
+            let matched = self.run_group(
+                &group.entries,
+                buf,
+                0,
+                false,
+                &mut out,
+                Some(firstline),
+                indir,
+                0,
+            );
+            if out.printed_something {
+                firstline = false;
+            }
+            if matched {
+                any = true;
+                if !self.keep_going {
+                    break;
+                }
+            }
+        }
+        if any {
+            Some(found)
+        } else {
+            None
+        }
+    }
+
+    /// Match a test and its continuations, `firstline` is `None` inside a
+    /// named test. Returns whether something matched.
+    #[allow(clippy::too_many_arguments)]
+    fn run_group(
+        &self,
+        entries: &[Entry],
+        buf: &[u8],
+        base: usize,
+        flip: bool,
+        out: &mut Output,
+        firstline: Option<bool>,
+        indir: usize,
+        names: usize,
+    ) -> bool {
+        let top = &entries[0];
+        let mut found_match = false;
+        let mut levels = vec![Level::default(); 2];
+
+        let end = if top.kind == Kind::Name {
+            base
+        } else {
+            let offset = match self.offset(top, buf, base, 0, flip) {
+                Some(offset) => offset,
+                None => return false,
+            };
+            match self.evaluate(top, buf, offset, flip) {
+                Some(matched) => {
+                    if !top.message.is_empty() {
+                        found_match = true;
+                        if firstline == Some(false) {
+                            out.found.desc.push_str("\n- ");
+                        }
+                        out.need_separator = true;
+                        out.printed_something = true;
+                        self.print(top, &matched.arg, out);
+                    }
+                    annotate(top, out.found);
+                    matched.end
+                }
+                None => return false,
+            }
+        };
+        levels[0].end = end;
+
+        let mut cont_level = 1;
+        for entry in &entries[1..] {
+            if entry.level > cont_level {
+                continue;
+            }
+            cont_level = entry.level;
+            let parent_end = levels[cont_level - 1].end;
+            let offset = match self.offset(entry, buf, base, parent_end, flip) {
+                Some(offset) => offset,
+                None => continue,
+            };
+
+            let matched = match entry.kind {
+                Kind::Use { flip: swap } => {
+                    if names >= self.limits.name {
+                        continue;
+                    }
+                    let name = match &entry.value {
+                        Value::Name(name) => name,
+                        _ => continue,
+                    };
+                    let group = match self.db.names.get(name) {
+                        Some(group) => group,
+                        None => continue,
+                    };
+                    let saved = out.need_separator;
+                    if entry.nospace {
+                        out.need_separator = false;
+                    }
+                    if !self.run_group(
+                        &group.entries,
+                        buf,
+                        offset,
+                        flip ^ swap,
+                        out,
+                        None,
+                        indir,
+                        names + 1,
+                    ) {
+                        out.need_separator = saved;
+                        continue;
+                    }
+                    found_match = true;
+                    Matched {
+                        end: offset,
+                        arg: Arg::None,
+                    }
+                }
+                Kind::Indirect { relative } => {
+                    let offset = if relative {
+                        match offset.checked_add(parent_end) {
+                            Some(offset) => offset,
+                            None => continue,
+                        }
+                    } else {
+                        offset
+                    };
+                    if offset == 0 || offset >= buf.len() || indir >= self.limits.indir {
+                        continue;
+                    }
+                    let sub = match self.run_nested(&buf[offset..], false, false, indir + 1) {
+                        Some(sub) => sub,
+                        None => continue,
+                    };
+                    found_match = true;
+                    if out.need_separator && !entry.nospace && !entry.message.is_empty() {
+                        out.found.desc.push(' ');
+                    }
+                    let arg = Arg::Int {
+                        value: offset as u64,
+                        size: 4,
+                    };
+                    out.found
+                        .desc
+                        .push_str(&format_message(&entry.message, &arg));
+                    out.found.desc.push_str(&sub.desc);
+                    out.need_separator = true;
+                    levels[cont_level].end = offset;
+                    cont_level += 1;
+                    reset_level(&mut levels, cont_level);
+                    continue;
+                }
+                _ => match self.evaluate(entry, buf, offset, flip) {
+                    Some(matched) => matched,
+                    None => continue,
+                },
+            };
+
+            let level = &mut levels[cont_level];
+            if entry.kind == Kind::Clear {
+                level.got_match = false;
+            } else if level.got_match {
+                if entry.kind == Kind::Default {
+                    continue;
+                }
+            } else {
+                level.got_match = true;
+            }
+
+            found_match = true;
+            if !entry.message.is_empty() {
+                if !out.printed_something {
+                    out.printed_something = true;
+                    if firstline == Some(false) {
+                        out.found.desc.push_str("\n- ");
+                    }
+                }
+                if out.need_separator && !entry.nospace {
+                    out.found.desc.push(' ');
+                    out.need_separator = false;
+                }
+                self.print(entry, &matched.arg, out);
+                out.need_separator = true;
+            }
+            annotate(entry, out.found);
+
+            levels[cont_level].end = matched.end;
+            cont_level += 1;
+            reset_level(&mut levels, cont_level);
+        }
+        found_match
+    }
+
+    /// Offset of the value tested by `entry`, `None` when it is outside of
+    /// the buffer or overflows
+    fn offset(
+        &self,
+        entry: &Entry,
+        buf: &[u8],
+        base: usize,
+        parent_end: usize,
+        flip: bool,
+    ) -> Option<usize> {
+        let origin = |relative: bool| if relative { parent_end } else { base };
+        let address = |value: i64, relative: bool| -> Option<usize> {
+            if value < 0 && !relative {
+                buf.len().checked_sub(value.unsigned_abs() as usize)
+            } else {
+                let address = (origin(relative) as i64).checked_add(value)?;
+                usize::try_from(address).ok()
+            }
+        };
+
+        let indirect = match entry.offset.indirect {
+            Some(indirect) => indirect,
+            None => return address(entry.offset.value, entry.offset.relative),
+        };
+        let at = address(entry.offset.value, indirect.relative)?;
+        let endian = if flip {
+            indirect.endian.flip()
+        } else {
+            indirect.endian
+        };
+        let read = |at: usize| -> Option<u64> {
+            let value = read_int(buf, at, indirect.size, endian)?;
+            Some(if indirect.signed {
+                sign_extend(value, indirect.size)
+            } else {
+                value
+            })
+        };
+        let mut value = read(at)?;
+        if let Some((op, arg)) = indirect.op {
+            let arg = if indirect.op_indirect {
+                read(usize::try_from(arg).ok()?)?
+            } else {
+                arg as u64
+            };
+            value = op.apply(value, arg);
+        }
+        if indirect.id3 {
+            value = (value & 0x7f)
+                | ((value >> 8) & 0x7f) << 7
+                | ((value >> 16) & 0x7f) << 14
+                | ((value >> 24) & 0x7f) << 21;
+        }
+        let value = if entry.offset.relative {
+            (value as i64).checked_add(parent_end as i64)?
+        } else {
+            value as i64
+        };
+        usize::try_from(value).ok()
+    }
+
+    /// Read the value at `offset` and compare it with the line
+    fn evaluate(&self, entry: &Entry, buf: &[u8], offset: usize, flip: bool) -> Option<Matched> {
+        let kind = if flip { entry.kind.flip() } else { entry.kind };
+        match kind {
+            Kind::Int { size, endian } | Kind::Date { size, endian, .. } => {
+                let value = convert(entry, read_int(buf, offset, size, endian)?, size);
+                compare_int(entry, value, size).then(|| Matched {
+                    end: offset + size,
+                    arg: match kind {
+                        Kind::Date { local, .. } => Arg::Str(format_date(value, size, local)),
+                        _ => Arg::Int { value, size },
+                    },
+                })
+            }
+            Kind::Offset => {
+                let value = convert(entry, offset as u64, 8);
+                if !compare_int(entry, value, 8) {
+                    return None;
+                }
+                Some(Matched {
+                    end: offset,
+                    arg: Arg::Int { value, size: 8 },
+                })
+            }
+            Kind::Float { size, endian } => {
+                let bits = read_int(buf, offset, size, endian)?;
+                let value = if size == 4 {
+                    f64::from(f32::from_bits(bits as u32))
+                } else {
+                    f64::from_bits(bits)
+                };
+                let test = match entry.value {
+                    Value::Float(test) => test,
+                    _ => 0.0,
+                };
+                let matched = match entry.relation {
+                    Relation::Any => true,
+                    Relation::Eq => value == test,
+                    Relation::Ne => value != test,
+                    Relation::Lt => value < test,
+                    Relation::Gt => value > test,
+                    _ => false,
+                };
+                matched.then(|| Matched {
+                    end: offset + size,
+                    arg: Arg::Float(value),
+                })
+            }
+            Kind::String => self.string(entry, buf.get(offset..)?, offset, 0),
+            Kind::PString {
+                size,
+                endian,
+                includes_length,
+            } => {
+                let mut length = read_int(buf, offset, size, endian)? as usize;
+                if includes_length {
+                    length = length.saturating_sub(size);
+                }
+                let start = offset + size;
+                let data = buf.get(start..)?;
+                let data = &data[..length.min(data.len())];
+                self.string(entry, data, start, size)
+            }
+            Kind::String16 { endian } => {
+                let data = buf.get(offset..)?;
+                let chars = data
+                    .chunks_exact(2)
+                    .map(|unit| match endian {
+                        Endian::Big => (unit[0], unit[1]),
+                        _ => (unit[1], unit[0]),
+                    })
+                    .take_while(|&unit| unit != (0, 0))
+                    .map(|(high, low)| if high == 0 { low } else { 0xff })
+                    .collect::<Vec<_>>();
+                let mut matched = self.string(entry, &chars, offset, 0)?;
+                matched.end = offset + 2 * (matched.end - offset);
+                Some(matched)
+            }
+            Kind::Search => self.search(entry, buf, offset),
+            Kind::Regex => self.regex(entry, buf, offset),
+            Kind::Default | Kind::Clear | Kind::Name => Some(Matched {
+                end: offset,
+                arg: Arg::None,
+            }),
+            Kind::Use { .. } | Kind::Indirect { .. } => None,
+        }
+    }
+
+    /// Compare a string with the data at `offset`; `prefix` is the length of
+    /// a pstring length, counted in the next offset
+    fn string(&self, entry: &Entry, data: &[u8], offset: usize, prefix: usize) -> Option<Matched> {
+        let pattern = match &entry.value {
+            Value::Bytes(pattern) => pattern.as_slice(),
+            _ => &[],
+        };
+        let compared = match entry.relation {
+            Relation::Any => 0,
+            _ => compare_string(pattern, data, entry),
+        };
+        let matched = match entry.relation {
+            Relation::Any => true,
+            Relation::Eq => compared == 0,
+            Relation::Ne => compared != 0,
+            Relation::Lt => compared < 0,
+            Relation::Gt => compared > 0,
+            // Tested against a value of 0, like the numbers
+            Relation::And => true,
+            Relation::Xor => false,
+        };
+        if !matched {
+            return None;
+        }
+        if matches!(entry.relation, Relation::Eq | Relation::Ne) {
+            return Some(Matched {
+                end: offset + pattern.len(),
+                arg: Arg::Str(pattern.to_vec()),
+            });
+        }
+        let mut length = data
+            .iter()
+            .take(MAXSTRING)
+            .position(|&b| b == 0)
+            .unwrap_or_else(|| data.len().min(MAXSTRING));
+        if matches!(pattern.first(), None | Some(0)) {
+            if let Some(end) = data[..length]
+                .iter()
+                .position(|&b| b == b'\r' || b == b'\n')
+            {
+                length = end;
+            }
+        }
+        let mut text = &data[..length];
+        if entry.flags.trim {
+            while let [first, rest @ ..] = text {
+                if !first.is_ascii_whitespace() {
+                    break;
+                }
+                text = rest;
+            }
+            while let [rest @ .., last] = text {
+                if !last.is_ascii_whitespace() {
+                    break;
+                }
+                text = rest;
+            }
+        }
+        Some(Matched {
+            end: offset + length + prefix,
+            arg: Arg::Str(text.to_vec()),
+        })
+    }
+
+    fn search(&self, entry: &Entry, buf: &[u8], offset: usize) -> Option<Matched> {
+        let pattern = match &entry.value {
+            Value::Bytes(pattern) => pattern.as_slice(),
+            _ => return None,
+        };
+        let data = buf.get(offset..)?;
+        let range = if entry.range == 0 {
+            data.len()
+        } else {
+            entry.range
+        };
+        let position = (0..range)
+            .take_while(|&i| i + pattern.len() <= data.len())
+            .find(|&i| compare_string(pattern, &data[i..], entry) == 0);
+        if !found_relation(entry.relation, position.is_some()) {
+            return None;
+        }
+        Some(Matched {
+            end: offset
+                + position.map_or(0, |i| i + if entry.flags.start { 0 } else { pattern.len() }),
+            arg: Arg::Str(pattern.to_vec()),
+        })
+    }
+
+    fn regex(&self, entry: &Entry, buf: &[u8], offset: usize) -> Option<Matched> {
+        let regex = match &entry.value {
+            Value::Regex(regex, _) => regex,
+            _ => return None,
+        };
+        let data = buf.get(offset..)?;
+        let mut length = if entry.flags.lines {
+            entry.range.saturating_mul(80)
+        } else {
+            entry.range
+        };
+        if length == 0 || length > data.len() {
+            length = data.len();
+        }
+        length = length.min(self.limits.regex);
+        let mut data = &data[..length];
+        if entry.flags.lines && entry.range > 0 {
+            if let Some((end, _)) = data
+                .iter()
+                .enumerate()
+                .filter(|(_, &b)| b == b'\n')
+                .nth(entry.range - 1)
+            {
+                data = &data[..end];
+            }
+        }
+        // The expression is run on a C string
+        if let Some(nul) = data.iter().position(|&b| b == 0) {
+            data = &data[..nul];
+        }
+        let found = regex.find(data);
+        if !found_relation(entry.relation, found.is_some()) {
+            return None;
+        }
+        Some(match found {
+            Some(m) => Matched {
+                end: offset
+                    + if entry.flags.start {
+                        m.start()
+                    } else {
+                        m.end()
+                    },
+                arg: Arg::Str(m.as_bytes().to_vec()),
+            },
+            None => Matched {
+                end: offset,
+                arg: Arg::None,
+            },
+        })
+    }
+
+    fn print(&self, entry: &Entry, arg: &Arg, out: &mut Output) {
+        out.found
+            .desc
+            .push_str(&format_message(&entry.message, arg));
+    }
+}
+
+/// Whether a `search` or `regex` line matches: like libmagic, the result
+/// is compared as 0 when found and 1 otherwise
+fn found_relation(relation: Relation, found: bool) -> bool {
+    match relation {
+        Relation::Eq => found,
+        Relation::Ne | Relation::Gt => !found,
+        Relation::Any | Relation::And => true,
+        Relation::Lt | Relation::Xor => false,
+    }
+}
+
+fn reset_level(levels: &mut Vec<Level>, level: usize) {
+    if levels.len() <= level {
+        levels.resize(level + 1, Level::default());
+    }
+    levels[level] = Level::default();
+}
+
+/// Keep the first MIME type, extension and Apple type of the matched lines
+fn annotate(entry: &Entry, found: &mut Found) {
+    if found.mime.is_none() {
+        found.mime = entry.mime.clone();
+    }
+    if found.ext.is_none() {
+        found.ext = entry.ext.clone();
+    }
+    if found.apple.is_none() {
+        found.apple = entry.apple.clone();
+    }
+}
+
+/// Read an unsigned number of `size` bytes
+fn read_int(buf: &[u8], offset: usize, size: usize, endian: Endian) -> Option<u64> {
+    let bytes = buf.get(offset..offset.checked_add(size)?)?;
+    Some(match endian {
+        Endian::Big => bytes.iter().fold(0, |acc, &b| (acc << 8) | u64::from(b)),
+        Endian::Little => bytes
+            .iter()
+            .rev()
+            .fold(0, |acc, &b| (acc << 8) | u64::from(b)),
+        Endian::Middle => {
+            (u64::from(bytes[1]) << 24)
+                | (u64::from(bytes[0]) << 16)
+                | (u64::from(bytes[3]) << 8)
+                | u64::from(bytes[2])
+        }
+    })
+}
+
+fn width_mask(size: usize) -> u64 {
+    if size >= 8 {
+        u64::MAX
+    } else {
+        (1 << (size * 8)) - 1
+    }
+}
+
+fn sign_extend(value: u64, size: usize) -> u64 {
+    if size >= 8 {
+        return value;
+    }
+    let shift = 64 - size * 8;
+    (((value << shift) as i64) >> shift) as u64
+}
+
+/// Apply the mask of the line to the value read
+fn convert(entry: &Entry, value: u64, size: usize) -> u64 {
+    let mut value = value;
+    if let Some((op, mask)) = entry.mask {
+        value = op.apply(value, mask);
+    }
+    if entry.inverse {
+        value = !value;
+    }
+    value & width_mask(size)
+}
+
+fn compare_int(entry: &Entry, value: u64, size: usize) -> bool {
+    let test = match entry.value {
+        Value::Int(test) => test,
+        _ => return entry.relation == Relation::Any,
+    };
+    let (value, test) = if entry.unsigned {
+        (value, test)
+    } else {
+        (sign_extend(value, size), sign_extend(test, size))
+    };
+    match entry.relation {
+        Relation::Any => true,
+        Relation::Eq => value == test,
+        Relation::Ne => value != test,
+        Relation::Lt if entry.unsigned => value < test,
+        Relation::Gt if entry.unsigned => value > test,
+        Relation::Lt => (value as i64) < test as i64,
+        Relation::Gt => (value as i64) > test as i64,
+        Relation::And => value & test == test,
+        Relation::Xor => value & test != test,
+    }
+}
+
+/// Compare a pattern with data the way libmagic's `file_strncmp` does:
+/// the difference of the first bytes that differ, data past the end reads
+/// as NUL
+fn compare_string(pattern: &[u8], data: &[u8], entry: &Entry) -> i32 {
+    let flags = entry.flags;
+    let at = |i: usize| data.get(i).copied().unwrap_or(0);
+    let mut b = 0;
+    let mut a = 0;
+    while a < pattern.len() {
+        let p = pattern[a];
+        a += 1;
+        if flags.lower && p.is_ascii_lowercase() {
+            let v = i32::from(at(b).to_ascii_lowercase()) - i32::from(p);
+            b += 1;
+            if v != 0 {
+                return v;
+            }
+        } else if flags.upper && p.is_ascii_uppercase() {
+            let v = i32::from(at(b).to_ascii_uppercase()) - i32::from(p);
+            b += 1;
+            if v != 0 {
+                return v;
+            }
+        } else if flags.compact_blank && p.is_ascii_whitespace() {
+            if !at(b).is_ascii_whitespace() {
+                return 1;
+            }
+            b += 1;
+            if !matches!(pattern.get(a), Some(c) if c.is_ascii_whitespace()) {
+                while b < data.len() && data[b].is_ascii_whitespace() {
+                    b += 1;
+                }
+            }
+        } else if flags.optional_blank && p.is_ascii_whitespace() {
+            while b < data.len() && data[b].is_ascii_whitespace() {
+                b += 1;
+            }
+        } else {
+            let v = i32::from(at(b)) - i32::from(p);
+            b += 1;
+            if v != 0 {
+                return v;
+            }
+        }
+    }
+    if flags.full_word && b < data.len() && data[b] != 0 && !data[b].is_ascii_whitespace() {
+        return 1;
+    }
+    0
+}
+
+/// Date in the format of `asctime`, in UTC or in local time
+fn format_date(value: u64, size: usize, local: bool) -> Vec<u8> {
+    const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let time = if size == 8 {
+        value as i64
+    } else {
+        value as i64 & 0xffff_ffff
+    };
+    match broken_down_time(time, local) {
+        Some(tm) => format!(
+            "{} {} {:2} {:02}:{:02}:{:02} {}",
+            DAYS[tm.tm_wday.rem_euclid(7) as usize],
+            MONTHS[tm.tm_mon.rem_euclid(12) as usize],
+            tm.tm_mday,
+            tm.tm_hour,
+            tm.tm_min,
+            tm.tm_sec,
+            i64::from(tm.tm_year) + 1900
+        )
+        .into_bytes(),
+        None => b"*Invalid time*".to_vec(),
+    }
+}
+
+/// Bytes as `file -c` shows string values
+pub fn show_bytes(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for &b in bytes {
+        match b {
+            0x20..=0x7e => out.push(b as char),
+            7 => out.push_str("\\a"),
+            8 => out.push_str("\\b"),
+            12 => out.push_str("\\f"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            11 => out.push_str("\\v"),
+            _ => out.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    out
+}
+
+/// String read from the file, with its unprintable bytes in octal
+fn printable(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for &b in bytes {
+        if b == b' ' || b.is_ascii_graphic() {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("\\{:03o}", b));
+        }
+    }
+    out
+}
+
+/// Conversion of a printf format
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+/// Expand the printf conversion of a message with the value read
+fn format_message(message: &str, arg: &Arg) -> String {
+    let mut out = String::new();
+    let mut chars = message.chars().peekable();
+    let mut used = false;
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            out.push('%');
+            continue;
+        }
+        let mut spec = Spec {
+            left: false,
+            plus: false,
+            space: false,
+            alternate: false,
+            zero: false,
+            width: 0,
+            precision: None,
+            conversion: 's',
+        };
+        let mut source = String::from("%");
+        while let Some(&c) = chars.peek() {
+            match c {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alternate = true,
+                '0' => spec.zero = true,
+                _ => break,
+            }
+            source.push(c);
+            chars.next();
+        }
+        while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+            spec.width = spec.width * 10 + d as usize;
+            source.push(chars.next().unwrap_or_default());
+        }
+        if chars.peek() == Some(&'.') {
+            source.push(chars.next().unwrap_or_default());
+            let mut precision = 0;
+            while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+                precision = precision * 10 + d as usize;
+                source.push(chars.next().unwrap_or_default());
+            }
+            spec.precision = Some(precision);
+        }
+        while let Some(&c) = chars.peek() {
+            if !matches!(c, 'h' | 'l' | 'q' | 'j' | 'z' | 't' | 'L') {
+                break;
+            }
+            source.push(c);
+            chars.next();
+        }
+        match chars.next() {
+            Some(c) if !used => {
+                spec.conversion = c;
+                used = true;
+                out.push_str(&format_arg(&spec, arg));
+            }
+            Some(c) => {
+                out.push_str(&source);
+                out.push(c);
+            }
+            None => out.push_str(&source),
+        }
+    }
+    out
+}
+
+fn format_arg(spec: &Spec, arg: &Arg) -> String {
+    match (arg, spec.conversion) {
+        (Arg::Int { value, size }, conversion) => {
+            let signed = match size {
+                1 | 2 => *value as i64,
+                4 => i64::from(*value as u32 as i32),
+                _ => *value as i64,
+            };
+            let unsigned = if *size <= 4 {
+                *value & 0xffff_ffff
+            } else {
+                *value
+            };
+            match conversion {
+                'c' => pad(spec, (unsigned as u8 as char).to_string(), false),
+                'u' => format_int(spec, unsigned.to_string(), "", false),
+                'x' => format_int(spec, format!("{:x}", unsigned), "0x", unsigned != 0),
+                'X' => format_int(spec, format!("{:X}", unsigned), "0X", unsigned != 0),
+                'o' => format_int(spec, format!("{:o}", unsigned), "0", unsigned != 0),
+                'e' | 'E' | 'f' | 'F' | 'g' | 'G' => format_float(spec, signed as f64),
+                _ => {
+                    let sign = if signed < 0 {
+                        "-"
+                    } else if spec.plus {
+                        "+"
+                    } else if spec.space {
+                        " "
+                    } else {
+                        ""
+                    };
+                    format_int(spec, signed.unsigned_abs().to_string(), sign, true)
+                }
+            }
+        }
+        (Arg::Float(value), _) => format_float(spec, *value),
+        (Arg::Str(bytes), _) => {
+            let mut text = printable(bytes);
+            if let Some(precision) = spec.precision {
+                text = text.chars().take(precision).collect();
+            }
+            pad(spec, text, false)
+        }
+        (Arg::None, _) => String::new(),
+    }
+}
+
+/// Integer digits with their precision, prefix and width. For signed
+/// conversions `prefix` is the sign, otherwise it is the `#` prefix.
+fn format_int(spec: &Spec, digits: String, prefix: &str, apply_prefix: bool) -> String {
+    let signed = matches!(spec.conversion, 'd' | 'i' | 's');
+    let mut digits = digits;
+    if let Some(precision) = spec.precision {
+        if precision == 0 && digits == "0" {
+            digits.clear();
+        }
+        if digits.len() < precision {
+            digits = format!("{}{}", "0".repeat(precision - digits.len()), digits);
+        }
+    }
+    let prefix = if signed || (spec.alternate && apply_prefix) {
+        prefix
+    } else {
+        ""
+    };
+    if prefix == "0" && digits.starts_with('0') {
+        return pad(spec, digits, spec.precision.is_none());
+    }
+    if spec.zero && !spec.left && spec.precision.is_none() && spec.width > prefix.len() {
+        let width = spec.width - prefix.len();
+        if digits.len() < width {
+            digits = format!("{}{}", "0".repeat(width - digits.len()), digits);
+        }
+    }
+    pad(spec, format!("{}{}", prefix, digits), false)
+}
+
+fn format_float(spec: &Spec, value: f64) -> String {
+    let precision = spec.precision.unwrap_or(6);
+    let text = match spec.conversion {
+        'e' | 'E' => exponent(value, precision, spec.conversion == 'E'),
+        'g' | 'G' => {
+            let precision = precision.max(1);
+            let exp = if value == 0.0 {
+                0
+            } else {
+                value.abs().log10().floor() as i32
+            };
+            let mut text = if exp < -4 || exp >= precision as i32 {
+                exponent(value, precision - 1, spec.conversion == 'G')
+            } else {
+                format!("{:.*}", (precision as i32 - 1 - exp).max(0) as usize, value)
+            };
+            if !spec.alternate {
+                // Trailing zeros of the fraction are dropped
+                let (mantissa, exp) = match text.find(['e', 'E']) {
+                    Some(i) => (text[..i].to_string(), text[i..].to_string()),
+                    None => (text.clone(), String::new()),
+                };
+                let mantissa = if mantissa.contains('.') {
+                    mantissa
+                        .trim_end_matches('0')
+                        .trim_end_matches('.')
+                        .to_string()
+                } else {
+                    mantissa
+                };
+                text = mantissa + &exp;
+            }
+            text
+        }
+        _ => format!("{:.*}", precision, value),
+    };
+    let text = if value >= 0.0 && spec.plus {
+        format!("+{}", text)
+    } else {
+        text
+    };
+    pad(spec, text, spec.zero)
+}
+
+/// `value` in the `%e` format of C
+fn exponent(value: f64, precision: usize, upper: bool) -> String {
+    let text = format!("{:.*e}", precision, value);
+    let (mantissa, exp) = text.split_once('e').unwrap_or((&text, "0"));
+    let exp = exp.parse::<i32>().unwrap_or(0);
+    format!(
+        "{}{}{}{:02}",
+        mantissa,
+        if upper { 'E' } else { 'e' },
+        if exp < 0 { '-' } else { '+' },
+        exp.abs()
+    )
+}
+
+fn pad(spec: &Spec, text: String, zero: bool) -> String {
+    let length = text.chars().count();
+    if length >= spec.width {
+        return text;
+    }
+    let fill = spec.width - length;
+    if spec.left {
+        format!("{}{}", text, " ".repeat(fill))
+    } else if zero {
+        format!("{}{}", "0".repeat(fill), text)
+    } else {
+        format!("{}{}", " ".repeat(fill), text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Description of `buf` by the binary tests of `magic`, then by its
+    /// text tests
+    fn describe(magic: &str, buf: &[u8]) -> Option<String> {
+        let mut db = Database::default();
+        let mut warnings = Vec::new();
+        assert!(db.parse(magic, "test", &mut warnings), "{:?}", warnings);
+        db.finish();
+        let matcher = Matcher {
+            db: &db,
+            keep_going: false,
+            limits: Limits {
+                indir: 15,
+                name: 30,
+                regex: 8192,
+            },
+        };
+        matcher
+            .run(buf, false, true)
+            .or_else(|| matcher.run(buf, true, true))
+            .map(|found| found.desc)
+    }
+
+    #[test]
+    fn test_numbers_and_endianness() {
+        let magic = "0\tbeshort\t0x1234\tbig\n>2\tlelong\tx\t\\b, %d\n>2\tbyte\t>4\t\\b, high\n";
+        assert_eq!(
+            describe(magic, b"\x12\x34\x05\x00\x00\x00").as_deref(),
+            Some("big, 5, high")
+        );
+        assert_eq!(describe(magic, b"\x34\x12\x05\x00\x00\x00"), None);
+    }
+
+    #[test]
+    fn test_strings_and_relative_offsets() {
+        let magic = "0\tstring\tAB\tab\n>&1\tbyte\tx\t\\b, %c\n>>&0\tstring\tx\t\\b, %s\n";
+        assert_eq!(
+            describe(magic, b"AB-Zrest\0").as_deref(),
+            Some("ab, Z, rest")
+        );
+    }
+
+    #[test]
+    fn test_search_and_regex() {
+        let magic = "0\tsearch/16\tneedle\tfound\n>&0\tregex\t[0-9]+\t\\b, %s\n";
+        assert_eq!(
+            describe(magic, b"hay hay needle 42 hay").as_deref(),
+            Some("found, 42")
+        );
+        assert_eq!(describe(magic, b"hay hay hay hay hay needle"), None);
+    }
+
+    #[test]
+    fn test_indirect_offsets_and_names() {
+        let magic =
+            "0\tstring\tIX\tix\n>(2.b+1)\tuse\tpart\n\n0\tname\tpart\n>0\tbyte\tx\t\\b, part %d\n";
+        assert_eq!(
+            describe(magic, b"IX\x03\x00\x07").as_deref(),
+            Some("ix, part 7")
+        );
+    }
+
+    #[test]
+    fn test_default_and_clear() {
+        let magic = "0\tstring\tDC\tdc\n>2\tbyte\t1\t\\b, one\n>2\tdefault\tx\t\\b, other\n";
+        assert_eq!(describe(magic, b"DC\x01").as_deref(), Some("dc, one"));
+        assert_eq!(describe(magic, b"DC\x02").as_deref(), Some("dc, other"));
+    }
+
+    #[test]
+    fn test_offset_overflow_is_no_match() {
+        let data = b"EBX\0\xff\xff\xff\xff\xff\xff\xff\x7f";
+        let magic = "0\tstring\tEBX\\0\ttest\n>&(4.q)\tbyte\tx\t\\b, %d\n";
+        assert_eq!(describe(magic, data).as_deref(), Some("test"));
+        let magic = "0\tstring\tEBX\\0\ttest\n>&0x7fffffffffffffff\tbyte\tx\t\\b, %d\n";
+        assert_eq!(describe(magic, data).as_deref(), Some("test"));
+    }
+
+    #[test]
+    fn test_regex_line_count_overflow() {
+        let magic = "0\tregex/1000000000000000000l\tfoo\tfoo\n";
+        assert_eq!(describe(magic, b"a\nfoo\n").as_deref(), Some("foo"));
+    }
+}
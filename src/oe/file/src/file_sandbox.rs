@@ -0,0 +1,257 @@
+//! This file is part of the easybox package.
+//
+// (c) Zhihua Zhao <YuukaC@outlook.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+//! seccomp-BPF sandbox installed once the magic database is loaded and
+//! before any input is read, as upstream `file` does. Only the system calls
+//! needed to read files, allocate memory, write the output and run the
+//! `--jobs` threads are allowed; any other one kills the process with
+//! `SIGSYS`.
+
+use std::io;
+
+use crate::file_unsafe::{install_seccomp_filter, no_new_privs};
+use libc::sock_filter;
+
+// Classic BPF opcodes, from linux/bpf_common.h
+/// `BPF_LD | BPF_W | BPF_ABS`
+const BPF_LD_W_ABS: u16 = 0x20;
+/// `BPF_ALU | BPF_AND | BPF_K`
+const BPF_ALU_AND_K: u16 = 0x54;
+/// `BPF_JMP | BPF_JEQ | BPF_K`
+const BPF_JMP_JEQ_K: u16 = 0x15;
+/// `BPF_JMP | BPF_JGE | BPF_K`
+const BPF_JMP_JGE_K: u16 = 0x35;
+/// `BPF_JMP | BPF_JSET | BPF_K`
+const BPF_JMP_JSET_K: u16 = 0x45;
+/// `BPF_RET | BPF_K`
+const BPF_RET_K: u16 = 0x06;
+
+/// Offsets in `struct seccomp_data`
+const DATA_NR: u32 = 0;
+const DATA_ARCH: u32 = 4;
+const DATA_ARG0: u32 = 16;
+const DATA_ARG1: u32 = 24;
+const DATA_ARG2: u32 = 32;
+
+/// `AUDIT_ARCH_*` of the architectures the allow-list is written for
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+#[cfg(target_arch = "riscv64")]
+const AUDIT_ARCH: u32 = 0xc000_00f3;
+#[cfg(target_arch = "loongarch64")]
+const AUDIT_ARCH: u32 = 0xc000_0102;
+
+/// System calls of the x32 ABI, which shares the x86_64 `AUDIT_ARCH`
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// `ioctl` requests about the terminal and pending input
+const IOCTL_ALLOWED: [u32; 3] = [
+    libc::FIONREAD as u32,
+    libc::TCGETS as u32,
+    libc::TIOCGWINSZ as u32,
+];
+
+/// `prctl(PR_SET_VMA, ...)`, with which the C library names its mappings
+const PR_SET_VMA: u32 = 0x5356_4d41;
+
+/// Bits of the `open` flags that must be clear: files are only opened for
+/// reading, never created or truncated
+const OPEN_FLAGS_CHECKED: u32 = (libc::O_ACCMODE | libc::O_CREAT | libc::O_TRUNC) as u32;
+
+/// System calls allowed without looking at their arguments
+const ALLOWED: &[libc::c_long] = &[
+    libc::SYS_brk,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_close,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_faccessat,
+    libc::SYS_fcntl,
+    libc::SYS_futex,
+    libc::SYS_getdents64,
+    libc::SYS_getpid,
+    libc::SYS_getrandom,
+    libc::SYS_gettid,
+    libc::SYS_lseek,
+    libc::SYS_madvise,
+    libc::SYS_mmap,
+    libc::SYS_mprotect,
+    libc::SYS_mremap,
+    libc::SYS_munmap,
+    libc::SYS_nanosleep,
+    libc::SYS_pread64,
+    libc::SYS_prlimit64,
+    libc::SYS_read,
+    libc::SYS_readlinkat,
+    libc::SYS_readv,
+    libc::SYS_rseq,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_sched_yield,
+    libc::SYS_set_robust_list,
+    libc::SYS_sigaltstack,
+    libc::SYS_statx,
+    libc::SYS_sysinfo,
+    libc::SYS_umask,
+    // -p restores the access time of the files read
+    libc::SYS_utimensat,
+    libc::SYS_write,
+    libc::SYS_writev,
+    #[cfg(not(target_arch = "loongarch64"))]
+    libc::SYS_fstat,
+    #[cfg(not(target_arch = "loongarch64"))]
+    libc::SYS_newfstatat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_access,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_dup2,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_getdents,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_lstat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_readlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_select,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_stat,
+    // -p with a C library that still uses utimes(2)
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_utimes,
+];
+
+fn stmt(code: u16, k: u32) -> sock_filter {
+    sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter { code, jt, jf, k }
+}
+
+/// Allow system call `nr` when the `mask` bits of its argument at `arg` are
+/// one of `values`, kill the process otherwise
+fn allow_values(
+    program: &mut Vec<sock_filter>,
+    nr: libc::c_long,
+    arg: u32,
+    mask: u32,
+    values: &[u32],
+) {
+    let count = values.len() as u8;
+    program.push(jump(BPF_JMP_JEQ_K, nr as u32, 0, count + 4));
+    program.push(stmt(BPF_LD_W_ABS, arg));
+    program.push(stmt(BPF_ALU_AND_K, mask));
+    for (i, &value) in values.iter().enumerate() {
+        program.push(jump(BPF_JMP_JEQ_K, value, count - i as u8, 0));
+    }
+    program.push(stmt(BPF_RET_K, libc::SECCOMP_RET_KILL_PROCESS));
+    program.push(stmt(BPF_RET_K, libc::SECCOMP_RET_ALLOW));
+    // The next check expects the system call number
+    program.push(stmt(BPF_LD_W_ABS, DATA_NR));
+}
+
+/// The filter program. It checks the architecture first, as system call
+/// numbers differ between ABIs.
+#[cfg(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64",
+    target_arch = "loongarch64"
+))]
+fn filter() -> Vec<sock_filter> {
+    let kill = libc::SECCOMP_RET_KILL_PROCESS;
+    let allow = libc::SECCOMP_RET_ALLOW;
+    let mut program = vec![
+        stmt(BPF_LD_W_ABS, DATA_ARCH),
+        jump(BPF_JMP_JEQ_K, AUDIT_ARCH, 1, 0),
+        stmt(BPF_RET_K, kill),
+        stmt(BPF_LD_W_ABS, DATA_NR),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    program.extend([
+        jump(BPF_JMP_JGE_K, X32_SYSCALL_BIT, 0, 1),
+        stmt(BPF_RET_K, kill),
+    ]);
+
+    for &nr in ALLOWED {
+        program.push(jump(BPF_JMP_JEQ_K, nr as u32, 0, 1));
+        program.push(stmt(BPF_RET_K, allow));
+    }
+
+    // Threads only: a new process could run anything the filter does not
+    // see. clone3 passes its flags in memory, out of reach of the filter,
+    // so it fails with ENOSYS and the C library falls back to clone.
+    program.extend([
+        jump(BPF_JMP_JEQ_K, libc::SYS_clone3 as u32, 0, 1),
+        stmt(BPF_RET_K, libc::SECCOMP_RET_ERRNO | libc::ENOSYS as u32),
+        jump(BPF_JMP_JEQ_K, libc::SYS_clone as u32, 0, 4),
+        stmt(BPF_LD_W_ABS, DATA_ARG0),
+        jump(BPF_JMP_JSET_K, libc::CLONE_THREAD as u32, 0, 1),
+        stmt(BPF_RET_K, allow),
+        stmt(BPF_RET_K, kill),
+    ]);
+
+    allow_values(&mut program, libc::SYS_ioctl, DATA_ARG1, !0, &IOCTL_ALLOWED);
+    allow_values(&mut program, libc::SYS_prctl, DATA_ARG0, !0, &[PR_SET_VMA]);
+    let read_only = [libc::O_RDONLY as u32];
+    allow_values(
+        &mut program,
+        libc::SYS_openat,
+        DATA_ARG2,
+        OPEN_FLAGS_CHECKED,
+        &read_only,
+    );
+    #[cfg(target_arch = "x86_64")]
+    allow_values(
+        &mut program,
+        libc::SYS_open,
+        DATA_ARG1,
+        OPEN_FLAGS_CHECKED,
+        &read_only,
+    );
+
+    program.push(stmt(BPF_RET_K, kill));
+    program
+}
+
+/// Install the sandbox in the calling process, for all the threads created
+/// after it. It cannot be removed.
+#[cfg(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64",
+    target_arch = "loongarch64"
+))]
+pub fn enable_sandbox() -> io::Result<()> {
+    no_new_privs()?;
+    install_seccomp_filter(&filter())
+}
+
+/// Without an allow-list for the architecture, only new privileges are
+/// dropped
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64",
+    target_arch = "loongarch64"
+)))]
+pub fn enable_sandbox() -> io::Result<()> {
+    no_new_privs()
+}
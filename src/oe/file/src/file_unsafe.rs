@@ -29,11 +29,55 @@ pub fn is_wide_print(c: char) -> bool {
     unsafe { iswprint(c as libc::wchar_t) != 0 }
 }
 
-#[cfg(feature = "sandbox")]
-pub fn prctl() -> bool {
-    // prevent child processes from getting more priv e.g. via setuid, capabilities, ...
-    unsafe {
+/// Prevent the process and its children from gaining privileges, e.g. via
+/// setuid or capabilities, and from being dumped
+pub fn no_new_privs() -> std::io::Result<()> {
+    let ok = unsafe {
         libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != -1
             && libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) != -1
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Install a seccomp filter program in the calling thread
+pub fn install_seccomp_filter(filter: &[libc::sock_filter]) -> std::io::Result<()> {
+    let program = libc::sock_fprog {
+        len: filter.len() as libc::c_ushort,
+        filter: filter.as_ptr() as *mut libc::sock_filter,
+    };
+    let result = unsafe {
+        libc::prctl(
+            libc::PR_SET_SECCOMP,
+            libc::SECCOMP_MODE_FILTER,
+            &program as *const libc::sock_fprog,
+        )
+    };
+    if result == -1 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Broken-down time of `time`, in local time or in UTC
+#[cfg(feature = "native-magic")]
+pub fn broken_down_time(time: i64, local: bool) -> Option<libc::tm> {
+    let time = time as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let result = unsafe {
+        if local {
+            libc::localtime_r(&time, &mut tm)
+        } else {
+            libc::gmtime_r(&time, &mut tm)
+        }
+    };
+    if result.is_null() {
+        None
+    } else {
+        Some(tm)
     }
 }
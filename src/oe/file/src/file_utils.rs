@@ -12,23 +12,44 @@ pub fn isprint(c: u8) -> bool {
     c.is_ascii_graphic() || c == b' '
 }
 
-pub fn fname_print(name: &str) {
+pub fn fname_printable(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
     #[cfg(feature = "wide")]
     for c in name.chars() {
         if is_wide_print(c) {
-            print!("{}", c);
+            out.push(c);
         } else {
-            print!("\\u{:04x}", c as u32);
+            out.push_str(&format!("\\u{:04x}", c as u32));
         }
     }
     #[cfg(not(feature = "wide"))]
     for c in name.bytes() {
         if isprint(c) {
-            print!("{}", c as char);
+            out.push(c as char);
         } else {
-            print!("\\{:03o}", c);
+            out.push_str(&format!("\\{:03o}", c));
         }
     }
+    out
+}
+
+/// `s` as a JSON string, with its quotes
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
 }
 
 pub fn file_mbswidth(s: &str, raw: bool) -> usize {
@@ -48,113 +69,3 @@ pub fn file_mbswidth(s: &str, raw: bool) -> usize {
     s.bytes()
         .fold(0, |width, c| width + if raw || isprint(c) { 1 } else { 4 })
 }
-
-#[cfg(feature = "sandbox")]
-pub fn enable_sandbox_full() -> bool {
-    use libseccomp::*;
-
-    if !crate::file_unsafe::prctl() {
-        return false;
-    }
-
-    let ctx = ScmpFilterContext::new_filter(ScmpAction::KillThread);
-    if ctx.is_err() {
-        return false;
-    }
-    let mut ctx = ctx.unwrap();
-
-    let syscalls = [
-        "access",
-        "brk",
-        "close",
-        "dup2",
-        "exit",
-        "exit_group",
-        "faccessat",
-        "fcntl",
-        "fcntl64",
-        "fstat",
-        "fstat64",
-        "fstatat64",
-        "futex",
-        "getdents",
-        "getdents64",
-        "lseek",
-        "_llseek",
-        "lstat",
-        "lstat64",
-        "madvise",
-        "mmap",
-        "mmap2",
-        "mprotect",
-        "mremap",
-        "munmap",
-        "newfstatat",
-        "open",
-        "openat",
-        "pread64",
-        "read",
-        "readlink",
-        "readlinkat",
-        "rt_sigaction",
-        "rt_sigprocmask",
-        "rt_sigreturn",
-        "select",
-        "stat",
-        "statx",
-        "stat64",
-        "sysinfo",
-        "umask",
-        "getpid",
-        "unlink",
-        "utimes",
-        "write",
-        "writev",
-        "sigaltstack", // for rust to work
-    ];
-
-    for syscall in syscalls {
-        let syscall = ScmpSyscall::from_name(syscall);
-        if syscall.is_err() || ctx.add_rule(ScmpAction::Allow, syscall.unwrap()).is_err() {
-            return false;
-        }
-    }
-
-    let syscall = ScmpSyscall::from_name("ioctl");
-    if syscall.is_err() {
-        return false;
-    }
-    let syscall = syscall.unwrap();
-    let params = [libc::FIONREAD, libc::TIOCGWINSZ, libc::TCGETS];
-
-    for param in params {
-        if ctx
-            .add_rule_conditional(ScmpAction::Allow, syscall, &[scmp_cmp!($arg1 == param)])
-            .is_err()
-        {
-            return false;
-        }
-    }
-
-    let syscall = ScmpSyscall::from_name("prctl");
-    if syscall.is_err() {
-        return false;
-    }
-    let syscall = syscall.unwrap();
-
-    if ctx
-        .add_rule_conditional(
-            ScmpAction::Allow,
-            syscall,
-            &[
-                scmp_cmp!($arg0 == libc::PR_SET_VMA as u64),
-                scmp_cmp!($arg1 == libc::PR_SET_VMA_ANON_NAME as u64),
-            ],
-        )
-        .is_err()
-    {
-        return false;
-    }
-
-    ctx.load().is_ok()
-}
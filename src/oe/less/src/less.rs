@@ -4,11 +4,17 @@
 //
 // For the full copyright and license information, please view the LICENSE file
 // that was distributed with this source code.
-use crate::less_common::{configs, less, less_app, reset_term, setup_term, Configs};
+use crate::less_common::preprocess;
+use crate::less_common::{
+    configs, less, less_app, prepend_env_options, reset_term, setup_term, split_initial_commands,
+    Bindings, Configs, FileList, Input, Navigation,
+};
 use clap::Command;
 use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 use crossterm::terminal::enable_raw_mode;
+use crossterm::tty::IsTty;
 use crossterm::{execute, terminal};
+use std::env;
 use std::fs::OpenOptions;
 use std::{
     fs,
@@ -38,6 +44,11 @@ impl Drop for TermGuard {
 
 #[uucore::main]
 pub fn oemain(args: impl uucore::Args) -> UResult<()> {
+    let bindings = Bindings::load();
+    let env_options = env::var("LESS").ok();
+    let env_options = env_options.as_deref().or_else(|| bindings.env("LESS"));
+    let args = prepend_env_options(args, env_options);
+    let (args, commands) = split_initial_commands(args.into_iter());
     let matches = match less_app().try_get_matches_from(args) {
         Ok(matches) => matches,
         Err(e) => {
@@ -46,184 +57,192 @@ pub fn oemain(args: impl uucore::Args) -> UResult<()> {
     };
 
     let mut configs = Configs::from(&matches);
+    configs.commands = commands;
+    configs.bindings = bindings;
 
-    enable_raw_mode()?;
-    execute!(std::io::stdout(), EnableMouseCapture)?;
+    let files = matches.get_many::<string::String>(configs::FILES);
+    if files.is_none() && std::io::stdin().is_tty() {
+        return Err(UUsageError::new(1, "Missing filename"));
+    }
 
-    let _guard = TermGuard;
+    let _guard = if configs.non_interactive {
+        None
+    } else {
+        enable_raw_mode()?;
+        execute!(std::io::stdout(), EnableMouseCapture)?;
+        Some(TermGuard)
+    };
 
     let output_file = matches.get_one::<String>(configs::OUTPUT).cloned();
 
-    let mut buff = string::String::new();
+    let names: Vec<string::String> = match files {
+        Some(files) => files.cloned().collect(),
+        None => vec!["-".to_string()],
+    };
+    let mut files = FileList::new(names);
+    let mut stdout = setup_term(!configs.non_interactive);
+    // A file that cannot be opened sends back to the file the user came from
+    let mut back = false;
+    while let Some(name) = files.current().map(string::String::from) {
+        let input = match open_input(Path::new(&name), &mut configs) {
+            Some(input) => input,
+            None => {
+                files.remove_current(back);
+                continue;
+            }
+        };
+        let (output, navigation) = less(input, &mut stdout, &mut files, &mut configs)?;
+        write_output(output_file.as_deref(), output);
 
-    if let Some(files) = matches.get_many::<string::String>(configs::FILES) {
-        let mut stdout = setup_term();
-        let mut file_nums = files.len();
+        back = matches!(navigation, Navigation::Previous | Navigation::Examine(_));
+        if !files.navigate(&navigation) {
+            break;
+        }
+    }
+    reset_term(&mut stdout);
 
-        let mut files_iter = files.map(|s| s.as_str()).peekable();
-        while let (Some(file), next_file) = (files_iter.next(), files_iter.peek()) {
-            let file = Path::new(file);
-            if !file.exists() {
+    Ok(())
+}
+
+/// Open a file to page, `-` being the standard input. Errors are reported
+/// here, `None` meaning the file is skipped.
+fn open_input(file: &Path, configs: &mut Configs) -> Option<Input> {
+    if file == Path::new("-") {
+        return Some(Input::stdin());
+    }
+    let mut buff = string::String::new();
+    // Raw content of a file: the bytes actually read are where following resumes
+    let mut bytes = Vec::new();
+    if !file.exists() {
+        terminal::disable_raw_mode().unwrap();
+        show!(USimpleError::new(
+            0,
+            format!("cannot open {}: No such file or directory", file.quote()),
+        ));
+        terminal::enable_raw_mode().unwrap();
+        return None;
+    }
+    // Errors are left to the plain open below, which reports them
+    if let Ok(Some(input)) = preprocess::open(file) {
+        return Some(input);
+    }
+    if file.is_dir() {
+        if configs.force_open {
+            configs.test_config.force_open = true;
+            buff.push_str(&format!("force read : '{}'\n", file.display()));
+            if let Ok(entries) = fs::read_dir(file) {
+                for entry in entries {
+                    if let Ok(entry) = entry {
+                        let entry_path = entry.path();
+                        let metadata = match entry.metadata() {
+                            Ok(m) => m,
+                            Err(e) => {
+                                buff.push_str(&format!(
+                                    "Error getting metadata for {}: {}\n",
+                                    entry_path.display(),
+                                    e
+                                ));
+                                continue;
+                            }
+                        };
+                        let entry_type = if metadata.is_dir() { "dir" } else { "file" };
+                        buff.push_str(&format!("{:10} {}\n", entry_type, entry_path.display()));
+                    }
+                }
+            } else {
                 terminal::disable_raw_mode().unwrap();
                 show!(USimpleError::new(
                     0,
-                    format!("cannot open {}: No such file or directory", file.quote()),
+                    format!("Failed to read {}", file.quote()),
                 ));
                 terminal::enable_raw_mode().unwrap();
-                continue;
+                return None;
             }
-            if file.is_dir() {
-                if configs.force_open {
-                    configs.test_config.force_open = true;
-                    buff.push_str(&format!("force read : '{}'\n", file.display()));
-                    if let Ok(entries) = fs::read_dir(file) {
-                        for entry in entries {
-                            if let Ok(entry) = entry {
-                                let entry_path = entry.path();
-                                let metadata = match entry.metadata() {
-                                    Ok(m) => m,
-                                    Err(e) => {
-                                        buff.push_str(&format!(
-                                            "Error getting metadata for {}: {}\n",
-                                            entry_path.display(),
-                                            e
-                                        ));
-                                        continue;
-                                    }
-                                };
-                                let entry_type = if metadata.is_dir() { "dir" } else { "file" };
-                                buff.push_str(&format!(
-                                    "{:10} {}\n",
-                                    entry_type,
-                                    entry_path.display()
-                                ));
-                            }
-                        }
-                    } else {
-                        terminal::disable_raw_mode().unwrap();
-                        show!(USimpleError::new(
-                            0,
-                            format!("Failed to read {}", file.quote()),
-                        ));
-                        terminal::enable_raw_mode().unwrap();
-                        continue;
-                    }
-                } else {
-                    terminal::disable_raw_mode().unwrap();
-                    show!(UUsageError::new(
-                        0,
-                        format!("{} is a directory.", file.quote()),
-                    ));
-                    terminal::enable_raw_mode().unwrap();
-                    continue;
+        } else {
+            terminal::disable_raw_mode().unwrap();
+            show!(UUsageError::new(
+                0,
+                format!("{} is a directory.", file.quote()),
+            ));
+            terminal::enable_raw_mode().unwrap();
+            return None;
+        }
+    } else {
+        let file_result = if configs.force_open {
+            configs.test_config.force_open = true;
+            OpenOptions::new()
+                .read(true)
+                .open(file)
+                .map_err(|e| (e, false))
+        } else {
+            File::open(file).map_err(|e| (e, true))
+        };
+
+        match file_result {
+            Ok(opened_file) => {
+                let mut reader = BufReader::new(opened_file);
+
+                if let Some(buffer_size) = configs.buffer_size {
+                    configs.test_config.buffer = true;
+                    reader = BufReader::with_capacity(buffer_size, reader.into_inner());
                 }
-            } else {
-                let file_result = if configs.force_open {
-                    configs.test_config.force_open = true;
-                    OpenOptions::new()
-                        .read(true)
-                        .open(file)
-                        .map_err(|e| (e, false))
-                } else {
-                    File::open(file).map_err(|e| (e, true))
-                };
-
-                match file_result {
-                    Ok(opened_file) => {
-                        let mut reader = BufReader::new(opened_file);
-
-                        if let Some(buffer_size) = configs.buffer_size {
-                            configs.test_config.buffer = true;
-                            reader = BufReader::with_capacity(buffer_size, reader.into_inner());
-                        }
 
-                        match reader.read_to_string(&mut buff) {
-                            Ok(_) => {}
-                            Err(why) => {
-                                if !configs.force_open {
-                                    terminal::disable_raw_mode().unwrap();
-                                    show!(USimpleError::new(
-                                        0,
-                                        format!(
-                                            "Failed to read file {}: {}",
-                                            file.quote(),
-                                            why.kind()
-                                        ),
-                                    ));
-                                    terminal::enable_raw_mode().unwrap();
-                                }
-                            }
-                        }
-                    }
-                    Err((why, show_error)) => {
-                        if show_error {
+                match reader.read_to_end(&mut bytes) {
+                    Ok(_) => {}
+                    Err(why) => {
+                        if !configs.force_open {
                             terminal::disable_raw_mode().unwrap();
                             show!(USimpleError::new(
                                 0,
-                                format!("cannot open {}: {}", file.quote(), why.kind()),
+                                format!("Failed to read file {}: {}", file.quote(), why.kind()),
                             ));
                             terminal::enable_raw_mode().unwrap();
                         }
-                        continue;
                     }
                 }
             }
-
-            let output = less(
-                &buff,
-                &mut stdout,
-                file_nums > 1,
-                file.to_str(),
-                next_file.copied(),
-                &mut configs,
-            )?;
-
-            if let Some(ref output_filename) = output_file {
-                if let Err(err) = fs::write(&output_filename, output) {
+            Err((why, show_error)) => {
+                if show_error {
                     terminal::disable_raw_mode().unwrap();
                     show!(USimpleError::new(
                         0,
-                        format!(
-                            "Failed to write output to file {}: {}",
-                            output_filename, err
-                        ),
+                        format!("cannot open {}: {}", file.quote(), why.kind()),
                     ));
                     terminal::enable_raw_mode().unwrap();
                 }
+                return None;
             }
-            if file_nums > 1 {
-                file_nums -= 1;
-            } else if file_nums == 0 {
-                // no more files to read
-                break;
-            }
-            buff.clear();
         }
-        reset_term(&mut stdout);
+    }
+
+    if file.is_file() {
+        Some(Input::from_file(file, bytes))
     } else {
-        if buff.is_empty() {
-            return Err(UUsageError::new(1, "Missing filename"));
-        }
-        let mut stdout = setup_term();
-        let output = less(&buff, &mut stdout, false, None, None, &mut configs)?;
+        buff.push_str(&String::from_utf8_lossy(&bytes));
+        Some(Input::from_string(buff))
+    }
+}
 
-        if let Some(ref output_filename) = output_file {
-            if let Err(err) = fs::write(&output_filename, output) {
+/// Save the paged output to the log file, if one was requested
+fn write_output(output_file: Option<&str>, output: string::String) {
+    if let Some(output_filename) = output_file {
+        if let Err(err) = fs::write(output_filename, output) {
+            let raw = terminal::is_raw_mode_enabled().unwrap_or(false);
+            if raw {
                 terminal::disable_raw_mode().unwrap();
-                show!(USimpleError::new(
-                    0,
-                    format!(
-                        "Failed to write output to file {}: {}",
-                        output_filename, err
-                    ),
-                ));
+            }
+            show!(USimpleError::new(
+                0,
+                format!(
+                    "Failed to write output to file {}: {}",
+                    output_filename, err
+                ),
+            ));
+            if raw {
                 terminal::enable_raw_mode().unwrap();
             }
         }
-        reset_term(&mut stdout);
     }
-
-    Ok(())
 }
 
 /// configuration for less
@@ -15,15 +15,34 @@ use crossterm::{
     terminal::{self, Clear, ClearType},
 };
 use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::env;
 use std::fs::File;
 use std::io::{stdout, Stdout, Write};
 use std::time::Duration;
-use unicode_segmentation::UnicodeSegmentation;
-use unicode_width::UnicodeWidthStr;
 use uucore::error::{UResult, USimpleError};
 use uucore::{format_usage, help_section, help_usage};
 
+pub use self::files::{FileList, Navigation};
+pub use self::input::Input;
+pub use self::keys::Bindings;
+use self::keys::{Action, Key, Lookup};
+use self::search::{Direction, Pattern, Search};
+
+/// control characters and display width
+pub mod display;
+/// list of the files to page
+pub mod files;
+/// incremental input sources
+pub mod input;
+/// key bindings and lesskey files
+pub mod keys;
+/// `LESSOPEN` preprocessing and decompression
+pub mod preprocess;
+/// interactive search and filtering
+pub mod search;
+
 const ABOUT: &str = help_section!("about", "less.md");
 const USAGE: &str = help_usage!("less.md");
 const BELL: &str = "\x07";
@@ -68,6 +87,10 @@ pub mod configs {
     pub const OUTPUT: &str = "log-file";
     /// none interactive mode
     pub const NON_INTERACTIVE: &str = "non-interactive";
+    /// raw control chars
+    pub const RAW_CONTROL_CHARS: &str = "RAW-CONTROL-CHARS";
+    /// chop long lines
+    pub const CHOP_LONG_LINES: &str = "chop-long-lines";
 }
 
 /// Config
@@ -102,6 +125,14 @@ pub struct Configs {
     pub test_config: TestConfig,
     ///
     pub non_interactive: bool,
+    /// pass SGR colour sequences through to the terminal
+    pub raw_control_chars: bool,
+    /// show long lines truncated instead of wrapped
+    pub chop_long_lines: bool,
+    /// commands given as `+cmd` arguments, run when the first file is shown
+    pub commands: Vec<String>,
+    /// commands bound to the keys
+    pub bindings: Bindings,
 }
 
 /// Test Config
@@ -149,17 +180,129 @@ impl Configs {
                 force_open: false,
             },
             non_interactive: matches.get_flag(configs::NON_INTERACTIVE),
+            raw_control_chars: matches.get_flag(configs::RAW_CONTROL_CHARS),
+            chop_long_lines: matches.get_flag(configs::CHOP_LONG_LINES),
+            commands: Vec::new(),
+            bindings: Bindings::default(),
         }
     }
 }
 
+/// Options whose value is the next argument
+const TAKES_VALUE: &[&str] = &[
+    "-b",
+    "--buffer-size",
+    "-F",
+    "--from-line",
+    "-n",
+    "--lines",
+    "--number",
+    "-o",
+    "--log-file",
+    "-P",
+    "--pattern",
+];
+
+/// Put the default options of the `LESS` variable before the command line
+/// arguments. Options this less does not know are skipped, so a `LESS`
+/// shared with other implementations does not prevent it from starting.
+///
+/// As in less(1), single letter options may be clustered and the leading
+/// dash may be left out, as in `LESS=FRX`.
+pub fn prepend_env_options(
+    args: impl uucore::Args,
+    env_options: Option<&str>,
+) -> Vec<std::ffi::OsString> {
+    let mut args: Vec<_> = args.collect();
+    let words: Vec<&str> = env_options.unwrap_or_default().split_whitespace().collect();
+    let mut options = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i];
+        i += 1;
+        if word.starts_with('+') {
+            options.push(word.to_string());
+            continue;
+        }
+        if word.starts_with("--") {
+            let len = usize::from(TAKES_VALUE.contains(&word) && i < words.len());
+            let option = &words[i - 1..i + len];
+            if is_known_option(option) {
+                options.extend(option.iter().map(|w| w.to_string()));
+            }
+            i += len;
+            continue;
+        }
+        let letters = word.strip_prefix('-').unwrap_or(word);
+        for (pos, letter) in letters.char_indices() {
+            let flag = format!("-{}", letter);
+            if !TAKES_VALUE.contains(&flag.as_str()) {
+                if is_known_option(&[&flag]) {
+                    options.push(flag);
+                }
+                continue;
+            }
+            // The value is the rest of the word, or the next word. When it
+            // is not a valid one, the letter is the flag of another less,
+            // like `-F` quitting on a single screen, and is skipped.
+            let rest = &letters[pos + letter.len_utf8()..];
+            let value = if rest.is_empty() {
+                words.get(i).copied()
+            } else {
+                Some(rest)
+            };
+            if let Some(value) = value {
+                if is_known_option(&[&flag, value]) {
+                    options.push(flag);
+                    options.push(value.to_string());
+                    if rest.is_empty() {
+                        i += 1;
+                    }
+                    break;
+                }
+            }
+        }
+    }
+    let at = args.len().min(1);
+    args.splice(at..at, options.into_iter().map(std::ffi::OsString::from));
+    args
+}
+
+/// Whether this less accepts `option`, with its value if any
+fn is_known_option(option: &[&str]) -> bool {
+    less_app()
+        .try_get_matches_from(std::iter::once("less").chain(option.iter().copied()))
+        .is_ok()
+}
+
+/// Separate the `+cmd` initial commands from the arguments handed to clap
+pub fn split_initial_commands(args: impl uucore::Args) -> (Vec<std::ffi::OsString>, Vec<String>) {
+    let mut rest = Vec::new();
+    let mut commands = Vec::new();
+    let mut value_expected = false;
+    let mut options_done = false;
+    for arg in args {
+        let text = arg.to_string_lossy();
+        if !options_done && !value_expected && text.len() > 1 && text.starts_with('+') {
+            commands.push(text[1..].to_string());
+            continue;
+        }
+        options_done |= text == "--";
+        value_expected = !options_done && TAKES_VALUE.contains(&text.as_ref());
+        rest.push(arg);
+    }
+    (rest, commands)
+}
+
 /// Command
 pub fn less_app<'a>() -> Command<'a> {
     let base_command = Command::new(uucore::util_name())
         .about(ABOUT)
         .override_usage(format_usage(USAGE))
         .version(crate_version!())
-        .infer_long_args(true);
+        .infer_long_args(true)
+        // Options from `LESS` may be given again on the command line
+        .args_override_self(true);
 
     let args = [
         Arg::new(configs::BUFFER_SIZE)
@@ -228,6 +371,16 @@ pub fn less_app<'a>() -> Command<'a> {
             .required(false)
             .value_name("pattern")
             .help("Display file beginning from pattern match"),
+        Arg::new(configs::RAW_CONTROL_CHARS)
+            .short('R')
+            .long(configs::RAW_CONTROL_CHARS)
+            .help("Output ANSI color escape sequences in raw form")
+            .action(ArgAction::SetTrue),
+        Arg::new(configs::CHOP_LONG_LINES)
+            .short('S')
+            .long(configs::CHOP_LONG_LINES)
+            .help("Chop long lines instead of wrapping them")
+            .action(ArgAction::SetTrue),
         Arg::new(configs::SILENT)
             .short('q')
             .long(configs::SILENT)
@@ -255,9 +408,11 @@ pub fn less_app<'a>() -> Command<'a> {
 }
 
 /// setup terminal
-pub fn setup_term() -> Stdout {
+pub fn setup_term(raw: bool) -> Stdout {
     let stdout = stdout();
-    terminal::enable_raw_mode().unwrap();
+    if raw {
+        terminal::enable_raw_mode().unwrap();
+    }
     stdout
 }
 
@@ -271,14 +426,13 @@ pub fn reset_term(stdout: &mut std::io::Stdout) {
 
 ///
 pub fn less(
-    buff: &str,
+    mut input: Input,
     stdout: &mut Stdout,
-    multiple_file: bool,
-    file: Option<&str>,
-    next_file: Option<&str>,
+    files: &mut FileList,
     configs: &mut Configs,
-) -> UResult<String> {
+) -> UResult<(String, Navigation)> {
     if configs.non_interactive {
+        let buff = input.read_to_end()?;
         let mut output = String::new();
         let mut lines: Vec<&str> = buff.lines().collect();
 
@@ -339,7 +493,7 @@ pub fn less(
         } else {
             print!("{}", output);
         }
-        return Ok(output);
+        return Ok((output, Navigation::Next));
     }
 
     let (cols, mut rows) = terminal::size()?;
@@ -348,91 +502,157 @@ pub fn less(
         configs.test_config.lines = true;
     }
     let mut output = String::new();
-    let lines = break_buff(buff, cols as usize);
-    let mut numbered_lines = Vec::new();
-    let mut pager = Pager::new(rows, lines.clone(), next_file, configs);
+    let mut pager = Pager::new(rows, cols, files, configs);
+    handle_line_numbers(configs, &mut pager);
+    wait_first_page(&mut input, &mut pager)?;
 
     initialize_pager(
         stdout,
         configs,
-        &lines,
-        file,
-        multiple_file,
+        files.current(),
+        files.position(),
         &mut pager,
-        &mut numbered_lines,
         &mut output,
     )?;
 
-    if should_exit_early(&mut pager, next_file, configs) {
-        return Ok(output);
+    if should_exit_early(&mut pager, configs) {
+        return Ok((output, Navigation::Quit));
+    }
+
+    for command in std::mem::take(&mut configs.commands) {
+        run_initial_command(stdout, configs, &mut pager, &command, &mut output)?;
+    }
+
+    let navigation = run_main_loop(stdout, configs, &mut pager, &mut input, &mut output)?;
+    files.save_position(pager.top_line());
+    Ok((output, navigation))
+}
+
+/// Run a command given on the command line as `+cmd`
+fn run_initial_command(
+    stdout: &mut Stdout,
+    configs: &Configs,
+    pager: &mut Pager,
+    command: &str,
+    output: &mut String,
+) -> std::io::Result<()> {
+    if command == "F" {
+        pager.start_following();
+        redraw_page(stdout, configs, pager, output)?;
     }
+    Ok(())
+}
 
-    run_main_loop(stdout, configs, &mut pager, &mut output)
+/// Time given to a stream to fill the first page before it is drawn
+const FIRST_PAGE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Load what is already available, giving a stream a short while to fill the first page
+fn wait_first_page(input: &mut Input, pager: &mut Pager) -> std::io::Result<()> {
+    let start = std::time::Instant::now();
+    loop {
+        if let Some(text) = input.poll()? {
+            pager.append(&text);
+        }
+        pager.eof = input.is_eof();
+        if pager.eof
+            || pager.line_count >= pager.content_rows
+            || start.elapsed() >= FIRST_PAGE_TIMEOUT
+        {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
 }
 
-fn initialize_pager<'a>(
+fn initialize_pager(
     stdout: &mut Stdout,
     configs: &mut Configs,
-    lines: &[&str],
     file: Option<&str>,
-    multiple_file: bool,
-    pager: &mut Pager<'a>,
-    numbered_lines: &'a mut Vec<String>,
+    position: Option<usize>,
+    pager: &mut Pager,
     output: &mut String,
 ) -> std::io::Result<()> {
-    handle_pattern_search(stdout, configs, pager)?;
+    // A file shown again comes back where it was left
+    match position {
+        Some(line) => pager.goto_line(line),
+        None => handle_pattern_search(stdout, configs, pager)?,
+    }
+    let multiple_file = pager.file_count > 1;
     handle_multiple_file(stdout, configs, file, pager, multiple_file)?;
-    handle_line_numbers(configs, lines, numbered_lines, pager);
     draw_pager(stdout, configs, pager, output)?;
     set_test_flags(configs, pager);
     Ok(())
 }
 
-fn should_exit_early(pager: &mut Pager, next_file: Option<&str>, configs: &Configs) -> bool {
-    pager.should_close() && next_file.is_none() && configs.exit_at_eof
+fn should_exit_early(pager: &mut Pager, configs: &Configs) -> bool {
+    pager.at_eof() && pager.next_file.is_none() && configs.exit_at_eof
 }
 
 enum EventResult {
     Continue,
-    Exit,
+    Exit(Navigation),
     Process,
     PageDown,
     PageUp,
     Resize(u16, u16),
+    Follow,
+    Search(Direction),
+    RepeatSearch(bool),
+    Filter,
+    Shift(bool),
+    ToggleOption,
+    Examine,
+    Redraw,
+    Pending,
+    SetMark,
+    GotoMark,
 }
 
 fn run_main_loop(
     stdout: &mut Stdout,
     configs: &Configs,
     pager: &mut Pager,
+    input: &mut Input,
     output: &mut String,
-) -> UResult<String> {
+) -> UResult<Navigation> {
     loop {
+        if !pager.eof || pager.following {
+            read_more_input(stdout, configs, pager, input, output)?;
+        }
+
         if cfg!(test) || env::var("CARGO_TEST").is_ok() {
             if pager.has_any_test_flag() {
                 reset_term(stdout);
                 execute!(std::io::stdout(), DisableMouseCapture)?;
                 terminal::disable_raw_mode()?;
-                return Ok(std::mem::take(output));
+                return Ok(Navigation::Next);
             }
         }
 
         if event::poll(Duration::from_millis(10))? {
-            match handle_event(event::read()?, pager, configs)? {
+            let event = event::read()?;
+            if pager.following {
+                if is_interrupt(&event) {
+                    pager.following = false;
+                    redraw_page(stdout, configs, pager, output)?;
+                }
+                continue;
+            }
+            match handle_event(event, pager, configs)? {
                 EventResult::Continue => continue,
-                EventResult::Exit => {
-                    reset_term(stdout);
-                    execute!(std::io::stdout(), DisableMouseCapture)?;
-                    terminal::disable_raw_mode()?;
-                    return Ok(std::mem::take(output));
+                EventResult::Exit(navigation) => {
+                    leave_file(stdout, &navigation)?;
+                    return Ok(navigation);
                 }
                 EventResult::Process => {
                     handle_print_and_draw(stdout, configs, pager, None, output)?;
                 }
                 EventResult::PageDown => {
                     if pager.should_close() {
-                        if configs.exit_at_eof {
-                            return Ok(std::mem::take(output));
+                        if configs.exit_at_eof && pager.at_eof() {
+                            let navigation = pager.navigation_at_eof();
+                            leave_file(stdout, &navigation)?;
+                            return Ok(navigation);
                         }
                     } else {
                         pager.page_down();
@@ -448,6 +668,79 @@ fn run_main_loop(
                     pager.page_resize(col, row, configs.lines);
                     handle_print_and_draw(stdout, configs, pager, None, output)?;
                 }
+                EventResult::Follow => {
+                    pager.start_following();
+                    redraw_page(stdout, configs, pager, output)?;
+                }
+                EventResult::Search(direction) => {
+                    let prefix = match direction {
+                        Direction::Forward => "/",
+                        Direction::Backward => "?",
+                    };
+                    if let Some(text) = read_command_line(stdout, prefix)? {
+                        match Pattern::parse(&text) {
+                            Some(pattern) => pager.search = Some(Search { pattern, direction }),
+                            // An empty pattern repeats the last search
+                            None => {
+                                if let Some(search) = pager.search.as_mut() {
+                                    search.direction = direction;
+                                }
+                            }
+                        }
+                        pager.repeat_search(false, true);
+                    }
+                    redraw_page(stdout, configs, pager, output)?;
+                }
+                EventResult::RepeatSearch(reverse) => {
+                    pager.repeat_search(reverse, false);
+                    redraw_page(stdout, configs, pager, output)?;
+                }
+                EventResult::Filter => {
+                    if let Some(text) = read_command_line(stdout, "&")? {
+                        pager.set_filter(Pattern::parse(&text));
+                    }
+                    redraw_page(stdout, configs, pager, output)?;
+                }
+                EventResult::Shift(right) => {
+                    pager.shift(right);
+                    redraw_page(stdout, configs, pager, output)?;
+                }
+                EventResult::ToggleOption => {
+                    if let Some(option) = read_command_letter(stdout, "-")? {
+                        pager.toggle_option(option);
+                    }
+                    redraw_page(stdout, configs, pager, output)?;
+                }
+                EventResult::Redraw => redraw_page(stdout, configs, pager, output)?,
+                EventResult::Pending => {
+                    // Show the start of a longer key sequence on the prompt line
+                    let keys: String = pager.pending_keys.iter().map(Key::to_string).collect();
+                    execute!(stdout, Clear(ClearType::CurrentLine))?;
+                    write!(stdout, "\r{keys}")?;
+                    stdout.flush()?;
+                }
+                EventResult::SetMark => {
+                    if let Some(letter) = read_command_letter(stdout, "mark: ")? {
+                        pager.set_mark(letter);
+                    }
+                    redraw_page(stdout, configs, pager, output)?;
+                }
+                EventResult::GotoMark => {
+                    if let Some(letter) = read_command_letter(stdout, "goto mark: ")? {
+                        pager.goto_mark(letter);
+                    }
+                    redraw_page(stdout, configs, pager, output)?;
+                }
+                EventResult::Examine => {
+                    if let Some(name) = read_command_line(stdout, "Examine: ")? {
+                        if !name.is_empty() {
+                            let navigation = Navigation::Examine(name);
+                            leave_file(stdout, &navigation)?;
+                            return Ok(navigation);
+                        }
+                    }
+                    redraw_page(stdout, configs, pager, output)?;
+                }
             }
         }
     }
@@ -467,34 +760,9 @@ fn handle_event(
         Event::Mouse(MouseEvent {
             kind: MouseEventKind::ScrollDown,
             ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::Char('e' | 'j'),
-            modifiers: KeyModifiers::NONE,
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::Char('e' | 'n'),
-            modifiers: KeyModifiers::CONTROL,
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::Enter,
-            modifiers: KeyModifiers::NONE,
-            ..
         }) => handle_scroll_down(pager, configs),
 
-        Event::Key(KeyEvent {
-            code: KeyCode::Char('y' | 'k'),
-            modifiers: KeyModifiers::NONE,
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::Char('y' | 'k' | 'p'),
-            modifiers: KeyModifiers::CONTROL,
-            ..
-        })
-        | Event::Mouse(MouseEvent {
+        Event::Mouse(MouseEvent {
             kind: MouseEventKind::ScrollUp,
             ..
         }) => {
@@ -502,79 +770,231 @@ fn handle_event(
             Ok(EventResult::Process)
         }
 
-        Event::Key(KeyEvent {
-            code: KeyCode::Char('f'),
-            modifiers: KeyModifiers::NONE,
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::Char('f' | 'v'),
-            modifiers: KeyModifiers::CONTROL,
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::Down,
-            modifiers: KeyModifiers::NONE,
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::PageDown,
-            modifiers: KeyModifiers::NONE,
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::Char(' '),
-            modifiers: KeyModifiers::NONE,
-            ..
-        }) => Ok(EventResult::PageDown),
+        Event::Resize(col, row) => Ok(EventResult::Resize(col, row)),
 
-        Event::Key(KeyEvent {
-            code: KeyCode::Char('b'),
-            modifiers: KeyModifiers::NONE,
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::Char('b'),
-            modifiers: KeyModifiers::CONTROL,
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::Up,
-            modifiers: KeyModifiers::NONE,
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::PageUp,
-            modifiers: KeyModifiers::NONE,
-            ..
-        }) => Ok(EventResult::PageUp),
+        Event::Key(event) => handle_key(Key::from(event), pager, configs),
 
-        Event::Resize(col, row) => Ok(EventResult::Resize(col, row)),
+        _ => Ok(EventResult::Process),
+    }
+}
 
-        Event::Key(KeyEvent {
-            code: KeyCode::Char('q'),
-            modifiers: KeyModifiers::NONE,
-            kind: KeyEventKind::Press,
-            ..
-        }) => Ok(EventResult::Exit),
+/// Look the keys typed so far up in the bindings and run the command found
+fn handle_key(key: Key, pager: &mut Pager, configs: &Configs) -> std::io::Result<EventResult> {
+    // Digits typed before a command are its count
+    if pager.pending_keys.is_empty() {
+        if let Some(digit) = key.digit() {
+            let count = pager.count.unwrap_or_default();
+            pager.count = Some(count.saturating_mul(10).saturating_add(digit));
+            return Ok(EventResult::Continue);
+        }
+    }
 
-        Event::Key(KeyEvent {
-            code: KeyCode::Char(_),
-            ..
-        }) => {
-            pager.test_flags.unknown_key = true;
+    pager.pending_keys.push(key);
+    let action = match configs.bindings.lookup(&pager.pending_keys) {
+        Lookup::Found(action) => action,
+        Lookup::Prefix => return Ok(EventResult::Pending),
+        Lookup::Unbound => Action::Invalid,
+    };
+    let keys = std::mem::take(&mut pager.pending_keys);
+    let count = pager.count.take();
+
+    let result = match action {
+        Action::ForwLine => return handle_scroll_down(pager, configs),
+        Action::BackLine => {
+            pager.prev_line();
+            EventResult::Process
+        }
+        Action::ForwScreen => EventResult::PageDown,
+        Action::BackScreen => EventResult::PageUp,
+        Action::ForwForever => EventResult::Follow,
+        Action::ForwSearch => EventResult::Search(Direction::Forward),
+        Action::BackSearch => EventResult::Search(Direction::Backward),
+        Action::RepeatSearch => EventResult::RepeatSearch(false),
+        Action::ReverseSearch => EventResult::RepeatSearch(true),
+        Action::Filter => EventResult::Filter,
+        Action::GotoLine => {
+            pager.jump_to_line(count.unwrap_or(1).saturating_sub(1));
+            EventResult::Redraw
+        }
+        Action::GotoEnd => {
+            match count {
+                Some(number) => pager.jump_to_line(number.saturating_sub(1)),
+                None => pager.jump_to_end(),
+            }
+            EventResult::Redraw
+        }
+        Action::Percent => {
+            pager.jump_to_percent(count.unwrap_or_default());
+            EventResult::Redraw
+        }
+        Action::SetMark => EventResult::SetMark,
+        Action::GotoMark => EventResult::GotoMark,
+        // Horizontal scrolling only applies to chopped lines
+        Action::LeftScroll if pager.chop => EventResult::Shift(false),
+        Action::RightScroll if pager.chop => EventResult::Shift(true),
+        Action::LeftScroll | Action::RightScroll => EventResult::Process,
+        Action::ToggleOption => EventResult::ToggleOption,
+        Action::NextFile | Action::PrevFile | Action::IndexFile | Action::RemoveFile => {
+            match pager.file_navigation(action) {
+                Some(navigation) => EventResult::Exit(navigation),
+                None => EventResult::Redraw,
+            }
+        }
+        Action::Examine => EventResult::Examine,
+        Action::Quit => EventResult::Exit(Navigation::Quit),
+        Action::Invalid => {
+            if keys.iter().any(Key::is_char) {
+                pager.test_flags.unknown_key = true;
+            }
+            EventResult::Process
+        }
+        Action::Noaction => EventResult::Continue,
+    };
+    Ok(result)
+}
 
-            Ok(EventResult::Process)
+/// Append newly arrived data, redrawing when it changes what is on screen
+fn read_more_input(
+    stdout: &mut Stdout,
+    configs: &Configs,
+    pager: &mut Pager,
+    input: &mut Input,
+    output: &mut String,
+) -> std::io::Result<()> {
+    let on_last_page = pager.should_close();
+    let appended = match input.poll()? {
+        Some(text) => {
+            if input.take_reset() {
+                pager.clear();
+            }
+            pager.append(&text);
+            true
         }
+        None => false,
+    };
+    let reached_eof = !pager.eof && input.is_eof();
+    pager.eof = input.is_eof();
+    if pager.following && appended {
+        pager.goto_end();
+        redraw_page(stdout, configs, pager, output)?;
+    } else if on_last_page && (appended || reached_eof) {
+        // New data is only visible from the last page, where the prompt also changes at eof
+        redraw_page(stdout, configs, pager, output)?;
+    }
+    Ok(())
+}
 
-        _ => Ok(EventResult::Process),
+/// Draw the current page over the previous one
+fn redraw_page(
+    stdout: &mut Stdout,
+    configs: &Configs,
+    pager: &mut Pager,
+    output: &mut String,
+) -> std::io::Result<()> {
+    execute!(stdout, MoveTo(0, 0), Clear(ClearType::FromCursorDown))?;
+    handle_print_and_draw(stdout, configs, pager, None, output)
+}
+
+/// Read the argument of a command on the prompt line, `None` when cancelled
+fn read_command_line(stdout: &mut Stdout, prefix: &str) -> std::io::Result<Option<String>> {
+    let mut text = String::new();
+    loop {
+        execute!(stdout, Clear(ClearType::CurrentLine))?;
+        write!(stdout, "\r{prefix}{text}")?;
+        stdout.flush()?;
+
+        let event = event::read()?;
+        if is_interrupt(&event) {
+            return Ok(None);
+        }
+        match event {
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Release,
+                ..
+            }) => {}
+            Event::Key(KeyEvent {
+                code: KeyCode::Enter,
+                ..
+            }) => return Ok(Some(text)),
+            Event::Key(KeyEvent {
+                code: KeyCode::Esc, ..
+            }) => return Ok(None),
+            // Erasing past the prefix leaves the command
+            Event::Key(KeyEvent {
+                code: KeyCode::Backspace,
+                ..
+            }) if text.is_empty() => return Ok(None),
+            Event::Key(KeyEvent {
+                code: KeyCode::Backspace,
+                ..
+            }) => {
+                text.pop();
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers,
+                ..
+            }) if !modifiers.contains(KeyModifiers::CONTROL) => text.push(c),
+            _ => {}
+        }
+    }
+}
+
+/// Read the letter following a `-` or `:` command, `None` when cancelled
+fn read_command_letter(stdout: &mut Stdout, prefix: &str) -> std::io::Result<Option<char>> {
+    execute!(stdout, Clear(ClearType::CurrentLine))?;
+    write!(stdout, "\r{prefix}")?;
+    stdout.flush()?;
+    loop {
+        match event::read()? {
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Release,
+                ..
+            }) => {}
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers,
+                ..
+            }) if !modifiers.contains(KeyModifiers::CONTROL) => return Ok(Some(c)),
+            Event::Key(_) => return Ok(None),
+            _ => {}
+        }
     }
 }
 
+/// Restore the terminal when quitting, or clear the prompt before the next file
+fn leave_file(stdout: &mut Stdout, navigation: &Navigation) -> std::io::Result<()> {
+    if *navigation == Navigation::Quit {
+        reset_term(stdout);
+        execute!(std::io::stdout(), DisableMouseCapture)?;
+        terminal::disable_raw_mode()?;
+    } else {
+        execute!(stdout, Clear(ClearType::CurrentLine))?;
+        write!(stdout, "\r")?;
+    }
+    Ok(())
+}
+
+/// Ctrl-C, which stops following in raw mode instead of raising SIGINT
+fn is_interrupt(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('c'),
+            modifiers: KeyModifiers::CONTROL,
+            kind: KeyEventKind::Press,
+            ..
+        })
+    )
+}
+
 fn handle_scroll_down(pager: &mut Pager, configs: &Configs) -> std::io::Result<EventResult> {
     if pager.should_close() {
+        if !pager.eof {
+            return Ok(EventResult::Continue);
+        }
         if configs.exit_at_eof || pager.next_file.is_some() {
-            return Ok(EventResult::Exit);
+            return Ok(EventResult::Exit(pager.navigation_at_eof()));
         }
     } else {
         pager.next_line();
@@ -590,7 +1010,13 @@ fn handle_pattern_search(
     if let Some(pat) = configs.pattern.as_ref() {
         pager.test_flags.pattern = true;
         match search_pattern_in_file(&pager.lines, pat) {
-            Some(number) => pager.upper_mark = number,
+            Some(number) => {
+                pager.goto_line(number);
+                pager.search = Some(Search {
+                    pattern: Pattern::literal(pat),
+                    direction: Direction::Forward,
+                });
+            }
             None => {
                 execute!(stdout, Clear(ClearType::CurrentLine))?;
                 stdout.write_all("\rPattern not found\n".as_bytes())?;
@@ -621,19 +1047,9 @@ fn handle_multiple_file(
     Ok(())
 }
 
-fn handle_line_numbers<'a>(
-    configs: &Configs,
-    lines: &[&str],
-    numbered_lines: &'a mut Vec<String>,
-    pager: &mut Pager<'a>,
-) {
+fn handle_line_numbers(configs: &Configs, pager: &mut Pager) {
     if configs.show_line_numbers {
-        for (i, line) in lines.iter().enumerate() {
-            let numbered_line = format!("\x1b[1m{:5}\x1b[0m {}", i + 1, line);
-            numbered_lines.push(numbered_line);
-        }
-        let numbered_line_refs: Vec<&str> = numbered_lines.iter().map(|s| s.as_str()).collect();
-        pager.bind_lines_numbered(numbered_line_refs);
+        pager.line_numbers = true;
         pager.test_flags.line_numbers = true;
     }
 }
@@ -687,13 +1103,54 @@ fn set_test_flags(configs: &Configs, pager: &mut Pager) {
     }
 }
 
+/// Width taken by the line number column
+const LINE_NUMBER_WIDTH: usize = 6;
+
+/// Screen row, part of an input line broken on the cols of the terminal
+#[derive(Clone, Copy)]
+struct Row {
+    /// index of the input line
+    line: usize,
+    /// byte range of the row in the line
+    start: usize,
+    end: usize,
+}
+
 /// Pager struct
-struct Pager<'a> {
+struct Pager {
     upper_mark: usize,
     content_rows: usize,
-    lines: Vec<&'a str>,
-    next_file: Option<&'a str>,
+    cols: usize,
+    /// input lines
+    lines: Vec<String>,
+    /// rows of the lines currently displayed, `upper_mark` indexes this
+    rows: Vec<Row>,
+    line_numbers: bool,
+    raw: bool,
+    /// one row per line, cut on the right edge of the screen
+    chop: bool,
+    /// columns scrolled out on the left of chopped lines
+    shift: usize,
+    /// count typed before a command
+    count: Option<usize>,
+    /// start of a key sequence bound to a command
+    pending_keys: Vec<Key>,
+    /// input lines marked with `m`
+    marks: HashMap<char, usize>,
+    /// top line before the last jump, returned to with `''`
+    last_position: Option<usize>,
+    search: Option<Search>,
+    filter: Option<Pattern>,
+    message: Option<String>,
+    next_file: Option<String>,
+    /// position of the file in the list, and size of the list
+    file_index: usize,
+    file_count: usize,
     line_count: usize,
+    /// the last line has not received its newline yet
+    partial_line: bool,
+    eof: bool,
+    following: bool,
     silent: bool,
     squeeze: bool,
     line_squeezed: usize,
@@ -721,18 +1178,35 @@ struct TestFlags {
 }
 
 /// Pager impl
-impl<'a> Pager<'a> {
+impl Pager {
     /// construct a new pager
-    fn new(rows: u16, lines: Vec<&'a str>, next_file: Option<&'a str>, options: &Configs) -> Self {
-        let line_count = lines.len();
+    fn new(rows: u16, cols: u16, files: &FileList, options: &Configs) -> Self {
         let content_rows = rows.saturating_sub(1) as usize;
 
         Self {
             upper_mark: options.from_line,
             content_rows,
-            lines,
-            next_file,
-            line_count,
+            cols: cols as usize,
+            lines: Vec::new(),
+            rows: Vec::new(),
+            line_numbers: false,
+            raw: options.raw_control_chars,
+            chop: options.chop_long_lines,
+            shift: 0,
+            count: None,
+            pending_keys: Vec::new(),
+            marks: HashMap::new(),
+            last_position: None,
+            search: None,
+            filter: None,
+            message: None,
+            next_file: files.next_name().map(String::from),
+            file_index: files.index(),
+            file_count: files.len(),
+            line_count: 0,
+            partial_line: false,
+            eof: false,
+            following: false,
             silent: options.silent,
             squeeze: options.squeeze,
             line_squeezed: 0,
@@ -755,6 +1229,310 @@ impl<'a> Pager<'a> {
             },
         }
     }
+    /// append text, breaking it on the cols of the terminal
+    fn append(&mut self, text: &str) {
+        let mut lines = text.lines();
+        if self.partial_line && !text.is_empty() {
+            // The first line completes the last one appended
+            if let Some(rest) = lines.next() {
+                let last = self.lines.len() - 1;
+                while matches!(self.rows.last(), Some(row) if row.line == last) {
+                    self.rows.pop();
+                }
+                self.lines[last].push_str(rest);
+                self.push_rows(last);
+            }
+        }
+        for line in lines {
+            self.lines.push(line.to_string());
+            self.push_rows(self.lines.len() - 1);
+        }
+        if !text.is_empty() {
+            self.partial_line = !self.lines.is_empty() && !text.ends_with('\n');
+        }
+        self.line_count = self.rows.len();
+    }
+
+    /// add the rows of a line, unless the filter hides it
+    fn push_rows(&mut self, index: usize) {
+        let line = &self.lines[index];
+        if let Some(filter) = &self.filter {
+            if !filter.is_match(&searched_text(line, self.raw)) {
+                return;
+            }
+        }
+        if self.chop {
+            self.rows.push(Row {
+                line: index,
+                start: 0,
+                end: line.len(),
+            });
+            return;
+        }
+        for (start, end) in display::break_line(line, self.text_cols(), self.raw) {
+            self.rows.push(Row {
+                line: index,
+                start,
+                end,
+            });
+        }
+    }
+
+    /// columns available for the text of a line
+    fn text_cols(&self) -> usize {
+        if self.line_numbers {
+            self.cols.saturating_sub(LINE_NUMBER_WIDTH)
+        } else {
+            self.cols
+        }
+    }
+
+    /// scroll chopped lines by half a screen, to the right or back to the left
+    fn shift(&mut self, right: bool) {
+        let step = (self.text_cols() / 2).max(1);
+        self.shift = if right {
+            self.shift.saturating_add(step)
+        } else {
+            self.shift.saturating_sub(step)
+        };
+    }
+
+    /// change an option from the `-` command
+    fn toggle_option(&mut self, option: char) {
+        match option {
+            'S' => {
+                self.chop = !self.chop;
+                self.shift = 0;
+                self.rebuild_rows();
+                self.message = Some(if self.chop {
+                    "Chop long lines".to_string()
+                } else {
+                    "Fold long lines".to_string()
+                });
+            }
+            _ => self.message = Some(format!("There is no -{option} option")),
+        }
+    }
+
+    /// break every line again, keeping the top line on screen
+    fn rebuild_rows(&mut self) {
+        let top = self.top_line();
+        self.rows.clear();
+        for index in 0..self.lines.len() {
+            self.push_rows(index);
+        }
+        self.line_count = self.rows.len();
+        self.goto_line(top);
+        if self.should_close() {
+            self.goto_end();
+        }
+    }
+
+    /// drop every line, before loading a truncated or replaced file again
+    fn clear(&mut self) {
+        self.lines.clear();
+        self.rows.clear();
+        self.partial_line = false;
+        self.line_count = 0;
+        self.upper_mark = 0;
+    }
+
+    /// input line shown at the top of the screen
+    fn top_line(&self) -> usize {
+        self.rows
+            .get(self.upper_mark)
+            .map_or(self.lines.len(), |row| row.line)
+    }
+
+    /// show the first displayed line at or after `line` at the top of the screen
+    fn goto_line(&mut self, line: usize) {
+        self.upper_mark = self.rows.partition_point(|row| row.line < line);
+    }
+
+    /// search the next line matching, `reverse` going against the search
+    /// direction; a new search, `inclusive`, may match the top line itself
+    fn repeat_search(&mut self, reverse: bool, inclusive: bool) {
+        let search = match &self.search {
+            Some(search) => search,
+            None => {
+                self.message = Some("No previous regular expression".to_string());
+                return;
+            }
+        };
+        let direction = if reverse {
+            search.direction.reverse()
+        } else {
+            search.direction
+        };
+        let top = self.top_line();
+        // The top line is where the previous match was shown
+        let start = match direction {
+            _ if inclusive => top,
+            Direction::Forward => top + 1,
+            Direction::Backward => match top.checked_sub(1) {
+                Some(start) => start,
+                None => {
+                    self.message = Some("Pattern not found".to_string());
+                    return;
+                }
+            },
+        };
+        let filter = self.filter.as_ref();
+        let raw = self.raw;
+        let found = search::find_line(&self.lines, start, direction, |line| {
+            let text = searched_text(line, raw);
+            search.pattern.is_match(&text) && filter.iter().all(|f| f.is_match(&text))
+        });
+        match found {
+            Some(line) => {
+                self.last_position = Some(top);
+                self.goto_line(line);
+            }
+            None => self.message = Some("Pattern not found".to_string()),
+        }
+    }
+
+    /// display only the lines selected by the pattern, or every line
+    fn set_filter(&mut self, filter: Option<Pattern>) {
+        self.filter = filter;
+        self.rebuild_rows();
+    }
+
+    /// byte ranges of the search matches in a line
+    fn search_matches(&self, line: &str) -> Vec<(usize, usize)> {
+        let search = match &self.search {
+            Some(search) => search,
+            None => return Vec::new(),
+        };
+        if !self.raw {
+            return search.pattern.matches(line);
+        }
+        // Colours are not part of the text being searched
+        let (text, offsets) = display::strip_sgr(line);
+        search
+            .pattern
+            .matches(&text)
+            .into_iter()
+            .map(|(start, end)| (offsets[start], offsets[end]))
+            .collect()
+    }
+
+    /// text of a row as drawn on screen, with line number and match highlighting
+    fn render_row(&self, row: &Row, highlight: bool) -> String {
+        let line = &self.lines[row.line];
+        let mut text = String::new();
+        if self.line_numbers {
+            if row.start == 0 {
+                text.push_str(&format!("\x1b[1m{:5}\x1b[0m ", row.line + 1));
+            } else {
+                text.push_str(&" ".repeat(LINE_NUMBER_WIDTH));
+            }
+        }
+        let matches = if highlight {
+            self.search_matches(line)
+        } else {
+            Vec::new()
+        };
+        if self.chop {
+            let (start, end, start_col) =
+                display::column_range(line, self.shift, self.text_cols(), self.raw);
+            text.push_str(&display::render(
+                line, start, end, start_col, self.raw, &matches,
+            ));
+        } else {
+            text.push_str(&display::render(
+                line, row.start, row.end, 0, self.raw, &matches,
+            ));
+        }
+        text
+    }
+
+    /// show an input line at the top, or the last page when it is near the end
+    fn jump_to_line(&mut self, line: usize) {
+        self.last_position = Some(self.top_line());
+        self.goto_line(line);
+        if self.should_close() {
+            self.goto_end();
+        }
+    }
+
+    /// show the last page, remembering where the jump was made from
+    fn jump_to_end(&mut self) {
+        self.last_position = Some(self.top_line());
+        self.goto_end();
+    }
+
+    /// show the line at `percent` of the input at the top
+    fn jump_to_percent(&mut self, percent: usize) {
+        let line = self.lines.len().saturating_mul(percent.min(100)) / 100;
+        self.jump_to_line(line);
+    }
+
+    /// mark the top line with a letter
+    fn set_mark(&mut self, letter: char) {
+        if letter.is_ascii_alphabetic() {
+            self.marks.insert(letter, self.top_line());
+        } else {
+            self.message = Some("Invalid mark letter".to_string());
+        }
+    }
+
+    /// jump to a mark, `'` being the position before the last jump, `^` the
+    /// start and `$` the end of the input
+    fn goto_mark(&mut self, letter: char) {
+        let line = match letter {
+            '\'' => self.last_position,
+            '^' => Some(0),
+            '$' => {
+                self.jump_to_end();
+                return;
+            }
+            _ => self.marks.get(&letter).copied(),
+        };
+        match line {
+            Some(line) => self.jump_to_line(line),
+            None => self.message = Some("Mark not set".to_string()),
+        }
+    }
+
+    /// file selected by a file command, `None` with a message when there is none
+    fn file_navigation(&mut self, action: Action) -> Option<Navigation> {
+        let message = match action {
+            Action::NextFile if self.file_index + 1 < self.file_count => {
+                return Some(Navigation::Next)
+            }
+            Action::PrevFile if self.file_index > 0 => return Some(Navigation::Previous),
+            Action::IndexFile if self.file_count > 0 => return Some(Navigation::First),
+            Action::RemoveFile if self.file_count > 1 => return Some(Navigation::Delete),
+            Action::NextFile => "No next file",
+            Action::PrevFile => "No previous file",
+            Action::RemoveFile => "Cannot remove the only file",
+            _ => return None,
+        };
+        self.message = Some(message.to_string());
+        None
+    }
+
+    /// file to show when leaving the end of this one
+    fn navigation_at_eof(&self) -> Navigation {
+        if self.next_file.is_some() {
+            Navigation::Next
+        } else {
+            Navigation::Quit
+        }
+    }
+
+    /// show the last page
+    fn goto_end(&mut self) {
+        self.upper_mark = self.line_count.saturating_sub(self.content_rows);
+    }
+
+    /// keep reading appended data, showing the end of the input
+    fn start_following(&mut self) {
+        self.following = true;
+        self.goto_end();
+    }
+
     /// check if close
     fn should_close(&mut self) -> bool {
         self.upper_mark
@@ -762,6 +1540,11 @@ impl<'a> Pager<'a> {
             .ge(&self.line_count)
     }
 
+    /// check if the last page of a complete input is shown
+    fn at_eof(&mut self) -> bool {
+        self.eof && self.should_close()
+    }
+
     /// page down
     fn page_down(&mut self) {
         let new_upper_mark = self.upper_mark.saturating_add(self.content_rows * 2);
@@ -779,8 +1562,8 @@ impl<'a> Pager<'a> {
             .saturating_sub(self.content_rows.saturating_add(self.line_squeezed));
 
         if self.squeeze {
-            for line in self.lines.iter().take(self.upper_mark).rev() {
-                if line.is_empty() {
+            for row in self.rows.iter().take(self.upper_mark).rev() {
+                if row.start == row.end {
                     self.upper_mark = self.upper_mark.saturating_sub(1);
                 } else {
                     break;
@@ -800,15 +1583,14 @@ impl<'a> Pager<'a> {
     }
 
     /// page resize
-    fn page_resize(&mut self, _: u16, row: u16, option_line: Option<u16>) {
+    fn page_resize(&mut self, col: u16, row: u16, option_line: Option<u16>) {
         if option_line.is_none() {
             self.content_rows = row.saturating_sub(1) as usize;
         };
-    }
-
-    /// bind lines numbered
-    fn bind_lines_numbered(&mut self, numbers_line: Vec<&'a str>) {
-        self.lines = numbers_line;
+        if col as usize != self.cols {
+            self.cols = col as usize;
+            self.rebuild_rows();
+        }
     }
 
     /// draw
@@ -833,14 +1615,14 @@ impl<'a> Pager<'a> {
         self.line_squeezed = 0;
         let mut previous_line_blank = false;
         let mut displayed_lines = Vec::new();
-        let mut iter = self.lines.iter().skip(self.upper_mark);
+        let mut iter = self.rows.iter().skip(self.upper_mark);
 
         while displayed_lines.len() < self.content_rows {
             match iter.next() {
                 Some(line) => {
                     if self.squeeze {
                         self.test_flags.squeeze = true;
-                        match (line.is_empty(), previous_line_blank) {
+                        match (line.start == line.end, previous_line_blank) {
                             (true, false) => {
                                 previous_line_blank = true;
                                 displayed_lines.push(line);
@@ -859,18 +1641,16 @@ impl<'a> Pager<'a> {
                         displayed_lines.push(line);
                     }
                 }
-                None => {
-                    self.upper_mark = self.line_count;
-                    break;
-                }
+                None => break,
             }
         }
 
-        for line in displayed_lines {
+        for row in displayed_lines {
             if let Some(ref mut output) = output {
-                output.push_str(line);
+                output.push_str(&self.render_row(row, false));
                 output.push('\n');
             }
+            let line = self.render_row(row, true);
             stdout.write_all(format!("\r{line}\n").as_bytes()).unwrap();
         }
     }
@@ -883,9 +1663,16 @@ impl<'a> Pager<'a> {
         wrong_key: Option<char>,
         output: &mut Option<&mut String>,
     ) {
-        let status_inner = if lower_mark == self.line_count {
+        let status_inner = if self.following {
+            "Waiting for data... (interrupt to abort)".to_string()
+        } else if lower_mark == self.line_count && !self.eof {
+            ":".to_string()
+        } else if lower_mark == self.line_count {
             if self.next_file.is_some() {
-                format!("Next file: {}", self.next_file.unwrap_or_default())
+                format!(
+                    "Next file: {}",
+                    self.next_file.as_deref().unwrap_or_default()
+                )
             } else {
                 "End of file".to_string()
             }
@@ -898,7 +1685,20 @@ impl<'a> Pager<'a> {
             }
         };
 
-        let status = format!("{status_inner}");
+        let status_inner = if self.file_count > 1 && !self.following {
+            format!(
+                "{status_inner} (file {} of {})",
+                self.file_index + 1,
+                self.file_count
+            )
+        } else {
+            status_inner
+        };
+        let status = match self.message.take() {
+            Some(message) => message,
+            None if self.shift > 0 => format!("{status_inner} (column {})", self.shift + 1),
+            None => status_inner,
+        };
         let banner = match (self.silent, wrong_key) {
             (true, Some(key)) => {
                 format!("{status} [Unknown key: '{key}'. Press 'h' for instructions.]")
@@ -951,7 +1751,7 @@ impl<'a> Pager<'a> {
 }
 
 /// search pattern in file
-fn search_pattern_in_file(lines: &[&str], pattern: &str) -> Option<usize> {
+fn search_pattern_in_file(lines: &[String], pattern: &str) -> Option<usize> {
     if lines.is_empty() || pattern.is_empty() {
         return None;
     }
@@ -972,43 +1772,11 @@ fn paging_add_back_message(options: &Configs, stdout: &mut Stdout) -> UResult<()
     Ok(())
 }
 
-// Break the lines on the cols of the terminal
-fn break_buff(buff: &str, cols: usize) -> Vec<&str> {
-    // We _could_ do a precise with_capacity here, but that would require scanning the
-    // whole buffer. Just guess a value instead.
-    let mut lines = Vec::with_capacity(2048);
-
-    for l in buff.lines() {
-        lines.append(&mut break_line(l, cols));
-    }
-    lines
-}
-
-/// Break a line on the cols of the terminal
-fn break_line(line: &str, cols: usize) -> Vec<&str> {
-    let width = UnicodeWidthStr::width(line);
-    let mut lines = Vec::new();
-    if width < cols {
-        lines.push(line);
-        return lines;
-    }
-
-    let gr_idx = UnicodeSegmentation::grapheme_indices(line, true);
-    let mut last_index = 0;
-    let mut total_width = 0;
-    for (index, grapheme) in gr_idx {
-        let width = UnicodeWidthStr::width(grapheme);
-        total_width += width;
-
-        if total_width > cols {
-            lines.push(&line[last_index..index]);
-            last_index = index;
-            total_width = width;
-        }
-    }
-
-    if last_index != line.len() {
-        lines.push(&line[last_index..]);
+/// Text of a line as searched, without the colours shown with `-R`
+fn searched_text(line: &str, raw: bool) -> Cow<'_, str> {
+    if raw {
+        Cow::Owned(display::strip_sgr(line).0)
+    } else {
+        Cow::Borrowed(line)
     }
-    lines
 }
@@ -0,0 +1,222 @@
+//! This file is part of the easybox package.
+//
+// (c) Yuyichen2025 <vyu112@foxmail.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+use crossterm::style::Attribute;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Distance between tab stops
+const TAB_WIDTH: usize = 8;
+
+/// Piece of an input line with its own display rules
+enum Token<'a> {
+    /// SGR escape sequence (`ESC [ ... m`)
+    Sgr(&'a str),
+    /// tab, expanded to the next tab stop
+    Tab,
+    /// other control character, shown in caret notation
+    Control(char),
+    /// grapheme cluster, including its combining marks
+    Text(&'a str),
+}
+
+/// Split `line[start..end]` into tokens, with the byte range of each
+fn tokens(line: &str, start: usize, end: usize) -> Vec<(usize, usize, Token<'_>)> {
+    let mut tokens = Vec::new();
+    let mut pos = start;
+    while pos < end {
+        let (len, token) = match sgr_len(&line.as_bytes()[pos..end]) {
+            Some(len) => (len, Token::Sgr(&line[pos..pos + len])),
+            None => {
+                let grapheme = line[pos..end].graphemes(true).next().unwrap_or_default();
+                let c = grapheme.chars().next().unwrap_or_default();
+                if c == '\t' {
+                    (1, Token::Tab)
+                } else if c.is_control() {
+                    (c.len_utf8(), Token::Control(c))
+                } else {
+                    (grapheme.len(), Token::Text(grapheme))
+                }
+            }
+        };
+        tokens.push((pos, pos + len, token));
+        pos += len;
+    }
+    tokens
+}
+
+/// Length of the SGR escape sequence at the start of `bytes`
+fn sgr_len(bytes: &[u8]) -> Option<usize> {
+    if !bytes.starts_with(b"\x1b[") {
+        return None;
+    }
+    let params = bytes[2..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit() || **b == b';' || **b == b':')
+        .count();
+    match bytes.get(2 + params) {
+        Some(b'm') => Some(3 + params),
+        _ => None,
+    }
+}
+
+/// Whether an SGR sequence resets every attribute
+fn is_sgr_reset(sgr: &str) -> bool {
+    matches!(sgr, "\x1b[m" | "\x1b[0m")
+}
+
+/// Caret notation of a control character, `^[` for ESC
+fn caret(c: char) -> String {
+    if c.is_ascii() {
+        format!("^{}", ((c as u8) ^ 0x40) as char)
+    } else {
+        format!("<U+{:04X}>", c as u32)
+    }
+}
+
+/// Columns taken by a token at column `col`
+fn token_width(token: &Token, col: usize, raw: bool) -> usize {
+    match token {
+        Token::Sgr(_) if raw => 0,
+        // ESC in caret notation followed by the rest of the sequence
+        Token::Sgr(sgr) => sgr.len() + 1,
+        Token::Tab => TAB_WIDTH - col % TAB_WIDTH,
+        Token::Control(c) => caret(*c).len(),
+        Token::Text(text) => UnicodeWidthStr::width(*text),
+    }
+}
+
+/// Break a line on `cols` display columns, returning the byte range of each
+/// row. A grapheme or an escape sequence is never split.
+pub fn break_line(line: &str, cols: usize, raw: bool) -> Vec<(usize, usize)> {
+    let mut rows = Vec::new();
+    let mut row_start = 0;
+    let mut col = 0;
+    for (pos, _, token) in tokens(line, 0, line.len()) {
+        let mut width = token_width(&token, col, raw);
+        if col > 0 && col + width > cols {
+            rows.push((row_start, pos));
+            row_start = pos;
+            col = 0;
+            width = token_width(&token, col, raw);
+        }
+        col += width;
+    }
+    rows.push((row_start, line.len()));
+    rows
+}
+
+/// Byte range of the part of a line shown in the columns
+/// `first_col..first_col + cols`, and the column where that part starts.
+/// A wide character crossing `first_col` is left out.
+pub fn column_range(line: &str, first_col: usize, cols: usize, raw: bool) -> (usize, usize, usize) {
+    let mut range: Option<(usize, usize)> = None;
+    let mut col = 0;
+    for (pos, _, token) in tokens(line, 0, line.len()) {
+        let width = token_width(&token, col, raw);
+        if range.is_none() && col >= first_col {
+            range = Some((pos, col));
+        }
+        if let Some((start, start_col)) = range {
+            if col + width > start_col + cols {
+                return (start, pos, start_col);
+            }
+        }
+        col += width;
+    }
+    match range {
+        Some((start, start_col)) => (start, line.len(), start_col),
+        None => (line.len(), line.len(), col),
+    }
+}
+
+/// Line without its SGR sequences, with the offset in `line` of each byte
+/// of the result, so matches found in the text can be mapped back
+pub fn strip_sgr(line: &str) -> (String, Vec<usize>) {
+    let mut text = String::with_capacity(line.len());
+    let mut offsets = Vec::with_capacity(line.len() + 1);
+    for (start, end, token) in tokens(line, 0, line.len()) {
+        if let Token::Sgr(_) = token {
+            continue;
+        }
+        offsets.extend(start..end);
+        text.push_str(&line[start..end]);
+    }
+    offsets.push(line.len());
+    (text, offsets)
+}
+
+/// Text to write on the terminal for `line[start..end]`, which is shown
+/// from column `start_col` of the line for tab expansion.
+///
+/// Control characters are shown in caret notation, except SGR sequences
+/// when `raw`, which are kept along with the colours set before `start`.
+/// `matches` are byte ranges over the whole line shown in reverse video.
+pub fn render(
+    line: &str,
+    start: usize,
+    end: usize,
+    start_col: usize,
+    raw: bool,
+    matches: &[(usize, usize)],
+) -> String {
+    let mut text = String::with_capacity(end - start);
+    let mut colored = false;
+    if raw {
+        for (_, _, token) in tokens(line, 0, start) {
+            if let Token::Sgr(sgr) = token {
+                if is_sgr_reset(sgr) {
+                    text.clear();
+                } else {
+                    text.push_str(sgr);
+                }
+            }
+        }
+        colored = !text.is_empty();
+    }
+
+    let mut col = start_col;
+    let mut reversed = false;
+    for (pos, _, token) in tokens(line, start, end) {
+        let in_match = matches.iter().any(|&(s, e)| s <= pos && pos < e);
+        if in_match != reversed {
+            reversed = in_match;
+            let attribute = if reversed {
+                Attribute::Reverse
+            } else {
+                Attribute::NoReverse
+            };
+            text.push_str(&attribute.to_string());
+        }
+        let width = token_width(&token, col, raw);
+        match token {
+            Token::Sgr(sgr) if raw => {
+                text.push_str(sgr);
+                colored = true;
+                if reversed {
+                    text.push_str(&Attribute::Reverse.to_string());
+                }
+            }
+            Token::Sgr(sgr) => {
+                text.push_str(&caret('\x1b'));
+                text.push_str(&sgr[1..]);
+            }
+            Token::Tab => text.push_str(&" ".repeat(width)),
+            Token::Control(c) => text.push_str(&caret(c)),
+            Token::Text(grapheme) => text.push_str(grapheme),
+        }
+        col += width;
+    }
+
+    if reversed {
+        text.push_str(&Attribute::NoReverse.to_string());
+    }
+    if colored {
+        text.push_str(&Attribute::Reset.to_string());
+    }
+    text
+}
@@ -0,0 +1,134 @@
+//! This file is part of the easybox package.
+//
+// (c) Yuyichen2025 <vyu112@foxmail.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+/// What to page after leaving the current file
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Navigation {
+    /// leave the pager (`q`, `:q`)
+    Quit,
+    /// next file of the list (`:n`)
+    Next,
+    /// previous file of the list (`:p`)
+    Previous,
+    /// first file of the list (`:x`)
+    First,
+    /// open a file, inserted after the current one (`:e`)
+    Examine(String),
+    /// remove the current file from the list (`:d`)
+    Delete,
+}
+
+/// File given on the command line or opened with `:e`
+struct Entry {
+    name: String,
+    /// input line shown at the top when the file was left
+    position: Option<usize>,
+}
+
+/// Files to page and the one currently shown
+pub struct FileList {
+    entries: Vec<Entry>,
+    current: usize,
+}
+
+impl FileList {
+    /// List of the files given on the command line
+    pub fn new<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            entries: names
+                .into_iter()
+                .map(|name| Entry {
+                    name: name.into(),
+                    position: None,
+                })
+                .collect(),
+            current: 0,
+        }
+    }
+
+    /// Name of the current file, `None` past the end of the list
+    pub fn current(&self) -> Option<&str> {
+        self.entries.get(self.current).map(|e| e.name.as_str())
+    }
+
+    /// Name of the file after the current one
+    pub fn next_name(&self) -> Option<&str> {
+        self.entries.get(self.current + 1).map(|e| e.name.as_str())
+    }
+
+    /// Index of the current file, from 0
+    pub fn index(&self) -> usize {
+        self.current
+    }
+
+    /// Number of files in the list
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the list is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Top line remembered for the current file
+    pub fn position(&self) -> Option<usize> {
+        self.entries.get(self.current).and_then(|e| e.position)
+    }
+
+    /// Remember the top line of the current file for when it is shown again
+    pub fn save_position(&mut self, line: usize) {
+        if let Some(entry) = self.entries.get_mut(self.current) {
+            entry.position = Some(line);
+        }
+    }
+
+    /// Move to the file selected by `navigation`, `false` when there is
+    /// nothing left to page
+    pub fn navigate(&mut self, navigation: &Navigation) -> bool {
+        match navigation {
+            Navigation::Quit => return false,
+            Navigation::Next => self.current += 1,
+            Navigation::Previous => self.current = self.current.saturating_sub(1),
+            Navigation::First => self.current = 0,
+            Navigation::Examine(name) => {
+                let at = (self.current + 1).min(self.entries.len());
+                self.entries.insert(
+                    at,
+                    Entry {
+                        name: name.clone(),
+                        position: None,
+                    },
+                );
+                self.current = at;
+            }
+            Navigation::Delete => {
+                self.remove_current(false);
+                // Deleting the last file shows the one before it
+                if self.current == self.entries.len() {
+                    self.current = self.current.saturating_sub(1);
+                }
+            }
+        }
+        self.current < self.entries.len()
+    }
+
+    /// Drop the current file, moving back to the file before it when `back`,
+    /// or on to the following one otherwise
+    pub fn remove_current(&mut self, back: bool) {
+        if self.current < self.entries.len() {
+            self.entries.remove(self.current);
+        }
+        if back {
+            self.current = self.current.saturating_sub(1);
+        }
+    }
+}
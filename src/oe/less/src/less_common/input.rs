@@ -0,0 +1,248 @@
+//! This file is part of the easybox package.
+//
+// (c) Yuyichen2025 <vyu112@foxmail.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+use super::preprocess::Close;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+/// Size of a single read from a streaming source
+const CHUNK_SIZE: usize = 8192;
+
+/// Text source of the pager.
+///
+/// A source is either a fully loaded buffer (regular files) or a stream
+/// (pipes, stdin) that is read by a background thread, so the pager can
+/// start displaying before the producer finishes. Regular files can also
+/// be polled again for appended data when following them.
+pub struct Input {
+    /// text loaded but not yet handed to the pager
+    loaded: Option<String>,
+    /// chunks coming from the reader thread
+    rx: Option<Receiver<io::Result<Vec<u8>>>>,
+    /// file watched for appended data
+    tail: Option<Tail>,
+    /// bytes received after the last complete line
+    pending: Vec<u8>,
+    /// no more data will arrive
+    eof: bool,
+    /// the file was truncated or replaced since the last poll
+    reset: bool,
+    /// `LESSCLOSE` command run when the input is dropped
+    _close: Option<Close>,
+}
+
+/// Position of a regular file already handed to the pager
+struct Tail {
+    path: PathBuf,
+    file: Option<File>,
+    pos: u64,
+    ino: u64,
+}
+
+impl Input {
+    /// Input backed by an already loaded buffer
+    pub fn from_string(buff: String) -> Self {
+        Self {
+            loaded: Some(buff),
+            rx: None,
+            tail: None,
+            pending: Vec::new(),
+            eof: true,
+            reset: false,
+            _close: None,
+        }
+    }
+
+    /// Input backed by the bytes read from a regular file, which can be
+    /// polled later for data appended after them
+    pub fn from_file(path: &Path, buff: Vec<u8>) -> Self {
+        let ino = fs::metadata(path).map(|m| m.ino()).unwrap_or_default();
+        let pos = buff.len() as u64;
+        let mut input = Self::from_string(String::from_utf8_lossy(&buff).into_owned());
+        input.tail = Some(Tail {
+            path: path.to_path_buf(),
+            file: None,
+            pos,
+            ino,
+        });
+        input
+    }
+
+    /// Input read incrementally from any reader
+    pub fn from_reader<R: Read + Send + 'static>(mut reader: R) -> Self {
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let mut chunk = vec![0u8; CHUNK_SIZE];
+            loop {
+                match reader.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        if tx.send(Ok(chunk[..n].to_vec())).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        break;
+                    }
+                }
+            }
+        });
+
+        Self {
+            loaded: None,
+            rx: Some(rx),
+            tail: None,
+            pending: Vec::new(),
+            eof: false,
+            reset: false,
+            _close: None,
+        }
+    }
+
+    /// Input read incrementally from the standard input
+    pub fn stdin() -> Self {
+        Self::from_reader(io::stdin())
+    }
+
+    /// Run the `LESSCLOSE` command once the input is no longer paged
+    pub fn on_close(mut self, close: Close) -> Self {
+        self._close = Some(close);
+        self
+    }
+
+    /// Whether all the data has been received
+    pub fn is_eof(&self) -> bool {
+        self.eof
+    }
+
+    /// Whether the content was truncated or replaced, so the pager must
+    /// drop what it has before appending the next poll result
+    pub fn take_reset(&mut self) -> bool {
+        std::mem::take(&mut self.reset)
+    }
+
+    /// Fetch the complete lines received since the last call without blocking.
+    ///
+    /// A trailing partial line is kept back until its newline arrives or
+    /// the source reaches end of file.
+    pub fn poll(&mut self) -> io::Result<Option<String>> {
+        if let Some(buff) = self.loaded.take() {
+            return Ok(Some(buff));
+        }
+        if self.tail.is_some() {
+            return self.poll_tail();
+        }
+        let rx = match &self.rx {
+            Some(rx) => rx,
+            None => return Ok(None),
+        };
+
+        let mut received = false;
+        loop {
+            match rx.try_recv() {
+                Ok(Ok(chunk)) => {
+                    self.pending.extend_from_slice(&chunk);
+                    received = true;
+                }
+                Ok(Err(e)) => {
+                    self.finish();
+                    return Err(e);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finish();
+                    break;
+                }
+            }
+        }
+
+        if !received && !self.eof {
+            return Ok(None);
+        }
+        Ok(self.take_lines())
+    }
+
+    /// Block until the source is exhausted and return everything not yet fetched
+    pub fn read_to_end(&mut self) -> io::Result<String> {
+        let mut buff = self.loaded.take().unwrap_or_default();
+        if let Some(rx) = self.rx.take() {
+            for chunk in rx {
+                self.pending.extend_from_slice(&chunk?);
+            }
+        }
+        self.eof = true;
+        buff.push_str(&String::from_utf8_lossy(&std::mem::take(&mut self.pending)));
+        Ok(buff)
+    }
+
+    /// Read what was appended to the file, following truncation and rotation
+    fn poll_tail(&mut self) -> io::Result<Option<String>> {
+        let tail = match self.tail.as_mut() {
+            Some(tail) => tail,
+            None => return Ok(None),
+        };
+        // The file may be missing for a moment while it is being rotated
+        let meta = match fs::metadata(&tail.path) {
+            Ok(meta) => meta,
+            Err(_) => return Ok(None),
+        };
+
+        if meta.ino() != tail.ino {
+            tail.file = None;
+            tail.ino = meta.ino();
+            tail.pos = 0;
+            self.pending.clear();
+            self.reset = true;
+        } else if meta.len() < tail.pos {
+            tail.pos = 0;
+            self.pending.clear();
+            self.reset = true;
+        }
+        if meta.len() == tail.pos {
+            return Ok(self.reset.then(String::new));
+        }
+
+        if tail.file.is_none() {
+            tail.file = Some(File::open(&tail.path)?);
+        }
+        if let Some(file) = tail.file.as_mut() {
+            file.seek(SeekFrom::Start(tail.pos))?;
+            let read = file.read_to_end(&mut self.pending)?;
+            tail.pos += read as u64;
+        }
+        Ok(self.take_lines().or_else(|| self.reset.then(String::new)))
+    }
+
+    fn finish(&mut self) {
+        self.rx = None;
+        self.eof = true;
+    }
+
+    fn take_lines(&mut self) -> Option<String> {
+        // A followed file may still complete its last line
+        let end = if self.eof && self.tail.is_none() {
+            self.pending.len()
+        } else {
+            match self.pending.iter().rposition(|&b| b == b'\n') {
+                Some(pos) => pos + 1,
+                None => return None,
+            }
+        };
+        if end == 0 {
+            return None;
+        }
+        let rest = self.pending.split_off(end);
+        let lines = std::mem::replace(&mut self.pending, rest);
+        Some(String::from_utf8_lossy(&lines).into_owned())
+    }
+}
@@ -0,0 +1,424 @@
+//! This file is part of the easybox package.
+//
+// (c) Yuyichen2025 <vyu112@foxmail.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use uucore::error::USimpleError;
+use uucore::show;
+
+/// Key pressed, with the modifiers that change its meaning
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // Shift is already part of the character
+        let modifiers = match code {
+            KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self {
+            code,
+            modifiers: modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT),
+        }
+    }
+
+    fn char(c: char) -> Self {
+        Self::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    fn control(c: char) -> Self {
+        Self::new(KeyCode::Char(c.to_ascii_lowercase()), KeyModifiers::CONTROL)
+    }
+
+    /// Digit typed as part of a count
+    pub fn digit(&self) -> Option<usize> {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers.is_empty() => {
+                c.to_digit(10).map(|digit| digit as usize)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the key types a character
+    pub fn is_char(&self) -> bool {
+        matches!(self.code, KeyCode::Char(_))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers.contains(KeyModifiers::CONTROL) => {
+                write!(f, "^{}", c.to_ascii_uppercase())
+            }
+            KeyCode::Char(c) => write!(f, "{c}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+/// Command a key sequence is bound to, named as in lesskey(1)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    /// forw-line
+    ForwLine,
+    /// back-line
+    BackLine,
+    /// forw-screen
+    ForwScreen,
+    /// back-screen
+    BackScreen,
+    /// forw-forever
+    ForwForever,
+    /// forw-search
+    ForwSearch,
+    /// back-search
+    BackSearch,
+    /// repeat-search
+    RepeatSearch,
+    /// reverse-search
+    ReverseSearch,
+    /// filter
+    Filter,
+    /// goto-line
+    GotoLine,
+    /// goto-end
+    GotoEnd,
+    /// percent
+    Percent,
+    /// set-mark
+    SetMark,
+    /// goto-mark
+    GotoMark,
+    /// left-scroll
+    LeftScroll,
+    /// right-scroll
+    RightScroll,
+    /// toggle-option
+    ToggleOption,
+    /// next-file
+    NextFile,
+    /// prev-file
+    PrevFile,
+    /// index-file
+    IndexFile,
+    /// examine
+    Examine,
+    /// remove-file
+    RemoveFile,
+    /// quit
+    Quit,
+    /// invalid
+    Invalid,
+    /// noaction
+    Noaction,
+}
+
+impl Action {
+    fn from_name(name: &str) -> Option<Self> {
+        let action = match name {
+            "forw-line" => Action::ForwLine,
+            "back-line" => Action::BackLine,
+            "forw-screen" => Action::ForwScreen,
+            "back-screen" => Action::BackScreen,
+            "forw-forever" => Action::ForwForever,
+            "forw-search" => Action::ForwSearch,
+            "back-search" => Action::BackSearch,
+            "repeat-search" => Action::RepeatSearch,
+            "reverse-search" => Action::ReverseSearch,
+            "filter" => Action::Filter,
+            "goto-line" => Action::GotoLine,
+            "goto-end" => Action::GotoEnd,
+            "percent" => Action::Percent,
+            "set-mark" => Action::SetMark,
+            "goto-mark" => Action::GotoMark,
+            "left-scroll" => Action::LeftScroll,
+            "right-scroll" => Action::RightScroll,
+            "toggle-option" => Action::ToggleOption,
+            "next-file" => Action::NextFile,
+            "prev-file" => Action::PrevFile,
+            "index-file" => Action::IndexFile,
+            "examine" => Action::Examine,
+            "remove-file" => Action::RemoveFile,
+            "quit" => Action::Quit,
+            "invalid" => Action::Invalid,
+            "noaction" => Action::Noaction,
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+/// Result of looking up the keys typed so far
+pub enum Lookup {
+    /// the keys are bound to a command
+    Found(Action),
+    /// the keys start a longer sequence
+    Prefix,
+    /// nothing is bound to the keys
+    Unbound,
+}
+
+/// Table of key bindings
+pub struct Bindings {
+    /// bindings from the lesskey file, looked up first
+    user: HashMap<Vec<Key>, Action>,
+    /// built-in bindings, dropped by `#stop`
+    default: HashMap<Vec<Key>, Action>,
+    /// variables of the `#env` section
+    env: HashMap<String, String>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Action::*;
+        let mut default = HashMap::new();
+        let mut bind = |keys: &[Key], action| {
+            default.insert(keys.to_vec(), action);
+        };
+        let plain = |keys: &str| keys.chars().map(Key::char).collect::<Vec<_>>();
+        let special = |code| Key::new(code, KeyModifiers::NONE);
+
+        for c in ['e', 'j'] {
+            bind(&[Key::char(c)], ForwLine);
+        }
+        for c in ['e', 'n'] {
+            bind(&[Key::control(c)], ForwLine);
+        }
+        bind(&[special(KeyCode::Enter)], ForwLine);
+        for c in ['y', 'k'] {
+            bind(&[Key::char(c)], BackLine);
+        }
+        for c in ['y', 'k', 'p'] {
+            bind(&[Key::control(c)], BackLine);
+        }
+        bind(&[Key::char('f')], ForwScreen);
+        bind(&[Key::char(' ')], ForwScreen);
+        for c in ['f', 'v'] {
+            bind(&[Key::control(c)], ForwScreen);
+        }
+        bind(&[special(KeyCode::Down)], ForwScreen);
+        bind(&[special(KeyCode::PageDown)], ForwScreen);
+        bind(&[Key::char('b')], BackScreen);
+        bind(&[Key::control('b')], BackScreen);
+        bind(&[special(KeyCode::Up)], BackScreen);
+        bind(&[special(KeyCode::PageUp)], BackScreen);
+        bind(&plain("F"), ForwForever);
+        bind(&plain("/"), ForwSearch);
+        bind(&plain("?"), BackSearch);
+        bind(&plain("n"), RepeatSearch);
+        bind(&plain("N"), ReverseSearch);
+        bind(&plain("&"), Filter);
+        bind(&plain("g"), GotoLine);
+        bind(&plain("<"), GotoLine);
+        bind(&plain("G"), GotoEnd);
+        bind(&plain(">"), GotoEnd);
+        bind(&plain("p"), Percent);
+        bind(&plain("%"), Percent);
+        bind(&plain("m"), SetMark);
+        bind(&plain("'"), GotoMark);
+        bind(&[special(KeyCode::Left)], LeftScroll);
+        bind(&[special(KeyCode::Right)], RightScroll);
+        bind(&plain("-"), ToggleOption);
+        bind(&plain(":n"), NextFile);
+        bind(&plain(":p"), PrevFile);
+        bind(&plain(":x"), IndexFile);
+        bind(&plain(":e"), Examine);
+        bind(&plain(":d"), RemoveFile);
+        for keys in ["q", "Q", ":q", ":Q", "ZZ"] {
+            bind(&plain(keys), Quit);
+        }
+
+        Self {
+            user: HashMap::new(),
+            default,
+            env: HashMap::new(),
+        }
+    }
+}
+
+impl Bindings {
+    /// Default bindings, with those of the lesskey file named by `$LESSKEY`,
+    /// or `lesskey` in the user configuration directory
+    pub fn load() -> Self {
+        let mut bindings = Self::default();
+        let path = match env::var_os("LESSKEY") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+                .map(|dir| dir.join("lesskey")),
+        };
+        if let Some(path) = path {
+            if let Ok(text) = fs::read_to_string(&path) {
+                bindings.parse(&text, &path.to_string_lossy());
+            }
+        }
+        bindings
+    }
+
+    /// Add the bindings of a lesskey source file
+    pub fn parse(&mut self, text: &str, name: &str) {
+        let mut section = "#command";
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('#') {
+                match line.split_whitespace().next().unwrap_or_default() {
+                    "#stop" => self.default.clear(),
+                    header @ ("#command" | "#line-edit" | "#env") => section = header,
+                    // Anything else is a comment
+                    _ => {}
+                }
+                continue;
+            }
+            match section {
+                "#command" => {
+                    let mut words = line.split_whitespace();
+                    let keys = words.next().and_then(parse_keys);
+                    let action = words.next().and_then(Action::from_name);
+                    match (keys, action) {
+                        (Some(keys), Some(action)) => {
+                            self.user.insert(keys, action);
+                        }
+                        _ => show!(USimpleError::new(
+                            0,
+                            format!("{}:{}: invalid key binding", name, number + 1)
+                        )),
+                    }
+                }
+                "#env" => {
+                    if let Some((variable, value)) = line.split_once('=') {
+                        self.env
+                            .insert(variable.trim().to_string(), value.trim().to_string());
+                    }
+                }
+                // Line editing keys are not configurable
+                _ => {}
+            }
+        }
+    }
+
+    /// Value of a variable of the `#env` section
+    pub fn env(&self, variable: &str) -> Option<&str> {
+        self.env.get(variable).map(String::as_str)
+    }
+
+    /// Command bound to the keys typed so far
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        if let Some(action) = self.user.get(keys).or_else(|| self.default.get(keys)) {
+            return Lookup::Found(*action);
+        }
+        let is_prefix = self
+            .user
+            .keys()
+            .chain(self.default.keys())
+            .any(|bound| bound.len() > keys.len() && bound.starts_with(keys));
+        if is_prefix {
+            Lookup::Prefix
+        } else {
+            Lookup::Unbound
+        }
+    }
+}
+
+/// Keys of a lesskey key string: `^X` for control keys, `\e` for escape,
+/// `\kX` for special keys, and escape sequences decoded as the terminal does
+fn parse_keys(text: &str) -> Option<Vec<Key>> {
+    const ESC: char = '\x1b';
+    let mut chars = Vec::new();
+    let mut iter = text.chars();
+    while let Some(c) = iter.next() {
+        let c = match c {
+            '^' => match iter.next()? {
+                '[' => ESC,
+                '?' => '\x7f',
+                c => ((c.to_ascii_uppercase() as u8) & 0x1f) as char,
+            },
+            '\\' => match iter.next()? {
+                'e' => ESC,
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'b' => '\x08',
+                'k' => {
+                    // Special keys are spelled as the escape sequences crossterm decodes
+                    let sequence = match iter.next()? {
+                        'u' => "[A",
+                        'd' => "[B",
+                        'r' => "[C",
+                        'l' => "[D",
+                        'U' => "[5~",
+                        'D' => "[6~",
+                        'h' => "[H",
+                        'e' => "[F",
+                        'x' => "[3~",
+                        _ => return None,
+                    };
+                    chars.push(ESC);
+                    chars.extend(sequence.chars());
+                    continue;
+                }
+                c => c,
+            },
+            c => c,
+        };
+        chars.push(c);
+    }
+
+    let mut keys = Vec::new();
+    let mut rest = chars.as_slice();
+    while let Some((&c, tail)) = rest.split_first() {
+        rest = tail;
+        let key = match c {
+            ESC => match rest {
+                ['[', 'A', tail @ ..] => (Key::new(KeyCode::Up, KeyModifiers::NONE), tail),
+                ['[', 'B', tail @ ..] => (Key::new(KeyCode::Down, KeyModifiers::NONE), tail),
+                ['[', 'C', tail @ ..] => (Key::new(KeyCode::Right, KeyModifiers::NONE), tail),
+                ['[', 'D', tail @ ..] => (Key::new(KeyCode::Left, KeyModifiers::NONE), tail),
+                ['[', 'H', tail @ ..] => (Key::new(KeyCode::Home, KeyModifiers::NONE), tail),
+                ['[', 'F', tail @ ..] => (Key::new(KeyCode::End, KeyModifiers::NONE), tail),
+                ['[', '3', '~', tail @ ..] => (Key::new(KeyCode::Delete, KeyModifiers::NONE), tail),
+                ['[', '5', '~', tail @ ..] => (Key::new(KeyCode::PageUp, KeyModifiers::NONE), tail),
+                ['[', '6', '~', tail @ ..] => {
+                    (Key::new(KeyCode::PageDown, KeyModifiers::NONE), tail)
+                }
+                [c, tail @ ..] => (Key::new(KeyCode::Char(*c), KeyModifiers::ALT), tail),
+                [] => (Key::new(KeyCode::Esc, KeyModifiers::NONE), rest),
+            },
+            '\r' | '\n' => (Key::new(KeyCode::Enter, KeyModifiers::NONE), rest),
+            '\t' => (Key::new(KeyCode::Tab, KeyModifiers::NONE), rest),
+            '\x7f' | '\x08' => (Key::new(KeyCode::Backspace, KeyModifiers::NONE), rest),
+            c if c.is_ascii_control() => (Key::control((c as u8 | 0x60) as char), rest),
+            c => (Key::char(c), rest),
+        };
+        keys.push(key.0);
+        rest = key.1;
+    }
+    if keys.is_empty() {
+        None
+    } else {
+        Some(keys)
+    }
+}
@@ -0,0 +1,183 @@
+//! This file is part of the easybox package.
+//
+// (c) Yuyichen2025 <vyu112@foxmail.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+use super::input::Input;
+use flate2::read::MultiGzDecoder;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+/// Input preprocessor run when a file is opened
+const LESSOPEN: &str = "LESSOPEN";
+/// Command run when a file opened through `LESSOPEN` is closed
+const LESSCLOSE: &str = "LESSCLOSE";
+
+/// Open a file through the `LESSOPEN` preprocessor, or decompress it when
+/// no preprocessor is set. `None` means the file is read as it is.
+pub fn open(path: &Path) -> io::Result<Option<Input>> {
+    match env::var(LESSOPEN) {
+        Ok(template) if !template.is_empty() => Ok(lessopen(path, &template)),
+        _ => decompress(path),
+    }
+}
+
+/// Run the `LESSOPEN` template on a file.
+///
+/// `|cmd %s` pages the output of the command, unless it is empty; `||cmd %s`
+/// pages it even when empty. Without a leading `|`, the command prints the
+/// name of a replacement file to page instead. A preprocessor that fails to
+/// run leaves the file as it is.
+fn lessopen(path: &Path, template: &str) -> Option<Input> {
+    let name = path.to_string_lossy();
+    if let Some(pipe) = template.strip_prefix('|') {
+        let (keep_empty, pipe) = match pipe.strip_prefix('|') {
+            Some(pipe) => (true, pipe),
+            None => (false, pipe),
+        };
+        let mut child = shell(&expand(pipe, &[&name]))
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let mut stdout = BufReader::new(child.stdout.take()?);
+        let empty = stdout.fill_buf().map_or(true, |buf| buf.is_empty());
+        if empty && !keep_empty {
+            let _ = child.wait();
+            return None;
+        }
+        let reader = PipeReader {
+            child,
+            stdout: Some(stdout),
+        };
+        return Some(Input::from_reader(reader).on_close(Close::new(path, "-")));
+    }
+
+    let output = shell(&expand(template, &[&name]))
+        .stdout(Stdio::piped())
+        .output()
+        .ok()?;
+    let replacement = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if replacement.is_empty() {
+        return None;
+    }
+    let close = Close::new(path, &replacement);
+    let buff = fs::read(&replacement).ok()?;
+    Some(Input::from_string(String::from_utf8_lossy(&buff).into_owned()).on_close(close))
+}
+
+/// Decompress a gzip, xz or zstd file, recognised by its magic number
+fn decompress(path: &Path) -> io::Result<Option<Input>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let mut file = BufReader::new(File::open(path)?);
+    let magic = file.fill_buf()?;
+    let input = if magic.starts_with(&[0x1f, 0x8b]) {
+        Input::from_reader(MultiGzDecoder::new(file))
+    } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Input::from_reader(XzDecoder {
+            input: Some(file),
+            output: Cursor::new(Vec::new()),
+        })
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        let decoder = ruzstd::StreamingDecoder::new(file)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        Input::from_reader(decoder)
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(input))
+}
+
+/// Replace each `%s` of a template with the next argument, quoted for the shell
+fn expand(template: &str, args: &[&str]) -> String {
+    let mut parts = template.split("%s");
+    let mut command = parts.next().unwrap_or_default().to_string();
+    let mut args = args.iter();
+    for part in parts {
+        match args.next() {
+            Some(arg) => command.push_str(&quote(arg)),
+            None => command.push_str("%s"),
+        }
+        command.push_str(part);
+    }
+    command
+}
+
+/// Single quote a word for `sh`
+fn quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command).stdin(Stdio::null());
+    shell
+}
+
+/// Output of a `LESSOPEN` pipe, reaping the command once dropped
+struct PipeReader {
+    child: Child,
+    stdout: Option<BufReader<ChildStdout>>,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stdout.as_mut() {
+            Some(stdout) => stdout.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        // Closing the pipe first stops a command whose output was not read
+        self.stdout = None;
+        let _ = self.child.wait();
+    }
+}
+
+/// `LESSCLOSE` command, run when the input it belongs to is dropped
+pub struct Close {
+    command: Option<String>,
+}
+
+impl Close {
+    fn new(original: &Path, replacement: &str) -> Self {
+        let command = env::var(LESSCLOSE)
+            .ok()
+            .filter(|template| !template.is_empty())
+            .map(|template| expand(&template, &[&original.to_string_lossy(), replacement]));
+        Self { command }
+    }
+}
+
+impl Drop for Close {
+    fn drop(&mut self) {
+        if let Some(command) = &self.command {
+            let _ = shell(command).status();
+        }
+    }
+}
+
+/// xz stream decoded at the first read, which happens on the input thread
+struct XzDecoder<R> {
+    input: Option<R>,
+    output: Cursor<Vec<u8>>,
+}
+
+impl<R: BufRead> Read for XzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(mut input) = self.input.take() {
+            lzma_rs::xz_decompress(&mut input, self.output.get_mut())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        }
+        self.output.read(buf)
+    }
+}
@@ -0,0 +1,118 @@
+//! This file is part of the easybox package.
+//
+// (c) Yuyichen2025 <vyu112@foxmail.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+use regex::Regex;
+
+/// Direction of a search
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// towards the end of the input (`/`)
+    Forward,
+    /// towards the beginning of the input (`?`)
+    Backward,
+}
+
+impl Direction {
+    /// the opposite direction, used by `N`
+    pub fn reverse(self) -> Self {
+        match self {
+            Direction::Forward => Direction::Backward,
+            Direction::Backward => Direction::Forward,
+        }
+    }
+}
+
+/// Pattern typed at the search or filter prompt
+pub struct Pattern {
+    regex: Regex,
+    /// select the lines that do not match, when prefixed with `!`
+    invert: bool,
+}
+
+impl Pattern {
+    /// Parse a pattern, `None` when it is empty.
+    ///
+    /// An invalid regular expression is searched as a literal string.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (invert, pattern) = match text.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Self {
+            regex: compile(pattern),
+            invert,
+        })
+    }
+
+    /// Pattern matching a literal string
+    pub fn literal(text: &str) -> Self {
+        Self {
+            regex: compile(&regex::escape(text)),
+            invert: false,
+        }
+    }
+
+    /// Whether the line is selected by the pattern
+    pub fn is_match(&self, line: &str) -> bool {
+        self.regex.is_match(line) != self.invert
+    }
+
+    /// Byte ranges of the matches to highlight in a line
+    pub fn matches(&self, line: &str) -> Vec<(usize, usize)> {
+        if self.invert {
+            return Vec::new();
+        }
+        self.regex
+            .find_iter(line)
+            .filter(|m| !m.as_str().is_empty())
+            .map(|m| (m.start(), m.end()))
+            .collect()
+    }
+}
+
+/// Last search, repeated by `n` and `N`
+pub struct Search {
+    /// what to look for
+    pub pattern: Pattern,
+    /// direction given by the command that started the search
+    pub direction: Direction,
+}
+
+fn compile(pattern: &str) -> Regex {
+    Regex::new(pattern).unwrap_or_else(|_| Regex::new(&regex::escape(pattern)).unwrap())
+}
+
+/// Find the first line selected by `is_match`, starting at `start` and
+/// moving in `direction`
+pub fn find_line<F>(
+    lines: &[String],
+    start: usize,
+    direction: Direction,
+    is_match: F,
+) -> Option<usize>
+where
+    F: Fn(&str) -> bool,
+{
+    match direction {
+        Direction::Forward => lines
+            .iter()
+            .enumerate()
+            .skip(start)
+            .find(|(_, line)| is_match(line))
+            .map(|(i, _)| i),
+        Direction::Backward => lines
+            .iter()
+            .enumerate()
+            .take(start.saturating_add(1))
+            .rev()
+            .find(|(_, line)| is_match(line))
+            .map(|(i, _)| i),
+    }
+}
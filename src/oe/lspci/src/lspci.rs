@@ -20,18 +20,17 @@ pub fn oemain(args: impl uucore::Args) -> UResult<()> {
     let config: lspci_common::Config = lspci_common::parse_base_cmd_args(args, ABOUT, USAGE)?;
     let bridges: Vec<lspci_common::Bridge>;
     if config.map_mode {
-        lspci_common::map_the_bus(&config);
-        return Ok(());
+        return lspci_common::map_the_bus(&config);
     }
     if config.path > 0 || config.tree {
-        bridges = lspci_common::grow_tree(&config);
+        bridges = lspci_common::grow_tree(&config)?;
     } else {
         bridges = Vec::new();
     }
     if config.tree {
         lspci_common::show_forest(&bridges, &config)
     } else {
-        lspci_common::show(&config);
+        lspci_common::show(&config)?;
     }
     Ok(())
 }
@@ -8,23 +8,29 @@
 use clap::{crate_version, Arg, Command};
 use std::collections::HashMap;
 use std::fs;
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::io::Read;
 use std::path::Path;
 use std::process;
-use std::process::Command as Cmd;
-use uucore::error::{UResult, UUsageError};
+use uucore::error::{UResult, USimpleError, UUsageError};
 use uucore::format_usage;
 ///
+pub mod lspci_access;
+///
+pub mod lspci_caps;
+///
 pub mod lspci_header;
+///
+pub mod lspci_ids;
+///
+pub mod lspci_json;
+///
+pub mod lspci_kernel;
+///
+pub mod lspci_net;
+use crate::lspci_common::lspci_access::{detect_access, new_access, AccessParams, PciAccess};
+use crate::lspci_common::lspci_caps::show_caps;
+use crate::lspci_common::lspci_ids::{find_ids_file, PciIds};
+use crate::lspci_common::lspci_net::NetLookup;
 use crate::lspci_common::lspci_header::*;
-const SYS_FS_SLOT: &str = "/sys/bus/pci/slots";
-const DIR_NAME: &str = "/sys/bus/pci/devices/";
-const PCI_ID_FILE1: &str = "/usr/share/hwdata/pci.ids";
-const PCI_ID_FILE2: &str = "/usr/share/misc/pci.ids";
-const DNS_DOMAIN: &str = "pci.id.ucw.cz";
 
 ///
 pub static LSPCI_CMD_PARSE_ERROR: i32 = 1;
@@ -79,16 +85,20 @@ pub struct Config {
     pub selection_slot: PciSlotFilter,
     ///
     pub selection_id: PciIDFilter,
-    ///
-    pub db_file: String,
-    ///
-    pub pci_ids_vendor: HashMap<(u32, Option<u32>, Option<u32>, Option<u32>), String>,
-    ///
-    pub pci_ids_class: HashMap<(u32, Option<u32>, Option<u32>), String>,
+    /// The PCI ID database
+    pub pci_ids: PciIds,
+    /// Lookups in the DNS version of the ID database
+    pub net: NetLookup,
     ///
     pub map_mode: bool,
     ///
     pub phy_slot_map: HashMap<String, String>,
+    ///
+    pub access: Box<dyn PciAccess>,
+    /// PCI entries of modules.alias, None if it could not be read
+    pub module_aliases: Option<Vec<(String, String)>>,
+    /// Print the devices as JSON
+    pub json: bool,
 }
 
 /// options.
@@ -126,6 +136,14 @@ pub mod options {
     pub static KERNEL_FILE: &str = "kernel_file";
     ///
     pub static MAP_MODE: &str = "map_mode";
+    ///
+    pub static ACCESS: &str = "access";
+    ///
+    pub static DUMP_FILE: &str = "dump_file";
+    ///
+    pub static PARAM: &str = "param";
+    ///
+    pub static JSON: &str = "json";
 }
 
 #[derive(Debug)]
@@ -177,6 +195,8 @@ pub struct Device {
     pub name: String,
     ///
     pub dev: PciDev,
+    /// Slots of the bridges above the device, followed by the device itself
+    pub bus_path: Vec<String>,
 }
 
 ///
@@ -277,25 +297,67 @@ impl Config {
             };
         }
 
-        let db_file: String;
-        if options.is_present(options::DB) {
-            db_file = options.value_of(options::DB).unwrap().to_string();
-        } else {
-            let db_file_path = Path::new(PCI_ID_FILE1);
+        let db_file = find_ids_file(options.value_of(options::DB));
 
-            if db_file_path.exists() {
-                db_file = PCI_ID_FILE1.to_string();
-            } else {
-                db_file = PCI_ID_FILE2.to_string();
+        let mut params = AccessParams::default();
+        if let Some(values) = options.values_of(options::PARAM) {
+            for param in values {
+                if param == "help" {
+                    params.help();
+                    process::exit(0);
+                }
+                if let Err(e) = params.set(param) {
+                    return Err(UUsageError::new(
+                        LSPCI_CMD_PARSE_ERROR,
+                        format!("-O: {}", e),
+                    ));
+                }
             }
         }
+        let access = if let Some(file) = options.value_of(options::DUMP_FILE) {
+            params.dump_name = file.to_string();
+            new_access("dump", &params).unwrap()
+        } else if let Some(method) = options.value_of(options::ACCESS) {
+            if method == "help" {
+                println!("Known PCI access methods:\n");
+                for name in lspci_access::ACCESS_METHODS {
+                    println!("{}", name);
+                }
+                process::exit(0);
+            }
+            match new_access(method, &params) {
+                Some(access) => access,
+                None => {
+                    return Err(UUsageError::new(
+                        LSPCI_CMD_PARSE_ERROR,
+                        format!("Unknown access method `{}'", method),
+                    ))
+                }
+            }
+        } else {
+            match detect_access(&params) {
+                Ok(access) => access,
+                Err(e) => return Err(USimpleError::new(LSPCI_CMD_PARSE_ERROR, e)),
+            }
+        };
+
+        let kernel = options.is_present(options::KERNEL);
+        let json = options.is_present(options::JSON);
+        let module_aliases = if kernel || json || v > 0 {
+            let alias_file = match options.value_of(options::KERNEL_FILE) {
+                Some(file) => file.to_string(),
+                None => lspci_kernel::default_alias_file(),
+            };
+            lspci_kernel::read_aliases(&alias_file)
+        } else {
+            None
+        };
 
-        let (pci_ids_vendor, pci_ids_class) = parse_pci_ids(db_file.as_str());
         Ok(Self {
             m: mm,
             tree: options.is_present(options::TREE),
             verbose: v,
-            kernel: options.is_present(options::KERNEL),
+            kernel,
             hex_dump: x_occurrences,
             bus_centric: options.is_present(options::BUS_CENTRIC),
             domain: options.is_present(options::DOMAIN),
@@ -304,11 +366,17 @@ impl Config {
             dns: dns,
             selection_slot: slot_filter,
             selection_id: id_filter,
-            db_file: db_file,
-            pci_ids_vendor: pci_ids_vendor,
-            pci_ids_class: pci_ids_class,
+            pci_ids: PciIds::new(db_file),
+            net: NetLookup::new(
+                &params.net_domain,
+                &params.net_nameserver,
+                &params.net_cache_name,
+            ),
             map_mode: options.is_present(options::MAP_MODE),
-            phy_slot_map: get_slots(),
+            phy_slot_map: access.phy_slots(),
+            access,
+            module_aliases,
+            json,
         })
     }
 }
@@ -436,32 +504,45 @@ pub fn lspci_app<'a>(about: &'a str, usage: &'a str) -> Command<'a> {
                 .takes_value(true)
                 .help("Use specified ID database instead of /usr/share/hwdata/pci.ids"),
         )
-}
-
-/// Read the file where the device saves the physical slot
-///
-pub fn get_slots() -> HashMap<String, String> {
-    let entries = fs::read_dir(SYS_FS_SLOT);
-    let mut map = HashMap::new();
-    match entries {
-        Ok(entries) => {
-            for entry in entries {
-                let entry = entry.unwrap();
-                let path = entry.path();
-                if path.is_dir() {
-                    let directory_name = path.file_name().and_then(|name| name.to_str()).unwrap();
-                    let address_file_path = path.join("address");
-                    if let Ok(mut file) = fs::File::open(address_file_path) {
-                        let mut contents = String::new();
-                        let _ = file.read_to_string(&mut contents);
-                        map.insert(contents.trim().to_string(), directory_name.to_string());
-                    }
-                }
-            }
-        }
-        Err(_) => return map,
-    }
-    map
+        .arg(
+            Arg::new(options::KERNEL_FILE)
+                .short('p')
+                .long(options::KERNEL_FILE)
+                .value_name("file")
+                .takes_value(true)
+                .help("Look up kernel modules in a given file instead of default modules.alias"),
+        )
+        .arg(
+            Arg::new(options::ACCESS)
+                .short('A')
+                .long(options::ACCESS)
+                .value_name("method")
+                .takes_value(true)
+                .help("Use the specified PCI access method (see `-A help' for a list)"),
+        )
+        .arg(
+            Arg::new(options::DUMP_FILE)
+                .short('F')
+                .long(options::DUMP_FILE)
+                .value_name("file")
+                .takes_value(true)
+                .help("Read PCI configuration dump from a given file"),
+        )
+        .arg(
+            Arg::new(options::PARAM)
+                .short('O')
+                .long(options::PARAM)
+                .value_name("par>=<value")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Set PCI access parameter (see `-O help' for a list)"),
+        )
+        .arg(
+            Arg::new(options::JSON)
+                .short('J')
+                .long(options::JSON)
+                .help("Produce JSON output"),
+        )
 }
 
 /// Parse a hex field
@@ -698,10 +779,9 @@ pub fn device_filter(d: &Device, c: &Config, id_filter: bool) -> bool {
 
 /// Read a directory
 ///
-pub fn read_dir(path: &str) -> Vec<String> {
-    std::fs::read_dir(path)
-        .unwrap()
-        .map(|f| f.unwrap().file_name().to_str().unwrap().to_string())
+pub fn read_dir(path: &str) -> std::io::Result<Vec<String>> {
+    std::fs::read_dir(path)?
+        .map(|f| f.map(|f| f.file_name().to_string_lossy().to_string()))
         .collect()
 }
 
@@ -714,132 +794,6 @@ pub fn read_file(path: &str) -> Result<String, std::io::Error> {
     }
 }
 
-/// Parse the pci.ids file
-///
-fn parse_pci_ids(
-    file_path: &str,
-) -> (
-    HashMap<(u32, Option<u32>, Option<u32>, Option<u32>), String>,
-    HashMap<(u32, Option<u32>, Option<u32>), String>,
-) {
-    let mut devices_vendor: HashMap<(u32, Option<u32>, Option<u32>, Option<u32>), String> =
-        HashMap::new();
-    let mut current_vendor: Option<u32> = None;
-    let mut current_device: Option<u32> = None;
-    let mut current_subvendor: Option<u32>;
-    let mut current_subdevice: Option<u32>;
-    let mut process_class = false;
-
-    let mut devices_class: HashMap<(u32, Option<u32>, Option<u32>), String> = HashMap::new();
-    let mut current_class: Option<u32> = None;
-    let mut current_subclass: Option<u32> = None;
-    let mut current_progif: Option<u32>;
-
-    let db_file_path = Path::new(file_path);
-
-    if !db_file_path.exists() {
-        return (devices_vendor, devices_class);
-    }
-    let mut file = File::open(file_path).expect("Failed to open file");
-    let mut contents = String::new();
-    let _ = file.read_to_string(&mut contents);
-    let lines: Vec<&str> = contents.split('\n').collect();
-
-    for line in lines {
-        let trimmed_line = line.trim();
-        if trimmed_line == "# List of known device classes, subclasses and programming interfaces" {
-            process_class = true;
-            continue;
-        }
-
-        if trimmed_line.is_empty() || trimmed_line.starts_with('#') {
-            continue;
-        }
-        if !process_class {
-            let parts: Vec<&str> = trimmed_line.split_whitespace().collect();
-            let num_tabs = line.chars().take_while(|&c| c == '\t').count();
-            match num_tabs {
-                0 => {
-                    current_vendor = Some(u32::from_str_radix(parts[0], 16).unwrap());
-                    current_device = None;
-                    current_subvendor = None;
-                    current_subdevice = None;
-                    devices_vendor.insert(
-                        (
-                            current_vendor.unwrap(),
-                            current_device,
-                            current_subvendor,
-                            current_subdevice,
-                        ),
-                        parts[1..].join(" ").to_string(),
-                    );
-                }
-                1 => {
-                    current_device = Some(u32::from_str_radix(parts[0], 16).unwrap());
-                    current_subvendor = None;
-                    current_subdevice = None;
-                    if let Some(vendor) = current_vendor {
-                        devices_vendor.insert(
-                            (vendor, current_device, current_subvendor, current_subdevice),
-                            parts[1..].join(" ").to_string(),
-                        );
-                    }
-                }
-                2 => {
-                    current_subvendor = Some(u32::from_str_radix(parts[0], 16).unwrap());
-                    current_subdevice = Some(u32::from_str_radix(parts[1], 16).unwrap());
-                    if let (Some(vendor), Some(device), Some(subvendor)) =
-                        (current_vendor, current_device, current_subvendor)
-                    {
-                        devices_vendor.insert(
-                            (vendor, Some(device), Some(subvendor), current_subdevice),
-                            parts[2..].join(" ").to_string(),
-                        );
-                    }
-                }
-                _ => {}
-            }
-        } else {
-            let parts: Vec<&str> = trimmed_line.split_whitespace().collect();
-            let num_tabs = line.chars().take_while(|&c| c == '\t').count();
-            match num_tabs {
-                0 => {
-                    current_class = Some(u32::from_str_radix(parts[1], 16).unwrap());
-                    current_subclass = None;
-                    current_progif = None;
-                    devices_class.insert(
-                        (current_class.unwrap(), current_subclass, current_progif),
-                        parts[2..].join(" ").to_string(),
-                    );
-                }
-                1 => {
-                    current_subclass = Some(u32::from_str_radix(parts[0], 16).unwrap());
-                    current_progif = None;
-                    if let Some(class) = current_class {
-                        devices_class.insert(
-                            (class, current_subclass, current_progif),
-                            parts[1..].join(" ").to_string(),
-                        );
-                    }
-                }
-                2 => {
-                    current_progif = Some(u32::from_str_radix(parts[0], 16).unwrap());
-                    if let (Some(class), Some(device), Some(subclass)) =
-                        (current_class, current_subclass, current_progif)
-                    {
-                        devices_class.insert(
-                            (class, Some(device), Some(subclass)),
-                            parts[1..].join(" ").to_string(),
-                        );
-                    }
-                }
-                _ => {}
-            }
-        }
-    }
-    (devices_vendor, devices_class)
-}
-
 /// Read a file and returns its content as u32
 ///
 pub fn read_u32_from_file(path: &str) -> Option<u32> {
@@ -859,147 +813,18 @@ pub fn read_u32_from_file(path: &str) -> Option<u32> {
     }
 }
 
-/// Read the device file and returns a Device
-///
-pub fn read_device(df: &str, c: &Config) -> Device {
-    let path = format!("{}/{}", DIR_NAME, df);
-    let class_id = read_u32_from_file(format!("{}/{}", path, "class").as_str());
-    let class_id_str = format!("{:06X}", class_id.unwrap());
-    let mut bytes = [0u8; 4096];
-    let mut file = File::open(format!("{}/{}", path, "config").as_str()).unwrap();
-    let bytes_read = file.read(&mut bytes).unwrap();
-
-    let irq;
-    match read_file(format!("{}/{}", path, "irq").as_str()) {
-        Ok(content) => {
-            irq = Some(u32::from_str_radix(&content[..content.len() - 1], 10).unwrap());
-        }
-        Err(_) => irq = None,
-    }
-    let label;
-    match read_file(format!("{}/{}", path, "label").as_str()) {
-        Ok(content) => {
-            label = Some(content[..content.len() - 1].to_string());
-        }
-        Err(_) => label = None,
-    }
-    let phy_slot;
-    let address = df[0..df.len() - 2].to_string();
-    if c.phy_slot_map.contains_key(&address) {
-        let value = c.phy_slot_map.get(&address).unwrap();
-        phy_slot = value.to_string();
-    } else {
-        phy_slot = String::new();
-    }
-
-    let mut base_addr = vec![0; 6];
-    let mut flags = vec![0; 6];
-    let mut size = vec![0; 6];
-    let mut i = 0;
-    if let Ok(file) = File::open(format!("{}/{}", path, "resource").as_str()) {
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                let values: Vec<&str> = line.split_whitespace().collect();
-                let mut start = 0;
-                let mut end = 0;
-                let mut flag = 0;
-                if values.len() >= 3 {
-                    if let Ok(number) = u64::from_str_radix(&values[0][2..], 16) {
-                        start = number;
-                    }
-                    if let Ok(number) = u64::from_str_radix(&values[1][2..], 16) {
-                        end = number;
-                    }
-                    if let Ok(number) = u64::from_str_radix(&values[2][2..], 16) {
-                        flag = number;
-                    }
-                }
-                let flag_value;
-                let start_value;
-                if end < start {
-                    start_value = 0;
-                } else if end != 0 {
-                    start_value = end - start + 1;
-                } else {
-                    start_value = 0;
-                }
-                if i < 6 {
-                    flags[i] = flag;
-                    flag_value = flag & PCI_ADDR_FLAG_MASK;
-                    base_addr[i] = start | flag_value;
-                    size[i] = start_value;
-                }
-            }
-            i = i + 1;
-        }
-    }
-    let pci_dev = PciDev {
-        device_class: Some(u32::from_str_radix(&class_id_str[0..4], 16).unwrap()),
-        class_id: Some(u32::from_str_radix(&class_id_str[0..2], 16).unwrap()),
-        subclass_id: Some(u32::from_str_radix(&class_id_str[2..4], 16).unwrap()),
-        prog_if: Some(u32::from_str_radix(&class_id_str[4..6], 16).unwrap()),
-        vendor_id: read_u32_from_file(format!("{}/{}", path, "vendor").as_str()),
-        device_id: read_u32_from_file(format!("{}/{}", path, "device").as_str()),
-        subvendor_id: read_u32_from_file(format!("{}/{}", path, "subsystem_vendor").as_str()),
-        subdevice_id: read_u32_from_file(format!("{}/{}", path, "subsystem_device").as_str()),
-        revision: read_u32_from_file(format!("{}/{}", path, "revision").as_str()),
-        config: bytes[0..bytes_read].to_vec(),
-        config_len: bytes_read as u32,
-        irq: irq,
-        numa_node: read_u32_from_file(format!("{}/{}", path, "numa_node").as_str()),
-        label: label,
-        phy_slot: Some(String::from(phy_slot)),
-        base_addr: base_addr,
-        flags: flags,
-        size: size,
-    };
-    let d = Device {
-        ppath: String::from(DIR_NAME),
-        name: String::from(df),
-        dev: pci_dev,
-    };
-
-    d
-}
-
-/// Scan the /sys/bus/pci/devices directory
+/// Read all devices through the selected access method
 ///
-pub fn read_devices(c: &Config) -> Vec<Device> {
-    let mut devices = read_dir(DIR_NAME);
-    devices.sort();
-    let mut devices_vec: Vec<Device> = Vec::new();
-    for device_file in devices {
-        devices_vec.push(read_device(&device_file, c));
-    }
-    devices_vec
-}
-
-/// Query the DNS server
-///
-fn run_dig(dnsname: &str) -> String {
-    let output = Cmd::new("dig")
-        .arg(dnsname)
-        .arg("TXT")
-        .arg("+short")
-        .output()
-        .unwrap();
-
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        if let Some(start_index) = stdout.find('=') {
-            let value = &stdout[start_index + 1..&stdout.len() - 2];
-            return String::from(value);
-        }
-        return String::new();
-    } else {
-        return String::new();
-    }
+pub fn read_devices(c: &Config) -> UResult<Vec<Device>> {
+    c.access
+        .scan(c)
+        .map_err(|e| USimpleError::new(LSPCI_CMD_PARSE_ERROR, e))
 }
 
 /// Query the PCI ID database via DNS
 ///
 pub fn id_lookup_net(
+    c: &Config,
     name: &str,
     id1: u32,
     id2: Option<u32>,
@@ -1030,9 +855,9 @@ pub fn id_lookup_net(
             );
         }
         "gen_subsys" => {
-            dns_name = format!("{:0>2x}.{:0>2x}.s", id2.unwrap_or(0), id1);
+            dns_name = format!("{:0>4x}.{:0>4x}.s", id2.unwrap_or(0), id1);
         }
-        "prog_if" => {
+        "progif" => {
             dns_name = format!(
                 "{:0>2x}.{:0>2x}.{:0>2x}.c",
                 id3.unwrap_or(0),
@@ -1044,15 +869,14 @@ pub fn id_lookup_net(
             return String::new();
         }
     }
-    let dnsquery = format!("{}.{}", dns_name, DNS_DOMAIN);
-    let dnsresult = run_dig(&dnsquery);
-    if dnsresult == String::new() && name == "subclass" {
-        return format!(
-            "{} [{:02x}{:02x}]",
-            id_lookup_net("class", id1, id2, id3, id4),
-            id1,
-            id2.unwrap()
-        );
+    let ids = [id1, id2.unwrap_or(0), id3.unwrap_or(0), id4.unwrap_or(0)];
+    let dnsresult = c.net.lookup(name, ids, &dns_name, c.dns == 2);
+    if dnsresult.is_empty() && name == "subclass" {
+        let class = id_lookup_net(c, "class", id1, None, None, None);
+        if class.is_empty() || c.number == 2 {
+            return class;
+        }
+        return format!("{} [{:02x}{:02x}]", class, id1, id2.unwrap_or(0));
     }
     return dnsresult;
 }
@@ -1068,15 +892,11 @@ pub fn id_lookup(
     id4: Option<u32>,
 ) -> String {
     if c.dns == 3 {
-        return id_lookup_net(name, id1, id2, id3, id4);
+        return id_lookup_net(c, name, id1, id2, id3, id4);
     }
     let ret = pci_id_lookup(c, name, id1, id2, id3, id4);
-    if ret == String::new() {
-        if c.dns == 1 {
-            return id_lookup_net(name, id1, id2, id3, id4);
-        } else if c.dns == 2 {
-            return pci_id_lookup(c, name, id1, id2, id3, id4);
-        }
+    if ret.is_empty() && c.dns > 0 {
+        return id_lookup_net(c, name, id1, id2, id3, id4);
     }
     return ret;
 }
@@ -1099,48 +919,35 @@ pub fn pci_id_lookup(
     } else {
         matchname = name;
     }
-    match matchname {
-        "vendor" => {
-            let vendor_name = String::from(
-                c.pci_ids_vendor
-                    .get(&(id1, id2, id3, id4))
-                    .unwrap_or(&String::new()),
-            );
-            return vendor_name;
-        }
-        "class" => {
-            if let Some(class_name) = c.pci_ids_class.get(&(id1, id2, None)) {
-                return class_name.to_string();
-            } else {
-                if let Some(class_name) = c.pci_ids_class.get(&(id1, None, None)) {
-                    if c.number == 2 {
-                        return class_name.to_string();
-                    }
-                    return format!("{} [{:02x}{:02x}]", class_name, id1, id2.unwrap());
-                }
+    let ids = &c.pci_ids;
+    let found = match matchname {
+        "vendor" | "device" => match (id2, id3, id4) {
+            (None, _, _) => ids.vendor(id1),
+            (Some(device), Some(subvendor), Some(subdevice)) => {
+                ids.subsystem(id1, device, subvendor, subdevice)
             }
-            return String::new();
-        }
-        "device" => {
-            let device_name = String::from(
-                c.pci_ids_vendor
-                    .get(&(id1, id2, id3, id4))
-                    .unwrap_or(&String::new()),
-            );
-            return device_name;
-        }
-        "progif" => {
-            let prog_if = String::from(
-                c.pci_ids_class
-                    .get(&(id1, id2, id3))
-                    .unwrap_or(&String::new()),
-            );
-            return prog_if;
-        }
-        _ => {
-            return String::new();
-        }
-    }
+            (Some(device), _, _) => ids.device(id1, device),
+        },
+        "class" => match id2 {
+            Some(subclass) => match ids.subclass(id1, subclass) {
+                Some(name) => Some(name),
+                None => {
+                    return match ids.class(id1) {
+                        Some(name) if c.number == 2 => name.to_string(),
+                        Some(name) => format!("{} [{:02x}{:02x}]", name, id1, subclass),
+                        None => String::new(),
+                    };
+                }
+            },
+            None => ids.class(id1),
+        },
+        "progif" => match (id2, id3) {
+            (Some(subclass), Some(prog_if)) => ids.prog_if(id1, subclass, prog_if),
+            _ => None,
+        },
+        _ => None,
+    };
+    found.unwrap_or_default().to_string()
 }
 
 /// Query subsystem
@@ -1315,37 +1122,46 @@ pub fn pci_lookup_name(d: &Device, name: &str, c: &Config) -> String {
 /// Show the slot path
 ///
 pub fn show_slot_path(d: &Device, c: &Config) {
-    if c.path == 0 {
+    let parts = &d.bus_path;
+    if c.path == 0 || parts.is_empty() {
         let parts: Vec<&str> = d.name.splitn(4, |c| c == ':' || c == '.').collect();
         print!("{:02}:{:02}.{:01}", parts[1], parts[2], parts[3]);
         return;
     }
-    let filename = format!("{}/{}", DIR_NAME, d.name);
-    if let Ok(target_path) = fs::read_link(filename) {
-        let path = target_path.to_str().unwrap();
-        let keyword = "devices/pci";
-        let remain: &str;
-        if let Some(index) = path.find(keyword) {
-            remain = &path[(index + keyword.len())..];
-        } else {
-            return;
+    for i in 0..parts.len() {
+        if i > 0 {
+            print!("/");
         }
-        let mut parts: Vec<&str> = remain.split('/').collect();
-        parts.remove(0);
-        for i in 0..parts.len() {
-            if i > 0 {
-                print!("/");
-            }
-            let cp: Vec<&str> = parts[i].splitn(4, |c| c == ':' || c == '.').collect();
-            if i == parts.len() - 1 && parts.len() > 1 {
-                if c.path > 1 {
-                    print!("{:02}:{:02}.{:01}", cp[1], cp[2], cp[3]);
-                } else {
-                    print!("{:02}.{:01}", cp[2], cp[3]);
-                }
-                continue;
+        let cp: Vec<&str> = parts[i].splitn(4, |c| c == ':' || c == '.').collect();
+        if i == parts.len() - 1 && parts.len() > 1 {
+            if c.path > 1 {
+                print!("{:02}:{:02}.{:01}", cp[1], cp[2], cp[3]);
+            } else {
+                print!("{:02}.{:01}", cp[2], cp[3]);
             }
-            print!("{:02}:{:02}.{:01}", cp[1], cp[2], cp[3]);
+            continue;
+        }
+        print!("{:02}:{:02}.{:01}", cp[1], cp[2], cp[3]);
+    }
+}
+
+/// Path of a file in the sysfs directory of a device, None if it was not read from sysfs
+///
+pub fn sysfs_file(d: &Device, file: &str) -> Option<String> {
+    if d.ppath.is_empty() {
+        return None;
+    }
+    Some(format!("{}/{}/{}", d.ppath, d.name, file))
+}
+
+/// Check whether the device is a bridge with a bus behind it
+///
+pub fn has_pci_bus(d: &Device) -> bool {
+    match sysfs_file(d, "pci_bus") {
+        Some(path) => Path::new(&path).exists(),
+        None => {
+            let ht = get_conf_byte(d, PCI_HEADER_TYPE) & 0x7f;
+            ht == PCI_HEADER_TYPE_BRIDGE || ht == PCI_HEADER_TYPE_CARDBUS
         }
     }
 }
@@ -1382,7 +1198,7 @@ pub fn show_terse(d: &Device, c: &Config) {
     );
 
     if d.dev.revision.unwrap() != 0 {
-        print!(" (rev {:02})", d.dev.revision.unwrap());
+        print!(" (rev {:02x})", d.dev.revision.unwrap());
     }
     if c.verbose > 0 {
         let pr = d.dev.prog_if.unwrap();
@@ -1462,7 +1278,9 @@ pub fn get_conf_word(d: &Device, pos: usize) -> u16 {
     return d.dev.config[pos] as u16 | ((d.dev.config[pos + 1] as u16) << 8) as u16;
 }
 
-fn get_conf_long(d: &Device, pos: usize) -> u32 {
+/// Get a long from configuration space
+///
+pub fn get_conf_long(d: &Device, pos: usize) -> u32 {
     check_conf_range(d, pos, 4);
     (d.dev.config[pos as usize] as u32)
         | ((d.dev.config[(pos + 1) as usize] as u32) << 8)
@@ -1495,31 +1313,15 @@ pub fn show_hex_dump(d: &Device, c: &Config) {
     }
 }
 
-/// Get the name of the kernel driver
+/// Show the kernel driver bound to a device and the modules able to handle it
 ///
-pub fn show_kernel(d: &Device) {
-    let driver = format!("{}/{}/driver", DIR_NAME, d.name);
-
-    if let Ok(target_path) = fs::read_link(driver.clone()) {
-        println!(
-            "\tKernel driver in use: {}",
-            Path::new(&target_path)
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-        );
+pub fn show_kernel(d: &Device, c: &Config) {
+    if let Some(driver) = lspci_kernel::driver(d) {
+        println!("\tKernel driver in use: {}", driver);
     }
-    let modules = format!("{}/module", driver);
-    if let Ok(target_path) = fs::read_link(modules) {
-        println!(
-            "\tKernel modules: {}",
-            Path::new(&target_path)
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-        );
+    let modules = lspci_kernel::modules(d, c);
+    if !modules.is_empty() {
+        println!("\tKernel modules: {}", modules.join(", "));
     }
 }
 
@@ -1623,17 +1425,17 @@ pub fn show_size(mut x: u64) {
 ///
 pub fn show_htype0(d: &Device, c: &Config) {
     show_bases(d, 6, c);
-    show_caps(d, PCI_CAPABILITY_LIST as usize);
+    show_caps(d, c, PCI_CAPABILITY_LIST as usize);
 }
 ///
 pub fn show_htype1(d: &Device, c: &Config) {
     show_bases(d, 2, c);
-    show_caps(d, PCI_CAPABILITY_LIST as usize);
+    show_caps(d, c, PCI_CAPABILITY_LIST as usize);
 }
 ///
 pub fn show_htype2(d: &Device, c: &Config) {
     show_bases(d, 1, c);
-    show_caps(d, PCI_CAPABILITY_LIST as usize);
+    show_caps(d, c, PCI_CAPABILITY_LIST as usize);
 }
 
 ///
@@ -1644,22 +1446,6 @@ pub fn show_htype_unknown(d: &Device, c: &Config) {
     show_bases(d, 6, c);
 }
 
-///
-pub fn show_caps(d: &Device, mut wh: usize) {
-    if get_conf_word(d, PCI_STATUS as usize) & PCI_STATUS_CAP_LIST as u16 != 0 {
-        wh = get_conf_byte(d, wh as usize) as usize & !3;
-        while wh != 0 {
-            print!("\tCapabilities: ");
-            if wh >= 64 {
-                print!("<access denied>\n");
-                break;
-            } else {
-                print!("[{:0>2x}] \n", wh);
-            }
-        }
-    }
-}
-
 /// Output devices in verbose mode
 ///
 pub fn show_verbose(d: &Device, c: &Config) {
@@ -1884,7 +1670,7 @@ pub fn get_sec_sub(d: &Device, path: &str) -> (u32, u32) {
     let tmp = (ht == PCI_HEADER_TYPE_BRIDGE) || (ht == PCI_HEADER_TYPE_CARDBUS);
     let sec;
     let sub;
-    if class >> 8 == PCI_BASE_CLASS_BRIDGE as u32 && tmp {
+    if class == PCI_BASE_CLASS_BRIDGE as u32 && tmp {
         if ht == PCI_HEADER_TYPE_BRIDGE {
             sub = get_conf_byte(d, PCI_SUBORDINATE_BUS as usize) as u32;
             sec = get_conf_byte(d, PCI_SECONDARY_BUS as usize) as u32;
@@ -1919,61 +1705,36 @@ pub fn get_sec_sub(d: &Device, path: &str) -> (u32, u32) {
 }
 
 ///
-pub fn grow_tree(c: &Config) -> Vec<Bridge> {
-    let devices = read_devices(c);
+pub fn grow_tree(c: &Config) -> UResult<Vec<Bridge>> {
+    let devices = read_devices(c)?;
     let mut brs: Vec<Bridge> = Vec::new();
     for device in devices {
         if device_filter(&device, c, true) {
-            let filename = format!("{}/{}", DIR_NAME, device.name);
-            if let Ok(target_path) = fs::read_link(filename) {
-                let path = target_path.to_str().unwrap();
-                let keyword = "devices/pci";
-                let remain: &str;
-                if let Some(index) = path.find(keyword) {
-                    remain = &path[(index + keyword.len())..];
-                } else {
-                    continue;
-                }
-                let mut parts: Vec<&str> = remain.split('/').collect();
-                parts.remove(0);
-                let path_bus_str = format!("{}/{}/pci_bus", DIR_NAME, device.name);
-                let path_bus = Path::new(path_bus_str.as_str());
-                let (sec, sub) = get_sec_sub(&device, &path_bus_str);
-                if parts.len() == 1 {
-                    let br = Bridge {
-                        slot: pci_filter_parse_slot(parts[0]).unwrap(),
-                        secondary: sec as u32,
-                        subordinate: sub as u32,
-                        br_dev: device,
-                        pci_bus: path_bus.exists(),
-                        child: Vec::new(),
-                    };
-                    brs.push(br);
-                } else if parts.len() > 1 {
-                    let br = Bridge {
-                        slot: pci_filter_parse_slot(parts[parts.len() - 1]).unwrap(),
-                        secondary: sec as u32,
-                        subordinate: sub as u32,
-                        br_dev: device,
-                        pci_bus: path_bus.exists(),
-                        child: Vec::new(),
-                    };
-                    let mut current_brs = &mut brs;
-                    for i in 0..parts.len() - 1 {
-                        let idx =
-                            find_bridges(&current_brs, pci_filter_parse_slot(parts[i]).unwrap());
-                        if idx != usize::MAX {
-                            current_brs = &mut current_brs[idx].child;
-                        }
-                    }
-                    current_brs.push(br);
-                } else {
-                    continue;
+            let parts = device.bus_path.clone();
+            if parts.is_empty() {
+                continue;
+            }
+            let path_bus_str = sysfs_file(&device, "pci_bus").unwrap_or_default();
+            let (sec, sub) = get_sec_sub(&device, &path_bus_str);
+            let br = Bridge {
+                slot: pci_filter_parse_slot(&parts[parts.len() - 1]).unwrap(),
+                secondary: sec as u32,
+                subordinate: sub as u32,
+                pci_bus: has_pci_bus(&device),
+                br_dev: device,
+                child: Vec::new(),
+            };
+            let mut current_brs = &mut brs;
+            for i in 0..parts.len() - 1 {
+                let idx = find_bridges(&current_brs, pci_filter_parse_slot(&parts[i]).unwrap());
+                if idx != usize::MAX {
+                    current_brs = &mut current_brs[idx].child;
                 }
             }
+            current_brs.push(br);
         }
     }
-    return brs;
+    Ok(brs)
 }
 
 ///
@@ -2056,10 +1817,10 @@ pub fn show_forest(brs: &Vec<Bridge>, c: &Config) {
 }
 
 ///
-pub fn map_the_bus(c: &Config) {
+pub fn map_the_bus(c: &Config) -> UResult<()> {
     println!("WARNING: Bus mapping can be reliable only with direct hardware access enabled.\n");
-    let devices = read_devices(c);
-    let brs = grow_tree(c);
+    let devices = read_devices(c)?;
+    let brs = grow_tree(c)?;
     let mut map_bridges: HashMap<u32, Vec<String>> = HashMap::new();
     let mut map_via: HashMap<u32, String> = HashMap::new();
     for i in 0..brs.len() {
@@ -2084,9 +1845,7 @@ pub fn map_the_bus(c: &Config) {
     for device in devices {
         if device_filter(&device, c, true) {
             show_device(&device, c);
-            let path_bus_str = format!("{}/{}/pci_bus", DIR_NAME, device.name);
-            let path_bus = Path::new(path_bus_str.as_str());
-            if path_bus.exists() {
+            if has_pci_bus(&device) {
                 let header = get_conf_byte(&device, PCI_HEADER_TYPE as usize) & 0x7;
                 let np;
                 let ns;
@@ -2166,6 +1925,7 @@ pub fn map_the_bus(c: &Config) {
             println!(" {}", value);
         }
     }
+    Ok(())
 }
 
 ///
@@ -2179,7 +1939,7 @@ pub fn show_device(d: &Device, c: &Config) {
             show_terse(d, c);
         }
         if c.kernel || c.verbose > 0 {
-            show_kernel(d);
+            show_kernel(d, c);
         }
     }
     if c.hex_dump > 0 {
@@ -2191,11 +1951,20 @@ pub fn show_device(d: &Device, c: &Config) {
 }
 
 ///
-pub fn show(config: &Config) {
-    let devices = read_devices(config);
+pub fn show(config: &Config) -> UResult<()> {
+    let devices = read_devices(config)?;
+    if config.json {
+        let selected: Vec<Device> = devices
+            .into_iter()
+            .filter(|device| device_filter(device, config, true))
+            .collect();
+        lspci_json::show_json(&selected, config);
+        return Ok(());
+    }
     for device in devices {
         if device_filter(&device, config, true) {
             show_device(&device, config);
         }
     }
+    Ok(())
 }
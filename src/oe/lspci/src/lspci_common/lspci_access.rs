@@ -0,0 +1,584 @@
+//! This file is part of the easybox package.
+//
+// (c) Haopeng Liu <657407891@qq.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+use crate::lspci_common::lspci_header::*;
+use crate::lspci_common::lspci_net::{NET_CACHE_NAME, NET_DOMAIN};
+use crate::lspci_common::{
+    pci_filter_parse_slot, read_dir, read_file, read_u32_from_file, Config, Device, PciDev,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// Default root of the PCI bus in sysfs
+pub const SYSFS_PATH: &str = "/sys/bus/pci";
+/// Default root of the PCI bus in procfs
+pub const PROC_PATH: &str = "/proc/bus/pci";
+
+/// A source of PCI devices and their configuration space
+pub trait PciAccess {
+    /// Name used to select the method with -A
+    fn name(&self) -> &'static str;
+    /// Whether the method can be used on this system
+    fn detect(&self) -> bool;
+    /// Read every device, sorted by slot
+    fn scan(&self, c: &Config) -> Result<Vec<Device>, String>;
+    /// Map of slot addresses to physical slot names
+    fn phy_slots(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
+}
+
+/// Names of all known access methods, in the order auto-detection tries them
+pub const ACCESS_METHODS: [&str; 3] = ["linux-sysfs", "linux-proc", "dump"];
+
+/// Access parameters settable with -O
+pub struct AccessParams {
+    /// sysfs.path
+    pub sysfs_path: String,
+    /// proc.path
+    pub proc_path: String,
+    /// dump.name
+    pub dump_name: String,
+    /// net.domain
+    pub net_domain: String,
+    /// net.cache_name
+    pub net_cache_name: String,
+    /// net.nameserver
+    pub net_nameserver: String,
+}
+
+impl Default for AccessParams {
+    fn default() -> Self {
+        Self {
+            sysfs_path: String::from(SYSFS_PATH),
+            proc_path: String::from(PROC_PATH),
+            dump_name: String::new(),
+            net_domain: String::from(NET_DOMAIN),
+            net_cache_name: String::from(NET_CACHE_NAME),
+            net_nameserver: String::new(),
+        }
+    }
+}
+
+impl AccessParams {
+    /// Set a parameter given as `name=value`
+    pub fn set(&mut self, param: &str) -> Result<(), String> {
+        let (name, value) = match param.split_once('=') {
+            Some(p) => p,
+            None => return Err(String::from("Missing `=' in parameter")),
+        };
+        match name {
+            "sysfs.path" => self.sysfs_path = value.to_string(),
+            "proc.path" => self.proc_path = value.to_string(),
+            "dump.name" => self.dump_name = value.to_string(),
+            "net.domain" => self.net_domain = value.to_string(),
+            "net.cache_name" => self.net_cache_name = value.to_string(),
+            "net.nameserver" => self.net_nameserver = value.to_string(),
+            _ => return Err(format!("Unknown parameter `{}'", name)),
+        }
+        Ok(())
+    }
+
+    /// Print the parameters and their current values
+    pub fn help(&self) {
+        println!("Known PCI access parameters:\n");
+        println!(
+            "sysfs.path\tPath to the sysfs device tree ({})",
+            self.sysfs_path
+        );
+        println!(
+            "proc.path\tPath to the procfs bus tree ({})",
+            self.proc_path
+        );
+        println!("dump.name\tName of the bus dump file to read from");
+        println!(
+            "net.domain\tDNS domain used for resolving of ID's ({})",
+            self.net_domain
+        );
+        println!(
+            "net.cache_name\tName of the ID cache file ({})",
+            self.net_cache_name
+        );
+        println!("net.nameserver\tDNS server to query instead of those in /etc/resolv.conf");
+    }
+}
+
+/// Build the access method called `name`
+pub fn new_access(name: &str, p: &AccessParams) -> Option<Box<dyn PciAccess>> {
+    match name {
+        "linux-sysfs" => Some(Box::new(SysfsAccess {
+            path: p.sysfs_path.clone(),
+        })),
+        "linux-proc" => Some(Box::new(ProcAccess {
+            path: p.proc_path.clone(),
+        })),
+        "dump" => Some(Box::new(DumpAccess {
+            file: p.dump_name.clone(),
+        })),
+        _ => None,
+    }
+}
+
+/// Pick the first access method that works on this system
+pub fn detect_access(p: &AccessParams) -> Result<Box<dyn PciAccess>, String> {
+    for name in ACCESS_METHODS {
+        if name == "dump" {
+            continue;
+        }
+        if let Some(a) = new_access(name, p) {
+            if a.detect() {
+                return Ok(a);
+            }
+        }
+    }
+    Err(String::from("Cannot find any working access method."))
+}
+
+/// Format a slot the way sysfs names devices
+fn slot_name(domain: u32, bus: u32, dev: u32, func: u32) -> String {
+    format!("{:04x}:{:02x}:{:02x}.{:x}", domain, bus, dev, func)
+}
+
+/// Fill a PciDev from the configuration space alone. Fields beyond the
+/// bytes that could be read are left unknown.
+fn dev_from_config(config: Vec<u8>) -> PciDev {
+    let byte = |pos: usize| config.get(pos).map(|b| *b as u32);
+    let word = |pos: usize| Some(byte(pos)? | (byte(pos + 1)? << 8));
+    let long = |pos: usize| Some(word(pos)? | (word(pos + 2)? << 16));
+
+    let htype = byte(PCI_HEADER_TYPE).unwrap_or(0) as u8 & 0x7f;
+    // The CardBus subsystem IDs lie past the 64-byte standard header
+    let (subvendor_id, subdevice_id) = match htype {
+        PCI_HEADER_TYPE_NORMAL => (
+            word(PCI_SUBSYSTEM_VENDOR_ID as usize),
+            word(PCI_SUBSYSTEM_ID as usize),
+        ),
+        PCI_HEADER_TYPE_CARDBUS => (
+            word(PCI_CB_SUBSYSTEM_VENDOR_ID as usize),
+            word(PCI_CB_SUBSYSTEM_ID as usize),
+        ),
+        _ => (None, None),
+    };
+    let cnt = match htype {
+        PCI_HEADER_TYPE_NORMAL => 6,
+        PCI_HEADER_TYPE_BRIDGE => 2,
+        PCI_HEADER_TYPE_CARDBUS => 1,
+        _ => 0,
+    };
+    let mut base_addr = vec![0; 6];
+    let mut i = 0;
+    while i < cnt {
+        let x = long(PCI_BASE_ADDRESS_0 as usize + 4 * i).unwrap_or(0) as u64;
+        if x != 0 && x != 0xffff_ffff {
+            base_addr[i] = x;
+            if x & PCI_BASE_ADDRESS_SPACE_IO as u64 == 0
+                && x & PCI_BASE_ADDRESS_MEM_TYPE_MASK as u64 == PCI_BASE_ADDRESS_MEM_TYPE_64 as u64
+                && i + 1 < cnt
+            {
+                i += 1;
+                base_addr[i - 1] |=
+                    (long(PCI_BASE_ADDRESS_0 as usize + 4 * i).unwrap_or(0) as u64) << 32;
+            }
+        }
+        i += 1;
+    }
+
+    PciDev {
+        device_class: word(PCI_CLASS_DEVICE as usize),
+        class_id: byte(PCI_CLASS_DEVICE as usize + 1),
+        subclass_id: byte(PCI_CLASS_DEVICE as usize),
+        prog_if: byte(PCI_CLASS_PROG as usize),
+        vendor_id: word(PCI_VENDOR_ID as usize),
+        device_id: word(PCI_DEVICE_ID as usize),
+        revision: byte(PCI_REVISION_ID as usize),
+        subvendor_id,
+        subdevice_id,
+        config_len: config.len() as u32,
+        irq: byte(PCI_INTERRUPT_LINE as usize),
+        numa_node: None,
+        label: None,
+        phy_slot: Some(String::new()),
+        base_addr,
+        flags: vec![0; 6],
+        size: vec![0; 6],
+        config,
+    }
+}
+
+/// Build a device from its configuration space, None if the header is unreadable
+fn device_from_config(name: String, config: Vec<u8>) -> Option<Device> {
+    if config.len() < 64 {
+        let parts: Vec<&str> = name.splitn(4, [':', '.']).collect();
+        eprintln!(
+            "lspci: Unable to read the standard configuration space header of device {}:{}:{}.{}",
+            parts[0], parts[1], parts[2], parts[3]
+        );
+        return None;
+    }
+    Some(Device {
+        ppath: String::new(),
+        name,
+        dev: dev_from_config(config),
+        bus_path: Vec::new(),
+    })
+}
+
+/// Fill the bus path of devices which are not in sysfs by following bridges upstream
+fn link_bridges(devices: &mut [Device]) {
+    let bus_of = |name: &str| match pci_filter_parse_slot(name) {
+        Ok(slot) => (slot.domain.unwrap_or(0), slot.bus.unwrap_or(0)),
+        Err(_) => (0, 0),
+    };
+    let mut upstream: HashMap<(u32, u32), String> = HashMap::new();
+    for d in devices.iter() {
+        let htype = d.dev.config[PCI_HEADER_TYPE] & 0x7f;
+        if htype == PCI_HEADER_TYPE_BRIDGE || htype == PCI_HEADER_TYPE_CARDBUS {
+            let secondary = d.dev.config[PCI_SECONDARY_BUS as usize] as u32;
+            if secondary != 0 {
+                upstream.insert((bus_of(&d.name).0, secondary), d.name.clone());
+            }
+        }
+    }
+    for d in devices.iter_mut() {
+        let mut path = vec![d.name.clone()];
+        while let Some(bridge) = upstream.get(&bus_of(&path[0])) {
+            if path.contains(bridge) {
+                break;
+            }
+            path.insert(0, bridge.clone());
+        }
+        d.bus_path = path;
+    }
+}
+
+/// Devices listed in /sys/bus/pci/devices
+pub struct SysfsAccess {
+    /// Root of the PCI bus, normally /sys/bus/pci
+    pub path: String,
+}
+
+impl SysfsAccess {
+    fn devices_dir(&self) -> String {
+        format!("{}/devices", self.path)
+    }
+
+    /// Read one device directory
+    fn read_device(&self, df: &str, c: &Config) -> Result<Device, String> {
+        let ppath = self.devices_dir();
+        let path = format!("{}/{}", ppath, df);
+        let class_id = match read_u32_from_file(format!("{}/class", path).as_str()) {
+            Some(class_id) => class_id,
+            None => return Err(format!("Cannot read {}/class", path)),
+        };
+
+        let config_file = format!("{}/config", path);
+        let mut config = Vec::new();
+        if let Err(e) = fs::File::open(&config_file).and_then(|mut f| f.read_to_end(&mut config)) {
+            return Err(format!("Cannot open {}: {}", config_file, e));
+        }
+        config.truncate(4096);
+        if config.len() < 64 {
+            return Err(format!(
+                "Unable to read the standard configuration space header of device {}",
+                df
+            ));
+        }
+
+        let irq = read_file(format!("{}/irq", path).as_str())
+            .ok()
+            .and_then(|content| content.trim().parse().ok());
+        let label = read_file(format!("{}/label", path).as_str())
+            .ok()
+            .map(|content| content.trim_end_matches('\n').to_string());
+        let address = &df[0..df.len() - 2];
+        let phy_slot = c.phy_slot_map.get(address).cloned().unwrap_or_default();
+
+        let mut base_addr = vec![0; 6];
+        let mut flags = vec![0; 6];
+        let mut size = vec![0; 6];
+        if let Ok(content) = read_file(format!("{}/resource", path).as_str()) {
+            for (i, line) in content.lines().take(6).enumerate() {
+                let values: Vec<u64> = line
+                    .split_whitespace()
+                    .filter_map(|v| u64::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+                    .collect();
+                if values.len() < 3 {
+                    continue;
+                }
+                let (start, end, flag) = (values[0], values[1], values[2]);
+                flags[i] = flag;
+                base_addr[i] = start | (flag & PCI_ADDR_FLAG_MASK);
+                size[i] = if end != 0 && end >= start {
+                    end - start + 1
+                } else {
+                    0
+                };
+            }
+        }
+
+        // Older kernels lack some of the attributes, the header has them all
+        let header = dev_from_config(config.clone());
+        let id = |file: &str, fallback: Option<u32>| {
+            read_u32_from_file(format!("{}/{}", path, file).as_str()).or(fallback)
+        };
+        let pci_dev = PciDev {
+            device_class: Some(class_id >> 8),
+            class_id: Some(class_id >> 16),
+            subclass_id: Some((class_id >> 8) & 0xff),
+            prog_if: Some(class_id & 0xff),
+            vendor_id: id("vendor", header.vendor_id),
+            device_id: id("device", header.device_id),
+            subvendor_id: id("subsystem_vendor", header.subvendor_id),
+            subdevice_id: id("subsystem_device", header.subdevice_id),
+            revision: id("revision", header.revision),
+            config_len: config.len() as u32,
+            config,
+            irq,
+            numa_node: read_file(format!("{}/numa_node", path).as_str())
+                .ok()
+                .and_then(|content| content.trim().parse().ok()),
+            label,
+            phy_slot: Some(phy_slot),
+            base_addr,
+            flags,
+            size,
+        };
+        Ok(Device {
+            bus_path: sysfs_bus_path(&path),
+            ppath,
+            name: String::from(df),
+            dev: pci_dev,
+        })
+    }
+}
+
+/// Slots of the bridges above a sysfs device, followed by the device itself
+fn sysfs_bus_path(path: &str) -> Vec<String> {
+    let target = match fs::read_link(path) {
+        Ok(target) => target.to_string_lossy().to_string(),
+        Err(_) => return Vec::new(),
+    };
+    let keyword = "devices/pci";
+    match target.find(keyword) {
+        Some(index) => target[(index + keyword.len())..]
+            .split('/')
+            .skip(1)
+            .map(String::from)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+impl PciAccess for SysfsAccess {
+    fn name(&self) -> &'static str {
+        "linux-sysfs"
+    }
+
+    fn detect(&self) -> bool {
+        Path::new(&self.devices_dir()).is_dir()
+    }
+
+    fn scan(&self, c: &Config) -> Result<Vec<Device>, String> {
+        let dir = self.devices_dir();
+        let mut names = match read_dir(&dir) {
+            Ok(names) => names,
+            Err(e) => return Err(format!("Cannot open {}: {}", dir, e)),
+        };
+        names.sort();
+        // An unreadable device is skipped, the others are still listed
+        let mut devices = Vec::new();
+        for df in &names {
+            match self.read_device(df, c) {
+                Ok(d) => devices.push(d),
+                Err(e) => {
+                    eprintln!("lspci: {}", e);
+                    continue;
+                }
+            }
+        }
+        Ok(devices)
+    }
+
+    fn phy_slots(&self) -> HashMap<String, String> {
+        let mut map = HashMap::new();
+        if let Ok(entries) = fs::read_dir(format!("{}/slots", self.path)) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if let Ok(address) = read_file(path.join("address").to_str().unwrap_or("")) {
+                    map.insert(
+                        address.trim().to_string(),
+                        entry.file_name().to_string_lossy().to_string(),
+                    );
+                }
+            }
+        }
+        map
+    }
+}
+
+/// Devices listed in /proc/bus/pci/devices
+pub struct ProcAccess {
+    /// Root of the PCI bus, normally /proc/bus/pci
+    pub path: String,
+}
+
+impl PciAccess for ProcAccess {
+    fn name(&self) -> &'static str {
+        "linux-proc"
+    }
+
+    fn detect(&self) -> bool {
+        Path::new(&format!("{}/devices", self.path)).is_file()
+    }
+
+    fn scan(&self, _c: &Config) -> Result<Vec<Device>, String> {
+        let list = format!("{}/devices", self.path);
+        let content = match read_file(&list) {
+            Ok(content) => content,
+            Err(e) => return Err(format!("Cannot open {}: {}", list, e)),
+        };
+        let mut devices = Vec::new();
+        for line in content.lines() {
+            let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+            let values: Vec<u64> = fields
+                .iter()
+                .take(17)
+                .map(|f| u64::from_str_radix(f, 16))
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("proc: parse error in {}", list))?;
+            if values.len() < 3 {
+                return Err(format!("proc: parse error in {}", list));
+            }
+            let bus = (values[0] >> 8) as u32;
+            let dev = ((values[0] >> 3) & 0x1f) as u32;
+            let func = (values[0] & 7) as u32;
+
+            let config_file = format!("{}/{:02x}/{:02x}.{}", self.path, bus, dev, func);
+            let config = match fs::read(&config_file) {
+                Ok(config) => config,
+                Err(e) => return Err(format!("Cannot open {}: {}", config_file, e)),
+            };
+            let mut d = match device_from_config(slot_name(0, bus, dev, func), config) {
+                Some(d) => d,
+                None => continue,
+            };
+            d.dev.irq = Some(values[2] as u32);
+            if values.len() >= 17 {
+                d.dev.base_addr.copy_from_slice(&values[3..9]);
+                d.dev.size.copy_from_slice(&values[10..16]);
+            }
+            devices.push(d);
+        }
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        link_bridges(&mut devices);
+        Ok(devices)
+    }
+}
+
+/// Devices read back from the output of lspci -x, -xxx or -xxxx
+pub struct DumpAccess {
+    /// The dump file
+    pub file: String,
+}
+
+/// Parse a slot at the start of a dump line, `[domain:]bus:dev.func`
+fn parse_dump_slot(line: &str) -> Option<(u32, u32, u32, u32)> {
+    let slot = line.split_whitespace().next()?;
+    let (rest, func) = slot.rsplit_once('.')?;
+    let parts: Vec<&str> = rest.split(':').collect();
+    let (domain, bus, dev) = match parts.as_slice() {
+        [bus, dev] if bus.len() == 2 && dev.len() == 2 => ("0", *bus, *dev),
+        [domain, bus, dev]
+            if (4..=6).contains(&domain.len()) && bus.len() == 2 && dev.len() == 2 =>
+        {
+            (*domain, *bus, *dev)
+        }
+        _ => return None,
+    };
+    if func.len() != 1 {
+        return None;
+    }
+    Some((
+        u32::from_str_radix(domain, 16).ok()?,
+        u32::from_str_radix(bus, 16).ok()?,
+        u32::from_str_radix(dev, 16).ok()?,
+        func.parse().ok().filter(|f| *f < 8)?,
+    ))
+}
+
+/// Parse a `XX: hh hh ...` data line into its offset and bytes
+fn parse_dump_data(line: &str) -> Option<(usize, Vec<u8>)> {
+    let (offset, data) = line.split_once(": ")?;
+    if !(2..=4).contains(&offset.len()) {
+        return None;
+    }
+    let offset = usize::from_str_radix(offset, 16).ok()?;
+    let bytes = data
+        .split(' ')
+        .map_while(|b| {
+            if b.len() == 2 {
+                u8::from_str_radix(b, 16).ok()
+            } else {
+                None
+            }
+        })
+        .collect();
+    Some((offset, bytes))
+}
+
+impl PciAccess for DumpAccess {
+    fn name(&self) -> &'static str {
+        "dump"
+    }
+
+    fn detect(&self) -> bool {
+        !self.file.is_empty()
+    }
+
+    fn scan(&self, _c: &Config) -> Result<Vec<Device>, String> {
+        if self.file.is_empty() {
+            return Err(String::from("dump: File name not given."));
+        }
+        let content = match fs::read(&self.file) {
+            Ok(content) => String::from_utf8_lossy(&content).to_string(),
+            Err(e) => return Err(format!("Cannot open {}: {}", self.file, e)),
+        };
+
+        let mut found: Vec<(String, Vec<u8>)> = Vec::new();
+        let mut in_device = false;
+        for line in content.lines() {
+            let line = line.trim_end_matches('\r');
+            if let Some((domain, bus, dev, func)) = parse_dump_slot(line) {
+                found.push((slot_name(domain, bus, dev, func), Vec::new()));
+                in_device = true;
+            } else if line.is_empty() {
+                in_device = false;
+            } else if let (true, Some((offset, bytes))) = (in_device, parse_dump_data(line)) {
+                let config = &mut found.last_mut().unwrap().1;
+                if offset + bytes.len() > 4096 {
+                    return Err(String::from(
+                        "dump: At most 4096 bytes of config space are supported",
+                    ));
+                }
+                if config.len() < offset + bytes.len() {
+                    config.resize(offset + bytes.len(), 0);
+                }
+                config[offset..offset + bytes.len()].copy_from_slice(&bytes);
+            }
+        }
+
+        let mut devices: Vec<Device> = found
+            .into_iter()
+            .filter_map(|(name, config)| device_from_config(name, config))
+            .collect();
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        link_bridges(&mut devices);
+        Ok(devices)
+    }
+}
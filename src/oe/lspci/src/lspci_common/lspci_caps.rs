@@ -0,0 +1,1656 @@
+//! This file is part of the easybox package.
+//
+// (c) Haopeng Liu <657407891@qq.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+use crate::lspci_common::lspci_header::*;
+use crate::lspci_common::{
+    get_conf_byte, get_conf_long, get_conf_word, pci_filter_parse_slot, Config, Device,
+};
+use std::fmt::Write;
+
+/// print! into the text of a capability
+macro_rules! out {
+    ($o:expr, $($arg:tt)*) => {{
+        let _ = write!($o, $($arg)*);
+    }};
+}
+
+/// println! into the text of a capability
+macro_rules! outln {
+    ($o:expr) => {{
+        $o.push('\n');
+    }};
+    ($o:expr, $($arg:tt)*) => {{
+        let _ = writeln!($o, $($arg)*);
+    }};
+}
+
+/// Check that `len` bytes at `pos` were read from the configuration space
+fn config_fetch(d: &Device, pos: usize, len: usize) -> bool {
+    pos + len <= d.dev.config_len as usize
+}
+
+/// `len` bits of `x` starting at bit `start`
+fn bits(x: u32, start: u32, len: u32) -> u32 {
+    (x >> start) & ((1 << len) - 1)
+}
+
+/// '+' if any of the bits `y` is set in `x`, '-' otherwise
+fn flag(x: u32, y: u32) -> char {
+    if x & y != 0 {
+        '+'
+    } else {
+        '-'
+    }
+}
+
+/// A capability found in the configuration space
+pub struct Capability {
+    /// Offset of the capability, None if the list could not be read
+    pub offset: Option<usize>,
+    /// Capability ID, None if the list is broken at this entry
+    pub id: Option<u16>,
+    /// Whether the capability is in the PCI Express extended space
+    pub extended: bool,
+    /// Version of an extended capability
+    pub version: u8,
+    /// Decoded name line followed by indented detail lines
+    pub text: String,
+}
+
+impl Capability {
+    fn new(offset: Option<usize>, id: Option<u16>) -> Self {
+        Self {
+            offset,
+            id,
+            extended: false,
+            version: 0,
+            text: String::new(),
+        }
+    }
+}
+
+/// Walk the capability list and decode every capability
+pub fn show_caps(d: &Device, c: &Config, where_: usize) {
+    for cap in read_caps(d, c, where_) {
+        print!("\tCapabilities: ");
+        match cap.offset {
+            Some(offset) if cap.extended => {
+                print!("[{:03x}", offset);
+                if c.verbose > 1 {
+                    print!(" v{}", cap.version);
+                }
+                print!("] ");
+            }
+            Some(offset) => print!("[{:02x}] ", offset),
+            None => {}
+        }
+        print!("{}", cap.text);
+    }
+}
+
+/// Decode the standard capability list starting at the pointer in `where_`,
+/// followed by the extended capabilities
+pub fn read_caps(d: &Device, c: &Config, where_: usize) -> Vec<Capability> {
+    let mut caps = Vec::new();
+    let mut can_have_ext_caps = false;
+    let mut exp_type = None;
+
+    if get_conf_word(d, PCI_STATUS as usize) & PCI_STATUS_CAP_LIST as u16 != 0 {
+        let mut been_there = [false; 256];
+        let mut where_ = (get_conf_byte(d, where_) & !3) as usize;
+        while where_ != 0 {
+            if !config_fetch(d, where_, 4) {
+                let mut entry = Capability::new(None, None);
+                entry.text.push_str("<access denied>\n");
+                caps.push(entry);
+                break;
+            }
+            let id = get_conf_byte(d, where_ + PCI_CAP_LIST_ID as usize);
+            let next = (get_conf_byte(d, where_ + PCI_CAP_LIST_NEXT as usize) & !3) as usize;
+            let cap = get_conf_word(d, where_ + PCI_CAP_FLAGS as usize) as u32;
+            let mut entry = Capability::new(Some(where_), None);
+            let o = &mut entry.text;
+            if been_there[where_] {
+                outln!(o, "<chain looped>");
+                caps.push(entry);
+                break;
+            }
+            been_there[where_] = true;
+            if id == 0xff {
+                outln!(o, "<chain broken>");
+                caps.push(entry);
+                break;
+            }
+            match id {
+                PCI_CAP_ID_NULL => outln!(o, "Null"),
+                PCI_CAP_ID_PM => cap_pm(o, d, c, where_, cap),
+                PCI_CAP_ID_SLOTID => outln!(
+                    o,
+                    "Slot ID: {} slots, First{}, chassis {:02x}",
+                    bits(cap, 0, 5),
+                    flag(cap, 0x20),
+                    bits(cap, 8, 8)
+                ),
+                PCI_CAP_ID_MSI => cap_msi(o, d, c, where_, cap),
+                PCI_CAP_ID_CHSWP => outln!(o, "CompactPCI hot-swap <?>"),
+                PCI_CAP_ID_VNDR => show_vendor_caps(o, d, c, where_, cap),
+                PCI_CAP_ID_DBG => outln!(
+                    o,
+                    "Debug port: BAR={} offset={:04x}",
+                    bits(cap, 13, 3),
+                    bits(cap, 0, 13)
+                ),
+                PCI_CAP_ID_CCRC => outln!(o, "CompactPCI central resource control <?>"),
+                PCI_CAP_ID_HOTPLUG => outln!(o, "Hot-plug capable"),
+                PCI_CAP_ID_AGP3 => outln!(o, "AGP3 <?>"),
+                PCI_CAP_ID_SECURE => outln!(o, "Secure device <?>"),
+                PCI_CAP_ID_EXP => {
+                    exp_type = Some(cap_express(o, d, c, where_, cap));
+                    can_have_ext_caps = true;
+                }
+                PCI_CAP_ID_MSIX => cap_msix(o, d, c, where_, cap),
+                PCI_CAP_ID_PCIX => {
+                    outln!(o, "PCI-X <?>");
+                    can_have_ext_caps = true;
+                }
+                _ => outln!(o, "Capability ID {:#x} [{:04x}]", id, cap),
+            }
+            entry.id = Some(id as u16);
+            caps.push(entry);
+            where_ = next;
+        }
+    }
+    if can_have_ext_caps {
+        read_ext_caps(&mut caps, d, c, exp_type);
+    }
+    caps
+}
+
+fn cap_pm(o: &mut String, d: &Device, c: &Config, where_: usize, cap: u32) {
+    const PM_AUX_CURRENT: [u32; 8] = [0, 55, 100, 160, 220, 270, 320, 375];
+
+    outln!(
+        o,
+        "Power Management version {}",
+        cap & PCI_PM_CAP_VER_MASK as u32
+    );
+    if c.verbose < 2 {
+        return;
+    }
+    outln!(
+        o,
+        "\t\tFlags: PMEClk{} DSI{} D1{} D2{} AuxCurrent={}mA PME(D0{},D1{},D2{},D3hot{},D3cold{})",
+        flag(cap, PCI_PM_CAP_PME_CLOCK as u32),
+        flag(cap, PCI_PM_CAP_DSI as u32),
+        flag(cap, PCI_PM_CAP_D1 as u32),
+        flag(cap, PCI_PM_CAP_D2 as u32),
+        PM_AUX_CURRENT[((cap & PCI_PM_CAP_AUX_C_MASK as u32) >> 6) as usize],
+        flag(cap, PCI_PM_CAP_PME_D0 as u32),
+        flag(cap, PCI_PM_CAP_PME_D1 as u32),
+        flag(cap, PCI_PM_CAP_PME_D2 as u32),
+        flag(cap, PCI_PM_CAP_PME_D3_HOT as u32),
+        flag(cap, PCI_PM_CAP_PME_D3_COLD as u32)
+    );
+    if !config_fetch(
+        d,
+        where_ + PCI_PM_CTRL as usize,
+        (PCI_PM_SIZEOF - PCI_PM_CTRL) as usize,
+    ) {
+        return;
+    }
+    let t = get_conf_word(d, where_ + PCI_PM_CTRL as usize) as u32;
+    outln!(
+        o,
+        "\t\tStatus: D{} NoSoftRst{} PME-Enable{} DSel={} DScale={} PME{}",
+        t & PCI_PM_CTRL_STATE_MASK as u32,
+        flag(t, PCI_PM_CTRL_NO_SOFT_RST as u32),
+        flag(t, PCI_PM_CTRL_PME_ENABLE as u32),
+        (t & PCI_PM_CTRL_DATA_SEL_MASK as u32) >> 9,
+        (t & PCI_PM_CTRL_DATA_SCALE_MASK as u32) >> 13,
+        flag(t, PCI_PM_CTRL_PME_STATUS as u32)
+    );
+    let b = get_conf_byte(d, where_ + PCI_PM_PPB_EXTENSIONS as usize) as u32;
+    if b != 0 {
+        outln!(
+            o,
+            "\t\tBridge: PM{} B3{}",
+            flag(b, PCI_PM_BPCC_ENABLE as u32),
+            flag(!b, PCI_PM_PPB_B2_B3 as u32)
+        );
+    }
+}
+
+fn cap_msi(o: &mut String, d: &Device, c: &Config, where_: usize, cap: u32) {
+    outln!(
+        o,
+        "MSI: Enable{} Count={}/{} Maskable{} 64bit{}",
+        flag(cap, PCI_MSI_FLAGS_ENABLE as u32),
+        1 << ((cap & PCI_MSI_FLAGS_QSIZE as u32) >> 4),
+        1 << ((cap & PCI_MSI_FLAGS_QMASK as u32) >> 1),
+        flag(cap, PCI_MSI_FLAGS_MASK_BIT as u32),
+        flag(cap, PCI_MSI_FLAGS_64BIT as u32)
+    );
+    if c.verbose < 2 {
+        return;
+    }
+    let is64 = cap & PCI_MSI_FLAGS_64BIT as u32 != 0;
+    let data = if is64 {
+        PCI_MSI_DATA_64
+    } else {
+        PCI_MSI_DATA_32
+    } as usize;
+    let address_lo = PCI_MSI_ADDRESS_LO as usize;
+    if !config_fetch(d, where_ + address_lo, data + 2 - address_lo) {
+        return;
+    }
+    out!(o, "\t\tAddress: ");
+    let t = get_conf_long(d, where_ + address_lo);
+    if is64 {
+        out!(
+            o,
+            "{:08x}",
+            get_conf_long(d, where_ + PCI_MSI_ADDRESS_HI as usize)
+        );
+    }
+    let w = get_conf_word(d, where_ + data);
+    outln!(o, "{:08x}  Data: {:04x}", t, w);
+    if cap & PCI_MSI_FLAGS_MASK_BIT as u32 != 0 {
+        let (mask, pending) = if is64 {
+            (PCI_MSI_MASK_BIT_64, PCI_MSI_PENDING_64)
+        } else {
+            (PCI_MSI_MASK_BIT_32, PCI_MSI_PENDING_32)
+        };
+        if !config_fetch(d, where_ + mask as usize, 8) {
+            return;
+        }
+        outln!(
+            o,
+            "\t\tMasking: {:08x}  Pending: {:08x}",
+            get_conf_long(d, where_ + mask as usize),
+            get_conf_long(d, where_ + pending as usize)
+        );
+    }
+}
+
+fn cap_msix(o: &mut String, d: &Device, c: &Config, where_: usize, cap: u32) {
+    outln!(
+        o,
+        "MSI-X: Enable{} Count={} Masked{}",
+        flag(cap, PCI_MSIX_ENABLE as u32),
+        (cap & PCI_MSIX_TABSIZE as u32) + 1,
+        flag(cap, PCI_MSIX_MASK as u32)
+    );
+    if c.verbose < 2 || !config_fetch(d, where_ + PCI_MSIX_TABLE as usize, 8) {
+        return;
+    }
+    let bir = PCI_MSIX_BIR as u32;
+    let off = get_conf_long(d, where_ + PCI_MSIX_TABLE as usize);
+    outln!(
+        o,
+        "\t\tVector table: BAR={} offset={:08x}",
+        off & bir,
+        off & !bir
+    );
+    let off = get_conf_long(d, where_ + PCI_MSIX_PBA as usize);
+    outln!(o, "\t\tPBA: BAR={} offset={:08x}", off & bir, off & !bir);
+}
+
+/// Decode the virtio vendor capability, false if it is not one
+fn show_vendor_caps_virtio(
+    o: &mut String,
+    d: &Device,
+    c: &Config,
+    where_: usize,
+    cap: u32,
+) -> bool {
+    let length = bits(cap, 0, 8) as usize;
+    let cfg_type = bits(cap, 8, 8);
+    if length < 16 || !config_fetch(d, where_, length) {
+        return false;
+    }
+    let name = match cfg_type {
+        1 => "CommonCfg",
+        2 => "Notify",
+        3 => "ISR",
+        4 => "DeviceCfg",
+        5 => "PCICfg",
+        8 => "SharedMemory",
+        9 => "Vendor",
+        _ => "<unknown>",
+    };
+    outln!(o, "VirtIO: {}", name);
+    if c.verbose < 2 {
+        return true;
+    }
+
+    let bar = get_conf_byte(d, where_ + 4);
+    let offset = get_conf_long(d, where_ + 8);
+    let size = get_conf_long(d, where_ + 12);
+    if cfg_type != 8 {
+        out!(o, "\t\tBAR={} offset={:08x} size={:08x}", bar, offset, size);
+    } else {
+        let offset_hi = get_conf_long(d, where_ + 16) as u64;
+        let size_hi = get_conf_long(d, where_ + 20) as u64;
+        out!(
+            o,
+            "\t\tBAR={} offset={:016x} size={:016x} id={}",
+            bar,
+            offset as u64 | offset_hi << 32,
+            size as u64 | size_hi << 32,
+            get_conf_byte(d, where_ + 5)
+        );
+    }
+    if cfg_type == 2 && length >= 20 {
+        out!(o, " multiplier={:08x}", get_conf_long(d, where_ + 16));
+    }
+    outln!(o);
+    true
+}
+
+fn show_vendor_caps(o: &mut String, d: &Device, c: &Config, where_: usize, cap: u32) {
+    out!(o, "Vendor Specific Information: ");
+    let vendor = d.dev.vendor_id.unwrap_or(0);
+    let device = d.dev.device_id.unwrap_or(0);
+    if vendor == 0x1af4
+        && (0x1000..=0x107f).contains(&device)
+        && show_vendor_caps_virtio(o, d, c, where_, cap)
+    {
+        return;
+    }
+    outln!(o, "Len={:02x} <?>", bits(cap, 0, 8));
+}
+
+fn latency_l0s(value: u32) -> &'static str {
+    const LATENCIES: [&str; 8] = [
+        "<64ns",
+        "<128ns",
+        "<256ns",
+        "<512ns",
+        "<1us",
+        "<2us",
+        "<4us",
+        "unlimited",
+    ];
+    LATENCIES[value as usize & 7]
+}
+
+fn latency_l1(value: u32) -> &'static str {
+    const LATENCIES: [&str; 8] = [
+        "<1us",
+        "<2us",
+        "<4us",
+        "<8us",
+        "<16us",
+        "<32us",
+        "<64us",
+        "unlimited",
+    ];
+    LATENCIES[value as usize & 7]
+}
+
+fn power_limit(value: u32, scale: u32) -> f64 {
+    const SCALES: [f64; 4] = [1.0, 0.1, 0.01, 0.001];
+    value as f64 * SCALES[scale as usize & 3]
+}
+
+/// Decode the PCI Express capability and return the port type
+fn cap_express(o: &mut String, d: &Device, c: &Config, where_: usize, cap: u32) -> u8 {
+    let exp_type = ((cap & PCI_EXP_FLAGS_TYPE as u32) >> 4) as u8;
+    let mut slot = false;
+    let mut link = true;
+
+    out!(o, "Express ");
+    if c.verbose >= 2 {
+        out!(o, "(v{}) ", cap & PCI_EXP_FLAGS_VERS as u32);
+    }
+    let slot_flag = flag(cap, PCI_EXP_FLAGS_SLOT as u32);
+    match exp_type {
+        PCI_EXP_TYPE_ENDPOINT => out!(o, "Endpoint"),
+        PCI_EXP_TYPE_LEG_END => out!(o, "Legacy Endpoint"),
+        PCI_EXP_TYPE_ROOT_PORT => {
+            slot = cap & PCI_EXP_FLAGS_SLOT as u32 != 0;
+            out!(o, "Root Port (Slot{})", slot_flag);
+        }
+        PCI_EXP_TYPE_UPSTREAM => out!(o, "Upstream Port"),
+        PCI_EXP_TYPE_DOWNSTREAM => {
+            slot = cap & PCI_EXP_FLAGS_SLOT as u32 != 0;
+            out!(o, "Downstream Port (Slot{})", slot_flag);
+        }
+        PCI_EXP_TYPE_PCI_BRIDGE => out!(o, "PCI-Express to PCI/PCI-X Bridge"),
+        PCI_EXP_TYPE_PCIE_BRIDGE => {
+            slot = cap & PCI_EXP_FLAGS_SLOT as u32 != 0;
+            out!(o, "PCI/PCI-X to PCI-Express Bridge (Slot{})", slot_flag);
+        }
+        PCI_EXP_TYPE_ROOT_INT_EP => {
+            link = false;
+            out!(o, "Root Complex Integrated Endpoint");
+        }
+        PCI_EXP_TYPE_ROOT_EC => {
+            link = false;
+            out!(o, "Root Complex Event Collector");
+        }
+        _ => out!(o, "Unknown type {}", exp_type),
+    }
+    outln!(o, ", MSI {:02x}", (cap & PCI_EXP_FLAGS_IRQ as u32) >> 9);
+    if c.verbose < 2 {
+        return exp_type;
+    }
+
+    let root = exp_type == PCI_EXP_TYPE_ROOT_PORT || exp_type == PCI_EXP_TYPE_ROOT_EC;
+    let size = if root {
+        32
+    } else if slot {
+        24
+    } else {
+        16
+    };
+    if !config_fetch(d, where_ + PCI_EXP_DEVCAP as usize, size) {
+        return exp_type;
+    }
+
+    cap_express_dev(o, d, where_, exp_type);
+    if link {
+        cap_express_link(o, d, where_, exp_type);
+    }
+    if slot {
+        cap_express_slot(o, d, where_);
+    }
+    if root {
+        cap_express_root(o, d, where_);
+    }
+
+    if (cap & PCI_EXP_FLAGS_VERS as u32) < 2 {
+        return exp_type;
+    }
+    let size = if slot { 24 } else { 16 };
+    if !config_fetch(d, where_ + PCI_EXP_DEVCAP2 as usize, size) {
+        return exp_type;
+    }
+
+    cap_express_dev2(o, d, where_, exp_type);
+    if link {
+        cap_express_link2(o, d, where_, exp_type);
+    }
+    exp_type
+}
+
+fn cap_express_dev(o: &mut String, d: &Device, where_: usize, exp_type: u8) {
+    let endpoint = exp_type == PCI_EXP_TYPE_ENDPOINT || exp_type == PCI_EXP_TYPE_LEG_END;
+    let flr_capable = endpoint || exp_type == PCI_EXP_TYPE_ROOT_INT_EP;
+
+    let t = get_conf_long(d, where_ + PCI_EXP_DEVCAP as usize);
+    out!(
+        o,
+        "\t\tDevCap:\tMaxPayload {} bytes, PhantFunc {}",
+        128 << (t & PCI_EXP_DEVCAP_PAYLOAD as u32),
+        (1 << ((t & PCI_EXP_DEVCAP_PHANTOM as u32) >> 3)) - 1
+    );
+    if endpoint {
+        out!(
+            o,
+            ", Latency L0s {}, L1 {}",
+            latency_l0s((t & PCI_EXP_DEVCAP_L0S as u32) >> 6),
+            latency_l1((t & PCI_EXP_DEVCAP_L1 as u32) >> 9)
+        );
+    }
+    outln!(o);
+    out!(o, "\t\t\tExtTag{}", flag(t, PCI_EXP_DEVCAP_EXT_TAG as u32));
+    if endpoint || exp_type == PCI_EXP_TYPE_UPSTREAM || exp_type == PCI_EXP_TYPE_PCI_BRIDGE {
+        out!(
+            o,
+            " AttnBtn{} AttnInd{} PwrInd{}",
+            flag(t, PCI_EXP_DEVCAP_ATN_BUT as u32),
+            flag(t, PCI_EXP_DEVCAP_ATN_IND as u32),
+            flag(t, PCI_EXP_DEVCAP_PWR_IND as u32)
+        );
+    }
+    out!(o, " RBE{}", flag(t, PCI_EXP_DEVCAP_RBE as u32));
+    if flr_capable {
+        out!(o, " FLReset{}", flag(t, PCI_EXP_DEVCAP_FLRESET));
+    }
+    if exp_type == PCI_EXP_TYPE_ENDPOINT
+        || exp_type == PCI_EXP_TYPE_UPSTREAM
+        || exp_type == PCI_EXP_TYPE_PCI_BRIDGE
+    {
+        out!(
+            o,
+            " SlotPowerLimit {:.3}W",
+            power_limit(
+                (t & PCI_EXP_DEVCAP_PWR_VAL) >> 18,
+                (t & PCI_EXP_DEVCAP_PWR_SCL) >> 26
+            )
+        );
+    }
+    outln!(o);
+
+    let w = get_conf_word(d, where_ + PCI_EXP_DEVCTL as usize) as u32;
+    outln!(
+        o,
+        "\t\tDevCtl:\tCorrErr{} NonFatalErr{} FatalErr{} UnsupReq{}",
+        flag(w, PCI_EXP_DEVCTL_CERE as u32),
+        flag(w, PCI_EXP_DEVCTL_NFERE as u32),
+        flag(w, PCI_EXP_DEVCTL_FERE as u32),
+        flag(w, PCI_EXP_DEVCTL_URRE as u32)
+    );
+    out!(
+        o,
+        "\t\t\tRlxdOrd{} ExtTag{} PhantFunc{} AuxPwr{} NoSnoop{}",
+        flag(w, PCI_EXP_DEVCTL_RELAXED as u32),
+        flag(w, PCI_EXP_DEVCTL_EXT_TAG as u32),
+        flag(w, PCI_EXP_DEVCTL_PHANTOM as u32),
+        flag(w, PCI_EXP_DEVCTL_AUX_PME as u32),
+        flag(w, PCI_EXP_DEVCTL_NOSNOOP as u32)
+    );
+    if exp_type == PCI_EXP_TYPE_PCI_BRIDGE {
+        out!(o, " BrConfRtry{}", flag(w, PCI_EXP_DEVCTL_BCRE as u32));
+    }
+    if flr_capable && t & PCI_EXP_DEVCAP_FLRESET != 0 {
+        out!(o, " FLReset{}", flag(w, PCI_EXP_DEVCTL_FLRESET as u32));
+    }
+    outln!(
+        o,
+        "\n\t\t\tMaxPayload {} bytes, MaxReadReq {} bytes",
+        128 << ((w & PCI_EXP_DEVCTL_PAYLOAD as u32) >> 5),
+        128 << ((w & PCI_EXP_DEVCTL_READRQ as u32) >> 12)
+    );
+
+    let w = get_conf_word(d, where_ + PCI_EXP_DEVSTA as usize) as u32;
+    outln!(
+        o,
+        "\t\tDevSta:\tCorrErr{} NonFatalErr{} FatalErr{} UnsupReq{} AuxPwr{} TransPend{}",
+        flag(w, PCI_EXP_DEVSTA_CED as u32),
+        flag(w, PCI_EXP_DEVSTA_NFED as u32),
+        flag(w, PCI_EXP_DEVSTA_FED as u32),
+        flag(w, PCI_EXP_DEVSTA_URD as u32),
+        flag(w, PCI_EXP_DEVSTA_AUXPD as u32),
+        flag(w, PCI_EXP_DEVSTA_TRPND as u32)
+    );
+}
+
+fn link_speed(speed: u32) -> &'static str {
+    match speed {
+        1 => "2.5GT/s",
+        2 => "5GT/s",
+        3 => "8GT/s",
+        4 => "16GT/s",
+        5 => "32GT/s",
+        6 => "64GT/s",
+        _ => "unknown",
+    }
+}
+
+fn link_compare(exp_type: u8, sta: u32, cap: u32) -> &'static str {
+    if sta > cap {
+        return " (overdriven)";
+    }
+    if sta == cap || exp_downstream_port(exp_type) {
+        return "";
+    }
+    " (downgraded)"
+}
+
+fn exp_downstream_port(exp_type: u8) -> bool {
+    exp_type == PCI_EXP_TYPE_ROOT_PORT
+        || exp_type == PCI_EXP_TYPE_DOWNSTREAM
+        || exp_type == PCI_EXP_TYPE_PCIE_BRIDGE
+}
+
+fn aspm_support(code: u32) -> &'static str {
+    match code {
+        0 => "not supported",
+        1 => "L0s",
+        2 => "L1",
+        3 => "L0s L1",
+        _ => "unknown",
+    }
+}
+
+fn aspm_enabled(code: u32) -> &'static str {
+    const DESC: [&str; 4] = ["Disabled", "L0s Enabled", "L1 Enabled", "L0s L1 Enabled"];
+    DESC[code as usize & 3]
+}
+
+fn cap_express_link(o: &mut String, d: &Device, where_: usize, exp_type: u8) {
+    let t = get_conf_long(d, where_ + PCI_EXP_LNKCAP as usize);
+    let aspm = (t & PCI_EXP_LNKCAP_ASPM) >> 10;
+    let cap_speed = t & PCI_EXP_LNKCAP_SPEED;
+    let cap_width = (t & PCI_EXP_LNKCAP_WIDTH) >> 4;
+    out!(
+        o,
+        "\t\tLnkCap:\tPort #{}, Speed {}, Width x{}, ASPM {}",
+        t >> 24,
+        link_speed(cap_speed),
+        cap_width,
+        aspm_support(aspm)
+    );
+    if aspm != 0 {
+        out!(o, ", Exit Latency ");
+        if aspm & 1 != 0 {
+            out!(o, "L0s {}", latency_l0s((t & PCI_EXP_LNKCAP_L0S) >> 12));
+        }
+        if aspm & 2 != 0 {
+            out!(
+                o,
+                "{}L1 {}",
+                if aspm & 1 != 0 { ", " } else { "" },
+                latency_l1((t & PCI_EXP_LNKCAP_L1) >> 15)
+            );
+        }
+    }
+    outln!(o);
+    outln!(
+        o,
+        "\t\t\tClockPM{} Surprise{} LLActRep{} BwNot{} ASPMOptComp{}",
+        flag(t, PCI_EXP_LNKCAP_CLOCKPM),
+        flag(t, PCI_EXP_LNKCAP_SURPRISE),
+        flag(t, PCI_EXP_LNKCAP_DLLA),
+        flag(t, PCI_EXP_LNKCAP_LBNC),
+        flag(t, PCI_EXP_LNKCAP_AOC)
+    );
+
+    let w = get_conf_word(d, where_ + PCI_EXP_LNKCTL as usize) as u32;
+    out!(
+        o,
+        "\t\tLnkCtl:\tASPM {};",
+        aspm_enabled(w & PCI_EXP_LNKCTL_ASPM as u32)
+    );
+    if exp_type == PCI_EXP_TYPE_ROOT_PORT
+        || exp_type == PCI_EXP_TYPE_ENDPOINT
+        || exp_type == PCI_EXP_TYPE_LEG_END
+        || exp_type == PCI_EXP_TYPE_PCI_BRIDGE
+    {
+        let rcb = if w & PCI_EXP_LNKCTL_RCB as u32 != 0 {
+            128
+        } else {
+            64
+        };
+        out!(o, " RCB {} bytes,", rcb);
+    }
+    outln!(
+        o,
+        " LnkDisable{} CommClk{}",
+        flag(w, PCI_EXP_LNKCTL_DISABLE as u32),
+        flag(w, PCI_EXP_LNKCTL_CLOCK as u32)
+    );
+    outln!(
+        o,
+        "\t\t\tExtSynch{} ClockPM{} AutWidDis{} BWInt{} AutBWInt{}",
+        flag(w, PCI_EXP_LNKCTL_XSYNCH as u32),
+        flag(w, PCI_EXP_LNKCTL_CLOCKPM as u32),
+        flag(w, PCI_EXP_LNKCTL_HWAUTWD as u32),
+        flag(w, PCI_EXP_LNKCTL_BWMIE as u32),
+        flag(w, PCI_EXP_LNKCTL_AUTBWIE as u32)
+    );
+
+    let w = get_conf_word(d, where_ + PCI_EXP_LNKSTA as usize) as u32;
+    let sta_speed = w & PCI_EXP_LNKSTA_SPEED as u32;
+    let sta_width = (w & PCI_EXP_LNKSTA_WIDTH as u32) >> 4;
+    outln!(
+        o,
+        "\t\tLnkSta:\tSpeed {}{}, Width x{}{}",
+        link_speed(sta_speed),
+        link_compare(exp_type, sta_speed, cap_speed),
+        sta_width,
+        link_compare(exp_type, sta_width, cap_width)
+    );
+    outln!(
+        o,
+        "\t\t\tTrErr{} Train{} SlotClk{} DLActive{} BWMgmt{} ABWMgmt{}",
+        flag(w, PCI_EXP_LNKSTA_TR_ERR as u32),
+        flag(w, PCI_EXP_LNKSTA_TRAIN as u32),
+        flag(w, PCI_EXP_LNKSTA_SL_CLK as u32),
+        flag(w, PCI_EXP_LNKSTA_DL_ACT as u32),
+        flag(w, PCI_EXP_LNKSTA_BWMGMT as u32),
+        flag(w, PCI_EXP_LNKSTA_AUTBW as u32)
+    );
+}
+
+fn cap_express_slot(o: &mut String, d: &Device, where_: usize) {
+    const INDICATOR: [&str; 4] = ["Unknown", "On", "Blink", "Off"];
+
+    let t = get_conf_long(d, where_ + PCI_EXP_SLTCAP as usize);
+    outln!(
+        o,
+        "\t\tSltCap:\tAttnBtn{} PwrCtrl{} MRL{} AttnInd{} PwrInd{} HotPlug{} Surprise{}",
+        flag(t, PCI_EXP_SLTCAP_ATNB as u32),
+        flag(t, PCI_EXP_SLTCAP_PWRC as u32),
+        flag(t, PCI_EXP_SLTCAP_MRL as u32),
+        flag(t, PCI_EXP_SLTCAP_ATNI as u32),
+        flag(t, PCI_EXP_SLTCAP_PWRI as u32),
+        flag(t, PCI_EXP_SLTCAP_HPC as u32),
+        flag(t, PCI_EXP_SLTCAP_HPS as u32)
+    );
+    outln!(
+        o,
+        "\t\t\tSlot #{}, PowerLimit {:.3}W; Interlock{} NoCompl{}",
+        t >> 19,
+        power_limit(
+            (t & PCI_EXP_SLTCAP_PWR_VAL) >> 7,
+            (t & PCI_EXP_SLTCAP_PWR_SCL) >> 15
+        ),
+        flag(t, PCI_EXP_SLTCAP_INTERLOCK),
+        flag(t, PCI_EXP_SLTCAP_NOCMDCOMP)
+    );
+
+    let w = get_conf_word(d, where_ + PCI_EXP_SLTCTL as usize) as u32;
+    outln!(
+        o,
+        "\t\tSltCtl:\tEnable: AttnBtn{} PwrFlt{} MRL{} PresDet{} CmdCplt{} HPIrq{} LinkChg{}",
+        flag(w, PCI_EXP_SLTCTL_ATNB as u32),
+        flag(w, PCI_EXP_SLTCTL_PWRF as u32),
+        flag(w, PCI_EXP_SLTCTL_MRLS as u32),
+        flag(w, PCI_EXP_SLTCTL_PRSD as u32),
+        flag(w, PCI_EXP_SLTCTL_CMDC as u32),
+        flag(w, PCI_EXP_SLTCTL_HPIE as u32),
+        flag(w, PCI_EXP_SLTCTL_LLCHG as u32)
+    );
+    outln!(
+        o,
+        "\t\t\tControl: AttnInd {}, PwrInd {}, Power{} Interlock{}",
+        INDICATOR[((w & PCI_EXP_SLTCTL_ATNI as u32) >> 6) as usize],
+        INDICATOR[((w & PCI_EXP_SLTCTL_PWRI as u32) >> 8) as usize],
+        flag(w, PCI_EXP_SLTCTL_PWRC as u32),
+        flag(w, PCI_EXP_SLTCTL_INTERLOCK as u32)
+    );
+
+    let w = get_conf_word(d, where_ + PCI_EXP_SLTSTA as usize) as u32;
+    outln!(
+        o,
+        "\t\tSltSta:\tStatus: AttnBtn{} PowerFlt{} MRL{} CmdCplt{} PresDet{} Interlock{}",
+        flag(w, PCI_EXP_SLTSTA_ATNB as u32),
+        flag(w, PCI_EXP_SLTSTA_PWRF as u32),
+        flag(w, PCI_EXP_SLTSTA_MRL_ST as u32),
+        flag(w, PCI_EXP_SLTSTA_CMDC as u32),
+        flag(w, PCI_EXP_SLTSTA_PRES as u32),
+        flag(w, PCI_EXP_SLTSTA_INTERLOCK as u32)
+    );
+    outln!(
+        o,
+        "\t\t\tChanged: MRL{} PresDet{} LinkState{}",
+        flag(w, PCI_EXP_SLTSTA_MRLS as u32),
+        flag(w, PCI_EXP_SLTSTA_PRSD as u32),
+        flag(w, PCI_EXP_SLTSTA_LLCHG as u32)
+    );
+}
+
+fn cap_express_root(o: &mut String, d: &Device, where_: usize) {
+    let w = get_conf_word(d, where_ + PCI_EXP_RTCTL as usize) as u32;
+    outln!(
+        o,
+        "\t\tRootCtl: ErrCorrectable{} ErrNon-Fatal{} ErrFatal{} PMEIntEna{} CRSVisible{}",
+        flag(w, PCI_EXP_RTCTL_SECEE as u32),
+        flag(w, PCI_EXP_RTCTL_SENFEE as u32),
+        flag(w, PCI_EXP_RTCTL_SEFEE as u32),
+        flag(w, PCI_EXP_RTCTL_PMEIE as u32),
+        flag(w, PCI_EXP_RTCTL_CRSVIS as u32)
+    );
+
+    let w = get_conf_word(d, where_ + PCI_EXP_RTCAP as usize) as u32;
+    outln!(
+        o,
+        "\t\tRootCap: CRSVisible{}",
+        flag(w, PCI_EXP_RTCAP_CRSVIS as u32)
+    );
+
+    let l = get_conf_long(d, where_ + PCI_EXP_RTSTA as usize);
+    outln!(
+        o,
+        "\t\tRootSta: PME ReqID {:04x}, PMEStatus{} PMEPending{}",
+        l & PCI_EXP_RTSTA_PME_REQID,
+        flag(l, PCI_EXP_RTSTA_PME_STATUS),
+        flag(l, PCI_EXP_RTSTA_PME_PENDING)
+    );
+}
+
+fn dev2_timeout_range(range: u32) -> &'static str {
+    match range {
+        0 => "Not Supported",
+        1 => "Range A",
+        2 => "Range B",
+        3 => "Range AB",
+        6 => "Range BC",
+        7 => "Range ABC",
+        14 => "Range BCD",
+        15 => "Range ABCD",
+        _ => "Unknown",
+    }
+}
+
+fn dev2_timeout_value(value: u32) -> &'static str {
+    match value {
+        0 => "50us to 50ms",
+        1 => "50us to 100us",
+        2 => "1ms to 10ms",
+        5 => "16ms to 55ms",
+        6 => "65ms to 210ms",
+        9 => "260ms to 900ms",
+        10 => "1s to 3.5s",
+        13 => "4s to 13s",
+        14 => "17s to 64s",
+        _ => "Unknown",
+    }
+}
+
+/// Whether the device decodes a memory BAR
+fn device_has_memory_space_bar(d: &Device) -> bool {
+    d.dev
+        .base_addr
+        .iter()
+        .zip(d.dev.size.iter())
+        .any(|(&addr, &size)| {
+            addr != 0 && size != 0 && addr & PCI_BASE_ADDRESS_SPACE_IO as u64 == 0
+        })
+}
+
+fn cap_express_dev2(o: &mut String, d: &Device, where_: usize, exp_type: u8) {
+    let has_mem_bar = device_has_memory_space_bar(d);
+    let root = exp_type == PCI_EXP_TYPE_ROOT_PORT;
+    let switch_port = exp_type == PCI_EXP_TYPE_UPSTREAM || exp_type == PCI_EXP_TYPE_DOWNSTREAM;
+    let ari_forwarding = root || exp_type == PCI_EXP_TYPE_DOWNSTREAM;
+
+    let l = get_conf_long(d, where_ + PCI_EXP_DEVCAP2 as usize);
+    out!(
+        o,
+        "\t\tDevCap2: Completion Timeout: {}, TimeoutDis{} NROPrPrP{} LTR{}",
+        dev2_timeout_range(bits(l, 0, 4)),
+        flag(l, PCI_EXP_DEVCAP2_TIMEOUT_DIS as u32),
+        flag(l, PCI_EXP_DEVCAP2_NROPRPRP as u32),
+        flag(l, PCI_EXP_DEVCAP2_LTR as u32)
+    );
+    let obff = match bits(l, 18, 2) {
+        0 => "Not Supported",
+        1 => "Via message",
+        2 => "Via WAKE#",
+        _ => "Via message/WAKE#",
+    };
+    out!(
+        o,
+        "\n\t\t\t 10BitTagComp{} 10BitTagReq{} OBFF {}, ExtFmt{} EETLPPrefix{}",
+        flag(l, PCI_EXP_DEVCAP2_10BIT_TAG_COMP),
+        flag(l, PCI_EXP_DEVCAP2_10BIT_TAG_REQ),
+        obff,
+        flag(l, PCI_EXP_DEVCAP2_EXTFMT),
+        flag(l, PCI_EXP_DEVCAP2_EE_TLP)
+    );
+    if l & PCI_EXP_DEVCAP2_EE_TLP != 0 {
+        let max = bits(l, 22, 2);
+        out!(o, ", MaxEETLPPrefixes {}", if max != 0 { max } else { 4 });
+    }
+    let epr = match bits(l, 24, 2) {
+        0 => "Not Supported",
+        1 => "Dev Specific",
+        2 => "Form Factor Dev Specific",
+        _ => "Reserved",
+    };
+    out!(
+        o,
+        "\n\t\t\t EmergencyPowerReduction {}, EmergencyPowerReductionInit{}",
+        epr,
+        flag(l, PCI_EXP_DEVCAP2_EPR_INIT)
+    );
+    out!(o, "\n\t\t\t FRS{}", flag(l, PCI_EXP_DEVCAP2_FRS));
+    if root {
+        let lncls = match bits(l, 14, 2) {
+            0 => "Not Supported",
+            1 => "64byte cachelines",
+            2 => "128byte cachelines",
+            _ => "Reserved",
+        };
+        out!(o, " LN System CLS {},", lncls);
+    }
+    if root || exp_type == PCI_EXP_TYPE_ENDPOINT {
+        let tph = match bits(l, 12, 2) {
+            0 => "TPHComp- ExtTPHComp-",
+            1 => "TPHComp+ ExtTPHComp-",
+            2 => "TPHComp- ExtTPHComp+",
+            _ => "TPHComp+ ExtTPHComp+",
+        };
+        out!(o, " {}", tph);
+    }
+    if ari_forwarding {
+        outln!(o, " ARIFwd{}", flag(l, PCI_EXP_DEVCAP2_ARI as u32));
+    } else {
+        outln!(o);
+    }
+    if root || switch_port || has_mem_bar {
+        out!(o, "\t\t\t AtomicOpsCap:");
+        if root || switch_port {
+            out!(
+                o,
+                " Routing{}",
+                flag(l, PCI_EXP_DEVCAP2_ATOMICOP_ROUTING as u32)
+            );
+        }
+        if root || has_mem_bar {
+            out!(
+                o,
+                " 32bit{} 64bit{} 128bitCAS{}",
+                flag(l, PCI_EXP_DEVCAP2_32BIT_ATOMICOP_COMP as u32),
+                flag(l, PCI_EXP_DEVCAP2_64BIT_ATOMICOP_COMP as u32),
+                flag(l, PCI_EXP_DEVCAP2_128BIT_CAS_COMP as u32)
+            );
+        }
+        outln!(o);
+    }
+
+    let w = get_conf_word(d, where_ + PCI_EXP_DEVCTL2 as usize) as u32;
+    out!(
+        o,
+        "\t\tDevCtl2: Completion Timeout: {}, TimeoutDis{}",
+        dev2_timeout_value(bits(w, 0, 4)),
+        flag(w, PCI_EXP_DEVCTL2_TIMEOUT_DIS as u32)
+    );
+    if ari_forwarding {
+        outln!(o, " ARIFwd{}", flag(w, PCI_EXP_DEVCTL2_ARI as u32));
+    } else {
+        outln!(o);
+    }
+    let requester = root
+        || exp_type == PCI_EXP_TYPE_ENDPOINT
+        || exp_type == PCI_EXP_TYPE_ROOT_INT_EP
+        || exp_type == PCI_EXP_TYPE_LEG_END;
+    if requester || switch_port {
+        out!(o, "\t\t\t AtomicOpsCtl:");
+        if requester {
+            out!(
+                o,
+                " ReqEn{}",
+                flag(w, PCI_EXP_DEVCTL2_ATOMICOP_REQUESTER_EN as u32)
+            );
+        }
+        if root || switch_port {
+            out!(
+                o,
+                " EgressBlck{}",
+                flag(w, PCI_EXP_DEVCTL2_ATOMICOP_EGRESS_BLOCK as u32)
+            );
+        }
+        outln!(o);
+    }
+    outln!(
+        o,
+        "\t\t\t IDOReq{} IDOCompl{} LTR{} EmergencyPowerReductionReq{}",
+        flag(w, PCI_EXP_DEVCTL2_IDO_REQ_EN as u32),
+        flag(w, PCI_EXP_DEVCTL2_IDO_CMP_EN as u32),
+        flag(w, PCI_EXP_DEVCTL2_LTR as u32),
+        flag(w, PCI_EXP_DEVCTL2_EPR_REQ as u32)
+    );
+    let obff = match bits(w, 13, 2) {
+        0 => "Disabled",
+        1 => "Via message A",
+        2 => "Via message B",
+        _ => "Via WAKE#",
+    };
+    outln!(
+        o,
+        "\t\t\t 10BitTagReq{} OBFF {}, EETLPPrefixBlk{}",
+        flag(w, PCI_EXP_DEVCTL2_10BIT_TAG_REQ as u32),
+        obff,
+        flag(w, PCI_EXP_DEVCTL2_EE_TLP_BLK as u32)
+    );
+}
+
+fn link2_speed_cap(vector: u32) -> &'static str {
+    if vector & 0x40 != 0 {
+        "RsvdP"
+    } else if vector & 0x20 != 0 {
+        "2.5-64GT/s"
+    } else if vector & 0x10 != 0 {
+        "2.5-32GT/s"
+    } else if vector & 0x08 != 0 {
+        "2.5-16GT/s"
+    } else if vector & 0x04 != 0 {
+        "2.5-8GT/s"
+    } else if vector & 0x02 != 0 {
+        "2.5-5GT/s"
+    } else if vector & 0x01 != 0 {
+        "2.5GT/s"
+    } else {
+        "Unknown"
+    }
+}
+
+fn link2_speed(speed: u32) -> &'static str {
+    match speed {
+        1 => "2.5GT/s",
+        2 => "5GT/s",
+        3 => "8GT/s",
+        4 => "16GT/s",
+        5 => "32GT/s",
+        6 => "64GT/s",
+        _ => "Unknown",
+    }
+}
+
+fn link2_deemphasis(level: u32) -> &'static str {
+    match level {
+        0 => "-6dB",
+        1 => "-3.5dB",
+        _ => "Unknown",
+    }
+}
+
+fn link2_transmargin(margin: u32) -> &'static str {
+    match margin {
+        0 => "Normal Operating Range",
+        1 => "800-1200mV(full-swing)/400-700mV(half-swing)",
+        2..=5 => "200-400mV(full-swing)/100-200mV(half-swing)",
+        _ => "Unknown",
+    }
+}
+
+fn link2_compliance_preset(preset: u32) -> &'static str {
+    match preset {
+        0 => "-6dB de-emphasis, 0dB preshoot",
+        1 => "-3.5dB de-emphasis, 0dB preshoot",
+        2 => "-4.4dB de-emphasis, 0dB preshoot",
+        3 => "-2.5dB de-emphasis, 0dB preshoot",
+        4 => "0dB de-emphasis, 0dB preshoot",
+        5 => "0dB de-emphasis, 1.9dB preshoot",
+        6 => "0dB de-emphasis, 2.5dB preshoot",
+        7 => "-6.0dB de-emphasis, 3.5dB preshoot",
+        8 => "-3.5dB de-emphasis, 3.5dB preshoot",
+        9 => "0dB de-emphasis, 3.5dB preshoot",
+        _ => "Unknown",
+    }
+}
+
+fn cap_express_link2(o: &mut String, d: &Device, where_: usize, exp_type: u8) {
+    let mut l = 0;
+    let endpoint = exp_type == PCI_EXP_TYPE_ENDPOINT || exp_type == PCI_EXP_TYPE_LEG_END;
+    let function_zero = match pci_filter_parse_slot(&d.name) {
+        Ok(slot) => slot.slot == Some(0) && slot.func == Some(0),
+        Err(_) => true,
+    };
+
+    // Link Capabilities 2 was reserved before PCIe r3.0
+    if !endpoint || function_zero {
+        l = get_conf_long(d, where_ + PCI_EXP_LNKCAP2 as usize);
+        if l != 0 {
+            outln!(
+                o,
+                "\t\tLnkCap2: Supported Link Speeds: {}, Crosslink{} Retimer{} 2Retimers{} DRS{}",
+                link2_speed_cap(bits(l, 1, 7)),
+                flag(l, PCI_EXP_LNKCAP2_CROSSLINK),
+                flag(l, PCI_EXP_LNKCAP2_RETIMER),
+                flag(l, PCI_EXP_LNKCAP2_2RETIMERS),
+                flag(l, PCI_EXP_LNKCAP2_DRS)
+            );
+        }
+
+        let w = get_conf_word(d, where_ + PCI_EXP_LNKCTL2 as usize) as u32;
+        out!(
+            o,
+            "\t\tLnkCtl2: Target Link Speed: {}, EnterCompliance{} SpeedDis{}",
+            link2_speed(bits(w, 0, 4)),
+            flag(w, PCI_EXP_LNKCTL2_CMPLNC as u32),
+            flag(w, PCI_EXP_LNKCTL2_SPEED_DIS as u32)
+        );
+        if exp_type == PCI_EXP_TYPE_DOWNSTREAM {
+            out!(
+                o,
+                ", Selectable De-emphasis: {}",
+                link2_deemphasis(bits(w, 6, 1))
+            );
+        }
+        outln!(o);
+        outln!(
+            o,
+            "\t\t\t Transmit Margin: {}, EnterModifiedCompliance{} ComplianceSOS{}",
+            link2_transmargin(bits(w, 7, 3)),
+            flag(w, PCI_EXP_LNKCTL2_MOD_CMPLNC as u32),
+            flag(w, PCI_EXP_LNKCTL2_CMPLNC_SOS as u32)
+        );
+        outln!(
+            o,
+            "\t\t\t Compliance Preset/De-emphasis: {}",
+            link2_compliance_preset(bits(w, 12, 4))
+        );
+    }
+
+    let w = get_conf_word(d, where_ + PCI_EXP_LNKSTA2 as usize) as u32;
+    let crosslink = match bits(w, 8, 2) {
+        0 => "unsupported",
+        1 => "Upstream Port",
+        2 => "Downstream Port",
+        _ => "Reserved",
+    };
+    out!(
+        o,
+        "\t\tLnkSta2: Current De-emphasis Level: {}, EqualizationComplete{} EqualizationPhase1{}\n\
+         \t\t\t EqualizationPhase2{} EqualizationPhase3{} LinkEqualizationRequest{}\n\
+         \t\t\t Retimer{} 2Retimers{} CrosslinkRes: {}",
+        link2_deemphasis(bits(w, 0, 1)),
+        flag(w, PCI_EXP_LINKSTA2_EQU_COMP as u32),
+        flag(w, PCI_EXP_LINKSTA2_EQU_PHASE1 as u32),
+        flag(w, PCI_EXP_LINKSTA2_EQU_PHASE2 as u32),
+        flag(w, PCI_EXP_LINKSTA2_EQU_PHASE3 as u32),
+        flag(w, PCI_EXP_LINKSTA2_EQU_REQ as u32),
+        flag(w, PCI_EXP_LINKSTA2_RETIMER as u32),
+        flag(w, PCI_EXP_LINKSTA2_2RETIMERS as u32),
+        crosslink
+    );
+    if exp_downstream_port(exp_type) && l & PCI_EXP_LNKCAP2_DRS != 0 {
+        let component = match bits(w, 12, 3) {
+            0 => "Link Down - Not Determined",
+            1 => "Link Down - Not Present",
+            2 => "Link Down - Present",
+            4 => "Link Up - Present",
+            5 => "Link Up - Present and DRS Received",
+            _ => "Reserved",
+        };
+        outln!(
+            o,
+            ", DRS{}\n\t\t\t DownstreamComp: {}",
+            flag(w, PCI_EXP_LINKSTA2_DRS_RCVD as u32),
+            component
+        );
+    } else {
+        outln!(o);
+    }
+}
+
+/// Walk the extended capability list of a PCI Express or PCI-X device
+fn read_ext_caps(caps: &mut Vec<Capability>, d: &Device, c: &Config, exp_type: Option<u8>) {
+    let mut been_there = vec![false; 0x1000];
+    let mut where_ = 0x100;
+    while where_ != 0 {
+        if !config_fetch(d, where_, 4) {
+            break;
+        }
+        let header = get_conf_long(d, where_);
+        if header == 0 || header == 0xffff_ffff {
+            break;
+        }
+        let id = bits(header, 0, 16);
+        let mut entry = Capability::new(Some(where_), None);
+        entry.extended = true;
+        entry.version = bits(header, 16, 4) as u8;
+        let o = &mut entry.text;
+        if been_there[where_] {
+            outln!(o, "<chain looped>");
+            caps.push(entry);
+            break;
+        }
+        been_there[where_] = true;
+        match u8::try_from(id).unwrap_or(0xff) {
+            PCI_EXT_CAP_ID_NULL => outln!(o, "Null"),
+            PCI_EXT_CAP_ID_AER => cap_aer(o, d, c, where_, exp_type),
+            PCI_EXT_CAP_ID_DPC => cap_dpc(o, d, c, where_),
+            PCI_EXT_CAP_ID_DSN => cap_dsn(o, d, where_),
+            PCI_EXT_CAP_ID_ACS => cap_acs(o, d, c, where_),
+            PCI_EXT_CAP_ID_ARI => cap_ari(o, d, c, where_),
+            PCI_EXT_CAP_ID_SRIOV => cap_sriov(o, d, c, where_),
+            PCI_EXT_CAP_ID_LTR => cap_ltr(o, d, c, where_),
+            PCI_EXT_CAP_ID_L1PM => cap_l1pm(o, d, c, where_),
+            PCI_EXT_CAP_ID_DVSEC => cap_dvsec(o, d, where_),
+            _ => outln!(o, "Extended Capability ID {:#x}", id),
+        }
+        entry.id = Some(id as u16);
+        caps.push(entry);
+        where_ = (bits(header, 20, 12) & !3) as usize;
+    }
+}
+
+/// Format the AER uncorrectable error bits
+fn aer_uncorrectable(l: u32) -> String {
+    format!(
+        "DLP{} SDES{} TLP{} FCP{} CmpltTO{} CmpltAbrt{} UnxCmplt{} RxOF{} MalfTLP{} ECRC{} UnsupReq{} ACSViol{}",
+        flag(l, PCI_ERR_UNC_DLP),
+        flag(l, PCI_ERR_UNC_SDES),
+        flag(l, PCI_ERR_UNC_POISON_TLP),
+        flag(l, PCI_ERR_UNC_FCP),
+        flag(l, PCI_ERR_UNC_COMP_TIME),
+        flag(l, PCI_ERR_UNC_COMP_ABORT),
+        flag(l, PCI_ERR_UNC_UNX_COMP),
+        flag(l, PCI_ERR_UNC_RX_OVER),
+        flag(l, PCI_ERR_UNC_MALF_TLP),
+        flag(l, PCI_ERR_UNC_ECRC),
+        flag(l, PCI_ERR_UNC_UNSUP),
+        flag(l, PCI_ERR_UNC_ACS_VIOL)
+    )
+}
+
+/// Format the AER correctable error bits
+fn aer_correctable(l: u32) -> String {
+    format!(
+        "RxErr{} BadTLP{} BadDLLP{} Rollover{} Timeout{} AdvNonFatalErr{}",
+        flag(l, PCI_ERR_COR_RCVR),
+        flag(l, PCI_ERR_COR_BAD_TLP),
+        flag(l, PCI_ERR_COR_BAD_DLLP),
+        flag(l, PCI_ERR_COR_REP_ROLL),
+        flag(l, PCI_ERR_COR_REP_TIMER),
+        flag(l, PCI_ERR_COR_REP_ANFE)
+    )
+}
+
+fn cap_aer(o: &mut String, d: &Device, c: &Config, where_: usize, exp_type: Option<u8>) {
+    outln!(o, "Advanced Error Reporting");
+    if c.verbose < 2 || !config_fetch(d, where_ + PCI_ERR_UNCOR_STATUS as usize, 40) {
+        return;
+    }
+
+    let reg = |offset: u16| get_conf_long(d, where_ + offset as usize);
+    outln!(
+        o,
+        "\t\tUESta:\t{}",
+        aer_uncorrectable(reg(PCI_ERR_UNCOR_STATUS))
+    );
+    outln!(
+        o,
+        "\t\tUEMsk:\t{}",
+        aer_uncorrectable(reg(PCI_ERR_UNCOR_MASK))
+    );
+    outln!(
+        o,
+        "\t\tUESvrt:\t{}",
+        aer_uncorrectable(reg(PCI_ERR_UNCOR_SEVER))
+    );
+    outln!(
+        o,
+        "\t\tCESta:\t{}",
+        aer_correctable(reg(PCI_ERR_COR_STATUS))
+    );
+    outln!(o, "\t\tCEMsk:\t{}", aer_correctable(reg(PCI_ERR_COR_MASK)));
+    let l = reg(PCI_ERR_CAP);
+    outln!(o,
+        "\t\tAERCap:\tFirst Error Pointer: {:02x}, ECRCGenCap{} ECRCGenEn{} ECRCChkCap{} ECRCChkEn{}\n\
+         \t\t\tMultHdrRecCap{} MultHdrRecEn{} TLPPfxPres{} HdrLogCap{}",
+        bits(l, 0, 5),
+        flag(l, PCI_ERR_CAP_ECRC_GENC),
+        flag(l, PCI_ERR_CAP_ECRC_GENE),
+        flag(l, PCI_ERR_CAP_ECRC_CHKC),
+        flag(l, PCI_ERR_CAP_ECRC_CHKE),
+        flag(l, PCI_ERR_CAP_MULT_HDRC),
+        flag(l, PCI_ERR_CAP_MULT_HDRE),
+        flag(l, PCI_ERR_CAP_TLP_PFX),
+        flag(l, PCI_ERR_CAP_HDR_LOG)
+    );
+    outln!(
+        o,
+        "\t\tHeaderLog: {:08x} {:08x} {:08x} {:08x}",
+        reg(PCI_ERR_HEADER_LOG),
+        reg(PCI_ERR_HEADER_LOG + 4),
+        reg(PCI_ERR_HEADER_LOG + 8),
+        reg(PCI_ERR_HEADER_LOG + 12)
+    );
+
+    if exp_type != Some(PCI_EXP_TYPE_ROOT_PORT) && exp_type != Some(PCI_EXP_TYPE_ROOT_EC) {
+        return;
+    }
+    if !config_fetch(d, where_ + PCI_ERR_ROOT_COMMAND as usize, 12) {
+        return;
+    }
+    let l = reg(PCI_ERR_ROOT_COMMAND);
+    outln!(
+        o,
+        "\t\tRootCmd: CERptEn{} NFERptEn{} FERptEn{}",
+        flag(l, PCI_ERR_ROOT_CMD_COR_EN),
+        flag(l, PCI_ERR_ROOT_CMD_NONFATAL_EN),
+        flag(l, PCI_ERR_ROOT_CMD_FATAL_EN)
+    );
+    let l = reg(PCI_ERR_ROOT_STATUS);
+    outln!(
+        o,
+        "\t\tRootSta: CERcvd{} MultCERcvd{} UERcvd{} MultUERcvd{}\n\
+         \t\t\t FirstFatal{} NonFatalMsg{} FatalMsg{} IntMsgNum {}",
+        flag(l, PCI_ERR_ROOT_COR_RCV),
+        flag(l, PCI_ERR_ROOT_MULTI_COR_RCV),
+        flag(l, PCI_ERR_ROOT_UNCOR_RCV),
+        flag(l, PCI_ERR_ROOT_MULTI_UNCOR_RCV),
+        flag(l, PCI_ERR_ROOT_FIRST_FATAL),
+        flag(l, PCI_ERR_ROOT_NONFATAL_RCV),
+        flag(l, PCI_ERR_ROOT_FATAL_RCV),
+        bits(l, 27, 5)
+    );
+    outln!(
+        o,
+        "\t\tErrorSrc: ERR_COR: {:04x} ERR_FATAL/NONFATAL: {:04x}",
+        get_conf_word(d, where_ + PCI_ERR_ROOT_COR_SRC as usize),
+        get_conf_word(d, where_ + PCI_ERR_ROOT_SRC as usize)
+    );
+}
+
+fn cap_dpc(o: &mut String, d: &Device, c: &Config, where_: usize) {
+    outln!(o, "Downstream Port Containment");
+    if c.verbose < 2 || !config_fetch(d, where_ + PCI_DPC_CAP as usize, 8) {
+        return;
+    }
+
+    let l = get_conf_word(d, where_ + PCI_DPC_CAP as usize) as u32;
+    outln!(
+        o,
+        "\t\tDpcCap:\tINT Msg #{}, RPExt{} PoisonedTLP{} SwTrigger{} RP PIO Log {}, DL_ActiveErr{}",
+        bits(l, 0, 5),
+        flag(l, PCI_DPC_CAP_RP_EXT as u32),
+        flag(l, PCI_DPC_CAP_TLP_BLOCK as u32),
+        flag(l, PCI_DPC_CAP_SW_TRIGGER as u32),
+        bits(l, 8, 4),
+        flag(l, PCI_DPC_CAP_DL_ACT_ERR as u32)
+    );
+    let l = get_conf_word(d, where_ + PCI_DPC_CTL as usize) as u32;
+    outln!(
+        o,
+        "\t\tDpcCtl:\tTrigger:{:x} Cmpl{} INT{} ErrCor{} PoisonedTLP{} SwTrigger{} DL_ActiveErr{}",
+        bits(l, 0, 2),
+        flag(l, PCI_DPC_CTL_CMPL as u32),
+        flag(l, PCI_DPC_CTL_INT as u32),
+        flag(l, PCI_DPC_CTL_ERR_COR as u32),
+        flag(l, PCI_DPC_CTL_TLP as u32),
+        flag(l, PCI_DPC_CTL_SW_TRIGGER as u32),
+        flag(l, PCI_DPC_CTL_DL_ACTIVE as u32)
+    );
+    let l = get_conf_word(d, where_ + PCI_DPC_STATUS as usize) as u32;
+    outln!(o,
+        "\t\tDpcSta:\tTrigger{} Reason:{:02x} INT{} RPBusy{} TriggerExt:{:02x} RP PIO ErrPtr:{:02x}",
+        flag(l, PCI_DPC_STS_TRIGGER as u32),
+        bits(l, 1, 2),
+        flag(l, PCI_DPC_STS_INT as u32),
+        flag(l, PCI_DPC_STS_RP_BUSY as u32),
+        bits(l, 5, 2),
+        bits(l, 8, 5)
+    );
+    outln!(
+        o,
+        "\t\tSource:\t{:04x}",
+        get_conf_word(d, where_ + PCI_DPC_SOURCE as usize)
+    );
+}
+
+fn cap_dsn(o: &mut String, d: &Device, where_: usize) {
+    if !config_fetch(d, where_ + 4, 8) {
+        return;
+    }
+    let t1 = get_conf_long(d, where_ + 4);
+    let t2 = get_conf_long(d, where_ + 8);
+    outln!(
+        o,
+        "Device Serial Number {:02x}-{:02x}-{:02x}-{:02x}-{:02x}-{:02x}-{:02x}-{:02x}",
+        t2 >> 24,
+        bits(t2, 16, 8),
+        bits(t2, 8, 8),
+        bits(t2, 0, 8),
+        t1 >> 24,
+        bits(t1, 16, 8),
+        bits(t1, 8, 8),
+        bits(t1, 0, 8)
+    );
+}
+
+fn cap_acs(o: &mut String, d: &Device, c: &Config, where_: usize) {
+    outln!(o, "Access Control Services");
+    if c.verbose < 2 || !config_fetch(d, where_ + PCI_ACS_CAP as usize, 4) {
+        return;
+    }
+
+    let w = get_conf_word(d, where_ + PCI_ACS_CAP as usize) as u32;
+    outln!(o,
+        "\t\tACSCap:\tSrcValid{} TransBlk{} ReqRedir{} CmpltRedir{} UpstreamFwd{} EgressCtrl{} DirectTrans{}",
+        flag(w, PCI_ACS_CAP_VALID as u32),
+        flag(w, PCI_ACS_CAP_BLOCK as u32),
+        flag(w, PCI_ACS_CAP_REQ_RED as u32),
+        flag(w, PCI_ACS_CAP_CMPLT_RED as u32),
+        flag(w, PCI_ACS_CAP_FORWARD as u32),
+        flag(w, PCI_ACS_CAP_EGRESS as u32),
+        flag(w, PCI_ACS_CAP_TRANS as u32)
+    );
+    let w = get_conf_word(d, where_ + PCI_ACS_CTRL as usize) as u32;
+    outln!(o,
+        "\t\tACSCtl:\tSrcValid{} TransBlk{} ReqRedir{} CmpltRedir{} UpstreamFwd{} EgressCtrl{} DirectTrans{}",
+        flag(w, PCI_ACS_CTRL_VALID as u32),
+        flag(w, PCI_ACS_CTRL_BLOCK as u32),
+        flag(w, PCI_ACS_CTRL_REQ_RED as u32),
+        flag(w, PCI_ACS_CTRL_CMPLT_RED as u32),
+        flag(w, PCI_ACS_CTRL_FORWARD as u32),
+        flag(w, PCI_ACS_CTRL_EGRESS as u32),
+        flag(w, PCI_ACS_CTRL_TRANS as u32)
+    );
+}
+
+fn cap_ari(o: &mut String, d: &Device, c: &Config, where_: usize) {
+    outln!(o, "Alternative Routing-ID Interpretation (ARI)");
+    if c.verbose < 2 || !config_fetch(d, where_ + PCI_ARI_CAP as usize, 4) {
+        return;
+    }
+
+    let w = get_conf_word(d, where_ + PCI_ARI_CAP as usize) as u32;
+    outln!(
+        o,
+        "\t\tARICap:\tMFVC{} ACS{}, Next Function: {}",
+        flag(w, PCI_ARI_CAP_MFVC as u32),
+        flag(w, PCI_ARI_CAP_ACS as u32),
+        bits(w, 8, 8)
+    );
+    let w = get_conf_word(d, where_ + PCI_ARI_CTRL as usize) as u32;
+    outln!(
+        o,
+        "\t\tARICtl:\tMFVC{} ACS{}, Function Group: {}",
+        flag(w, PCI_ARI_CTRL_MFVC as u32),
+        flag(w, PCI_ARI_CTRL_ACS as u32),
+        bits(w, 4, 3)
+    );
+}
+
+fn cap_sriov(o: &mut String, d: &Device, c: &Config, where_: usize) {
+    outln!(o, "Single Root I/O Virtualization (SR-IOV)");
+    if c.verbose < 2 || !config_fetch(d, where_ + PCI_IOV_CAP as usize, 0x3c) {
+        return;
+    }
+
+    let word = |offset: u8| get_conf_word(d, where_ + offset as usize) as u32;
+    let long = |offset: u8| get_conf_long(d, where_ + offset as usize);
+    let l = long(PCI_IOV_CAP);
+    outln!(
+        o,
+        "\t\tIOVCap:\tMigration{} 10BitTagReq{} Interrupt Message Number: {:03x}",
+        flag(l, PCI_IOV_CAP_VFM),
+        flag(l, PCI_IOV_CAP_VF_10BIT_TAG_REQ),
+        bits(l, 21, 11)
+    );
+    let w = word(PCI_IOV_CTRL);
+    outln!(
+        o,
+        "\t\tIOVCtl:\tEnable{} Migration{} Interrupt{} MSE{} ARIHierarchy{} 10BitTagReq{}",
+        flag(w, PCI_IOV_CTRL_VFE as u32),
+        flag(w, PCI_IOV_CTRL_VFME as u32),
+        flag(w, PCI_IOV_CTRL_VFMIE as u32),
+        flag(w, PCI_IOV_CTRL_MSE as u32),
+        flag(w, PCI_IOV_CTRL_ARI as u32),
+        flag(w, PCI_IOV_CTRL_VF_10BIT_TAG_REQ_EN as u32)
+    );
+    outln!(
+        o,
+        "\t\tIOVSta:\tMigration{}",
+        flag(word(PCI_IOV_STATUS), PCI_IOV_STATUS_MS as u32)
+    );
+    outln!(
+        o,
+        "\t\tInitial VFs: {}, Total VFs: {}, Number of VFs: {}, Function Dependency Link: {:02x}",
+        word(PCI_IOV_INITIALVF),
+        word(PCI_IOV_TOTALVF),
+        word(PCI_IOV_NUMVF),
+        get_conf_byte(d, where_ + PCI_IOV_FDL as usize)
+    );
+    outln!(
+        o,
+        "\t\tVF offset: {}, stride: {}, Device ID: {:04x}",
+        word(PCI_IOV_OFFSET),
+        word(PCI_IOV_STRIDE),
+        word(PCI_IOV_DID)
+    );
+    outln!(
+        o,
+        "\t\tSupported Page Size: {:08x}, System Page Size: {:08x}",
+        long(PCI_IOV_SUPPS),
+        long(PCI_IOV_SYSPS)
+    );
+
+    let bar = |i: u16| get_conf_long(d, where_ + PCI_IOV_BAR_BASE as usize + 4 * i as usize);
+    let mut i = 0;
+    while i < PCI_IOV_NUM_BAR {
+        let mut l = bar(i);
+        if l == 0xffff_ffff {
+            l = 0;
+        }
+        if l != 0 {
+            out!(o, "\t\tRegion {}: Memory at ", i);
+            let mem_type = l & PCI_BASE_ADDRESS_MEM_TYPE_MASK as u32;
+            if mem_type == PCI_BASE_ADDRESS_MEM_TYPE_64 as u32 {
+                i += 1;
+                out!(o, "{:08x}", bar(i));
+            }
+            outln!(
+                o,
+                "{:08x} ({}-bit, {}prefetchable)",
+                l & PCI_ADDR_MEM_MASK,
+                if mem_type == PCI_BASE_ADDRESS_MEM_TYPE_32 as u32 {
+                    "32"
+                } else {
+                    "64"
+                },
+                if l & PCI_BASE_ADDRESS_MEM_PREFETCH as u32 != 0 {
+                    ""
+                } else {
+                    "non-"
+                }
+            );
+        }
+        i += 1;
+    }
+
+    let l = long(PCI_IOV_MSAO);
+    outln!(
+        o,
+        "\t\tVF Migration: offset: {:08x}, BIR: {:x}",
+        l & !7,
+        l & 7
+    );
+}
+
+/// Multiplier of an LTR scale value, in nanoseconds
+fn ltr_scale(scale: u32) -> u64 {
+    1 << (scale * 5)
+}
+
+fn cap_ltr(o: &mut String, d: &Device, c: &Config, where_: usize) {
+    outln!(o, "Latency Tolerance Reporting");
+    if c.verbose < 2 || !config_fetch(d, where_ + PCI_LTR_MAX_SNOOP as usize, 4) {
+        return;
+    }
+
+    let latency = |offset: u16| {
+        let value = get_conf_word(d, where_ + offset as usize) as u32;
+        let scale = (value >> PCI_LTR_SCALE_SHIFT) & PCI_LTR_SCALE_MASK;
+        (value & PCI_LTR_VALUE_MASK) as u64 * ltr_scale(scale)
+    };
+    outln!(o, "\t\tMax snoop latency: {}ns", latency(PCI_LTR_MAX_SNOOP));
+    outln!(
+        o,
+        "\t\tMax no snoop latency: {}ns",
+        latency(PCI_LTR_MAX_NOSNOOP)
+    );
+}
+
+/// L1 PM substates time in microseconds, None for a reserved scale
+fn calc_l1pm_time(scale: u32, value: u32) -> Option<u32> {
+    match scale {
+        0 => Some(value * 2),
+        1 => Some(value * 10),
+        2 => Some(value * 100),
+        _ => None,
+    }
+}
+
+fn cap_l1pm(o: &mut String, d: &Device, c: &Config, where_: usize) {
+    outln!(o, "L1 PM Substates");
+    if c.verbose < 2 {
+        return;
+    }
+    if !config_fetch(d, where_ + PCI_L1PM_SUBSTAT_CAP as usize, 12) {
+        outln!(o, "\t\t<unreadable>");
+        return;
+    }
+
+    let l1_cap = get_conf_long(d, where_ + PCI_L1PM_SUBSTAT_CAP as usize);
+    outln!(
+        o,
+        "\t\tL1SubCap: PCI-PM_L1.2{} PCI-PM_L1.1{} ASPM_L1.2{} ASPM_L1.1{} L1_PM_Substates{}",
+        flag(l1_cap, PCI_L1PM_SUBSTAT_CAP_PM_L12 as u32),
+        flag(l1_cap, PCI_L1PM_SUBSTAT_CAP_PM_L11 as u32),
+        flag(l1_cap, PCI_L1PM_SUBSTAT_CAP_ASPM_L12 as u32),
+        flag(l1_cap, PCI_L1PM_SUBSTAT_CAP_ASPM_L11 as u32),
+        flag(l1_cap, PCI_L1PM_SUBSTAT_CAP_L1PM_SUPP as u32)
+    );
+    let l12 =
+        l1_cap & (PCI_L1PM_SUBSTAT_CAP_PM_L12 as u32 | PCI_L1PM_SUBSTAT_CAP_ASPM_L12 as u32) != 0;
+    let show_time = |time: Option<u32>| match time {
+        Some(time) => format!("{}us", time),
+        None => String::from("<error>"),
+    };
+    if l12 {
+        outln!(
+            o,
+            "\t\t\t  PortCommonModeRestoreTime={}us PortTPowerOnTime={}",
+            bits(l1_cap, 8, 8),
+            show_time(calc_l1pm_time(bits(l1_cap, 16, 2), bits(l1_cap, 19, 5)))
+        );
+    }
+
+    let val = get_conf_long(d, where_ + PCI_L1PM_SUBSTAT_CTL1 as usize);
+    outln!(
+        o,
+        "\t\tL1SubCtl1: PCI-PM_L1.2{} PCI-PM_L1.1{} ASPM_L1.2{} ASPM_L1.1{}",
+        flag(val, PCI_L1PM_SUBSTAT_CTL1_PM_L12 as u32),
+        flag(val, PCI_L1PM_SUBSTAT_CTL1_PM_L11 as u32),
+        flag(val, PCI_L1PM_SUBSTAT_CTL1_ASPM_L12 as u32),
+        flag(val, PCI_L1PM_SUBSTAT_CTL1_ASPM_L11 as u32)
+    );
+    if l12 {
+        out!(o, "\t\t\t   T_CommonMode={}us", bits(val, 8, 8));
+        if l1_cap & PCI_L1PM_SUBSTAT_CAP_ASPM_L12 as u32 != 0 {
+            let scale = bits(val, 29, 3);
+            if scale > 5 {
+                out!(o, " LTR1.2_Threshold=<error>");
+            } else {
+                out!(
+                    o,
+                    " LTR1.2_Threshold={}ns",
+                    bits(val, 16, 10) as u64 * ltr_scale(scale)
+                );
+            }
+        }
+        outln!(o);
+    }
+
+    let val = get_conf_long(d, where_ + PCI_L1PM_SUBSTAT_CTL2 as usize);
+    out!(o, "\t\tL1SubCtl2:");
+    if l12 {
+        out!(
+            o,
+            " T_PwrOn={}",
+            show_time(calc_l1pm_time(bits(val, 0, 2), bits(val, 3, 5)))
+        );
+    }
+    outln!(o);
+}
+
+fn cap_dvsec(o: &mut String, d: &Device, where_: usize) {
+    if !config_fetch(d, where_, 12) {
+        outln!(o, "<unreadable>");
+        return;
+    }
+    let hdr = get_conf_long(d, where_ + PCI_DVSEC_HEADER1 as usize);
+    let id = get_conf_word(d, where_ + PCI_DVSEC_HEADER2 as usize);
+    outln!(
+        o,
+        "Designated Vendor-Specific: Vendor={:04x} ID={:04x} Rev={} Len={} <?>",
+        bits(hdr, 0, 16),
+        id,
+        bits(hdr, 16, 4),
+        bits(hdr, 20, 12)
+    );
+}
@@ -2285,3 +2285,153 @@ pub const PCI_BASE_ADDRESS_IO_MASK: u32 = !(0x03 as u32);
 pub const PCI_ADDR_MEM_MASK: u32 = !(0x0f as u32);
 ///
 pub const PCI_IORESOURCE_PCI_EA_BEI: u32 = 1 << 5;
+
+// PCI Express device capabilities
+/// Slot power limit value
+pub const PCI_EXP_DEVCAP_PWR_VAL: u32 = 0x03fc_0000;
+/// Slot power limit scale
+pub const PCI_EXP_DEVCAP_PWR_SCL: u32 = 0x0c00_0000;
+/// Function level reset
+pub const PCI_EXP_DEVCAP_FLRESET: u32 = 0x1000_0000;
+/// Maximum link speed
+pub const PCI_EXP_LNKCAP_SPEED: u32 = 0x0000_000f;
+/// Maximum link width
+pub const PCI_EXP_LNKCAP_WIDTH: u32 = 0x0000_03f0;
+/// Active state power management
+pub const PCI_EXP_LNKCAP_ASPM: u32 = 0x0000_0c00;
+/// L0s exit latency
+pub const PCI_EXP_LNKCAP_L0S: u32 = 0x0000_7000;
+/// L1 exit latency
+pub const PCI_EXP_LNKCAP_L1: u32 = 0x0003_8000;
+/// Clock power management
+pub const PCI_EXP_LNKCAP_CLOCKPM: u32 = 0x0004_0000;
+/// Surprise down error reporting
+pub const PCI_EXP_LNKCAP_SURPRISE: u32 = 0x0008_0000;
+/// Data link layer active reporting
+pub const PCI_EXP_LNKCAP_DLLA: u32 = 0x0010_0000;
+/// Link bandwidth notification
+pub const PCI_EXP_LNKCAP_LBNC: u32 = 0x0020_0000;
+/// ASPM optionality compliance
+pub const PCI_EXP_LNKCAP_AOC: u32 = 0x0040_0000;
+/// Slot power limit value
+pub const PCI_EXP_SLTCAP_PWR_VAL: u32 = 0x0000_7f80;
+/// Slot power limit scale
+pub const PCI_EXP_SLTCAP_PWR_SCL: u32 = 0x0001_8000;
+/// Electromechanical interlock
+pub const PCI_EXP_SLTCAP_INTERLOCK: u32 = 0x0002_0000;
+/// No command completed support
+pub const PCI_EXP_SLTCAP_NOCMDCOMP: u32 = 0x0004_0000;
+/// PME requester ID
+pub const PCI_EXP_RTSTA_PME_REQID: u32 = 0x0000_ffff;
+/// PME status
+pub const PCI_EXP_RTSTA_PME_STATUS: u32 = 0x0001_0000;
+/// PME pending
+pub const PCI_EXP_RTSTA_PME_PENDING: u32 = 0x0002_0000;
+/// 10-bit tag completer
+pub const PCI_EXP_DEVCAP2_10BIT_TAG_COMP: u32 = 0x0001_0000;
+/// 10-bit tag requester
+pub const PCI_EXP_DEVCAP2_10BIT_TAG_REQ: u32 = 0x0002_0000;
+/// Extended fmt field
+pub const PCI_EXP_DEVCAP2_EXTFMT: u32 = 0x0010_0000;
+/// End-end TLP prefix
+pub const PCI_EXP_DEVCAP2_EE_TLP: u32 = 0x0020_0000;
+/// Emergency power reduction initialization
+pub const PCI_EXP_DEVCAP2_EPR_INIT: u32 = 0x0400_0000;
+/// Function readiness status
+pub const PCI_EXP_DEVCAP2_FRS: u32 = 0x8000_0000;
+/// Crosslink support
+pub const PCI_EXP_LNKCAP2_CROSSLINK: u32 = 0x0000_0100;
+/// Retimer presence detect
+pub const PCI_EXP_LNKCAP2_RETIMER: u32 = 0x0080_0000;
+/// Two retimers presence detect
+pub const PCI_EXP_LNKCAP2_2RETIMERS: u32 = 0x0100_0000;
+/// Device readiness status
+pub const PCI_EXP_LNKCAP2_DRS: u32 = 0x8000_0000;
+
+// Advanced error reporting
+/// Data link protocol error
+pub const PCI_ERR_UNC_DLP: u32 = 0x0000_0010;
+/// Surprise down error
+pub const PCI_ERR_UNC_SDES: u32 = 0x0000_0020;
+/// Poisoned TLP
+pub const PCI_ERR_UNC_POISON_TLP: u32 = 0x0000_1000;
+/// Flow control protocol error
+pub const PCI_ERR_UNC_FCP: u32 = 0x0000_2000;
+/// Completion timeout
+pub const PCI_ERR_UNC_COMP_TIME: u32 = 0x0000_4000;
+/// Completer abort
+pub const PCI_ERR_UNC_COMP_ABORT: u32 = 0x0000_8000;
+/// Unexpected completion
+pub const PCI_ERR_UNC_UNX_COMP: u32 = 0x0001_0000;
+/// Receiver overflow
+pub const PCI_ERR_UNC_RX_OVER: u32 = 0x0002_0000;
+/// Malformed TLP
+pub const PCI_ERR_UNC_MALF_TLP: u32 = 0x0004_0000;
+/// ECRC error
+pub const PCI_ERR_UNC_ECRC: u32 = 0x0008_0000;
+/// Unsupported request
+pub const PCI_ERR_UNC_UNSUP: u32 = 0x0010_0000;
+/// ACS violation
+pub const PCI_ERR_UNC_ACS_VIOL: u32 = 0x0020_0000;
+/// Receiver error
+pub const PCI_ERR_COR_RCVR: u32 = 0x0000_0001;
+/// Bad TLP
+pub const PCI_ERR_COR_BAD_TLP: u32 = 0x0000_0040;
+/// Bad DLLP
+pub const PCI_ERR_COR_BAD_DLLP: u32 = 0x0000_0080;
+/// REPLAY_NUM rollover
+pub const PCI_ERR_COR_REP_ROLL: u32 = 0x0000_0100;
+/// Replay timer timeout
+pub const PCI_ERR_COR_REP_TIMER: u32 = 0x0000_1000;
+/// Advisory non-fatal error
+pub const PCI_ERR_COR_REP_ANFE: u32 = 0x0000_2000;
+/// ECRC generation capable
+pub const PCI_ERR_CAP_ECRC_GENC: u32 = 0x0000_0020;
+/// ECRC generation enable
+pub const PCI_ERR_CAP_ECRC_GENE: u32 = 0x0000_0040;
+/// ECRC check capable
+pub const PCI_ERR_CAP_ECRC_CHKC: u32 = 0x0000_0080;
+/// ECRC check enable
+pub const PCI_ERR_CAP_ECRC_CHKE: u32 = 0x0000_0100;
+/// Multiple header recording capable
+pub const PCI_ERR_CAP_MULT_HDRC: u32 = 0x0000_0200;
+/// Multiple header recording enable
+pub const PCI_ERR_CAP_MULT_HDRE: u32 = 0x0000_0400;
+/// TLP prefix log present
+pub const PCI_ERR_CAP_TLP_PFX: u32 = 0x0000_0800;
+/// Completion timeout prefix/header log capable
+pub const PCI_ERR_CAP_HDR_LOG: u32 = 0x0000_1000;
+/// Correctable error reporting enable
+pub const PCI_ERR_ROOT_CMD_COR_EN: u32 = 0x0000_0001;
+/// Non-fatal error reporting enable
+pub const PCI_ERR_ROOT_CMD_NONFATAL_EN: u32 = 0x0000_0002;
+/// Fatal error reporting enable
+pub const PCI_ERR_ROOT_CMD_FATAL_EN: u32 = 0x0000_0004;
+/// ERR_COR received
+pub const PCI_ERR_ROOT_COR_RCV: u32 = 0x0000_0001;
+/// Multiple ERR_COR received
+pub const PCI_ERR_ROOT_MULTI_COR_RCV: u32 = 0x0000_0002;
+/// ERR_FATAL/NONFATAL received
+pub const PCI_ERR_ROOT_UNCOR_RCV: u32 = 0x0000_0004;
+/// Multiple ERR_FATAL/NONFATAL received
+pub const PCI_ERR_ROOT_MULTI_UNCOR_RCV: u32 = 0x0000_0008;
+/// First uncorrectable fatal
+pub const PCI_ERR_ROOT_FIRST_FATAL: u32 = 0x0000_0010;
+/// Non-fatal error messages received
+pub const PCI_ERR_ROOT_NONFATAL_RCV: u32 = 0x0000_0020;
+/// Fatal error messages received
+pub const PCI_ERR_ROOT_FATAL_RCV: u32 = 0x0000_0040;
+
+// Single root I/O virtualization
+/// VF migration capable
+pub const PCI_IOV_CAP_VFM: u32 = 0x0000_0001;
+/// VF 10-bit tag requester supported
+pub const PCI_IOV_CAP_VF_10BIT_TAG_REQ: u32 = 0x0000_0004;
+
+// Latency tolerance reporting
+/// Latency value
+pub const PCI_LTR_VALUE_MASK: u32 = 0x0000_03ff;
+/// Latency scale position
+pub const PCI_LTR_SCALE_SHIFT: u32 = 10;
+/// Latency scale
+pub const PCI_LTR_SCALE_MASK: u32 = 0x0000_0007;
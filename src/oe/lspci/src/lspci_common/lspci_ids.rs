@@ -0,0 +1,226 @@
+//! This file is part of the easybox package.
+//
+// (c) Haopeng Liu <657407891@qq.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+use flate2::read::MultiGzDecoder;
+use once_cell::unsync::OnceCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+
+/// Standard locations of the PCI ID database, in order of preference
+pub const PCI_IDS_PATH: [&str; 4] = [
+    "/usr/share/hwdata/pci.ids",
+    "/usr/share/misc/pci.ids",
+    "/usr/share/pci.ids",
+    "/usr/local/share/pci.ids",
+];
+
+/// Pick the ID database: the `-i` file if given, otherwise the first of the
+/// standard locations that exists. A compressed `<file>.gz` is used when
+/// the plain file is missing.
+pub fn find_ids_file(name: Option<&str>) -> String {
+    let candidates: Vec<&str> = match name {
+        Some(name) => vec![name],
+        None => PCI_IDS_PATH.to_vec(),
+    };
+    for candidate in &candidates {
+        if Path::new(candidate).exists() {
+            return candidate.to_string();
+        }
+        let compressed = format!("{}.gz", candidate);
+        if Path::new(&compressed).exists() {
+            return compressed;
+        }
+    }
+    candidates[0].to_string()
+}
+
+/// Read the database, decompressing it if it is gzipped
+fn read_ids(path: &str) -> Option<String> {
+    let data = fs::read(path).ok()?;
+    if data.starts_with(&[0x1f, 0x8b]) {
+        let mut text = String::new();
+        MultiGzDecoder::new(&data[..])
+            .read_to_string(&mut text)
+            .ok()?;
+        Some(text)
+    } else {
+        Some(String::from_utf8_lossy(&data).to_string())
+    }
+}
+
+/// The database text with the position of every vendor and class block
+struct IdsIndex {
+    text: String,
+    vendors: HashMap<u32, Range<usize>>,
+    classes: HashMap<u32, Range<usize>>,
+}
+
+impl IdsIndex {
+    /// Only the top-level lines are looked at, the entries inside a block
+    /// are parsed when the block is searched
+    fn new(text: String) -> Self {
+        let mut vendors = HashMap::new();
+        let mut classes = HashMap::new();
+        let mut current: Option<(bool, u32, usize)> = None;
+        let mut pos = 0;
+        for line in text.split_inclusive('\n') {
+            let start = pos;
+            pos += line.len();
+            if line.starts_with(|c: char| c == '#' || c.is_whitespace()) {
+                continue;
+            }
+            if let Some((class, id, begin)) = current.take() {
+                let map = if class { &mut classes } else { &mut vendors };
+                map.entry(id).or_insert(begin..start);
+            }
+            let (class, id) = match line.strip_prefix("C ") {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let id = id.split_whitespace().next().unwrap_or_default();
+            if let Ok(id) = u32::from_str_radix(id, 16) {
+                current = Some((class, id, start));
+            }
+        }
+        if let Some((class, id, begin)) = current {
+            let map = if class { &mut classes } else { &mut vendors };
+            map.entry(id).or_insert(begin..pos);
+        }
+        Self {
+            text,
+            vendors,
+            classes,
+        }
+    }
+}
+
+/// Name of an entry whose line starts with `key`
+fn entry_name<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(key)?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(rest.trim())
+}
+
+/// Follow `keys` down the nested entries of a block, one tab per level
+fn find_in_block<'a>(block: &'a str, keys: &[String]) -> Option<&'a str> {
+    let mut lines = block.lines();
+    let first = lines.next()?;
+    if keys.is_empty() {
+        let first = first.strip_prefix("C ").unwrap_or(first);
+        return first
+            .split_once(char::is_whitespace)
+            .map(|(_, name)| name.trim());
+    }
+    let mut level = 0;
+    for line in lines {
+        let depth = line.bytes().take_while(|b| *b == b'\t').count();
+        if depth == 0 || line[depth..].starts_with('#') {
+            continue;
+        }
+        if depth <= level {
+            return None;
+        }
+        if depth == level + 1 {
+            if let Some(name) = entry_name(&line[depth..], &keys[level]) {
+                level += 1;
+                if level == keys.len() {
+                    return Some(name);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The PCI ID database, read and indexed on the first lookup
+pub struct PciIds {
+    path: String,
+    index: OnceCell<Option<IdsIndex>>,
+}
+
+impl PciIds {
+    /// Database backed by `path`, which is not read yet
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            index: OnceCell::new(),
+        }
+    }
+
+    /// Path of the database
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn index(&self) -> Option<&IdsIndex> {
+        self.index
+            .get_or_init(|| read_ids(&self.path).map(IdsIndex::new))
+            .as_ref()
+    }
+
+    fn vendor_entry(&self, vendor: u32, keys: &[String]) -> Option<&str> {
+        let index = self.index()?;
+        let range = index.vendors.get(&vendor)?;
+        find_in_block(&index.text[range.clone()], keys)
+    }
+
+    fn class_entry(&self, class: u32, keys: &[String]) -> Option<&str> {
+        let index = self.index()?;
+        let range = index.classes.get(&class)?;
+        find_in_block(&index.text[range.clone()], keys)
+    }
+
+    /// Vendor name
+    pub fn vendor(&self, vendor: u32) -> Option<&str> {
+        self.vendor_entry(vendor, &[])
+    }
+
+    /// Device name
+    pub fn device(&self, vendor: u32, device: u32) -> Option<&str> {
+        self.vendor_entry(vendor, &[format!("{:04x}", device)])
+    }
+
+    /// Subsystem name listed under a device
+    pub fn subsystem(
+        &self,
+        vendor: u32,
+        device: u32,
+        subvendor: u32,
+        subdevice: u32,
+    ) -> Option<&str> {
+        self.vendor_entry(
+            vendor,
+            &[
+                format!("{:04x}", device),
+                format!("{:04x} {:04x}", subvendor, subdevice),
+            ],
+        )
+    }
+
+    /// Class name
+    pub fn class(&self, class: u32) -> Option<&str> {
+        self.class_entry(class, &[])
+    }
+
+    /// Subclass name
+    pub fn subclass(&self, class: u32, subclass: u32) -> Option<&str> {
+        self.class_entry(class, &[format!("{:02x}", subclass)])
+    }
+
+    /// Programming interface name
+    pub fn prog_if(&self, class: u32, subclass: u32, prog_if: u32) -> Option<&str> {
+        self.class_entry(
+            class,
+            &[format!("{:02x}", subclass), format!("{:02x}", prog_if)],
+        )
+    }
+}
@@ -0,0 +1,281 @@
+//! This file is part of the easybox package.
+//
+// (c) Haopeng Liu <657407891@qq.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+use crate::lspci_common::lspci_caps::read_caps;
+use crate::lspci_common::lspci_header::*;
+use crate::lspci_common::{
+    get_conf_byte, get_conf_long, get_conf_word, id_lookup, id_lookup_subsys, lspci_kernel,
+    sysfs_file, Config, Device,
+};
+use serde::Serialize;
+use std::fs;
+
+/// An ID together with its name from the ID database
+#[derive(Serialize)]
+pub struct JsonId {
+    /// Numeric ID in hex
+    pub id: String,
+    /// Name of the ID, None if it is not in the database
+    pub name: Option<String>,
+}
+
+/// A base address region
+#[derive(Serialize)]
+pub struct JsonBar {
+    /// Index of the region
+    pub region: usize,
+    /// "io" or "memory"
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// Address in hex, None if unassigned
+    pub address: Option<String>,
+    /// Size in bytes
+    pub size: u64,
+    /// Width of a memory region, 32 or 64
+    pub bits: Option<u32>,
+    /// Whether a memory region is prefetchable
+    pub prefetchable: Option<bool>,
+    /// Whether decoding of the region is disabled in the command register
+    pub disabled: bool,
+    /// Whether the region comes from an Enhanced Allocation entry
+    pub enhanced: bool,
+}
+
+/// A decoded capability
+#[derive(Serialize)]
+pub struct JsonCapability {
+    /// Offset in the configuration space
+    pub offset: usize,
+    /// Capability ID
+    pub id: u16,
+    /// Whether the capability is in the PCI Express extended space
+    pub extended: bool,
+    /// Version of an extended capability
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u8>,
+    /// Decoded name of the capability
+    pub name: String,
+    /// Decoded detail lines
+    pub details: Vec<String>,
+}
+
+/// A device as printed by `-J`
+#[derive(Serialize)]
+pub struct JsonDevice {
+    /// Slot with domain
+    pub slot: String,
+    /// Device class
+    pub class: JsonId,
+    /// Programming interface
+    pub prog_if: JsonId,
+    /// Vendor
+    pub vendor: JsonId,
+    /// Device
+    pub device: JsonId,
+    /// Subsystem vendor
+    pub subsystem_vendor: Option<JsonId>,
+    /// Subsystem device
+    pub subsystem_device: Option<JsonId>,
+    /// Revision
+    pub revision: Option<u32>,
+    /// Interrupt pin, A to D
+    pub interrupt_pin: Option<char>,
+    /// IRQ the interrupt is routed to
+    pub irq: Option<u32>,
+    /// NUMA node
+    pub numa_node: Option<u32>,
+    /// IOMMU group
+    pub iommu_group: Option<String>,
+    /// Physical slot
+    pub physical_slot: Option<String>,
+    /// Base address regions
+    pub bars: Vec<JsonBar>,
+    /// Kernel driver in use
+    pub driver: Option<String>,
+    /// Kernel modules able to handle the device
+    pub modules: Vec<String>,
+    /// Decoded capabilities
+    pub capabilities: Vec<JsonCapability>,
+}
+
+fn json_id(id: String, name: String) -> JsonId {
+    JsonId {
+        id,
+        name: if name.is_empty() { None } else { Some(name) },
+    }
+}
+
+fn json_bars(d: &Device) -> Vec<JsonBar> {
+    let cnt = match get_conf_byte(d, PCI_HEADER_TYPE) & 0x7f {
+        PCI_HEADER_TYPE_NORMAL => 6,
+        PCI_HEADER_TYPE_BRIDGE => 2,
+        PCI_HEADER_TYPE_CARDBUS => 1,
+        _ => 6,
+    };
+    let cmd = get_conf_word(d, PCI_COMMAND as usize);
+    let mut bars = Vec::new();
+    for i in 0..cnt.min(d.dev.base_addr.len()) {
+        let pos = d.dev.base_addr[i];
+        let len = d.dev.size.get(i).copied().unwrap_or(0);
+        let ioflg = d.dev.flags.get(i).copied().unwrap_or(0);
+        let mut flg = get_conf_long(d, PCI_BASE_ADDRESS_0 as usize + 4 * i) as u64;
+        if flg == 0xffffffff {
+            flg = 0;
+        }
+        if pos == 0 && len == 0 {
+            continue;
+        }
+        let enhanced = ioflg & PCI_IORESOURCE_PCI_EA_BEI as u64 != 0;
+        let bar = if flg & PCI_BASE_ADDRESS_SPACE_IO as u64 != 0 {
+            let a = pos & PCI_BASE_ADDRESS_IO_MASK as u64;
+            JsonBar {
+                region: i,
+                kind: "io",
+                address: if a != 0 || cmd & PCI_COMMAND_IO as u16 != 0 {
+                    Some(format!("{:x}", a))
+                } else {
+                    None
+                },
+                size: len,
+                bits: None,
+                prefetchable: None,
+                disabled: cmd & PCI_COMMAND_IO as u16 == 0,
+                enhanced,
+            }
+        } else {
+            let a = pos & PCI_ADDR_MEM_MASK as u64;
+            let t = flg & PCI_BASE_ADDRESS_MEM_TYPE_MASK as u64;
+            JsonBar {
+                region: i,
+                kind: "memory",
+                address: if a != 0 {
+                    Some(format!("{:x}", a))
+                } else {
+                    None
+                },
+                size: len,
+                bits: Some(if t == PCI_BASE_ADDRESS_MEM_TYPE_64 as u64 {
+                    64
+                } else {
+                    32
+                }),
+                prefetchable: Some(flg & PCI_BASE_ADDRESS_MEM_PREFETCH as u64 != 0),
+                disabled: cmd & PCI_COMMAND_MEMORY as u16 == 0,
+                enhanced,
+            }
+        };
+        bars.push(bar);
+    }
+    bars
+}
+
+fn json_caps(d: &Device, c: &Config) -> Vec<JsonCapability> {
+    let where_ = match get_conf_byte(d, PCI_HEADER_TYPE) & 0x7f {
+        PCI_HEADER_TYPE_NORMAL | PCI_HEADER_TYPE_BRIDGE => PCI_CAPABILITY_LIST as usize,
+        PCI_HEADER_TYPE_CARDBUS => PCI_CB_CAPABILITY_LIST as usize,
+        _ => return Vec::new(),
+    };
+    read_caps(d, c, where_)
+        .into_iter()
+        .filter_map(|cap| {
+            let mut lines = cap.text.lines();
+            let name = lines.next().unwrap_or_default().to_string();
+            Some(JsonCapability {
+                offset: cap.offset?,
+                id: cap.id?,
+                extended: cap.extended,
+                version: if cap.extended {
+                    Some(cap.version)
+                } else {
+                    None
+                },
+                name,
+                details: lines.map(|line| line.trim().replace('\t', " ")).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Build the JSON model of a device
+pub fn json_device(d: &Device, c: &Config) -> JsonDevice {
+    let p = &d.dev;
+    let class_id = p.class_id.unwrap_or(0);
+    let subclass_id = p.subclass_id.unwrap_or(0);
+    let prog_if = p.prog_if.unwrap_or(0);
+    let vendor_id = p.vendor_id.unwrap_or(0);
+    let device_id = p.device_id.unwrap_or(0);
+
+    let (subsystem_vendor, subsystem_device) = match (p.subvendor_id, p.subdevice_id) {
+        (Some(sv), Some(sd)) if sv != 0 && sv != 0xffff => (
+            Some(json_id(
+                format!("{:04x}", sv),
+                id_lookup(c, "vendor", sv, None, None, None),
+            )),
+            Some(json_id(
+                format!("{:04x}", sd),
+                id_lookup_subsys(c, "device", vendor_id, Some(device_id), Some(sv), Some(sd)),
+            )),
+        ),
+        _ => (None, None),
+    };
+
+    let int_pin = get_conf_byte(d, PCI_INTERRUPT_PIN as usize);
+    let iommu_group = sysfs_file(d, "iommu_group")
+        .and_then(|group| fs::read_link(group).ok())
+        .and_then(|group| group.file_name().map(|g| g.to_string_lossy().to_string()));
+
+    JsonDevice {
+        slot: d.name.clone(),
+        class: json_id(
+            format!("{:02x}{:02x}", class_id, subclass_id),
+            id_lookup(c, "subclass", class_id, Some(subclass_id), None, None),
+        ),
+        prog_if: json_id(
+            format!("{:02x}", prog_if),
+            id_lookup(
+                c,
+                "progif",
+                class_id,
+                Some(subclass_id),
+                Some(prog_if),
+                None,
+            ),
+        ),
+        vendor: json_id(
+            format!("{:04x}", vendor_id),
+            id_lookup(c, "vendor", vendor_id, None, None, None),
+        ),
+        device: json_id(
+            format!("{:04x}", device_id),
+            id_lookup(c, "device", vendor_id, Some(device_id), None, None),
+        ),
+        subsystem_vendor,
+        subsystem_device,
+        revision: p.revision,
+        interrupt_pin: match int_pin {
+            1..=4 => Some((b'A' + int_pin - 1) as char),
+            _ => None,
+        },
+        irq: p.irq.filter(|irq| *irq != 0),
+        numa_node: p.numa_node,
+        iommu_group,
+        physical_slot: p.phy_slot.clone().filter(|slot| !slot.is_empty()),
+        bars: json_bars(d),
+        driver: lspci_kernel::driver(d),
+        modules: lspci_kernel::modules(d, c),
+        capabilities: json_caps(d, c),
+    }
+}
+
+/// Print the selected devices as a JSON array
+pub fn show_json(devices: &[Device], c: &Config) {
+    let devices: Vec<JsonDevice> = devices.iter().map(|d| json_device(d, c)).collect();
+    match serde_json::to_string_pretty(&devices) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("lspci: {}", e),
+    }
+}
@@ -0,0 +1,127 @@
+//! This file is part of the easybox package.
+//
+// (c) Haopeng Liu <657407891@qq.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+use crate::lspci_common::{read_file, sysfs_file, Config, Device};
+use nix::sys::utsname::uname;
+use std::fs;
+
+/// The modules.alias of the running kernel
+pub fn default_alias_file() -> String {
+    let release = match uname() {
+        Ok(u) => u.release().to_string_lossy().to_string(),
+        Err(_) => String::new(),
+    };
+    format!("/lib/modules/{}/modules.alias", release)
+}
+
+/// Read the PCI entries of a modules.alias file as (pattern, module) pairs
+pub fn read_aliases(path: &str) -> Option<Vec<(String, String)>> {
+    let content = read_file(path).ok()?;
+    let aliases = content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some("alias"), Some(pattern), Some(module)) if pattern.starts_with("pci:") => {
+                    Some((pattern.to_string(), module.to_string()))
+                }
+                _ => None,
+            }
+        })
+        .collect();
+    Some(aliases)
+}
+
+/// The modalias the kernel announces for a device
+pub fn modalias(d: &Device) -> String {
+    let p = &d.dev;
+    format!(
+        "pci:v{:08X}d{:08X}sv{:08X}sd{:08X}bc{:02X}sc{:02X}i{:02X}",
+        p.vendor_id.unwrap_or(0),
+        p.device_id.unwrap_or(0),
+        p.subvendor_id.unwrap_or(0),
+        p.subdevice_id.unwrap_or(0),
+        p.class_id.unwrap_or(0),
+        p.subclass_id.unwrap_or(0),
+        p.prog_if.unwrap_or(0)
+    )
+}
+
+/// Modules whose alias matches the device, in modules.alias order
+pub fn find_modules(aliases: &[(String, String)], d: &Device) -> Vec<String> {
+    let alias = modalias(d);
+    let mut modules: Vec<String> = Vec::new();
+    for (pattern, module) in aliases {
+        if !modules.contains(module) && fnmatch(pattern.as_bytes(), alias.as_bytes()) {
+            modules.push(module.clone());
+        }
+    }
+    modules
+}
+
+/// Shell wildcard matching of `*`, `?` and `[...]` as done by fnmatch(3)
+fn fnmatch(pattern: &[u8], s: &[u8]) -> bool {
+    match pattern.first() {
+        None => s.is_empty(),
+        Some(b'*') => (0..=s.len()).any(|i| fnmatch(&pattern[1..], &s[i..])),
+        Some(b'?') => !s.is_empty() && fnmatch(&pattern[1..], &s[1..]),
+        Some(b'[') => {
+            let (c, rest) = match s.split_first() {
+                Some(x) => x,
+                None => return false,
+            };
+            match match_class(&pattern[1..], *c) {
+                Some((true, end)) => fnmatch(&pattern[1 + end..], rest),
+                Some((false, _)) => false,
+                None => *c == b'[' && fnmatch(&pattern[1..], rest),
+            }
+        }
+        Some(p) => s.first() == Some(p) && fnmatch(&pattern[1..], &s[1..]),
+    }
+}
+
+/// Match `c` against a bracket expression, returning the result and the length
+/// of the expression up to and including `]`, None if it is not terminated
+fn match_class(class: &[u8], c: u8) -> Option<(bool, usize)> {
+    let negate = matches!(class.first(), Some(b'!') | Some(b'^'));
+    let mut i = negate as usize;
+    let mut matched = false;
+    let mut first = true;
+    while i < class.len() {
+        if class[i] == b']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        if i + 2 < class.len() && class[i + 1] == b'-' && class[i + 2] != b']' {
+            matched |= class[i] <= c && c <= class[i + 2];
+            i += 3;
+        } else {
+            matched |= class[i] == c;
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Name of the driver bound to the device
+pub fn driver(d: &Device) -> Option<String> {
+    let target = fs::read_link(sysfs_file(d, "driver")?).ok()?;
+    Some(target.file_name()?.to_string_lossy().to_string())
+}
+
+/// Modules able to handle the device. Without a modules.alias, only the
+/// module of the bound driver is known.
+pub fn modules(d: &Device, c: &Config) -> Vec<String> {
+    if let Some(aliases) = &c.module_aliases {
+        return find_modules(aliases, d);
+    }
+    sysfs_file(d, "driver/module")
+        .and_then(|module| fs::read_link(module).ok())
+        .and_then(|module| module.file_name().map(|m| m.to_string_lossy().to_string()))
+        .into_iter()
+        .collect()
+}
@@ -0,0 +1,360 @@
+//! This file is part of the easybox package.
+//
+// (c) Haopeng Liu <657407891@qq.com>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+use crate::lspci_common::read_file;
+use once_cell::unsync::OnceCell;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Domain serving the PCI ID database over DNS
+pub const NET_DOMAIN: &str = "pci.id.ucw.cz";
+/// Default location of the cache of names resolved over DNS
+pub const NET_CACHE_NAME: &str = "~/.pciids-cache";
+/// A cache file not rewritten for this long is ignored
+pub const NET_CACHE_EXPIRY: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const CACHE_HEADER: &str = "#PCI-CACHE-1.0";
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const DNS_PORT: u16 = 53;
+const DNS_TIMEOUT: Duration = Duration::from_secs(2);
+const DNS_TRIES: usize = 2;
+const DNS_TYPE_TXT: u16 = 16;
+const DNS_CLASS_IN: u16 = 1;
+const DNS_RCODE_NXDOMAIN: u16 = 3;
+
+/// Cache key: category and the four IDs of a lookup
+type CacheKey = (u32, u32, u32, u32, u32);
+
+/// Category of a lookup as numbered in the cache file
+fn category(name: &str) -> Option<u32> {
+    match name {
+        "vendor" => Some(1),
+        "device" => Some(2),
+        "subsystem" => Some(3),
+        "gen_subsys" => Some(4),
+        "class" => Some(5),
+        "subclass" => Some(6),
+        "progif" => Some(7),
+        _ => None,
+    }
+}
+
+/// Expand a leading `~/` to the home directory
+fn expand_home(path: &str) -> Option<String> {
+    match path.strip_prefix("~/") {
+        Some(rest) => {
+            let home = std::env::var("HOME").ok()?;
+            Some(format!("{}/{}", home.trim_end_matches('/'), rest))
+        }
+        None => Some(path.to_string()),
+    }
+}
+
+/// Parse `addr`, `addr:port` or `[addr]:port`
+fn parse_server(server: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = server.parse::<SocketAddr>() {
+        return Some(addr);
+    }
+    server
+        .parse::<IpAddr>()
+        .ok()
+        .map(|ip| SocketAddr::new(ip, DNS_PORT))
+}
+
+/// Name servers listed in resolv.conf, the local one if there are none
+fn system_servers() -> Vec<SocketAddr> {
+    let mut servers: Vec<SocketAddr> = read_file(RESOLV_CONF)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some("nameserver"), Some(ip)) => ip.parse::<IpAddr>().ok(),
+                _ => None,
+            }
+        })
+        .map(|ip| SocketAddr::new(ip, DNS_PORT))
+        .collect();
+    if servers.is_empty() {
+        servers.push(SocketAddr::from(([127, 0, 0, 1], DNS_PORT)));
+    }
+    servers
+}
+
+/// Build a recursive query for the TXT record of `name`
+fn build_query(id: u16, name: &str) -> Option<Vec<u8>> {
+    let mut packet = Vec::with_capacity(name.len() + 18);
+    packet.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired, one question
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return None;
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&DNS_TYPE_TXT.to_be_bytes());
+    packet.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+    Some(packet)
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    let bytes = packet.get(pos..pos + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Position just after the (possibly compressed) name starting at `pos`
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(pos)?;
+        if len == 0 {
+            return Some(pos + 1);
+        }
+        if len & 0xc0 == 0xc0 {
+            return Some(pos + 2);
+        }
+        pos += 1 + len as usize;
+    }
+}
+
+/// Extract the first TXT record of a response. Ok(None) means that the
+/// server has no such record, Err that the response is unusable.
+fn parse_response(id: u16, packet: &[u8]) -> Result<Option<String>, ()> {
+    if read_u16(packet, 0) != Some(id) {
+        return Err(());
+    }
+    let flags = read_u16(packet, 2).ok_or(())?;
+    // Not a response, or truncated
+    if flags & 0x8000 == 0 || flags & 0x0200 != 0 {
+        return Err(());
+    }
+    match flags & 0x000f {
+        0 => {}
+        DNS_RCODE_NXDOMAIN => return Ok(None),
+        _ => return Err(()),
+    }
+    let qdcount = read_u16(packet, 4).ok_or(())?;
+    let ancount = read_u16(packet, 6).ok_or(())?;
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        pos = skip_name(packet, pos).ok_or(())? + 4;
+    }
+    for _ in 0..ancount {
+        pos = skip_name(packet, pos).ok_or(())?;
+        let rtype = read_u16(packet, pos).ok_or(())?;
+        let rdlen = read_u16(packet, pos + 8).ok_or(())? as usize;
+        pos += 10;
+        let rdata = packet.get(pos..pos + rdlen).ok_or(())?;
+        pos += rdlen;
+        if rtype != DNS_TYPE_TXT {
+            continue;
+        }
+        let mut txt = Vec::new();
+        let mut i = 0;
+        while i < rdata.len() {
+            let len = rdata[i] as usize;
+            txt.extend_from_slice(rdata.get(i + 1..i + 1 + len).ok_or(())?);
+            i += 1 + len;
+        }
+        return Ok(Some(String::from_utf8_lossy(&txt).to_string()));
+    }
+    Ok(None)
+}
+
+/// Ask `server` for the TXT record of `name`
+fn query_server(server: SocketAddr, id: u16, query: &[u8]) -> Result<Option<String>, ()> {
+    let local = match server {
+        SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+        SocketAddr::V6(_) => SocketAddr::from(([0u16; 8], 0)),
+    };
+    let socket = UdpSocket::bind(local).map_err(|_| ())?;
+    socket.set_read_timeout(Some(DNS_TIMEOUT)).map_err(|_| ())?;
+    socket.connect(server).map_err(|_| ())?;
+    let mut buf = [0u8; 4096];
+    for _ in 0..DNS_TRIES {
+        if socket.send(query).is_err() {
+            return Err(());
+        }
+        // Skip stray answers to earlier queries
+        while let Ok(len) = socket.recv(&mut buf) {
+            if read_u16(&buf[..len], 0) == Some(id) {
+                return parse_response(id, &buf[..len]);
+            }
+        }
+    }
+    Err(())
+}
+
+/// Resolve the TXT record of `name` using the first server that answers.
+/// Ok(None) means that the record does not exist.
+fn resolve_txt(servers: &[SocketAddr], name: &str) -> Result<Option<String>, ()> {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let id = (seed ^ std::process::id()) as u16;
+    let query = build_query(id, name).ok_or(())?;
+    for server in servers {
+        if let Ok(answer) = query_server(*server, id, &query) {
+            return Ok(answer);
+        }
+    }
+    Err(())
+}
+
+/// Name lookups in the DNS version of the ID database, remembered in a
+/// cache file between runs
+pub struct NetLookup {
+    domain: String,
+    nameserver: String,
+    cache_name: String,
+    servers: OnceCell<Vec<SocketAddr>>,
+    cache: RefCell<Option<HashMap<CacheKey, String>>>,
+    resolved: RefCell<HashSet<CacheKey>>,
+    dirty: Cell<bool>,
+}
+
+impl NetLookup {
+    /// Lookups under `domain` through `nameserver`, or the system's name
+    /// servers if it is empty, cached in `cache_name`
+    pub fn new(domain: &str, nameserver: &str, cache_name: &str) -> Self {
+        Self {
+            domain: domain.to_string(),
+            nameserver: nameserver.to_string(),
+            cache_name: cache_name.to_string(),
+            servers: OnceCell::new(),
+            cache: RefCell::new(None),
+            resolved: RefCell::new(HashSet::new()),
+            dirty: Cell::new(false),
+        }
+    }
+
+    fn servers(&self) -> &[SocketAddr] {
+        self.servers.get_or_init(|| {
+            if self.nameserver.is_empty() {
+                system_servers()
+            } else {
+                parse_server(&self.nameserver).into_iter().collect()
+            }
+        })
+    }
+
+    /// Read the cache in the format of pciutils, one `cat id1 id2 id3 id4
+    /// name` line per entry. The file has no per-entry time, so a file that
+    /// has not been rewritten within the expiry period is ignored as a whole.
+    fn load_cache(&self) -> HashMap<CacheKey, String> {
+        let mut cache = HashMap::new();
+        let path = match expand_home(&self.cache_name) {
+            Some(path) => path,
+            None => return cache,
+        };
+        let age = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|mtime| SystemTime::now().duration_since(mtime).ok());
+        match age {
+            Some(age) if age < NET_CACHE_EXPIRY => {}
+            _ => return cache,
+        }
+        let content = match read_file(&path) {
+            Ok(content) => content,
+            Err(_) => return cache,
+        };
+        let mut lines = content.lines();
+        if lines.next() != Some(CACHE_HEADER) {
+            return cache;
+        }
+        for line in lines {
+            let fields: Vec<&str> = line.splitn(6, ' ').collect();
+            if fields.len() != 6 || fields[5].is_empty() {
+                continue;
+            }
+            let cat = fields[0].parse::<u32>();
+            let ids: Vec<u32> = fields[1..5]
+                .iter()
+                .filter_map(|id| u32::from_str_radix(id, 16).ok())
+                .collect();
+            if let (Ok(cat), [id1, id2, id3, id4]) = (cat, &ids[..]) {
+                cache.insert((cat, *id1, *id2, *id3, *id4), fields[5].to_string());
+            }
+        }
+        cache
+    }
+
+    /// Write the cache back if new names were resolved. Unknown IDs are
+    /// only remembered for the current run, as pciutils does.
+    pub fn save(&self) {
+        if !self.dirty.get() {
+            return;
+        }
+        let path = match expand_home(&self.cache_name) {
+            Some(path) => path,
+            None => return,
+        };
+        let cache = self.cache.borrow();
+        let cache = match cache.as_ref() {
+            Some(cache) => cache,
+            None => return,
+        };
+        let mut entries: Vec<_> = cache.iter().filter(|(_, name)| !name.is_empty()).collect();
+        entries.sort();
+        let mut content = format!("{}\n", CACHE_HEADER);
+        for ((cat, id1, id2, id3, id4), name) in entries {
+            content.push_str(&format!(
+                "{} {:x} {:x} {:x} {:x} {}\n",
+                cat, id1, id2, id3, id4, name
+            ));
+        }
+        let tmp = format!("{}.{}", path, std::process::id());
+        if fs::write(&tmp, content).is_ok() && fs::rename(&tmp, &path).is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        self.dirty.set(false);
+    }
+
+    /// Resolve `query` (without the domain) for the IDs of a `name` lookup.
+    /// A cached name is returned without asking the name server, unless
+    /// `refresh` is set and it was read from the cache file. An empty name
+    /// means the ID is unknown.
+    pub fn lookup(&self, name: &str, ids: [u32; 4], query: &str, refresh: bool) -> String {
+        let key = match category(name) {
+            Some(cat) => (cat, ids[0], ids[1], ids[2], ids[3]),
+            None => return String::new(),
+        };
+        let mut cache = self.cache.borrow_mut();
+        let cache = cache.get_or_insert_with(|| self.load_cache());
+        if !refresh || self.resolved.borrow().contains(&key) {
+            if let Some(cached) = cache.get(&key) {
+                return cached.clone();
+            }
+        }
+        let dns_name = format!("{}.{}", query, self.domain);
+        let found = match resolve_txt(self.servers(), &dns_name) {
+            Ok(Some(txt)) => txt.strip_prefix("i=").unwrap_or_default().to_string(),
+            Ok(None) => String::new(),
+            // Do not remember failures of the network
+            Err(()) => return String::new(),
+        };
+        cache.insert(key, found.clone());
+        self.resolved.borrow_mut().insert(key);
+        if !found.is_empty() {
+            self.dirty.set(true);
+        }
+        found
+    }
+}
+
+impl Drop for NetLookup {
+    fn drop(&mut self) {
+        self.save();
+    }
+}
@@ -399,3 +399,402 @@ fn test_column_empty_column_at_eol2() {
 
     assert_eq!(expect_result.stdout_str(), actual_result.stdout_str());
 }
+
+#[test]
+fn test_column_wide_characters() {
+    let ts = TestScenario::new(util_name!());
+    let stdin = "名前 年齢 都市\n田中太郎 30 東京\nBob 7 Zürich\nJosé 41 😀x\n";
+
+    ts.ucmd_keepenv()
+        .arg("--table")
+        .pipe_in(stdin)
+        .succeeds()
+        .stdout_is(
+            "名前      年齢  都市\n\
+             田中太郎  30    東京\n\
+             Bob       7     Zürich\n\
+             José      41    😀x\n",
+        );
+    ts.ucmd_keepenv()
+        .args(&["-x", "-c", "60"])
+        .pipe_in("α\nβββ\n中文中文\nd\nabcdefghi\n")
+        .succeeds()
+        .stdout_is("α\t\tβββ\t\t中文中文\nd\t\tabcdefghi\n");
+}
+
+#[test]
+fn test_column_truncate_wrap_graphemes() {
+    let ts = TestScenario::new(util_name!());
+    let stdin = "名前 年齢 都市\n田中太郎 30 東京\ne\u{301}e\u{301}e\u{301}e\u{301}e\u{301} 7 x\n";
+    let args = ["--table", "-N", "NAME,AGE,CITY", "-c", "16"];
+
+    ts.ucmd_keepenv()
+        .args(&args)
+        .args(&["-T", "NAME"])
+        .pipe_in(stdin)
+        .succeeds()
+        .stdout_is(
+            "NAME  AGE   CITY\n\
+             名前  年齢  都市\n\
+             田中  30    東京\n\
+             e\u{301}e\u{301}e\u{301}e\u{301}  7     x\n",
+        );
+    ts.ucmd_keepenv()
+        .args(&args)
+        .args(&["-W", "NAME"])
+        .pipe_in(stdin)
+        .succeeds()
+        .stdout_is(
+            "NAME  AGE   CITY\n\
+             名前  年齢  都市\n\
+             田中  30    東京\n\
+             太郎\n\
+             e\u{301}e\u{301}e\u{301}e\u{301}  7     x\n\
+             e\u{301}\n",
+        );
+}
+
+#[test]
+fn test_column_invalid_utf8() {
+    let ts = TestScenario::new(util_name!());
+
+    ts.ucmd_keepenv()
+        .pipe_in(b"\x94~ \xe7\x94\n\xc3\xa9\x01\n".to_vec())
+        .succeeds()
+        .stdout_is("\\x94~ \\xe7\\x94\té\\x01\n");
+}
+
+#[test]
+fn test_column_single_row() {
+    let ts = TestScenario::new(util_name!());
+
+    // The cells of a lone row keep their separators and their own spaces
+    ts.ucmd_keepenv()
+        .arg("-t")
+        .pipe_in("a b c\n")
+        .succeeds()
+        .stdout_is("a  b  c\n");
+    ts.ucmd_keepenv()
+        .args(&["-t", "-s", ","])
+        .pipe_in("a,b c,d\n")
+        .succeeds()
+        .stdout_is("a  b c  d\n");
+    ts.ucmd_keepenv()
+        .args(&["-t", "-o", "|"])
+        .pipe_in("a b c\n")
+        .succeeds()
+        .stdout_is("a|b|c\n");
+}
+
+#[test]
+fn test_column_csv_input() {
+    let ts = TestScenario::new(util_name!());
+    let input_file_path = get_test_file_path("tests/fixtures/column/records.csv");
+
+    ts.ucmd_keepenv()
+        .args(&["--input-format", "csv", &input_file_path])
+        .succeeds()
+        .stdout_is(
+            "name       note       n\n\
+             Doe, Jane  said \"hi\"  3\n           \
+             then left\n\
+             Bob                   7\n\
+             x          plain\n",
+        );
+    ts.ucmd_keepenv()
+        .args(&["--input-format", "tsv", "-o", "|"])
+        .pipe_in("a\tb c\t\"q\tq\"\n")
+        .succeeds()
+        .stdout_is("a|b c|q\tq\n");
+    ts.ucmd_keepenv()
+        .args(&["--input-format", "csv", "-s", ";", "-N", "A,B", "-R", "A"])
+        .pipe_in("a;\"b;c\"\nlong;d\n")
+        .succeeds()
+        .stdout_is("   A  B\n   a  b;c\nlong  d\n");
+}
+
+#[test]
+fn test_column_csv_json() {
+    let ts = TestScenario::new(util_name!());
+    let input_file_path = get_test_file_path("tests/fixtures/column/records.csv");
+
+    ts.ucmd_keepenv()
+        .args(&["--input-format", "csv", "-J", "-n", "people"])
+        .args(&["-N", "NAME,NOTE,N", &input_file_path])
+        .succeeds()
+        .stdout_contains("\"note\": \"said \\\"hi\\\"\\nthen left\"")
+        .stdout_contains("\"name\": \"Doe, Jane\"");
+}
+
+#[test]
+fn test_column_csv_errors() {
+    let ts = TestScenario::new(util_name!());
+
+    ts.ucmd_keepenv()
+        .args(&["--input-format", "csv"])
+        .pipe_in("a,b\n\"c,d\n")
+        .fails()
+        .stderr_contains("line 2: unterminated quoted field");
+    ts.ucmd_keepenv()
+        .args(&["--input-format", "csv", "-s", "ab"])
+        .pipe_in("a\n")
+        .fails()
+        .stderr_contains("must be one character");
+}
+
+#[test]
+fn test_column_json_typed() {
+    let ts = TestScenario::new(util_name!());
+    let args = [
+        "-J",
+        "-n",
+        "disks",
+        "-C",
+        "name=NAME",
+        "-C",
+        "name=SIZE,json=number",
+        "-C",
+        "name=RM,json=boolean",
+        "-C",
+        "name=MOUNTS,json=array-string",
+    ];
+
+    ts.ucmd_keepenv()
+        .args(&args)
+        .pipe_in("sda 10 1 /\nsdb x n\n")
+        .succeeds()
+        .stdout_is(
+            "{\n   \"disks\": [\n      {\n         \"name\": \"sda\",\n         \
+             \"size\": 10,\n         \"rm\": true,\n         \"mounts\": [\n            \
+             \"/\"\n         ]\n      },{\n         \"name\": \"sdb\",\n         \
+             \"size\": \"x\",\n         \"rm\": false,\n         \"mounts\": [\n         \
+             ]\n      }\n   ]\n}\n",
+        );
+    ts.ucmd_keepenv()
+        .args(&["-J", "-N", "A,B"])
+        .pipe_in("1 2\n3 4 5\n")
+        .fails()
+        .stderr_contains("line 2: 3 cells, but 2 column names");
+    ts.ucmd_keepenv()
+        .args(&["-J", "-C", "name=A,json=int"])
+        .pipe_in("1\n")
+        .fails()
+        .stderr_contains("unsupported JSON type 'int'");
+}
+
+#[test]
+fn test_column_json_tree() {
+    let ts = TestScenario::new(util_name!());
+    let args = [
+        "-J",
+        "-N",
+        "ID,PARENT,NAME",
+        "-H",
+        "PARENT",
+        "--tree",
+        "NAME",
+        "--tree-id",
+        "ID",
+        "--tree-parent",
+        "PARENT",
+    ];
+
+    ts.ucmd_keepenv()
+        .args(&args)
+        .pipe_in("1 0 root\n2 1 child\n3 2 leaf\n4 0 other\n")
+        .succeeds()
+        .stdout_is(
+            "{\n   \"table\": [\n      {\n         \"id\": \"1\",\n         \
+             \"name\": \"root\",\n         \"children\": [\n            {\n               \
+             \"id\": \"2\",\n               \"name\": \"child\",\n               \
+             \"children\": [\n                  {\n                     \"id\": \"3\",\n                     \
+             \"name\": \"leaf\"\n                  }\n               ]\n            }\n         \
+             ]\n      },{\n         \"id\": \"4\",\n         \"name\": \"other\"\n      }\n   \
+             ]\n}\n",
+        );
+}
+
+#[test]
+fn test_column_table_column_attributes() {
+    let ts = TestScenario::new(util_name!());
+    let input_file_path = get_test_file_path("tests/fixtures/column/mountinfo");
+
+    let shorthands = ts
+        .ucmd_keepenv()
+        .args(&["--table", &input_file_path, "--output-width", "80"])
+        .args(&[
+            "--table-columns",
+            "ID,PARENT,MAJMIN,ROOT,TARGET,VFS-OPTS,PROP,SEP,TYPE,SOURCE,FS-OPTS",
+            "--table-hide",
+            "SEP,ID,PARENT,ROOT",
+            "--table-truncate",
+            "VFS-OPTS,FS-OPTS",
+            "--table-right",
+            "TYPE",
+        ])
+        .succeeds()
+        .stdout_move_str();
+    let mut args = vec!["--table", &input_file_path, "--output-width", "80"];
+    for spec in [
+        "name=ID,hide",
+        "name=PARENT,hide",
+        "name=MAJMIN",
+        "name=ROOT,hide",
+        "name=TARGET",
+        "name=VFS-OPTS,trunc",
+        "name=PROP",
+        "name=SEP,hide",
+        "name=TYPE,right",
+        "name=SOURCE",
+        "name=FS-OPTS,trunc",
+    ] {
+        args.extend(["--table-column", spec]);
+    }
+    ts.ucmd_keepenv()
+        .args(&args)
+        .succeeds()
+        .stdout_is(shorthands);
+
+    let stdin = "a 1 x\nbbbbbbbbbbbb 22 y\ncc 333 z\n";
+    ts.ucmd_keepenv()
+        .args(&[
+            "-t",
+            "-C",
+            "name=A,strictwidth,width=5",
+            "-C",
+            "name=B,right",
+        ])
+        .args(&["-C", "name=C"])
+        .pipe_in(stdin)
+        .succeeds()
+        .stdout_is("A        B  C\na        1  x\nbbbbb   22  y\ncc     333  z\n");
+    ts.ucmd_keepenv()
+        .args(&[
+            "-t",
+            "-C",
+            "name=A,strictwidth,width=5,wrap",
+            "-C",
+            "name=B",
+        ])
+        .args(&["-C", "name=C,width=3"])
+        .pipe_in(stdin)
+        .succeeds()
+        .stdout_is("A      B    C\na      1    x\nbbbbb  22   y\nbbbbb\nbb\ncc     333  z\n");
+    ts.ucmd_keepenv()
+        .args(&["-t", "-C", "name=A,strictwidth"])
+        .pipe_in(stdin)
+        .fails()
+        .stderr_contains("strictwidth needs a width in 'name=A,strictwidth'");
+
+    // A single row is laid out like any other table
+    ts.ucmd_keepenv()
+        .args(&["-t", "-d", "-C", "name=X,width=10", "-C", "name=Y"])
+        .pipe_in("a b\n")
+        .succeeds()
+        .stdout_is("a           b\n");
+    ts.ucmd_keepenv()
+        .args(&["-t", "-d", "-C", "name=X,right,width=5", "-C", "name=Y"])
+        .pipe_in("a b\n")
+        .succeeds()
+        .stdout_is("    a  b\n");
+    ts.ucmd_keepenv()
+        .args(&[
+            "-t",
+            "-d",
+            "-C",
+            "name=X,strictwidth,width=2",
+            "-C",
+            "name=Y",
+        ])
+        .pipe_in("abcd e\n")
+        .succeeds()
+        .stdout_is("ab  e\n");
+    ts.ucmd_keepenv()
+        .args(&[
+            "-t",
+            "-C",
+            "name=X,hide",
+            "-C",
+            "name=Y,right",
+            "-C",
+            "name=Z",
+        ])
+        .pipe_in("a bbb c\n")
+        .succeeds()
+        .stdout_is("  Y  Z\nbbb  c\n");
+}
+
+#[test]
+fn test_column_order_keeps_all_columns() {
+    let ts = TestScenario::new(util_name!());
+
+    ts.ucmd_keepenv()
+        .args(&["-t", "-N", "A,B,C,D", "-O", "D,B", "-H", "C"])
+        .pipe_in("a1 b1 c1 d1\na2 b2 c2 d2\n")
+        .succeeds()
+        .stdout_is("A   D   B\na1  d1  b1\na2  d2  b2\n");
+}
+
+#[test]
+fn test_column_colors() {
+    let ts = TestScenario::new(util_name!());
+    let args = [
+        "-t",
+        "-C",
+        "name=A,color=red",
+        "-C",
+        "name=B",
+        "-C",
+        "name=C,color=bold",
+        "--table-header-color",
+        "blue",
+    ];
+
+    // Escape sequences take no room in the layout
+    ts.ucmd_keepenv()
+        .args(&args)
+        .arg("--color=always")
+        .pipe_in("a b m\nlonger x m\n")
+        .succeeds()
+        .stdout_is(
+            "\x1b[38;5;4mA\x1b[39m       \x1b[38;5;4mB\x1b[39m  \x1b[38;5;4mC\x1b[39m\n\
+             \x1b[38;5;1ma\x1b[39m       b  \x1b[1mm\x1b[0m\n\
+             \x1b[38;5;1mlonger\x1b[39m  x  \x1b[1mm\x1b[0m\n",
+        );
+
+    // Output to a pipe is not coloured by default
+    for color in ["--color=never", "--color=auto", "--color"] {
+        ts.ucmd_keepenv()
+            .args(&args)
+            .arg(color)
+            .pipe_in("a b m\nlonger x m\n")
+            .succeeds()
+            .stdout_is("A       B  C\na       b  m\nlonger  x  m\n");
+    }
+
+    ts.ucmd_keepenv()
+        .args(&["-t", "-C", "name=A,color=pink"])
+        .fails()
+        .stderr_contains("unsupported color name 'pink'");
+}
+
+#[test]
+fn test_column_table_maxout() {
+    let ts = TestScenario::new(util_name!());
+
+    let result = ts
+        .ucmd_keepenv()
+        .args(&["-t", "-m", "-c", "30", "-N", "A,B,C"])
+        .pipe_in("a b c\nlonger x y\n")
+        .succeeds();
+    let lines: Vec<&str> = result.stdout_str().lines().collect();
+    assert_eq!(lines.len(), 3);
+    for line in lines {
+        assert_eq!(line.len(), 30);
+    }
+
+    ts.ucmd_keepenv()
+        .args(&["-m"])
+        .fails()
+        .stderr_contains("option --table required for all --table-*");
+}
@@ -432,3 +432,260 @@ fn test_arg_special_files() {
     let ures = task.ucmd().args(&args).succeeds();
     assert_eq!(cres.stdout_str(), ures.stdout_str());
 }
+
+#[test]
+fn test_magic_offsets_and_names() {
+    let task = TestScenario::new(util_name!());
+    let args = ["-m", "native.magic", "native.file"];
+
+    task.ucmd().args(&args).succeeds().stdout_is(
+        "native.file: easybox test data, name magic, v258, v513, marked !, 42 items, plain\n",
+    );
+}
+
+#[test]
+fn test_magic_list_strength() {
+    let task = TestScenario::new(util_name!());
+    let args = ["-m", "native.magic", "-l"];
+
+    task.ucmd().args(&args).succeeds().stdout_is(
+        "Set 0:\nBinary patterns:\nStrength =  70@2: easybox test data []\n\
+         Text patterns:\nSet 1:\nBinary patterns:\nText patterns:\n",
+    );
+}
+
+#[test]
+fn test_magic_invalid_file() {
+    let task = TestScenario::new(util_name!());
+    let args = ["-m", "invalid.magic", "native.file"];
+
+    task.ucmd()
+        .args(&args)
+        .fails()
+        .stderr_contains("invalid.magic, 1: Warning: offset `bad x y' invalid")
+        .stderr_contains("could not find any valid magic files!");
+}
+
+#[test]
+fn test_library_detector() {
+    let task = TestScenario::new(util_name!());
+    let mut detector = ::file::Detector::new(None, 0).unwrap();
+
+    let file_type = detector
+        .detect_path(&task.fixtures.plus_as_string("json.file"))
+        .unwrap();
+    assert_eq!(file_type.description, "JSON text data");
+    assert_eq!(file_type.mime_type, "application/json");
+    assert_eq!(file_type.encoding, "us-ascii");
+    assert!(!file_type.compressed);
+
+    let file_type = detector
+        .detect_path(&task.fixtures.plus_as_string("zstd.file"))
+        .unwrap();
+    assert_eq!(file_type.mime_type, "application/zstd");
+    assert_eq!(file_type.encoding, "binary");
+    assert!(file_type.extensions.is_empty());
+    assert!(file_type.compressed);
+
+    let mut detector = ::file::Detector::new(None, ::file::file_magic::MAGIC_EXTENSION).unwrap();
+    let file_type = detector
+        .detect_path(&task.fixtures.plus_as_string("zstd.file"))
+        .unwrap();
+    assert_eq!(file_type.mime_type, "application/zstd");
+    assert_eq!(file_type.extensions, ["zst"]);
+
+    let file_type = detector.detect_buffer(b"\x1f\x8b\x08\x00").unwrap();
+    assert!(file_type.description.starts_with("gzip compressed data"));
+    assert!(file_type.compressed);
+}
+
+#[test]
+fn test_json_output() {
+    let task = TestScenario::new(util_name!());
+
+    task.ucmd()
+        .args(&["--json", "json.file"])
+        .succeeds()
+        .stdout_is(
+            "{\"name\":\"json.file\",\"description\":\"JSON text data\",\
+         \"mime_type\":\"application/json\",\"encoding\":\"us-ascii\"}\n",
+        );
+    task.ucmd()
+        .args(&["--json", "missing.file"])
+        .succeeds()
+        .stdout_contains("{\"name\":\"missing.file\",\"error\":");
+}
+
+#[test]
+fn test_jobs_keep_input_order() {
+    let task = TestScenario::new(util_name!());
+    let files = [
+        "json.file",
+        "zstd.file",
+        "cmd.file",
+        "COPYING",
+        "rpm.file",
+        "native.file",
+    ];
+    let serial = task.ucmd().args(&files).succeeds().stdout_move_str();
+
+    task.ucmd()
+        .args(&["--jobs", "4"])
+        .args(&files)
+        .succeeds()
+        .stdout_is(&serial);
+    task.ucmd()
+        .args(&["-j", "3", "--json"])
+        .args(&files)
+        .succeeds()
+        .stdout_is(
+            task.ucmd()
+                .arg("--json")
+                .args(&files)
+                .succeeds()
+                .stdout_str(),
+        );
+    task.ucmd()
+        .args(&["-j", "x", "json.file"])
+        .fails()
+        .stderr_contains("invalid number of jobs: 'x'");
+}
+
+/// Test file --jobs with a magic database that can only be read once
+#[test]
+fn test_jobs_magic_from_pipe() {
+    let task = TestScenario::new(util_name!());
+    let magic = task.fixtures.read("native.magic");
+
+    task.ucmd()
+        .args(&["-m", "/dev/stdin", "-j", "2", "native.file", "json.file"])
+        .pipe_in(magic)
+        .succeeds()
+        .stdout_contains("native.file: easybox test data")
+        .stdout_contains("json.file:   JSON text data");
+}
+
+#[test]
+fn test_uncompress_in_process() {
+    let task = TestScenario::new(util_name!());
+
+    task.ucmd()
+        .args(&["-Z", "text.bz2", "text.lzma", "text.xz", "text.zst"])
+        .succeeds()
+        .stdout_is(
+            "text.bz2:  ASCII text\ntext.lzma: ASCII text\n\
+             text.xz:   ASCII text\ntext.zst:  ASCII text\n",
+        );
+    task.ucmd()
+        .args(&["-z", "--mime-type", "text.xz"])
+        .succeeds()
+        .stdout_is("text.xz: text/plain\n");
+    task.ucmd()
+        .args(&["-zb", "text.bz2", "rpm.tar.gz"])
+        .succeeds()
+        .stdout_contains("ASCII text (bzip2 compressed data, block size = 900k)\n")
+        .stdout_contains("POSIX tar archive (GNU) (gzip compressed data, from Unix");
+
+    // Text followed by binary data: `-P bytes=` stops before the binary part
+    task.ucmd()
+        .args(&["-Zb", "mixed.gz"])
+        .succeeds()
+        .stdout_is("data\n");
+    task.ucmd()
+        .args(&["-Zb", "-P", "bytes=20", "mixed.gz"])
+        .succeeds()
+        .stdout_is("ASCII text\n");
+}
+
+#[test]
+fn test_uncompress_corrupt_data() {
+    let task = TestScenario::new(util_name!());
+
+    task.ucmd().args(&["-zb", "bad.bz2"]).succeeds().stdout_is(
+        "ERROR:[bzip2: orig ptr truncated] (bzip2 compressed data, block size = 900k)\n",
+    );
+    task.ucmd()
+        .args(&["-z", "--mime-type", "bad.bz2"])
+        .succeeds()
+        .stdout_is("bad.bz2: application/x-decompression-error-bzip2-orig-ptr-truncated\n");
+}
+
+/// Set in the copy of the test binary that runs under the sandbox
+const SANDBOX_CHILD: &str = "EASYBOX_FILE_SANDBOX_CHILD";
+
+#[test]
+fn test_sandbox_blocks_forbidden_syscalls() {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+
+    if let Ok(action) = std::env::var(SANDBOX_CHILD) {
+        ::file::file_sandbox::enable_sandbox().unwrap();
+        match action.as_str() {
+            "read" => {
+                let data = std::fs::read("tests/fixtures/file/text.xz").unwrap();
+                std::io::Write::write_all(&mut std::io::stdout(), &data[..6]).unwrap();
+            }
+            "socket" => unsafe {
+                libc::socket(libc::AF_INET, libc::SOCK_STREAM, 0);
+            },
+            "spawn" => {
+                let _ = Command::new("true").status();
+            }
+            "write" => {
+                let _ = std::fs::OpenOptions::new()
+                    .append(true)
+                    .open("tests/fixtures/file/text.xz");
+            }
+            "create" => {
+                let _ = std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(false)
+                    .custom_flags(libc::O_CREAT)
+                    .open("tests/fixtures/file/created");
+            }
+            "unlink" => {
+                let _ = std::fs::remove_file("tests/fixtures/file/text.xz");
+            }
+            _ => {}
+        }
+        std::process::exit(0);
+    }
+
+    let run = |action: &str| {
+        Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "test_file::test_sandbox_blocks_forbidden_syscalls",
+                "--test-threads=1",
+                "--nocapture",
+                "-q",
+            ])
+            .env(SANDBOX_CHILD, action)
+            .output()
+            .unwrap()
+    };
+    let read = run("read");
+    assert!(read.status.success());
+    assert!(read.stdout.windows(6).any(|w| w == b"\xfd7zXZ\0"));
+    assert_eq!(run("socket").status.signal(), Some(libc::SIGSYS));
+    assert_eq!(run("spawn").status.signal(), Some(libc::SIGSYS));
+    assert_eq!(run("write").status.signal(), Some(libc::SIGSYS));
+    assert_eq!(run("create").status.signal(), Some(libc::SIGSYS));
+    assert_eq!(run("unlink").status.signal(), Some(libc::SIGSYS));
+    assert!(std::path::Path::new("tests/fixtures/file/text.xz").exists());
+    assert!(!std::path::Path::new("tests/fixtures/file/created").exists());
+}
+
+#[test]
+fn test_no_sandbox() {
+    let task = TestScenario::new(util_name!());
+
+    let args = ["-z", "-j", "2", "text.bz2", "rpm.tar.gz", "json.file"];
+    let sandboxed = task.ucmd().args(&args).succeeds().stdout_move_str();
+    task.ucmd()
+        .arg("--no-sandbox")
+        .args(&args)
+        .succeeds()
+        .stdout_is(sandboxed);
+}
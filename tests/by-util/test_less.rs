@@ -12,7 +12,10 @@ use std::env::set_var;
 use std::fs;
 use std::io::{Read, Write};
 use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep, JoinHandle};
+use std::time::{Duration, Instant};
 
 const C_EXPECT_PATH: &str = "/usr/bin/expect";
 const C_SCRIPT_PATH: &str = "/usr/bin/script";
@@ -152,6 +155,137 @@ fn run_child_thread(args: &str, bin: &str) -> std::io::Result<String> {
     }
 }
 
+/// Longest wait for the screen of a pseudo terminal to show something
+const SCREEN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Rust less running in a 80x6 pseudo terminal with GNU script
+struct LessSession {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    /// everything drawn on the screen so far
+    screen: Arc<Mutex<Vec<u8>>>,
+    reader: Option<JoinHandle<()>>,
+    /// end of the screen output already matched by `wait_for`
+    seen: usize,
+}
+
+impl LessSession {
+    /// Start less with `args` in the fixtures directory and wait for its first page
+    fn start(task: &TestScenario, args: &str, env: &[(&str, &str)]) -> Self {
+        let command = format!(
+            "stty cols 80 rows 6; {} less {}",
+            task.bin_path.display(),
+            args
+        );
+        let mut child = Command::new(C_SCRIPT_PATH)
+            .current_dir(task.fixtures.as_string())
+            .args(["-q", "-c", &command, "/dev/null"])
+            // The user's less settings do not apply to the tests
+            .env_remove("LESS")
+            .env_remove("LESSKEY")
+            .env_remove("LESSOPEN")
+            .envs(env.iter().copied())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to run script");
+
+        let mut stdout = child.stdout.take().unwrap();
+        let screen = Arc::new(Mutex::new(Vec::new()));
+        let drawn = Arc::clone(&screen);
+        let reader = thread::spawn(move || {
+            let mut chunk = [0u8; 4096];
+            while let Ok(n) = stdout.read(&mut chunk) {
+                if n == 0 {
+                    break;
+                }
+                drawn.lock().unwrap().extend_from_slice(&chunk[..n]);
+            }
+        });
+
+        let session = Self {
+            stdin: child.stdin.take(),
+            child,
+            screen,
+            reader: Some(reader),
+            seen: 0,
+        };
+        session.wait_until(|screen| !screen.is_empty());
+        session
+    }
+
+    /// Everything drawn on the screen so far
+    fn screen(&self) -> String {
+        String::from_utf8_lossy(&self.screen.lock().unwrap()).into_owned()
+    }
+
+    /// Wait until `done` holds for the screen output, false on timeout
+    fn wait_until(&self, done: impl Fn(&[u8]) -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < SCREEN_TIMEOUT {
+            if done(&self.screen.lock().unwrap()) {
+                return true;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    /// Type `key` and wait for less to redraw the screen in response
+    fn press(&mut self, key: &str) {
+        let drawn = self.screen.lock().unwrap().len();
+        let stdin = self.stdin.as_mut().unwrap();
+        stdin.write_all(key.as_bytes()).unwrap();
+        stdin.flush().unwrap();
+        self.wait_until(|screen| screen.len() > drawn);
+    }
+
+    /// Wait until `text` is drawn after the text found by the previous call
+    fn wait_for(&mut self, text: &str) -> bool {
+        let seen = self.seen;
+        let found = self.wait_until(|screen| {
+            String::from_utf8_lossy(&screen[seen.min(screen.len())..]).contains(text)
+        });
+        if found {
+            let screen = self.screen();
+            if let Some(pos) = screen[seen.min(screen.len())..].find(text) {
+                self.seen = seen + pos + text.len();
+            }
+        }
+        found
+    }
+
+    /// Close the terminal input, wait for less to exit and return everything drawn
+    fn finish(mut self) -> String {
+        drop(self.stdin.take());
+        self.child.wait().expect("Failed to wait for script");
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+        let stdout = self.screen();
+        println!("\x1b[33;1mStd Out:\x1b[0m {}", stdout);
+        stdout
+    }
+}
+
+/// Run Rust less in a 80x6 pseudo terminal with GNU script, typing each
+/// element of `keys` in turn once the screen reacted to the previous one,
+/// and return everything drawn on the screen
+fn run_less_with_keys(args: &str, keys: &[&str]) -> String {
+    run_less_with_env(args, &[], keys)
+}
+
+/// Same as `run_less_with_keys`, with extra environment variables
+fn run_less_with_env(args: &str, env: &[(&str, &str)], keys: &[&str]) -> String {
+    let task = TestScenario::new(util_name!());
+    let mut session = LessSession::start(&task, args, env);
+    for key in keys {
+        session.press(key);
+    }
+    session.finish()
+}
+
 /// Get the path to the Rust less executable and the project root directory
 fn get_rust_less_path() -> (String, String) {
     let project_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -1074,3 +1208,457 @@ fn test_less_in_ci() {
     );
     println!("\x1b[32;1m === test less pattern not found finish ===\x1b[0m");
 }
+
+/// Test less reading its input from a pipe
+///
+/// This test verifies that when no file is given and stdin is not a
+/// terminal, less pages the data read from stdin, and that `-` selects
+/// stdin explicitly.
+#[test]
+fn test_less_stdin_pipe() {
+    println!("\n\x1b[32;1m === test Rust less stdin pipe ===\x1b[0m");
+
+    let input = "Rust\nPython\nC++\n";
+    let task = TestScenario::new(util_name!());
+    let output = task
+        .ucmd()
+        .env("CARGO_TEST", "1")
+        .arg("--non-interactive")
+        .pipe_in(input)
+        .succeeds();
+    let stdout = String::from_utf8_lossy(&output.stdout());
+    println!("Rust less output: {}", stdout);
+    assert!(stdout.starts_with(input), "Unexpected stdout: {}", stdout);
+
+    let output = task
+        .ucmd()
+        .env("CARGO_TEST", "1")
+        .arg("--non-interactive")
+        .arg("-N")
+        .arg("-")
+        .pipe_in(input)
+        .succeeds();
+    let clean_output = strip_ansi_sequences(&String::from_utf8_lossy(&output.stdout()));
+    assert!(
+        clean_output.contains("     2  Python\n"),
+        "Unexpected stdout: {}",
+        clean_output
+    );
+
+    println!("\x1b[32;1m === test less stdin pipe finish ===\x1b[0m");
+}
+
+/// Test less initial commands given as `+cmd`
+///
+/// This test verifies that `+F` is taken as the follow command rather
+/// than a file name, and that arguments after `--` are still file names.
+#[test]
+fn test_less_initial_command() {
+    println!("\n\x1b[32;1m === test Rust less initial command ===\x1b[0m");
+
+    let task = TestScenario::new(util_name!());
+    let output = task
+        .ucmd()
+        .env("CARGO_TEST", "1")
+        .arg("--non-interactive")
+        .arg("+F")
+        .arg("input.txt")
+        .succeeds();
+    let stdout = String::from_utf8_lossy(&output.stdout());
+    println!("Rust less output: {}", stdout);
+    assert!(stdout.contains("Rust"), "Unexpected stdout: {}", stdout);
+    assert!(
+        !String::from_utf8_lossy(&output.stderr()).contains("cannot open"),
+        "+F was taken as a file name"
+    );
+
+    task.ucmd()
+        .env("CARGO_TEST", "1")
+        .arg("--non-interactive")
+        .arg("--")
+        .arg("+F")
+        .run()
+        .stderr_contains("cannot open '+F'");
+
+    println!("\x1b[32;1m === test less initial command finish ===\x1b[0m");
+}
+
+/// Append `text` to a fixture followed by less
+fn append_fixture(task: &TestScenario, name: &str, text: &[u8]) {
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(task.fixtures.plus(name))
+        .unwrap();
+    file.write_all(text).unwrap();
+}
+
+/// Test less follow mode
+///
+/// This test verifies that `F` and `+F` keep showing the data appended to
+/// the file, that a last line without newline is completed in place, that
+/// a truncated or rotated file is shown again from its start, that
+/// following resumes after the bytes actually read even when they are not
+/// valid UTF-8, and that Ctrl-C stops following.
+#[test]
+fn test_less_follow_mode() {
+    println!("\n\x1b[32;1m === test Rust less follow mode ===\x1b[0m");
+
+    let task = TestScenario::new(util_name!());
+    task.fixtures.write("follow.log", "line 1\nline 2\npartial");
+    let mut session = LessSession::start(&task, "follow.log", &[]);
+    session.press("F");
+    assert!(session.wait_for("Waiting for data"), "{}", session.screen());
+
+    append_fixture(&task, "follow.log", b" done\nline 3\n");
+    assert!(session.wait_for("\rpartial done\n"), "{}", session.screen());
+    assert!(session.wait_for("\rline 3\n"), "{}", session.screen());
+
+    task.fixtures.write("follow.log", "truncated 1\n");
+    assert!(session.wait_for("\rtruncated 1\n"), "{}", session.screen());
+
+    fs::rename(
+        task.fixtures.plus("follow.log"),
+        task.fixtures.plus("follow.log.1"),
+    )
+    .unwrap();
+    task.fixtures.write("follow.log", "rotated 1\n");
+    assert!(session.wait_for("\rrotated 1\n"), "{}", session.screen());
+
+    session.press("\x03");
+    assert!(session.wait_for("End of file"), "{}", session.screen());
+    session.press("q");
+    let stdout = session.finish();
+    let last_page = stdout.rsplit("\x1b[J").nth(1).unwrap_or_default();
+    assert!(
+        !last_page.contains("line 1"),
+        "Unexpected stdout: {}",
+        stdout
+    );
+
+    task.fixtures
+        .write_bytes("binary.log", b"\xffbad\nline 2\n");
+    let mut session = LessSession::start(&task, "+F binary.log", &[]);
+    assert!(session.wait_for("Waiting for data"), "{}", session.screen());
+    append_fixture(&task, "binary.log", b"line 3\n");
+    assert!(session.wait_for("\rline 3\n"), "{}", session.screen());
+    session.press("\x03");
+    session.press("q");
+    let stdout = session.finish();
+    let last_page = stdout.rsplit("\x1b[J").next().unwrap();
+    assert_eq!(
+        last_page.matches("bad").count(),
+        1,
+        "Unexpected stdout: {}",
+        stdout
+    );
+
+    println!("\x1b[32;1m === test less follow mode finish ===\x1b[0m");
+}
+
+/// Test less interactive search
+///
+/// This test verifies that `/` and `?` search forwards and backwards,
+/// `n` and `N` repeat the search from the match shown, even on the first
+/// line, every visible match is highlighted, and `&` only displays the
+/// matching lines.
+#[test]
+fn test_less_interactive_search() {
+    println!("\n\x1b[32;1m === test Rust less interactive search ===\x1b[0m");
+
+    let stdout = run_less_with_keys("search.txt", &["/foo\r", "n", "q"]);
+    assert!(stdout.contains("\rline 7 \x1b[7mfoo\x1b[27m\n"));
+    assert!(stdout.contains("\rline 14 \x1b[7mfoo\x1b[27m\n"));
+
+    let stdout = run_less_with_keys("search.txt", &["/foo\r", "n", "N", "q"]);
+    let last_page = stdout.rsplit("\x1b[J").next().unwrap();
+    assert!(last_page.contains("\rline 7 \x1b[7mfoo\x1b[27m\n"));
+
+    // A match on the first line is shown, and `n` still moves past it
+    let stdout = run_less_with_keys("search.txt", &["/bar\r", "n", "n", "q"]);
+    let last_page = stdout.rsplit("\x1b[J").next().unwrap();
+    assert!(
+        last_page.starts_with("\x1b[2K\rline 3 \x1b[7mbar\x1b[27m\n"),
+        "Unexpected stdout: {}",
+        stdout
+    );
+
+    let stdout = run_less_with_keys("search.txt", &["?nothing\r", "q"]);
+    assert!(stdout.contains("Pattern not found"));
+
+    // A match broken over two rows is highlighted on both
+    let stdout = run_less_with_keys("search.txt", &["/x+LONG\r", "q"]);
+    assert!(stdout.contains("xxLO\x1b[27m\n\r\x1b[7mNG\x1b[27mMATCH"));
+
+    let stdout = run_less_with_keys("search.txt", &["&foo\r", "q"]);
+    let last_page = stdout.rsplit("\x1b[J").next().unwrap();
+    assert!(last_page.contains("\rline 7 foo\n\rline 14 foo\n"));
+    assert!(!last_page.contains("bar"));
+
+    println!("\x1b[32;1m === test less interactive search finish ===\x1b[0m");
+}
+
+/// Test less raw control chars and display width
+///
+/// This test verifies that with -R colour sequences are passed through and
+/// take no room on screen, that without it they are shown in caret notation,
+/// and that lines are broken by display width, wide and combining characters
+/// included.
+#[test]
+fn test_less_raw_control_chars() {
+    println!("\n\x1b[32;1m ======= test Rust less raw control chars ===\x1b[0m");
+
+    let stdout = run_less_with_keys("-R color.txt", &["q"]);
+    let red = format!("\x1b[31m{}\x1b[0mta\x1b[0m\n\ril\n", "r".repeat(78));
+    assert!(stdout.contains(&red), "Unexpected stdout: {}", stdout);
+    let wide = format!("\r{}\n\r中\n", "中".repeat(40));
+    assert!(stdout.contains(&wide), "Unexpected stdout: {}", stdout);
+    let combining = format!("\r{}\n", "e\u{301}".repeat(80));
+    assert!(stdout.contains(&combining), "Unexpected stdout: {}", stdout);
+
+    let stdout = run_less_with_keys("color.txt", &["q"]);
+    assert!(
+        stdout.contains("\r^[[31mrrr"),
+        "Unexpected stdout: {}",
+        stdout
+    );
+
+    println!("\x1b[32;1m ======= test less raw control chars finish ===\x1b[0m");
+}
+
+/// Test less chopping of long lines
+///
+/// This test verifies that `-S` shows one row per line cut on the right
+/// edge, keeping colors and wide characters whole, that the arrow keys
+/// scroll horizontally and show the column in the prompt, and that `-S`
+/// typed in the pager folds the lines again.
+#[test]
+fn test_less_chop_long_lines() {
+    println!("\n\x1b[32;1m ======= test Rust less chop long lines ===\x1b[0m");
+
+    let stdout = run_less_with_keys("-R -S color.txt", &["\x1b[C", "\x1b[D", "-S", "q"]);
+    let chopped = format!(
+        "\r\x1b[31m{}\x1b[0mta\x1b[0m\n\r{}\n",
+        "r".repeat(78),
+        "中".repeat(40)
+    );
+    assert!(stdout.contains(&chopped), "Unexpected stdout: {}", stdout);
+    let shifted = format!(
+        "\r\x1b[31m{}\x1b[0mtail\x1b[0m\n\r{}\n",
+        "r".repeat(38),
+        "中".repeat(21)
+    );
+    assert!(stdout.contains(&shifted), "Unexpected stdout: {}", stdout);
+    assert!(
+        stdout.contains("(column 41)"),
+        "Unexpected stdout: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Fold long lines"),
+        "Unexpected stdout: {}",
+        stdout
+    );
+    let folded = format!("\r{}\n\r中\n", "中".repeat(40));
+    assert!(stdout.contains(&folded), "Unexpected stdout: {}", stdout);
+
+    println!("\x1b[32;1m ======= test less chop long lines finish ===\x1b[0m");
+}
+
+/// Test less navigation between files
+///
+/// This test verifies that `:n` and `:p` move to the next and previous
+/// file, coming back to a file where it was left, that `:e` adds a file
+/// to the list, that `:d` removes the current one, and that moving past
+/// the last file reports it.
+#[test]
+fn test_less_file_navigation() {
+    println!("\n\x1b[32;1m ======= test Rust less file navigation ===\x1b[0m");
+
+    let stdout = run_less_with_keys(
+        "search.txt color.txt",
+        &[" ", ":n", ":p", ":etest_file.txt\r", ":d", ":n", "q"],
+    );
+    let expected = [
+        "line 1 bar",
+        "(file 1 of 2)",
+        "(file 2 of 2)",
+        // Coming back to a file shows it where it was left
+        "line 11 bar\n\rline 12 bar\n\rline 13 bar\n\rline 14 foo\n\rline 15 bar\n\r: (file 1 of 2)",
+        "test_file line 1",
+        "(file 2 of 3)",
+        "No next file",
+    ];
+    let mut rest = stdout.as_str();
+    for text in expected {
+        let pos = rest
+            .find(text)
+            .unwrap_or_else(|| panic!("{:?} missing in stdout: {}", text, stdout));
+        rest = &rest[pos + text.len()..];
+    }
+
+    println!("\x1b[32;1m ======= test less file navigation finish ===\x1b[0m");
+}
+
+/// Test less marks and jumps
+///
+/// This test verifies that `g` and `G` go to a line or to the end, `p`
+/// to a percentage, `m` marks the top line and `'` comes back to a mark,
+/// `''` returns to the position before the last jump, and that an unset
+/// mark is reported.
+#[test]
+fn test_less_marks_and_jumps() {
+    println!("\n\x1b[32;1m ======= test Rust less marks and jumps ===\x1b[0m");
+
+    let stdout = run_less_with_keys(
+        "search.txt",
+        &["20g", "ma", "G", "''", "g", "'a", "50p", "'z", "q"],
+    );
+    // Top line of the page drawn after each command
+    let expected = [
+        "\x1b[J\x1b[2K\rline 20 bar",
+        "\x1b[J\x1b[2K\rline 20 bar",
+        "\x1b[J\x1b[2K\rline 37 bar",
+        "\x1b[J\x1b[2K\rline 20 bar",
+        "\x1b[J\x1b[2K\rline 1 bar",
+        "\x1b[J\x1b[2K\rline 20 bar",
+        "\x1b[J\x1b[2K\rline 21 foo",
+        "Mark not set",
+    ];
+    let mut rest = stdout.as_str();
+    for text in expected {
+        let pos = rest
+            .find(text)
+            .unwrap_or_else(|| panic!("{:?} missing in stdout: {}", text, stdout));
+        rest = &rest[pos + text.len()..];
+    }
+
+    println!("\x1b[32;1m ======= test less marks and jumps finish ===\x1b[0m");
+}
+
+/// Test less input preprocessor
+///
+/// This test verifies that gzip, xz and zstd files are decompressed when
+/// `LESSOPEN` is empty, that the pipe form of `LESSOPEN` pages the output
+/// of the command, and that the file form pages the file it names and
+/// runs `LESSCLOSE` afterwards.
+#[test]
+fn test_less_preprocessor() {
+    println!("\n\x1b[32;1m ======= test Rust less preprocessor ===\x1b[0m");
+
+    let task = TestScenario::new(util_name!());
+    let expected = "compressed line 1\ncompressed line 2\n";
+    for file in ["compressed.gz", "compressed.xz", "compressed.zst"] {
+        let output = task
+            .ucmd()
+            .env("CARGO_TEST", "1")
+            .env("LESSOPEN", "")
+            .args(&["--non-interactive", file])
+            .succeeds();
+        let stdout = String::from_utf8_lossy(&output.stdout());
+        assert!(
+            stdout.starts_with(expected),
+            "Unexpected stdout: {}",
+            stdout
+        );
+    }
+
+    let output = task
+        .ucmd()
+        .env("CARGO_TEST", "1")
+        .env("LESSOPEN", "|tr a-z A-Z < %s")
+        .args(&["--non-interactive", "input.txt"])
+        .succeeds();
+    let stdout = String::from_utf8_lossy(&output.stdout());
+    let input = task.fixtures.read("input.txt");
+    assert!(
+        stdout.starts_with(&input.to_uppercase()),
+        "Unexpected stdout: {}",
+        stdout
+    );
+
+    // The file form pages the file named by the preprocessor
+    let output = task
+        .ucmd()
+        .env("CARGO_TEST", "1")
+        .env("LESSOPEN", "echo input.txt # %s")
+        .env("LESSCLOSE", "echo %s %s > closed.txt")
+        .args(&["--non-interactive", "compressed.gz"])
+        .succeeds();
+    let stdout = String::from_utf8_lossy(&output.stdout());
+    assert!(stdout.starts_with(&input), "Unexpected stdout: {}", stdout);
+    assert_eq!(
+        task.fixtures.read("closed.txt"),
+        "compressed.gz input.txt\n"
+    );
+
+    println!("\x1b[32;1m ======= test less preprocessor finish ===\x1b[0m");
+}
+
+/// Test less key bindings from a lesskey file
+///
+/// This test verifies that the commands of the file named by `LESSKEY`
+/// are bound, including sequences of several keys, that its `#env`
+/// section only provides `LESS` when the environment does not set it, and
+/// that options unknown to this less are skipped, even when clustered.
+#[test]
+fn test_less_key_bindings() {
+    println!("\n\x1b[32;1m ======= test Rust less key bindings ===\x1b[0m");
+
+    let task = TestScenario::new(util_name!());
+    task.fixtures.write(
+        "lesskey",
+        "# vi style\n#command\nJ goto-end\nK goto-line\n,, quit\n#env\nLESS = -N\n",
+    );
+    let lesskey = task.fixtures.plus_as_string("lesskey");
+    let stdout = run_less_with_env(
+        "search.txt",
+        &[("LESSKEY", &lesskey), ("LESS", "")],
+        &["J", "K", ",", ","],
+    );
+    // A LESS variable set in the environment, even empty, wins over the lesskey file
+    assert!(!stdout.contains("\x1b[1m"), "Unexpected stdout: {}", stdout);
+    let expected = [
+        "line 37 bar",
+        "End of file",
+        "\x1b[J\x1b[2K\rline 1 bar",
+        "\r,",
+    ];
+    let mut rest = stdout.as_str();
+    for text in expected {
+        let pos = rest
+            .find(text)
+            .unwrap_or_else(|| panic!("{:?} missing in stdout: {}", text, stdout));
+        rest = &rest[pos + text.len()..];
+    }
+
+    // The lesskey file provides LESS when it is not set
+    let stdout = run_less_with_env("search.txt", &[("LESSKEY", &lesskey)], &["J", "q"]);
+    assert!(
+        stdout.contains("\x1b[1m   37\x1b[0m line 37 bar"),
+        "Unexpected stdout: {}",
+        stdout
+    );
+
+    // Options unknown to this less are skipped
+    let output = task
+        .ucmd()
+        .env("CARGO_TEST", "1")
+        .env("LESS", "-X -N --mouse")
+        .args(&["--non-interactive", "input.txt"])
+        .succeeds();
+    let stdout = String::from_utf8_lossy(&output.stdout());
+    assert!(
+        stdout.starts_with("     1  Rust\n"),
+        "Unexpected stdout: {}",
+        stdout
+    );
+
+    // Clustered letters, with or without a dash, keep the known ones
+    let red = format!("\x1b[31m{}\x1b[0mta\x1b[0m\n", "r".repeat(78));
+    for less in ["-FRX", "FRX"] {
+        let stdout = run_less_with_env("color.txt", &[("LESS", less)], &["q"]);
+        assert!(stdout.contains(&red), "Unexpected stdout: {}", stdout);
+    }
+
+    println!("\x1b[32;1m ======= test less key bindings finish ===\x1b[0m");
+}
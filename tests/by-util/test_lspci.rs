@@ -7,7 +7,12 @@
 //
 
 use crate::common::util::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::UdpSocket;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
 const C_LSPCI_PATH: &str = "/usr/bin/lspci";
 
 #[test]
@@ -162,6 +167,30 @@ fn test_lspci_v() {
     }
 }
 
+#[test]
+fn test_lspci_caps() {
+    let lspci_path = Path::new(C_LSPCI_PATH);
+    for test_args in [&["-vv"], &["-vvv"]] {
+        let task = TestScenario::new(util_name!());
+        let resu = task.ucmd().args(test_args).succeeds();
+        if lspci_path.exists() {
+            let refe = task.cmd(C_LSPCI_PATH).args(test_args).run();
+            let refcaps = refe
+                .stdout_str()
+                .lines()
+                .filter(|l| l.starts_with("\tCapabilities: ["))
+                .count();
+            let rescaps = resu
+                .stdout_str()
+                .lines()
+                .filter(|l| l.starts_with("\tCapabilities: ["))
+                .count();
+            assert_eq!(refcaps, rescaps);
+        }
+        assert!(!resu.stdout_str().contains("<chain looped>"));
+    }
+}
+
 #[test]
 fn test_lspci_kernel() {
     let lspci_path = Path::new(C_LSPCI_PATH);
@@ -373,3 +402,729 @@ fn test_lspci_ilegal_args() {
     let task = TestScenario::new(util_name!());
     task.ucmd().args(test_args).fails().code_is(1);
 }
+
+#[test]
+fn test_lspci_dump_file() {
+    let task = TestScenario::new(util_name!());
+    task.ucmd()
+        .args(&["-F", "pcie.dump", "-n"])
+        .succeeds()
+        .stdout_only(
+            "00:00.0 0600: 8086:3e30 (rev 0d)\n\
+             00:1c.0 0604: 8086:a33c (rev f0)\n\
+             01:00.0 0108: 144d:a808\n",
+        );
+    task.ucmd()
+        .args(&["-A", "dump", "-O", "dump.name=pcie.dump", "-n", "-s", "01:"])
+        .succeeds()
+        .stdout_only("01:00.0 0108: 144d:a808\n");
+}
+
+#[test]
+fn test_lspci_dump_tree() {
+    let task = TestScenario::new(util_name!());
+    task.ucmd()
+        .args(&["-F", "pcie.dump", "-t"])
+        .succeeds()
+        .stdout_only("-[0000:00]-+-00.0\n           \\-1c.0-[01]----00.0\n");
+    task.ucmd()
+        .args(&["-F", "pcie.dump", "-PP", "-n", "-s", "01:00.0"])
+        .succeeds()
+        .stdout_only("00:1c.0/01:00.0 0108: 144d:a808\n");
+}
+
+#[test]
+fn test_lspci_dump_caps() {
+    let task = TestScenario::new(util_name!());
+    let res = task
+        .ucmd()
+        .args(&["-F", "pcie.dump", "-vvv", "-s", "01:00.0"])
+        .succeeds();
+    for line in [
+        "\tCapabilities: [40] Power Management version 3\n",
+        "\t\tStatus: D0 NoSoftRst+ PME-Enable- DSel=0 DScale=0 PME-\n",
+        "\tCapabilities: [70] Express (v2) Endpoint, MSI 00\n",
+        "\t\tLnkCap:\tPort #0, Speed 8GT/s, Width x4, ASPM L0s L1, Exit Latency L0s <64ns, L1 <1us\n",
+        "\t\tLnkCtl:\tASPM L1 Enabled; RCB 64 bytes, LnkDisable- CommClk+\n",
+        "\tCapabilities: [b0] MSI-X: Enable+ Count=33 Masked-\n",
+        "\tCapabilities: [100 v2] Advanced Error Reporting\n",
+        "\tCapabilities: [148 v1] Device Serial Number 00-25-38-a0-21-43-65-87\n",
+        "\t\tMax snoop latency: 3145728ns\n",
+        "\tCapabilities: [160 v1] L1 PM Substates\n",
+    ] {
+        assert!(res.stdout_str().contains(line), "missing {:?}", line);
+    }
+
+    let res = task
+        .ucmd()
+        .args(&["-F", "pcie.dump", "-v", "-s", "00:1c.0"])
+        .succeeds();
+    assert!(res
+        .stdout_str()
+        .contains("\tCapabilities: [140] Access Control Services\n"));
+    assert!(!res.stdout_str().contains("ACSCap:"));
+}
+
+#[test]
+fn test_lspci_dump_caps_fields() {
+    let task = TestScenario::new(util_name!());
+    let res = task
+        .ucmd()
+        .args(&["-F", "pcie.dump", "-vv", "-s", "00:1c.0"])
+        .succeeds();
+    for block in [
+        "\tCapabilities: [40] Express (v2) Root Port (Slot+), MSI 00\n\
+         \t\tDevCap:\tMaxPayload 256 bytes, PhantFunc 0\n\
+         \t\t\tExtTag- RBE+\n\
+         \t\tDevCtl:\tCorrErr+ NonFatalErr+ FatalErr+ UnsupReq-\n\
+         \t\t\tRlxdOrd- ExtTag- PhantFunc- AuxPwr- NoSnoop-\n\
+         \t\t\tMaxPayload 256 bytes, MaxReadReq 128 bytes\n\
+         \t\tDevSta:\tCorrErr- NonFatalErr- FatalErr- UnsupReq- AuxPwr+ TransPend-\n\
+         \t\tLnkCap:\tPort #9, Speed 8GT/s, Width x4, ASPM L0s L1, Exit Latency L0s <64ns, L1 <4us\n\
+         \t\t\tClockPM- Surprise- LLActRep+ BwNot+ ASPMOptComp+\n\
+         \t\tLnkCtl:\tASPM Disabled; RCB 64 bytes, LnkDisable- CommClk+\n\
+         \t\t\tExtSynch- ClockPM- AutWidDis- BWInt- AutBWInt-\n\
+         \t\tLnkSta:\tSpeed 8GT/s, Width x4\n\
+         \t\t\tTrErr- Train- SlotClk+ DLActive+ BWMgmt+ ABWMgmt-\n\
+         \t\tSltCap:\tAttnBtn- PwrCtrl- MRL- AttnInd- PwrInd- HotPlug+ Surprise+\n\
+         \t\t\tSlot #9, PowerLimit 0.000W; Interlock- NoCompl-\n\
+         \t\tSltCtl:\tEnable: AttnBtn- PwrFlt- MRL- PresDet+ CmdCplt- HPIrq+ LinkChg+\n\
+         \t\t\tControl: AttnInd Unknown, PwrInd Unknown, Power- Interlock-\n\
+         \t\tSltSta:\tStatus: AttnBtn- PowerFlt- MRL- CmdCplt- PresDet+ Interlock-\n\
+         \t\t\tChanged: MRL- PresDet- LinkState-\n\
+         \t\tRootCtl: ErrCorrectable- ErrNon-Fatal- ErrFatal- PMEIntEna- CRSVisible-\n\
+         \t\tRootCap: CRSVisible-\n\
+         \t\tRootSta: PME ReqID 0000, PMEStatus- PMEPending-\n",
+        "\t\tLnkCap2: Supported Link Speeds: 2.5-8GT/s, Crosslink- Retimer- 2Retimers- DRS-\n\
+         \t\tLnkCtl2: Target Link Speed: 8GT/s, EnterCompliance- SpeedDis-\n",
+        "\tCapabilities: [80] MSI: Enable+ Count=1/1 Maskable- 64bit+\n\
+         \t\tAddress: 00000000fee00000  Data: 4021\n",
+        "\tCapabilities: [100 v1] Advanced Error Reporting\n\
+         \t\tUESta:\tDLP- SDES- TLP- FCP- CmpltTO- CmpltAbrt- UnxCmplt- RxOF- MalfTLP- ECRC- UnsupReq- ACSViol-\n\
+         \t\tUEMsk:\tDLP+ SDES+ TLP- FCP+ CmpltTO- CmpltAbrt- UnxCmplt- RxOF+ MalfTLP+ ECRC- UnsupReq- ACSViol-\n\
+         \t\tUESvrt:\tDLP- SDES- TLP- FCP- CmpltTO- CmpltAbrt- UnxCmplt- RxOF- MalfTLP- ECRC- UnsupReq- ACSViol-\n\
+         \t\tCESta:\tRxErr- BadTLP- BadDLLP- Rollover- Timeout- AdvNonFatalErr-\n\
+         \t\tCEMsk:\tRxErr- BadTLP- BadDLLP- Rollover- Timeout- AdvNonFatalErr+\n\
+         \t\tAERCap:\tFirst Error Pointer: 00, ECRCGenCap+ ECRCGenEn- ECRCChkCap+ ECRCChkEn-\n\
+         \t\t\tMultHdrRecCap- MultHdrRecEn- TLPPfxPres- HdrLogCap-\n\
+         \t\tHeaderLog: 00000000 00000000 00000000 00000000\n\
+         \t\tRootCmd: CERptEn+ NFERptEn+ FERptEn+\n\
+         \t\tRootSta: CERcvd- MultCERcvd- UERcvd- MultUERcvd-\n\
+         \t\t\t FirstFatal- NonFatalMsg- FatalMsg- IntMsgNum 0\n\
+         \t\tErrorSrc: ERR_COR: 0000 ERR_FATAL/NONFATAL: 0000\n",
+        "\tCapabilities: [140 v1] Access Control Services\n\
+         \t\tACSCap:\tSrcValid+ TransBlk+ ReqRedir+ CmpltRedir+ UpstreamFwd+ EgressCtrl- DirectTrans-\n\
+         \t\tACSCtl:\tSrcValid- TransBlk- ReqRedir- CmpltRedir- UpstreamFwd- EgressCtrl- DirectTrans-\n",
+    ] {
+        assert!(res.stdout_str().contains(block), "missing {:?}", block);
+    }
+
+    let res = task
+        .ucmd()
+        .args(&["-F", "pcie.dump", "-vv", "-s", "01:00.0"])
+        .succeeds();
+    for block in [
+        "\tCapabilities: [40] Power Management version 3\n\
+         \t\tFlags: PMEClk- DSI- D1- D2- AuxCurrent=0mA PME(D0-,D1-,D2-,D3hot-,D3cold-)\n\
+         \t\tStatus: D0 NoSoftRst+ PME-Enable- DSel=0 DScale=0 PME-\n",
+        "\tCapabilities: [70] Express (v2) Endpoint, MSI 00\n\
+         \t\tDevCap:\tMaxPayload 256 bytes, PhantFunc 0, Latency L0s unlimited, L1 unlimited\n\
+         \t\t\tExtTag+ AttnBtn- AttnInd- PwrInd- RBE+ FLReset+ SlotPowerLimit 0.000W\n\
+         \t\tDevCtl:\tCorrErr- NonFatalErr- FatalErr- UnsupReq-\n\
+         \t\t\tRlxdOrd+ ExtTag- PhantFunc- AuxPwr- NoSnoop- FLReset-\n\
+         \t\t\tMaxPayload 128 bytes, MaxReadReq 512 bytes\n",
+        "\tCapabilities: [b0] MSI-X: Enable+ Count=33 Masked-\n\
+         \t\tVector table: BAR=0 offset=00003000\n\
+         \t\tPBA: BAR=0 offset=00002000\n",
+        "\tCapabilities: [158 v1] Latency Tolerance Reporting\n\
+         \t\tMax snoop latency: 3145728ns\n\
+         \t\tMax no snoop latency: 3145728ns\n",
+        "\tCapabilities: [160 v1] L1 PM Substates\n\
+         \t\tL1SubCap: PCI-PM_L1.2+ PCI-PM_L1.1+ ASPM_L1.2+ ASPM_L1.1+ L1_PM_Substates+\n\
+         \t\t\t  PortCommonModeRestoreTime=40us PortTPowerOnTime=10us\n\
+         \t\tL1SubCtl1: PCI-PM_L1.2+ PCI-PM_L1.1+ ASPM_L1.2+ ASPM_L1.1+\n\
+         \t\t\t   T_CommonMode=40us LTR1.2_Threshold=163840ns\n\
+         \t\tL1SubCtl2: T_PwrOn=10us\n",
+    ] {
+        assert!(res.stdout_str().contains(block), "missing {:?}", block);
+    }
+}
+
+#[test]
+fn test_lspci_dump_ext_caps() {
+    let task = TestScenario::new(util_name!());
+    let res = task
+        .ucmd()
+        .args(&["-F", "caps.dump", "-vv", "-s", "00:03.0"])
+        .succeeds();
+    assert!(res.stdout_str().contains(
+        "\tCapabilities: [100 v1] Downstream Port Containment\n\
+         \t\tDpcCap:\tINT Msg #0, RPExt+ PoisonedTLP+ SwTrigger+ RP PIO Log 4, DL_ActiveErr+\n\
+         \t\tDpcCtl:\tTrigger:1 Cmpl- INT+ ErrCor- PoisonedTLP- SwTrigger- DL_ActiveErr-\n\
+         \t\tDpcSta:\tTrigger- Reason:00 INT- RPBusy- TriggerExt:00 RP PIO ErrPtr:1f\n\
+         \t\tSource:\t0000\n"
+    ));
+
+    let res = task
+        .ucmd()
+        .args(&["-F", "caps.dump", "-vv", "-s", "00:04.0"])
+        .succeeds();
+    assert!(res.stdout_str().contains(
+        "\tCapabilities: [40] Vendor Specific Information: VirtIO: CommonCfg\n\
+         \t\tBAR=4 offset=00000000 size=00001000\n\
+         \tCapabilities: [50] Vendor Specific Information: VirtIO: Notify\n\
+         \t\tBAR=4 offset=00003000 size=00001000 multiplier=00000004\n\
+         \tCapabilities: [64] MSI: Enable+ Count=1/1 Maskable+ 64bit+\n\
+         \t\tAddress: 00000000fee00000  Data: 0021\n\
+         \t\tMasking: 00000000  Pending: 00000000\n"
+    ));
+
+    let res = task
+        .ucmd()
+        .args(&["-F", "caps.dump", "-vv", "-s", "03:00.0"])
+        .succeeds();
+    for block in [
+        "\tCapabilities: [40] Vendor Specific Information: Len=0c <?>\n",
+        "\tCapabilities: [100 v1] Alternative Routing-ID Interpretation (ARI)\n\
+         \t\tARICap:\tMFVC- ACS+, Next Function: 1\n\
+         \t\tARICtl:\tMFVC- ACS-, Function Group: 0\n",
+        "\tCapabilities: [150 v1] Single Root I/O Virtualization (SR-IOV)\n\
+         \t\tIOVCap:\tMigration- 10BitTagReq- Interrupt Message Number: 000\n\
+         \t\tIOVCtl:\tEnable+ Migration- Interrupt- MSE+ ARIHierarchy+ 10BitTagReq-\n\
+         \t\tIOVSta:\tMigration-\n\
+         \t\tInitial VFs: 64, Total VFs: 64, Number of VFs: 8, Function Dependency Link: 00\n\
+         \t\tVF offset: 384, stride: 2, Device ID: 10ed\n\
+         \t\tSupported Page Size: 00000553, System Page Size: 00000001\n\
+         \t\tRegion 0: Memory at 00000000fb400000 (64-bit, prefetchable)\n\
+         \t\tRegion 3: Memory at 00000000fb300000 (64-bit, prefetchable)\n\
+         \t\tVF Migration: offset: 00000000, BIR: 0\n",
+        "\tCapabilities: [190 v1] Designated Vendor-Specific: Vendor=8086 ID=0002 Rev=1 Len=56 <?>\n",
+    ] {
+        assert!(res.stdout_str().contains(block), "missing {:?}", block);
+    }
+
+    // Only the capability names are decoded below -vv
+    let res = task
+        .ucmd()
+        .args(&["-F", "caps.dump", "-v", "-s", "03:00.0"])
+        .succeeds();
+    assert!(res.stdout_str().contains(
+        "\tCapabilities: [150] Single Root I/O Virtualization (SR-IOV)\n\
+         \tCapabilities: [190] Designated Vendor-Specific: Vendor=8086 ID=0002 Rev=1 Len=56 <?>\n"
+    ));
+    assert!(!res.stdout_str().contains("IOVCtl:"));
+}
+
+#[test]
+fn test_lspci_dump_short_header() {
+    let task = TestScenario::new(util_name!());
+    task.fixtures.write(
+        "short.dump",
+        "00:02.0 VGA compatible controller\n00: 86 80\n",
+    );
+    task.ucmd()
+        .args(&["-F", "short.dump"])
+        .succeeds()
+        .stdout_is("")
+        .stderr_contains("Unable to read the standard configuration space header");
+}
+
+#[test]
+fn test_lspci_cardbus_short_config() {
+    // A CardBus bridge keeps its subsystem IDs past the 64-byte header
+    let task = TestScenario::new(util_name!());
+    task.ucmd()
+        .args(&["-F", "cardbus.dump", "-v"])
+        .succeeds()
+        .stdout_is(
+            "03:00.0 Class 0607: Device 104c:ac56\n\
+             \tFlags: bus master, medium devsel, latency 168, IRQ 11\n\
+             \tMemory at b0000000 (32-bit, non-prefetchable)\n\n",
+        );
+
+    let at = &task.fixtures;
+    let dir = "sys/devices/pci0000:03/0000:03:00.0";
+    let config: Vec<u8> = at
+        .read("cardbus.dump")
+        .lines()
+        .skip(1)
+        .flat_map(|line| {
+            line[4..]
+                .split(' ')
+                .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+                .collect::<Vec<u8>>()
+        })
+        .collect();
+    assert_eq!(config.len(), 64);
+    at.mkdir_all(dir);
+    at.write_bytes(&format!("{}/config", dir), &config);
+    at.write(&format!("{}/class", dir), "0x060700\n");
+    at.mkdir_all("sys/bus/pci/devices");
+    at.relative_symlink_dir(
+        "../../../devices/pci0000:03/0000:03:00.0",
+        "sys/bus/pci/devices/0000:03:00.0",
+    );
+    let sysfs_path = format!("sysfs.path={}", at.plus_as_string("sys/bus/pci"));
+    task.ucmd()
+        .args(&["-A", "linux-sysfs", "-O", &sysfs_path, "-mm"])
+        .succeeds()
+        .stdout_is("03:00.0 \"Class 0607\" \"Vendor 104c\" \"Device ac56\" -p00 \"\" \"\"\n");
+}
+
+#[test]
+fn test_lspci_sysfs_path() {
+    let task = TestScenario::new(util_name!());
+    let at = &task.fixtures;
+    let bridge = "sys/devices/pci0000:00/0000:00:1c.0";
+    let nvme = "sys/devices/pci0000:00/0000:00:1c.0/0000:01:00.0";
+    let mut bridge_config = [0u8; 64];
+    bridge_config[..16].copy_from_slice(&[
+        0x86, 0x80, 0x3c, 0xa3, 0x07, 0x04, 0x00, 0x00, 0xf0, 0x00, 0x04, 0x06, 0x00, 0x00, 0x01,
+        0x00,
+    ]);
+    bridge_config[0x18..0x1b].copy_from_slice(&[0x00, 0x01, 0x01]);
+    let mut nvme_config = [0u8; 64];
+    nvme_config[..16].copy_from_slice(&[
+        0x4d, 0x14, 0x08, 0xa8, 0x06, 0x04, 0x00, 0x00, 0x00, 0x02, 0x08, 0x01, 0x00, 0x00, 0x00,
+        0x00,
+    ]);
+    for (dir, config, vendor, device, class) in [
+        (bridge, bridge_config, "0x8086", "0xa33c", "0x060400"),
+        (nvme, nvme_config, "0x144d", "0xa808", "0x010802"),
+    ] {
+        at.mkdir_all(dir);
+        at.write_bytes(&format!("{}/config", dir), &config);
+        at.write(&format!("{}/vendor", dir), &format!("{}\n", vendor));
+        at.write(&format!("{}/device", dir), &format!("{}\n", device));
+        at.write(&format!("{}/class", dir), &format!("{}\n", class));
+    }
+    at.mkdir_all(&format!("{}/pci_bus/0000:01", bridge));
+    at.mkdir_all("sys/bus/pci/devices");
+    at.mkdir_all("sys/bus/pci/drivers/nvme");
+    at.mkdir_all("sys/module/nvme");
+    at.relative_symlink_dir(
+        "../../../devices/pci0000:00/0000:00:1c.0",
+        "sys/bus/pci/devices/0000:00:1c.0",
+    );
+    at.relative_symlink_dir(
+        "../../../devices/pci0000:00/0000:00:1c.0/0000:01:00.0",
+        "sys/bus/pci/devices/0000:01:00.0",
+    );
+    at.relative_symlink_dir(
+        "../../../../bus/pci/drivers/nvme",
+        &format!("{}/driver", nvme),
+    );
+    at.relative_symlink_dir("../../../module/nvme", "sys/bus/pci/drivers/nvme/module");
+
+    let sysfs_path = format!("sysfs.path={}", at.plus_as_string("sys/bus/pci"));
+    task.ucmd()
+        .args(&[
+            "-A",
+            "linux-sysfs",
+            "-O",
+            &sysfs_path,
+            "-n",
+            "-k",
+            "-p",
+            "no-such.alias",
+        ])
+        .succeeds()
+        .stdout_only(
+            "00:1c.0 0604: 8086:a33c (rev f0)\n\
+             01:00.0 0108: 144d:a808\n\
+             \tKernel driver in use: nvme\n\
+             \tKernel modules: nvme\n",
+        );
+    task.ucmd()
+        .args(&["-A", "linux-sysfs", "-O", &sysfs_path, "-t"])
+        .succeeds()
+        .stdout_only("-[0000:00]---1c.0-[01]----00.0\n");
+
+    // Unreadable devices are reported and skipped
+    let short = "sys/devices/pci0000:00/0000:02:00.0";
+    at.mkdir_all(short);
+    at.write_bytes(&format!("{}/config", short), &nvme_config[..32]);
+    at.write(&format!("{}/class", short), "0x010802\n");
+    let classless = "sys/devices/pci0000:00/0000:03:00.0";
+    at.mkdir_all(classless);
+    at.write_bytes(&format!("{}/config", classless), &nvme_config);
+    for dir in [short, classless] {
+        let name = dir.rsplit('/').next().unwrap();
+        at.relative_symlink_dir(
+            &format!("../../../devices/pci0000:00/{}", name),
+            &format!("sys/bus/pci/devices/{}", name),
+        );
+    }
+    task.ucmd()
+        .args(&["-A", "linux-sysfs", "-O", &sysfs_path, "-n"])
+        .succeeds()
+        .stdout_is(
+            "00:1c.0 0604: 8086:a33c (rev f0)\n\
+             01:00.0 0108: 144d:a808\n",
+        )
+        .stderr_contains(
+            "lspci: Unable to read the standard configuration space header of device 0000:02:00.0",
+        )
+        .stderr_contains("lspci: Cannot read ")
+        .stderr_contains("0000:03:00.0/class");
+}
+
+#[test]
+fn test_lspci_kernel_modules() {
+    let task = TestScenario::new(util_name!());
+    task.ucmd()
+        .args(&["-F", "pcie.dump", "-n", "-k", "-p", "modules.alias"])
+        .succeeds()
+        .stdout_only(
+            "00:00.0 0600: 8086:3e30 (rev 0d)\n\
+             \tSubsystem: 1028:0869\n\
+             \tKernel modules: ie31200_edac, igen6_edac\n\
+             00:1c.0 0604: 8086:a33c (rev f0)\n\
+             \tKernel modules: shpchp\n\
+             01:00.0 0108: 144d:a808\n\
+             \tSubsystem: 144d:a801\n\
+             \tKernel modules: nvme\n",
+        );
+}
+
+#[test]
+fn test_lspci_access_methods() {
+    let task = TestScenario::new(util_name!());
+    task.ucmd()
+        .args(&["-A", "help"])
+        .succeeds()
+        .stdout_only("Known PCI access methods:\n\nlinux-sysfs\nlinux-proc\ndump\n");
+    task.ucmd()
+        .args(&["-A", "no-such-method"])
+        .fails()
+        .code_is(1)
+        .stderr_contains("Unknown access method `no-such-method'");
+    task.ucmd()
+        .args(&["-O", "no.such=1"])
+        .fails()
+        .code_is(1)
+        .stderr_contains("-O: Unknown parameter `no.such'");
+    task.ucmd()
+        .args(&["-F", "no-such.dump"])
+        .fails()
+        .code_is(1)
+        .stderr_contains("Cannot open no-such.dump");
+}
+
+#[test]
+fn test_lspci_json() {
+    let task = TestScenario::new(util_name!());
+    let res = task
+        .ucmd()
+        .args(&["-F", "pcie.dump", "-J", "-p", "modules.alias"])
+        .succeeds();
+    let devices: Value = serde_json::from_str(res.stdout_str()).unwrap();
+    let devices = devices.as_array().unwrap();
+    assert_eq!(devices.len(), 3);
+
+    let host = &devices[0];
+    assert_eq!(host["slot"], "0000:00:00.0");
+    assert_eq!(host["vendor"]["id"], "8086");
+    assert_eq!(host["device"]["id"], "3e30");
+    assert_eq!(host["subsystem_vendor"]["id"], "1028");
+    assert_eq!(host["revision"], 0x0d);
+    assert_eq!(host["modules"][0], "ie31200_edac");
+    assert_eq!(host["modules"][1], "igen6_edac");
+
+    let nvme = &devices[2];
+    assert_eq!(nvme["class"]["id"], "0108");
+    assert_eq!(nvme["prog_if"]["id"], "02");
+    assert_eq!(nvme["bars"][0]["type"], "memory");
+    assert_eq!(nvme["bars"][0]["address"], "f7000000");
+    assert_eq!(nvme["bars"][0]["bits"], 64);
+    assert_eq!(nvme["modules"][0], "nvme");
+    let caps: Vec<(u64, bool)> = nvme["capabilities"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|cap| (cap["offset"].as_u64().unwrap(), cap["extended"] == true))
+        .collect();
+    assert_eq!(
+        caps,
+        [
+            (0x40, false),
+            (0x50, false),
+            (0x70, false),
+            (0xb0, false),
+            (0x100, true),
+            (0x148, true),
+            (0x158, true),
+            (0x160, true)
+        ]
+    );
+    assert_eq!(nvme["capabilities"][4]["version"], 2);
+    assert_eq!(
+        nvme["capabilities"][0]["name"],
+        "Power Management version 3"
+    );
+
+    let res = task
+        .ucmd()
+        .args(&["-F", "pcie.dump", "-J", "-vv", "-s", "01:00.0"])
+        .succeeds();
+    let devices: Value = serde_json::from_str(res.stdout_str()).unwrap();
+    assert_eq!(devices.as_array().unwrap().len(), 1);
+    assert!(devices[0]["capabilities"][0]["details"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|line| line == "Status: D0 NoSoftRst+ PME-Enable- DSel=0 DScale=0 PME-"));
+}
+
+#[test]
+fn test_lspci_ids_file() {
+    let task = TestScenario::new(util_name!());
+    let expected = "00:00.0 Host bridge: Intel Corporation 8th Gen Core 8-core Desktop Processor Host Bridge/DRAM Registers [Coffee Lake S] (rev 0d)\n\
+                    00:1c.0 PCI bridge: Intel Corporation Cannon Lake PCH PCI Express Root Port #13 (rev f0)\n\
+                    01:00.0 Non-Volatile memory controller: Samsung Electronics Co Ltd NVMe SSD Controller SM981/PM981/PM983\n";
+    task.ucmd()
+        .args(&["-F", "pcie.dump", "-i", "pci.ids"])
+        .succeeds()
+        .stdout_only(expected);
+    task.ucmd()
+        .args(&["-F", "pcie.dump", "-i", "pci.ids.gz"])
+        .succeeds()
+        .stdout_only(expected);
+
+    // Only the compressed database is installed
+    task.fixtures.mkdir_all("share");
+    task.fixtures
+        .write_bytes("share/pci.ids.gz", &task.fixtures.read_bytes("pci.ids.gz"));
+    task.ucmd()
+        .args(&["-F", "pcie.dump", "-i", "share/pci.ids"])
+        .succeeds()
+        .stdout_only(expected);
+
+    let res = task
+        .ucmd()
+        .args(&["-F", "pcie.dump", "-i", "pci.ids", "-vnn", "-s", "01:00.0"])
+        .succeeds();
+    assert!(res.stdout_str().starts_with(
+        "01:00.0 Non-Volatile memory controller [0108]: Samsung Electronics Co Ltd NVMe SSD Controller SM981/PM981/PM983 [144d:a808] (prog-if 02 [NVM Express])\n\
+         \tSubsystem: Samsung Electronics Co Ltd SSD 970 EVO/PRO [144d:a801]\n"
+    ));
+}
+
+#[test]
+fn test_lspci_ids_fallback() {
+    let task = TestScenario::new(util_name!());
+    let expected = "00:00.0 Class 0600: Device 8086:3e30 (rev 0d)\n\
+                    00:1c.0 Class 0604: Device 8086:a33c (rev f0)\n\
+                    01:00.0 Class 0108: Device 144d:a808\n";
+    task.ucmd()
+        .args(&["-F", "pcie.dump", "-i", "no-such.ids"])
+        .succeeds()
+        .stdout_only(expected);
+
+    // Known vendor with an unknown device, known class with an unknown subclass
+    task.fixtures.write(
+        "partial.ids",
+        "8086  Intel Corporation\n\tffff  Something else\nC 06  Bridge\n\t80  Bridge\n",
+    );
+    task.ucmd()
+        .args(&["-F", "pcie.dump", "-i", "partial.ids", "-s", "00:00.0"])
+        .succeeds()
+        .stdout_only("00:00.0 Bridge [0600]: Intel Corporation Device 3e30 (rev 0d)\n");
+    task.ucmd()
+        .args(&[
+            "-F",
+            "pcie.dump",
+            "-i",
+            "partial.ids",
+            "-nn",
+            "-s",
+            "00:00.0",
+        ])
+        .succeeds()
+        .stdout_only("00:00.0 Bridge [0600]: Intel Corporation Device [8086:3e30] (rev 0d)\n");
+}
+
+/// Serve TXT records of `records` over UDP on a local port, recording the
+/// names queried
+fn start_dns_stub(records: &[(&str, &str)]) -> (u16, Arc<Mutex<Vec<String>>>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let port = socket.local_addr().unwrap().port();
+    let queries = Arc::new(Mutex::new(Vec::new()));
+    let log = queries.clone();
+    let records: HashMap<String, String> = records
+        .iter()
+        .map(|(name, txt)| (name.to_string(), txt.to_string()))
+        .collect();
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        while let Ok((len, peer)) = socket.recv_from(&mut buf) {
+            let query = &buf[..len];
+            let mut labels = Vec::new();
+            let mut pos = 12;
+            while query[pos] != 0 {
+                let label_len = query[pos] as usize;
+                labels.push(
+                    String::from_utf8_lossy(&query[pos + 1..pos + 1 + label_len]).to_string(),
+                );
+                pos += 1 + label_len;
+            }
+            let question = &query[12..pos + 5];
+            let name = labels.join(".");
+            let txt = records.get(&name);
+            log.lock().unwrap().push(name);
+            let mut reply = Vec::new();
+            reply.extend_from_slice(&query[..2]);
+            reply.extend_from_slice(if txt.is_some() {
+                &[0x81, 0x80]
+            } else {
+                &[0x81, 0x83]
+            });
+            reply.extend_from_slice(&[0, 1, 0, txt.is_some() as u8, 0, 0, 0, 0]);
+            reply.extend_from_slice(question);
+            if let Some(txt) = txt {
+                reply.extend_from_slice(&[0xc0, 0x0c, 0, 16, 0, 1, 0, 0, 0x0e, 0x10]);
+                reply.extend_from_slice(&(txt.len() as u16 + 1).to_be_bytes());
+                reply.push(txt.len() as u8);
+                reply.extend_from_slice(txt.as_bytes());
+            }
+            socket.send_to(&reply, peer).unwrap();
+        }
+    });
+    (port, queries)
+}
+
+#[test]
+fn test_lspci_dns_lookup() {
+    let task = TestScenario::new(util_name!());
+    let (port, queries) = start_dns_stub(&[
+        ("8086.pci.id.ucw.cz", "i=Intel Corporation"),
+        ("3e30.8086.pci.id.ucw.cz", "i=Host Bridge/DRAM Registers"),
+        ("a33c.8086.pci.id.ucw.cz", "i=Root Port #13"),
+        ("144d.pci.id.ucw.cz", "i=Samsung Electronics Co Ltd"),
+        ("06.c.pci.id.ucw.cz", "i=Bridge"),
+        ("04.06.c.pci.id.ucw.cz", "i=PCI bridge"),
+    ]);
+    let server = format!("net.nameserver=127.0.0.1:{}", port);
+    let args = [
+        "-F",
+        "pcie.dump",
+        "-i",
+        "no-such.ids",
+        "-O",
+        &server,
+        "-O",
+        "net.cache_name=pciids-cache",
+    ];
+    let expected = "00:00.0 Bridge [0600]: Intel Corporation Host Bridge/DRAM Registers (rev 0d)\n\
+                    00:1c.0 PCI bridge: Intel Corporation Root Port #13 (rev f0)\n\
+                    01:00.0 Class 0108: Samsung Electronics Co Ltd Device a808\n";
+
+    let take = || std::mem::take(&mut *queries.lock().unwrap());
+    let known = |names: &[String]| names.iter().any(|name| name.starts_with("8086."));
+
+    task.ucmd()
+        .args(&args)
+        .arg("-q")
+        .succeeds()
+        .stdout_only(expected);
+    let sent = take();
+    assert!(known(&sent));
+    // pciutils' format, without the unknown IDs
+    assert_eq!(
+        task.fixtures.read("pciids-cache"),
+        "#PCI-CACHE-1.0\n\
+         1 144d 0 0 0 Samsung Electronics Co Ltd\n\
+         1 8086 0 0 0 Intel Corporation\n\
+         2 8086 3e30 0 0 Host Bridge/DRAM Registers\n\
+         2 8086 a33c 0 0 Root Port #13\n\
+         5 6 0 0 0 Bridge\n\
+         6 6 4 0 0 PCI bridge\n"
+    );
+
+    // Known names are served from the cache, unknown ones asked again
+    task.ucmd()
+        .args(&args)
+        .arg("-q")
+        .succeeds()
+        .stdout_only(expected);
+    let again = take();
+    assert!(!again.is_empty() && !known(&again));
+
+    // -qq asks again
+    task.ucmd()
+        .args(&args)
+        .arg("-qq")
+        .succeeds()
+        .stdout_only(expected);
+    assert_eq!(take(), sent);
+
+    // An expired cache file is ignored
+    let month_ago = filetime::FileTime::from_system_time(
+        std::time::SystemTime::now() - std::time::Duration::from_secs(31 * 24 * 60 * 60),
+    );
+    filetime::set_file_mtime(task.fixtures.plus("pciids-cache"), month_ago).unwrap();
+    task.ucmd()
+        .args(&args)
+        .arg("-q")
+        .succeeds()
+        .stdout_only(expected);
+    assert_eq!(take(), sent);
+
+    // Entries written by pciutils are used as they are
+    task.fixtures.write(
+        "pciids-cache",
+        "#PCI-CACHE-1.0\n1 8086 0 0 0 Cached Intel\n",
+    );
+    task.ucmd()
+        .args(&args)
+        .args(&["-q", "-s", "00:1c.0"])
+        .succeeds()
+        .stdout_only("00:1c.0 PCI bridge: Cached Intel Root Port #13 (rev f0)\n");
+    assert!(!take().contains(&"8086.pci.id.ucw.cz".to_string()));
+
+    // -Q prefers the network over the local database
+    task.ucmd()
+        .args(&[
+            "-F",
+            "pcie.dump",
+            "-i",
+            "pci.ids",
+            "-O",
+            &server,
+            "-O",
+            "net.cache_name=other-cache",
+            "-Q",
+            "-s",
+            "00:1c.0",
+        ])
+        .succeeds()
+        .stdout_only("00:1c.0 PCI bridge: Intel Corporation Root Port #13 (rev f0)\n");
+}
+
+#[test]
+fn test_lspci_dns_unreachable() {
+    let task = TestScenario::new(util_name!());
+    // Bind a port and close it again so that nothing answers there
+    let port = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    task.ucmd()
+        .args(&[
+            "-F",
+            "pcie.dump",
+            "-i",
+            "no-such.ids",
+            "-O",
+            &format!("net.nameserver=127.0.0.1:{}", port),
+            "-O",
+            "net.cache_name=pciids-cache",
+            "-q",
+            "-s",
+            "01:00.0",
+        ])
+        .succeeds()
+        .stdout_only("01:00.0 Class 0108: Device 144d:a808\n");
+    assert!(!task.fixtures.file_exists("pciids-cache"));
+}